@@ -0,0 +1,88 @@
+//! Storing a 300-chunk document: one INSERT per chunk, as ingestion used to, versus
+//! `insert_chunks`' multi-row batches. Each iteration runs in a transaction that is
+//! rolled back. Needs `DATABASE_URL` (see the README); the gap grows with the
+//! round-trip time to the database, so run it against the deployment's region too.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pgvector::Vector;
+use serde_json::json;
+use sqlx::PgConnection;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+use conversai_rag::services::chunking::Chunk;
+use conversai_rag::services::ingest::{self, prepare_document, ChunkSettings, DocumentSource};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const SECTIONS: usize = 300;
+
+/// The per-chunk loop `insert_chunks` replaced.
+async fn insert_one_by_one(conn: &mut PgConnection, document_id: Uuid, chunks: &[Chunk], embeddings: &[Vec<f32>]) {
+    for (i, (chunk, embedding)) in chunks.iter().zip(embeddings).enumerate() {
+        sqlx::query(
+            "INSERT INTO chunks (document_id, content, content_tokens, section, span, metadata, embedding, chunk_index)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(document_id)
+        .bind(&chunk.content)
+        .bind(chunk.tokens as i32)
+        .bind(&chunk.section)
+        .bind(&chunk.span)
+        .bind(&chunk.metadata)
+        .bind(Vector::from(embedding.clone()))
+        .bind(i as i32)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    }
+}
+
+fn insert_chunks(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let Some(pool) = runtime.block_on(common::test_pool()) else { return };
+
+    let markdown: String = (0..SECTIONS)
+        .map(|i| format!("# Section {}\n\nParagraph {} of the runbook, long enough to look like real prose.\n\n", i, i))
+        .collect();
+    let source = DocumentSource::upload("runbook.md", markdown.as_bytes());
+    let settings = ChunkSettings::default();
+    let chunks = runtime.block_on(prepare_document(&source, markdown.as_bytes(), settings)).unwrap().chunks;
+    let embeddings: Vec<Vec<f32>> = chunks.iter().map(|chunk| common::embed_text(&chunk.content)).collect();
+
+    // The chunks hang off a real document; only their rows are rolled back
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(common::StubEmbedder::default())));
+    let document = runtime.block_on(common::ingest_text(&router, json!({ "content": "# Runbook" })));
+    let document_id = common::uuid(&document["document_id"]);
+
+    let mut group = c.benchmark_group("insert_chunks");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(chunks.len() as u64));
+    group.bench_with_input(BenchmarkId::new("one_by_one", chunks.len()), &chunks, |b, chunks| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let mut tx = pool.begin().await.unwrap();
+                insert_one_by_one(&mut tx, document_id, chunks, &embeddings).await;
+                tx.rollback().await.unwrap();
+            })
+        })
+    });
+    group.bench_with_input(BenchmarkId::new("batched", chunks.len()), &chunks, |b, chunks| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let mut tx = pool.begin().await.unwrap();
+                ingest::insert_chunks(&mut tx, document_id, chunks, 1, &embeddings, "stub", settings)
+                    .await
+                    .unwrap();
+                tx.rollback().await.unwrap();
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, insert_chunks);
+criterion_main!(benches);
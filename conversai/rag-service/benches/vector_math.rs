@@ -0,0 +1,58 @@
+//! Reranking-sized similarity workloads: one 1536-dimension query against a few
+//! hundred candidates. Run with and without `--features simd` to compare the
+//! explicit SIMD lanes with the default path; `naive` is a sequential loop for
+//! reference.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use conversai_rag::services::vector_math;
+
+const DIMENSIONS: usize = 1536;
+
+/// Deterministic pseudo-random vector, so runs are comparable without a seeded RNG.
+fn vector(seed: usize) -> Vec<f32> {
+    (0..DIMENSIONS)
+        .map(|i| ((seed * DIMENSIONS + i) as f32 * 0.618_034).sin())
+        .collect()
+}
+
+fn naive_cosine(a: &[f32], b: &[f32]) -> f32 {
+    let mut dot = 0.0;
+    let mut norm_a = 0.0;
+    let mut norm_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+fn cosine_many(c: &mut Criterion) {
+    let query = vector(0);
+    let mut group = c.benchmark_group("cosine_many");
+    for count in [50, 200, 500] {
+        let candidates: Vec<Vec<f32>> = (1..=count).map(vector).collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("naive", count), &candidates, |b, candidates| {
+            b.iter(|| {
+                candidates
+                    .iter()
+                    .map(|candidate| naive_cosine(black_box(&query), candidate))
+                    .collect::<Vec<f32>>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("lanes", count), &candidates, |b, candidates| {
+            b.iter(|| vector_math::cosine_many(black_box(&query), candidates))
+        });
+    }
+    group.finish();
+}
+
+fn dot(c: &mut Criterion) {
+    let (a, b) = (vector(1), vector(2));
+    c.bench_function("dot/1536", |bench| bench.iter(|| vector_math::dot(black_box(&a), black_box(&b))));
+}
+
+criterion_group!(benches, cosine_many, dot);
+criterion_main!(benches);
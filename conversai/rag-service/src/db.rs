@@ -0,0 +1,70 @@
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use sqlx::Executor;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    pub idle_timeout: Duration,
+    pub statement_timeout: Duration,
+}
+
+impl PoolConfig {
+    pub fn from_env() -> Self {
+        let max_connections = env_parse("DB_MAX_CONNECTIONS").unwrap_or(10).max(1);
+        let min_connections = env_parse("DB_MIN_CONNECTIONS").unwrap_or(0).min(max_connections);
+        let acquire_timeout = env_parse("DB_ACQUIRE_TIMEOUT_SECS").unwrap_or(10);
+        let idle_timeout = env_parse("DB_IDLE_TIMEOUT_SECS").unwrap_or(600);
+        let statement_timeout = env_parse("DB_STATEMENT_TIMEOUT_SECS").unwrap_or(30);
+
+        Self {
+            max_connections,
+            min_connections,
+            acquire_timeout: Duration::from_secs(acquire_timeout),
+            idle_timeout: Duration::from_secs(idle_timeout),
+            statement_timeout: Duration::from_secs(statement_timeout),
+        }
+    }
+
+    pub async fn connect(&self, database_url: &str) -> Result<PgPool, sqlx::Error> {
+        info!(
+            "Database pool: max={} min={} acquire_timeout={:?} idle_timeout={:?} statement_timeout={:?}",
+            self.max_connections,
+            self.min_connections,
+            self.acquire_timeout,
+            self.idle_timeout,
+            self.statement_timeout
+        );
+
+        let options = PgConnectOptions::from_str(database_url)?;
+
+        // Set on every connection so a runaway query can't hold it forever (0 disables).
+        // A plain SET rather than the `options` startup parameter, which connection
+        // poolers such as PgBouncer and Supavisor reject.
+        let set_timeout = format!("SET statement_timeout = {}", self.statement_timeout.as_millis());
+
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+            .after_connect(move |conn, _meta| {
+                let set_timeout = set_timeout.clone();
+                Box::pin(async move {
+                    conn.execute(set_timeout.as_str()).await?;
+                    Ok(())
+                })
+            })
+            .connect_with(options)
+            .await
+    }
+}
+
+fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
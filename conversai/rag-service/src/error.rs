@@ -0,0 +1,185 @@
+use axum::{
+    extract::multipart::MultipartError,
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::services::ingest::{DocumentError, DocumentFormat, IngestError};
+use crate::services::url_fetch::FetchError;
+
+/// Error returned by HTTP handlers, rendered as
+/// `{"error": {"code", "message", "details", "request_id"}}`.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    Validation(String),
+
+    #[error("{0}")]
+    PayloadTooLarge(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("embedding provider request failed: {0}")]
+    Embedding(#[source] anyhow::Error),
+
+    #[error("{0}")]
+    Fetch(#[from] FetchError),
+
+    /// An upload that can't be read as its detected format
+    #[error("could not read {} document: {error}", format.as_str())]
+    InvalidDocument { format: DocumentFormat, error: DocumentError },
+
+    /// A concurrent ingest stored the version this one was about to
+    #[error("version {version} of document_key {key:?} was stored by a concurrent ingest; retry to store the next version")]
+    VersionConflict { key: String, version: i32 },
+
+    #[error("database not configured; the service is running in health-check-only mode")]
+    DatabaseUnavailable,
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("internal error: {0}")]
+    Internal(#[source] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Embedding(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Fetch(e) => match e {
+                FetchError::InvalidUrl { .. } | FetchError::BlockedAddress { .. } => StatusCode::BAD_REQUEST,
+                FetchError::UnsupportedContentType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                FetchError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                FetchError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+                FetchError::TooManyRedirects { .. }
+                | FetchError::Status { .. }
+                | FetchError::Resolve { .. }
+                | FetchError::Request { .. } => StatusCode::BAD_GATEWAY,
+            },
+            ApiError::InvalidDocument { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::VersionConflict { .. } => StatusCode::CONFLICT,
+            ApiError::DatabaseUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "invalid_request",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Embedding(_) => "embedding_failed",
+            ApiError::Fetch(e) => match e {
+                FetchError::InvalidUrl { .. } => "invalid_url",
+                FetchError::BlockedAddress { .. } => "blocked_address",
+                FetchError::TooManyRedirects { .. } => "too_many_redirects",
+                FetchError::Timeout { .. } => "fetch_timeout",
+                FetchError::Status { .. } => "upstream_status",
+                FetchError::UnsupportedContentType { .. } => "unsupported_content_type",
+                FetchError::TooLarge { .. } => "payload_too_large",
+                FetchError::Resolve { .. } | FetchError::Request { .. } => "fetch_failed",
+            },
+            ApiError::InvalidDocument { error, .. } => match error {
+                DocumentError::Corrupt(_) => "corrupt_document",
+                DocumentError::Encrypted(_) => "encrypted_document",
+            },
+            ApiError::VersionConflict { .. } => "version_conflict",
+            ApiError::DatabaseUnavailable => "database_unavailable",
+            ApiError::Database(_) => "database_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            ApiError::Embedding(e) | ApiError::Internal(e) => {
+                json!({ "causes": e.chain().skip(1).map(|c| c.to_string()).collect::<Vec<_>>() })
+            }
+            ApiError::Fetch(e) => match e {
+                FetchError::Status { status, .. } => json!({ "url": e.url(), "status": status }),
+                FetchError::BlockedAddress { address, .. } => json!({ "url": e.url(), "address": address }),
+                FetchError::UnsupportedContentType { content_type, .. } => {
+                    json!({ "url": e.url(), "content_type": content_type })
+                }
+                _ => json!({ "url": e.url() }),
+            },
+            ApiError::InvalidDocument { format, .. } => json!({ "format": format.as_str() }),
+            ApiError::VersionConflict { key, version, .. } => json!({ "document_key": key, "version": version }),
+            ApiError::Database(e) => match e.as_database_error() {
+                Some(db) => json!({ "constraint": db.constraint(), "sql_state": db.code() }),
+                None => Value::Null,
+            },
+            ApiError::Validation(_)
+            | ApiError::PayloadTooLarge(_)
+            | ApiError::NotFound(_)
+            | ApiError::DatabaseUnavailable => Value::Null,
+        }
+    }
+}
+
+/// Service code uses `anyhow`; database failures keep their own code when they
+/// surface through it.
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<sqlx::Error>() {
+            Ok(e) => ApiError::Database(e),
+            Err(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+impl From<IngestError> for ApiError {
+    fn from(error: IngestError) -> Self {
+        match error {
+            IngestError::Embedding(e) => ApiError::Embedding(e),
+            IngestError::InvalidDocument { format, error } => ApiError::InvalidDocument { format, error },
+            IngestError::VersionConflict { key, version } => ApiError::VersionConflict { key, version },
+            IngestError::Other(e) => e.into(),
+        }
+    }
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(error: MultipartError) -> Self {
+        if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            ApiError::PayloadTooLarge(error.body_text())
+        } else {
+            ApiError::Validation(error.body_text())
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = Uuid::new_v4().to_string();
+        let details = self.details();
+
+        error!(request_id = %request_id, code = self.code(), details = %details, "{}", self);
+
+        let body = json!({
+            "error": {
+                "code": self.code(),
+                "message": self.to_string(),
+                "details": details,
+                "request_id": request_id,
+            }
+        });
+
+        let mut response = (status, Json(body)).into_response();
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert("x-request-id", value);
+        }
+        response
+    }
+}
@@ -0,0 +1,156 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use pgvector::Vector;
+use serde_json::{json, Value};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::{ChunkDetail, DeleteChunkResponse, UpdateChunkRequest};
+use crate::services::{chunking, embedding};
+use crate::state::AppState;
+
+const CHUNK_DETAIL_SELECT: &str =
+    "SELECT id, document_id, chunk_index, content, content_tokens, section, span, metadata, created_at FROM chunks";
+
+/// One chunk with its metadata, for inspecting how it was made.
+pub async fn handle_get_chunk(
+    State(state): State<AppState>,
+    Path(chunk_id): Path<Uuid>,
+) -> Result<Json<ChunkDetail>, ApiError> {
+    let pool = state.pool()?;
+
+    let chunk = sqlx::query_as::<_, ChunkDetail>(&format!("{} WHERE id = $1", CHUNK_DETAIL_SELECT))
+        .bind(chunk_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("chunk {} not found", chunk_id)))?;
+
+    Ok(Json(chunk))
+}
+
+/// Corrects a chunk's content and/or metadata. New content is recounted and
+/// re-embedded before anything is written, so a failed embedding call leaves the
+/// chunk exactly as it was; the write itself is one transaction.
+pub async fn handle_update_chunk(
+    State(state): State<AppState>,
+    Path(chunk_id): Path<Uuid>,
+    Json(request): Json<UpdateChunkRequest>,
+) -> Result<Json<ChunkDetail>, ApiError> {
+    let pool = state.pool()?;
+
+    if request.content.is_none() && request.metadata.is_none() {
+        return Err(ApiError::Validation("nothing to update: send content and/or metadata".to_string()));
+    }
+    if request.content.as_deref().is_some_and(|c| c.trim().is_empty()) {
+        return Err(ApiError::Validation("content must not be empty".to_string()));
+    }
+
+    let not_found = || ApiError::NotFound(format!("chunk {} not found", chunk_id));
+
+    // Embedded up front so the transaction below doesn't hold a connection while
+    // waiting on the provider; unknown ids fail before the embedding call
+    let embedded = match request.content.as_deref() {
+        Some(content) => {
+            let heading_prefix: Option<Option<String>> =
+                sqlx::query_scalar("SELECT metadata->>'heading_prefix' FROM chunks WHERE id = $1")
+                    .bind(chunk_id)
+                    .fetch_optional(pool)
+                    .await?;
+            let content = with_heading_prefix(content, heading_prefix.ok_or_else(not_found)?.as_deref());
+            let embedded = embedding::get_embeddings_cached(pool, state.embedder.as_ref(), &[content.as_str()])
+                .await
+                .map_err(ApiError::Embedding)?;
+            let vector = embedded
+                .embeddings
+                .into_iter()
+                .next()
+                .map(Vector::from)
+                .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("embedding provider returned no embedding")))?;
+            Some((content, vector))
+        }
+        None => None,
+    };
+
+    let mut tx = pool.begin().await?;
+    let metadata: Option<Option<Value>> = sqlx::query_scalar("SELECT metadata FROM chunks WHERE id = $1 FOR UPDATE")
+        .bind(chunk_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let mut metadata = match metadata {
+        Some(Some(Value::Object(metadata))) => metadata,
+        Some(_) => serde_json::Map::new(),
+        None => return Err(not_found()),
+    };
+    metadata.extend(request.metadata.unwrap_or_default());
+
+    match embedded {
+        Some((content, vector)) => {
+            // Provenance follows the new embedding
+            metadata.insert("embedding_model".to_string(), json!(state.embedder.model_name()));
+            metadata.insert("edited_at".to_string(), json!(Utc::now().to_rfc3339()));
+
+            sqlx::query("UPDATE chunks SET content = $2, content_tokens = $3, embedding = $4, metadata = $5 WHERE id = $1")
+                .bind(chunk_id)
+                .bind(&content)
+                .bind(chunking::count_tokens(&content) as i32)
+                .bind(vector)
+                .bind(Value::Object(metadata))
+                .execute(&mut *tx)
+                .await?;
+        }
+        None => {
+            sqlx::query("UPDATE chunks SET metadata = $2 WHERE id = $1")
+                .bind(chunk_id)
+                .bind(Value::Object(metadata))
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let chunk = sqlx::query_as::<_, ChunkDetail>(&format!("{} WHERE id = $1", CHUNK_DETAIL_SELECT))
+        .bind(chunk_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    state.query_cache.invalidate();
+
+    info!(
+        "Updated chunk {} ({})",
+        chunk_id,
+        if request.content.is_some() { "content re-embedded" } else { "metadata only" }
+    );
+
+    Ok(Json(chunk))
+}
+
+/// Edited content keeps the chunk's heading prefix, as ingestion would have
+/// written it, unless the edit already starts with it.
+fn with_heading_prefix(content: &str, prefix: Option<&str>) -> String {
+    match prefix {
+        Some(prefix) if !content.starts_with(prefix) => format!("{}{}", prefix, content),
+        _ => content.to_string(),
+    }
+}
+
+/// Deletes a single chunk; its document and the other chunks stay.
+pub async fn handle_delete_chunk(
+    State(state): State<AppState>,
+    Path(chunk_id): Path<Uuid>,
+) -> Result<Json<DeleteChunkResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    let document_id: Uuid = sqlx::query_scalar("DELETE FROM chunks WHERE id = $1 RETURNING document_id")
+        .bind(chunk_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("chunk {} not found", chunk_id)))?;
+    state.query_cache.invalidate();
+
+    info!("Deleted chunk {} of document {}", chunk_id, document_id);
+
+    Ok(Json(DeleteChunkResponse { chunk_id, document_id }))
+}
@@ -0,0 +1,383 @@
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::FromRow;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::{
+    ChunkPreview, DeleteDocumentResponse, DeleteDocumentsParams, DeleteDocumentsResponse, DocumentDetailResponse,
+    DocumentListResponse, DocumentSummary, DocumentTagsResponse, ListDocumentsParams, SummaryResponse,
+    UpdateTagsRequest,
+};
+use crate::state::AppState;
+use crate::utils::{truncate_with, TruncateBoundary, TruncateOptions};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+const CHUNK_PREVIEW_CHARS: usize = 200;
+
+// Documents with their chunk count and token total, aggregated in one pass; like
+// ingest responses, these leave out the summary chunk
+const DOCUMENT_SUMMARY_SELECT: &str = r#"
+    SELECT d.id, d.source_type, d.source_uri, d.document_key,
+           COALESCE(d.document_version, 1) AS version,
+           COALESCE(d.tags, '{}') AS tags,
+           count(c.id) AS chunk_count,
+           COALESCE(sum(c.content_tokens), 0)::bigint AS total_tokens,
+           d.created_at, d.superseded_at
+    FROM documents d
+    LEFT JOIN chunks c ON c.document_id = d.id AND c.metadata->>'kind' IS DISTINCT FROM 'summary'
+"#;
+
+/// Lists documents newest first, optionally only those with a tag or source type.
+pub async fn handle_list_documents(
+    State(state): State<AppState>,
+    Query(params): Query<ListDocumentsParams>,
+) -> Result<Json<DocumentListResponse>, ApiError> {
+    let pool = state.pool()?;
+    let limit = page_size(&params)?;
+    let tag = params.tag.as_deref().map(str::trim).filter(|t| !t.is_empty());
+    let source_type = params.source_type.as_deref().map(str::trim).filter(|t| !t.is_empty());
+
+    let filter = "WHERE ($1::text IS NULL OR $1 = ANY(d.tags)) AND ($2::text IS NULL OR d.source_type = $2)";
+    let total: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM documents d {}", filter))
+        .bind(tag)
+        .bind(source_type)
+        .fetch_one(pool)
+        .await?;
+    // The id breaks created_at ties so pages don't overlap
+    let documents = sqlx::query_as::<_, DocumentSummary>(&format!(
+        "{} {} GROUP BY d.id ORDER BY d.created_at DESC, d.id DESC LIMIT $3 OFFSET $4",
+        DOCUMENT_SUMMARY_SELECT, filter
+    ))
+    .bind(tag)
+    .bind(source_type)
+    .bind(limit)
+    .bind(params.offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Json(DocumentListResponse {
+        next_offset: next_offset(params.offset, documents.len(), total),
+        documents,
+        total,
+        limit,
+        offset: params.offset,
+    }))
+}
+
+/// The page size to use, after checking the offset too. Larger pages are clamped;
+/// the response's `limit` is the page size used.
+fn page_size(params: &ListDocumentsParams) -> Result<i64, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit < 1 {
+        return Err(ApiError::Validation(format!("limit must be positive, got {}", limit)));
+    }
+    if params.offset < 0 {
+        return Err(ApiError::Validation(format!("offset must not be negative, got {}", params.offset)));
+    }
+    Ok(limit.min(MAX_PAGE_SIZE))
+}
+
+/// Offset of the page after `returned` documents read from `offset`; `None` once
+/// nothing is left, including for an offset past the end.
+fn next_offset(offset: i64, returned: usize, total: i64) -> Option<i64> {
+    let end = offset + returned as i64;
+    (end < total).then_some(end)
+}
+
+#[derive(FromRow)]
+struct ChunkRow {
+    id: Uuid,
+    chunk_index: Option<i32>,
+    section: Option<String>,
+    content_tokens: Option<i32>,
+    content: String,
+}
+
+/// `metadata.summary` as `summarize::store_summary` writes it
+#[derive(Deserialize)]
+struct StoredSummary {
+    text: String,
+    model: String,
+    generated_at: DateTime<Utc>,
+}
+
+/// One document with previews of its chunks in document order.
+pub async fn handle_get_document(
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<Json<DocumentDetailResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    let document = sqlx::query_as::<_, DocumentSummary>(&format!(
+        "{} WHERE d.id = $1 GROUP BY d.id",
+        DOCUMENT_SUMMARY_SELECT
+    ))
+    .bind(document_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| ApiError::NotFound(format!("document {} not found", document_id)))?;
+
+    let rows = sqlx::query_as::<_, ChunkRow>(
+        r#"
+        SELECT id, chunk_index, section, content_tokens, content
+        FROM chunks
+        WHERE document_id = $1
+        ORDER BY chunk_index NULLS LAST, created_at
+        "#
+    )
+    .bind(document_id)
+    .fetch_all(pool)
+    .await?;
+
+    let options = TruncateOptions {
+        boundary: TruncateBoundary::Word,
+        ..TruncateOptions::default()
+    };
+    let chunks = rows
+        .into_iter()
+        .map(|row| {
+            let preview = truncate_with(&row.content, CHUNK_PREVIEW_CHARS, &options);
+            ChunkPreview {
+                id: row.id,
+                chunk_index: row.chunk_index,
+                section: row.section,
+                content_tokens: row.content_tokens,
+                truncated: preview.len() < row.content.len(),
+                preview,
+            }
+        })
+        .collect();
+
+    Ok(Json(DocumentDetailResponse { document, chunks }))
+}
+
+
+/// Adds and removes tags on an ingested document without re-embedding it.
+/// Adding an existing tag or removing an absent one is a no-op.
+pub async fn handle_update_tags(
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Json(request): Json<UpdateTagsRequest>,
+) -> Result<Json<DocumentTagsResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    let add = normalize_tags(&request.add);
+    let remove = normalize_tags(&request.remove);
+
+    // Removes win over adds when a tag appears in both lists
+    let tags: Option<Option<Vec<String>>> = sqlx::query_scalar(
+        r#"
+        UPDATE documents
+        SET tags = ARRAY(
+                SELECT DISTINCT t
+                FROM unnest(COALESCE(tags, '{}') || $2::text[]) AS t
+                WHERE NOT (t = ANY($3::text[]))
+                ORDER BY t
+            ),
+            updated_at = now()
+        WHERE id = $1
+        RETURNING tags
+        "#
+    )
+    .bind(document_id)
+    .bind(&add)
+    .bind(&remove)
+    .fetch_optional(pool)
+    .await?;
+
+    let tags = tags
+        .ok_or_else(|| ApiError::NotFound(format!("document {} not found", document_id)))?
+        .unwrap_or_default();
+    state.query_cache.invalidate();
+
+    info!(
+        "Updated tags on document {} (+{} -{}): {:?}",
+        document_id,
+        add.len(),
+        remove.len(),
+        tags
+    );
+
+    Ok(Json(DocumentTagsResponse { document_id, tags }))
+}
+
+/// The summary generated for a document at ingest (`DOCUMENT_SUMMARIES`). Documents
+/// ingested without summaries, or too short to get one, answer 404.
+pub async fn handle_get_summary(
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<Json<SummaryResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    let summary: Option<Option<serde_json::Value>> =
+        sqlx::query_scalar("SELECT metadata->'summary' FROM documents WHERE id = $1")
+            .bind(document_id)
+            .fetch_optional(pool)
+            .await?;
+    let summary = summary
+        .ok_or_else(|| ApiError::NotFound(format!("document {} not found", document_id)))?
+        .ok_or_else(|| ApiError::NotFound(format!("document {} has no summary", document_id)))?;
+
+    let stored: StoredSummary = serde_json::from_value(summary)
+        .map_err(|e| ApiError::Internal(anyhow!("document {} has a malformed summary: {}", document_id, e)))?;
+
+    Ok(Json(SummaryResponse {
+        document_id,
+        summary: stored.text,
+        model: stored.model,
+        generated_at: stored.generated_at,
+    }))
+}
+
+/// Deletes a document and its chunks in one transaction.
+pub async fn handle_delete_document(
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<Json<DeleteDocumentResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    let mut tx = pool.begin().await?;
+    let chunks_deleted = sqlx::query("DELETE FROM chunks WHERE document_id = $1")
+        .bind(document_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let deleted = sqlx::query("DELETE FROM documents WHERE id = $1")
+        .bind(document_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    // Dropping the transaction rolls it back
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("document {} not found", document_id)));
+    }
+    tx.commit().await?;
+    state.query_cache.invalidate();
+
+    info!("Deleted document {} with {} chunks", document_id, chunks_deleted);
+
+    Ok(Json(DeleteDocumentResponse {
+        document_id,
+        chunks_deleted,
+    }))
+}
+
+/// Deletes every document carrying `tag`, with its chunks, in one transaction.
+/// With `dry_run` nothing is deleted and the counts say what would be.
+pub async fn handle_delete_documents(
+    State(state): State<AppState>,
+    Query(params): Query<DeleteDocumentsParams>,
+) -> Result<Json<DeleteDocumentsResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    // Without a tag this would delete everything, which is never what a caller meant
+    let tag = params
+        .tag
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| ApiError::Validation("the tag query parameter is required".to_string()))?;
+
+    let mut tx = pool.begin().await?;
+    let document_ids: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM documents WHERE $1 = ANY(tags) ORDER BY created_at FOR UPDATE"
+    )
+    .bind(tag)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let chunks_deleted = if params.dry_run {
+        let count: i64 = sqlx::query_scalar("SELECT count(*) FROM chunks WHERE document_id = ANY($1)")
+            .bind(&document_ids)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.rollback().await?;
+        count as u64
+    } else {
+        let chunks_deleted = sqlx::query("DELETE FROM chunks WHERE document_id = ANY($1)")
+            .bind(&document_ids)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM documents WHERE id = ANY($1)")
+            .bind(&document_ids)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        state.query_cache.invalidate();
+        chunks_deleted
+    };
+
+    info!(
+        "{} {} documents tagged {:?} with {} chunks",
+        if params.dry_run { "Dry run: would delete" } else { "Deleted" },
+        document_ids.len(),
+        tag,
+        chunks_deleted
+    );
+
+    Ok(Json(DeleteDocumentsResponse {
+        documents_deleted: document_ids.len(),
+        document_ids,
+        chunks_deleted,
+        dry_run: params.dry_run,
+    }))
+}
+
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    tags.iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(limit: Option<i64>, offset: i64) -> ListDocumentsParams {
+        ListDocumentsParams { limit, offset, tag: None, source_type: None }
+    }
+
+    #[test]
+    fn page_size_defaults_and_clamps() {
+        assert_eq!(page_size(&params(None, 0)).unwrap(), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(&params(Some(1), 0)).unwrap(), 1);
+        assert_eq!(page_size(&params(Some(MAX_PAGE_SIZE), 0)).unwrap(), MAX_PAGE_SIZE);
+        assert_eq!(page_size(&params(Some(10_000), 0)).unwrap(), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn page_size_rejects_bad_limits_and_offsets() {
+        for (limit, offset) in [(Some(0), 0), (Some(-5), 0), (None, -1)] {
+            assert!(matches!(page_size(&params(limit, offset)), Err(ApiError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn next_offset_points_past_the_returned_page() {
+        // 120 documents in pages of 50
+        assert_eq!(next_offset(0, 50, 120), Some(50));
+        assert_eq!(next_offset(50, 50, 120), Some(100));
+        assert_eq!(next_offset(100, 20, 120), None);
+        // A page ending exactly at the total is the last one
+        assert_eq!(next_offset(70, 50, 120), None);
+        // Offsets that don't fall on a page boundary still continue from where they stopped
+        assert_eq!(next_offset(7, 50, 120), Some(57));
+    }
+
+    #[test]
+    fn next_offset_is_absent_past_the_end() {
+        assert_eq!(next_offset(120, 0, 120), None);
+        assert_eq!(next_offset(500, 0, 120), None);
+        assert_eq!(next_offset(0, 0, 0), None);
+    }
+}
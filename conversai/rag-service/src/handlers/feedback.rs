@@ -1,11 +1,11 @@
 use axum::{extract::State, http::StatusCode, Json};
-use sqlx::PgPool;
 use tracing::info;
 
 use crate::models::FeedbackRequest;
+use crate::state::AppState;
 
 pub async fn handle_feedback(
-    State(_pool): State<PgPool>,
+    State(_state): State<AppState>,
     Json(request): Json<FeedbackRequest>,
 ) -> StatusCode {
     // Log feedback for now, implement storage later
@@ -1,138 +1,627 @@
 use axum::{
-    extract::{Multipart, State},
+    extract::{multipart::Field, Multipart, Path, Query, State},
     http::StatusCode,
+    response::{IntoResponse, Response},
     Json,
 };
 use bytes::Bytes;
 use sha2::{Digest, Sha256};
 use sqlx::PgPool;
-use tracing::{info, error};
-use crate::models::{Document, IngestResponse};
-use crate::services::{chunking, embedding, markdown};
-use pgvector::Vector;
+use tracing::{info, error, warn};
+use uuid::Uuid;
+use crate::error::ApiError;
+use crate::models::{
+    BatchIngestError, BatchIngestResponse, BatchIngestSummary, IngestJob, IngestJobAccepted,
+    IngestParams, IngestPreviewResponse, IngestRequest, IngestResponse, IngestTextRequest, PreviewChunk,
+    PreviewParams,
+};
+use crate::services::ingest::{
+    build_response, duplicate_warning, find_document_by_sha, ingest_document, input_limit_warning, insert_chunks,
+    insert_document, prepare_document, warning, ChunkSettings, DocumentSource, PreparedDocument,
+};
+use crate::services::summarize::{self, DocumentSummary};
+use crate::services::{embedding, jobs};
+use crate::state::AppState;
 
+/// Ingests a single `file`, versioned under the `document_key` field if there is
+/// one. With `?async=true` the pipeline runs on a background task and a 202 with a
+/// job id is returned immediately.
 pub async fn handle_ingest(
-    State(pool): State<PgPool>,
+    State(state): State<AppState>,
+    Query(params): Query<IngestParams>,
     mut multipart: Multipart,
-) -> Result<Json<IngestResponse>, StatusCode> {
-    let mut file_data: Option<Bytes> = None;
+) -> Result<Response, ApiError> {
+    let pool = state.pool()?;
+    let mut upload: Option<Upload> = None;
     let mut filename: Option<String> = None;
     let mut tags: Vec<String> = Vec::new();
+    let mut document_key: Option<String> = None;
+    let mut max_tokens: Option<usize> = None;
+    let mut overlap_tokens: Option<usize> = None;
 
     // Parse multipart data
-    while let Some(field) = multipart.next_field().await.unwrap() {
+    while let Some(field) = multipart.next_field().await? {
         let field_name = field.name().unwrap_or("").to_string();
         
         match field_name.as_str() {
             "file" => {
                 filename = field.file_name().map(|s| s.to_string());
-                file_data = Some(field.bytes().await.unwrap());
+                upload = Some(read_file_field(field, state.max_upload_bytes).await?);
             }
             "tags" => {
-                let text = field.text().await.unwrap();
-                tags = text.split(',').map(|s| s.trim().to_string()).collect();
+                let text = field.text().await?;
+                tags = parse_tags(&text);
             }
+            "document_key" => document_key = Some(field.text().await?),
+            "max_tokens" => max_tokens = Some(parse_token_field("max_tokens", &field.text().await?)?),
+            "overlap_tokens" => overlap_tokens = Some(parse_token_field("overlap_tokens", &field.text().await?)?),
             _ => {}
         }
     }
 
-    let file_data = file_data.ok_or(StatusCode::BAD_REQUEST)?;
-    let filename = filename.ok_or(StatusCode::BAD_REQUEST)?;
+    let Upload { data: file_data, sha256 } = upload
+        .ok_or_else(|| ApiError::Validation("missing `file` field".to_string()))?;
+    let filename = filename
+        .ok_or_else(|| ApiError::Validation("`file` field has no filename".to_string()))?;
+    if file_data.is_empty() {
+        return Err(ApiError::Validation(format!("{} is empty", filename)));
+    }
+    let settings = chunk_settings(&state, max_tokens, overlap_tokens)?;
 
-    // Calculate SHA256
-    let mut hasher = Sha256::new();
-    hasher.update(&file_data);
-    let sha256 = format!("{:x}", hasher.finalize());
-
-    // Check if document already exists
-    let existing = sqlx::query_as::<_, Document>(
-        "SELECT * FROM documents WHERE content_sha256 = $1"
-    )
-    .bind(&sha256)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let document_id = if let Some(doc) = existing {
-        info!("Document already exists with ID: {}", doc.id);
-        doc.id
-    } else {
-        // Upload to Supabase Storage (placeholder for now)
-        let source_uri = format!("storage://{}", filename);
-
-        // Insert document
-        let doc = sqlx::query_as::<_, Document>(
-            r#"
-            INSERT INTO documents (source_type, source_uri, content_sha256, tags)
-            VALUES ($1, $2, $3, $4)
-            RETURNING *
-            "#
-        )
-        .bind("md") // Assuming markdown for now
-        .bind(&source_uri)
-        .bind(&sha256)
-        .bind(&tags)
-        .fetch_one(&pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        // Parse markdown
-        let content = String::from_utf8_lossy(&file_data);
-        let sections = markdown::parse_markdown(&content);
-
-        // Chunk sections
-        let chunks = chunking::chunk_sections(&sections, 500, 50);
-
-        // Get embeddings
+    if !params.run_async {
+        let source = DocumentSource::upload(&filename, &file_data)
+            .with_key(document_key.as_deref())
+            .with_sha256(sha256);
+        let response = ingest_document(pool, state.embedder.as_ref(), &source, &file_data, &tags, settings, None).await?;
+        state.query_cache.invalidate();
+        return Ok(Json(response).into_response());
+    }
+
+    let job_id = jobs::create_job(pool, &filename).await?;
+    info!("Queued ingest job {} for {}", job_id, filename);
+    let pool = pool.clone();
+    let semaphore = state.ingest_jobs.clone();
+    let query_cache = state.query_cache.clone();
+    let embedder = state.embedder.clone();
+
+    tokio::spawn(async move {
+        // Waits as `queued` until a slot frees up
+        let Ok(_permit) = semaphore.acquire_owned().await else { return };
+
+        // Run on a nested task so a panic is recorded as a failure instead of vanishing
+        let job_pool = pool.clone();
+        let outcome = tokio::spawn(async move {
+            jobs::mark_processing(&job_pool, job_id).await?;
+            let source = DocumentSource::upload(&filename, &file_data)
+                .with_key(document_key.as_deref())
+                .with_sha256(sha256);
+            ingest_document(&job_pool, embedder.as_ref(), &source, &file_data, &tags, settings, Some(job_id))
+                .await
+                .map_err(anyhow::Error::from)
+        })
+        .await;
+        query_cache.invalidate();
+
+        let recorded = match outcome {
+            Ok(Ok(response)) => {
+                info!("Ingest job {} completed", job_id);
+                jobs::mark_completed(&pool, job_id, &response).await
+            }
+            Ok(Err(e)) => {
+                error!("Ingest job {} failed: {:#}", job_id, e);
+                jobs::mark_failed(&pool, job_id, &format!("{:#}", e)).await
+            }
+            Err(e) => {
+                error!("Ingest job {} panicked: {}", job_id, e);
+                jobs::mark_failed(&pool, job_id, "ingest task panicked").await
+            }
+        };
+
+        if let Err(e) = recorded {
+            error!("Failed to record status of ingest job {}: {}", job_id, e);
+        }
+    });
+
+    let accepted = IngestJobAccepted {
+        job_id,
+        status: "queued".to_string(),
+    };
+    Ok((StatusCode::ACCEPTED, Json(accepted)).into_response())
+}
+
+/// Status of a background ingest job started with `?async=true`.
+pub async fn handle_ingest_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<IngestJob>, ApiError> {
+    let job = jobs::get_job(state.pool()?, job_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("ingest job {} not found", job_id)))?;
+
+    Ok(Json(job))
+}
+
+/// Fetches `url` and ingests the page like an uploaded markdown file, stored with
+/// `source_type = "url"`, the URL after redirects as `source_uri` and the served
+/// content type in `metadata`. Pages are versioned by `document_key`, default the
+/// URL after redirects: refetching an unchanged page returns the existing document,
+/// a changed one is stored as its next version.
+pub async fn handle_ingest_url(
+    State(state): State<AppState>,
+    Json(request): Json<IngestRequest>,
+) -> Result<Json<IngestResponse>, ApiError> {
+    let pool = state.pool()?;
+    let url = request
+        .url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .ok_or_else(|| ApiError::Validation("missing `url` field".to_string()))?;
+    let tags = request.tags.unwrap_or_default();
+    let settings = chunk_settings(&state, request.max_tokens, request.overlap_tokens)?;
+
+    let fetched = state.url_fetcher.fetch(url, state.max_upload_bytes).await?;
+    if fetched.markdown.trim().is_empty() {
+        return Err(ApiError::Validation(format!("{} has no text content", fetched.final_url)));
+    }
+    info!(
+        "Fetched {} ({}, {} bytes of markdown)",
+        fetched.final_url,
+        fetched.content_type,
+        fetched.markdown.len()
+    );
+
+    let key = request.document_key.as_deref().unwrap_or(&fetched.final_url);
+    let source = DocumentSource::url(&fetched.final_url, &fetched.content_type).with_key(Some(key));
+    let markdown = fetched.markdown.as_bytes();
+    let response = ingest_document(pool, state.embedder.as_ref(), &source, markdown, &tags, settings, None).await;
+    state.query_cache.invalidate();
+
+    let mut response = response?;
+    if fetched.final_url != url {
+        response.warnings.push(warning("redirected", format!("{} redirected to {}", url, fetched.final_url)));
+    }
+    Ok(Json(response))
+}
+
+/// Ingests markdown sent as JSON, for callers that already hold the text in
+/// memory. Runs the same pipeline as an uploaded file, including dedup by content.
+/// A `document_key`, or else an explicit `source_uri`, versions the document.
+pub async fn handle_ingest_text(
+    State(state): State<AppState>,
+    Json(request): Json<IngestTextRequest>,
+) -> Result<Json<IngestResponse>, ApiError> {
+    let pool = state.pool()?;
+    if request.content.trim().is_empty() {
+        return Err(ApiError::Validation("`content` must not be empty".to_string()));
+    }
+    if request.content.len() > state.max_upload_bytes {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "`content` exceeds the maximum upload size of {} bytes",
+            state.max_upload_bytes
+        )));
+    }
+    let settings = chunk_settings(&state, request.max_tokens, request.overlap_tokens)?;
+
+    let filename = request
+        .filename
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or("untitled.md");
+    let source_uri = request.source_uri.as_deref().map(str::trim).filter(|uri| !uri.is_empty());
+    let source = match source_uri {
+        Some(uri) => DocumentSource::text(uri),
+        // Always markdown, whatever the filename's extension says
+        None => DocumentSource::text(&format!("storage://{}", filename)),
+    };
+    let source = source.with_key(request.document_key.as_deref().or(source_uri));
+    let tags = request.tags.unwrap_or_default();
+
+    let content = request.content.as_bytes();
+    let response = ingest_document(pool, state.embedder.as_ref(), &source, content, &tags, settings, None).await;
+    state.query_cache.invalidate();
+    Ok(Json(response?))
+}
+
+/// Parses and chunks an uploaded `file` like `POST /api/ingest` would, without
+/// touching the database. `max_tokens` and `overlap_tokens` fields override the
+/// chunk bounds; with `?embed=true` the chunks are also embedded, uncached.
+pub async fn handle_ingest_preview(
+    State(state): State<AppState>,
+    Query(params): Query<PreviewParams>,
+    mut multipart: Multipart,
+) -> Result<Json<IngestPreviewResponse>, ApiError> {
+    let mut upload: Option<Upload> = None;
+    let mut filename: Option<String> = None;
+    let mut max_tokens: Option<usize> = None;
+    let mut overlap_tokens: Option<usize> = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        let field_name = field.name().unwrap_or("").to_string();
+
+        match field_name.as_str() {
+            "file" => {
+                filename = field.file_name().map(|s| s.to_string());
+                upload = Some(read_file_field(field, state.max_upload_bytes).await?);
+            }
+            "max_tokens" => max_tokens = Some(parse_token_field("max_tokens", &field.text().await?)?),
+            "overlap_tokens" => overlap_tokens = Some(parse_token_field("overlap_tokens", &field.text().await?)?),
+            _ => {}
+        }
+    }
+
+    let Upload { data, .. } = upload
+        .ok_or_else(|| ApiError::Validation("missing `file` field".to_string()))?;
+    let filename = filename
+        .ok_or_else(|| ApiError::Validation("`file` field has no filename".to_string()))?;
+    if data.is_empty() {
+        return Err(ApiError::Validation(format!("{} is empty", filename)));
+    }
+    let settings = chunk_settings(&state, max_tokens, overlap_tokens)?;
+
+    let source = DocumentSource::upload(&filename, &data);
+    let PreparedDocument { chunks, mut warnings, .. } = prepare_document(&source, &data, settings).await?;
+    warnings.extend(input_limit_warning(state.embedder.as_ref(), &chunks));
+
+    let mut embeddings = if params.embed && !chunks.is_empty() {
         let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
-        let embeddings = embedding::get_embeddings(&texts).await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        // Insert chunks
-        for (chunk, embedding) in chunks.iter().zip(embeddings.iter()) {
-            // Convert Vec<f32> to pgvector::Vector
-            let vector = Vector::from(embedding.clone());
-            
-            match sqlx::query(
-                r#"
-                INSERT INTO chunks (document_id, content, content_tokens, section, span, metadata, embedding)
-                VALUES ($1, $2, $3, $4, $5, $6, $7)
-                "#
-            )
-            .bind(doc.id)
-            .bind(&chunk.content)
-            .bind(chunk.tokens as i32)
-            .bind(&chunk.section)
-            .bind(&chunk.span)
-            .bind(&chunk.metadata)
-            .bind(vector)
-            .execute(&pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => {
-                    error!("Failed to insert chunk: {}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        let embedded = state.embedder.embed(&texts).await.map_err(ApiError::Embedding)?;
+        embedded.into_iter().map(Some).collect()
+    } else {
+        Vec::new()
+    };
+    embeddings.resize(chunks.len(), None);
+
+    let chunks: Vec<PreviewChunk> = chunks
+        .into_iter()
+        .zip(embeddings)
+        .enumerate()
+        .map(|(chunk_index, (chunk, embedding))| PreviewChunk {
+            chunk_index,
+            section: chunk.section,
+            content: chunk.content,
+            tokens: chunk.tokens,
+            span: chunk.span,
+            metadata: chunk.metadata,
+            embedding,
+        })
+        .collect();
+
+    Ok(Json(IngestPreviewResponse {
+        format: source.source_type.to_string(),
+        max_tokens: settings.max_tokens,
+        overlap_tokens: settings.overlap_tokens,
+        chunks_count: chunks.len(),
+        total_tokens: chunks.iter().map(|c| c.tokens).sum(),
+        warnings,
+        embedding_model: params.embed.then(|| state.embedder.model_name().to_string()),
+        chunks,
+    }))
+}
+
+fn parse_token_field(name: &str, value: &str) -> Result<usize, ApiError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ApiError::Validation(format!("`{}` must be a non-negative integer, got {:?}", name, value)))
+}
+
+/// The configured chunk bounds with the request's overrides; invalid combinations are a 400.
+fn chunk_settings(
+    state: &AppState,
+    max_tokens: Option<usize>,
+    overlap_tokens: Option<usize>,
+) -> Result<ChunkSettings, ApiError> {
+    state
+        .chunk_settings
+        .with_overrides(max_tokens, overlap_tokens, state.embedder.as_ref())
+        .map_err(ApiError::Validation)
+}
+
+/// An uploaded file and the sha256 of its content.
+struct Upload {
+    data: Bytes,
+    sha256: String,
+}
+
+/// Reads an uploaded file chunk by chunk, hashing as it goes and failing with 413
+/// as soon as it grows past `limit` instead of buffering the whole field first.
+async fn read_file_field(mut field: Field<'_>, limit: usize) -> Result<Upload, ApiError> {
+    let mut data = Vec::new();
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = field.chunk().await? {
+        if data.len() + chunk.len() > limit {
+            let name = field.file_name().unwrap_or("upload").to_string();
+            return Err(ApiError::PayloadTooLarge(format!(
+                "{} exceeds the maximum upload size of {} bytes",
+                name, limit
+            )));
+        }
+        hasher.update(&chunk);
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Upload {
+        data: Bytes::from(data),
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// Ingests every `file` field of a multipart body, sharing one `tags` field.
+/// Failures are recorded per file and never abort the rest of the batch. Batch
+/// files aren't versioned; content stored before returns the existing document.
+pub async fn handle_ingest_batch(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<BatchIngestResponse>, ApiError> {
+    let pool = state.pool()?;
+    let embedder = state.embedder.as_ref();
+    let mut files: Vec<(String, Upload)> = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    let mut max_tokens: Option<usize> = None;
+    let mut overlap_tokens: Option<usize> = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        let field_name = field.name().unwrap_or("").to_string();
+
+        match field_name.as_str() {
+            "file" => {
+                let filename = field.file_name()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| format!("file-{}", files.len() + 1));
+                let upload = read_file_field(field, state.max_upload_bytes).await?;
+                files.push((filename, upload));
+            }
+            "tags" => {
+                let text = field.text().await?;
+                tags = parse_tags(&text);
+            }
+            "max_tokens" => max_tokens = Some(parse_token_field("max_tokens", &field.text().await?)?),
+            "overlap_tokens" => overlap_tokens = Some(parse_token_field("overlap_tokens", &field.text().await?)?),
+            _ => {}
+        }
+    }
+
+    if files.is_empty() {
+        return Err(ApiError::Validation("no `file` fields in request".to_string()));
+    }
+    let settings = chunk_settings(&state, max_tokens, overlap_tokens)?;
+
+    let files_received = files.len();
+    let mut results: Vec<IngestResponse> = Vec::new();
+    let mut errors: Vec<BatchIngestError> = Vec::new();
+    let mut pending: Vec<PendingFile> = Vec::new();
+    // Repeats of content an earlier file in this batch brings, resolved once it's stored
+    let mut repeats: Vec<(String, String)> = Vec::new();
+
+    // Stage 1: dedup and chunk each new file; nothing is written yet
+    for (filename, Upload { data, sha256 }) in &files {
+        if data.is_empty() {
+            errors.push(BatchIngestError::new(filename, "file is empty"));
+            continue;
+        }
+        if pending.iter().any(|file| file.sha256 == *sha256) {
+            repeats.push((filename.clone(), sha256.clone()));
+            continue;
+        }
+
+        match find_document_by_sha(pool, sha256).await {
+            Ok(Some(doc)) => {
+                info!("Document {} already exists with ID: {}", filename, doc.id);
+                match build_response(pool, doc.id).await {
+                    Ok(mut response) => {
+                        response.warnings.push(duplicate_warning(doc.id));
+                        results.push(IngestResponse { deduplicated: true, ..response })
+                    }
+                    Err(e) => errors.push(BatchIngestError::new(filename, e)),
                 }
             }
+            Ok(None) => {
+                let source = DocumentSource::upload(filename, data);
+                let mut prepared = match prepare_document(&source, data, settings).await {
+                    Ok(prepared) => prepared,
+                    Err(e) => {
+                        errors.push(BatchIngestError::new(filename, e));
+                        continue;
+                    }
+                };
+                prepared.warnings.extend(input_limit_warning(embedder, &prepared.chunks));
+                pending.push(PendingFile {
+                    filename: filename.clone(),
+                    source,
+                    sha256: sha256.clone(),
+                    prepared,
+                });
+            }
+            Err(e) => errors.push(BatchIngestError::new(filename, e)),
         }
+    }
 
-        info!("Ingested document {} with {} chunks", doc.id, chunks.len());
-        doc.id
+    // Stage 2: embed the chunks of all new files together to minimize API calls
+    let texts: Vec<&str> = pending.iter()
+        .flat_map(|file| file.prepared.chunks.iter().map(|c| c.content.as_str()))
+        .collect();
+
+    let mut batched_embeddings = match embedding::get_embeddings_cached(pool, embedder, &texts).await {
+        Ok(embedded) if embedded.embeddings.len() == texts.len() => {
+            Some(embedded.embeddings.into_iter().zip(embedded.cached))
+        }
+        Ok(_) => {
+            warn!("Batched embedding returned an unexpected count, falling back to per-file calls");
+            None
+        }
+        Err(e) => {
+            warn!("Batched embedding failed ({}), falling back to per-file calls", e);
+            None
+        }
     };
 
-    // Get chunk count
-    let chunk_count = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM chunks WHERE document_id = $1"
-    )
-    .bind(document_id)
-    .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(IngestResponse {
-        document_id,
-        chunks_count: chunk_count as usize,
-        tokens_estimate: chunk_count as usize * 400, // Rough estimate
-        warnings: vec![],
-    }))
-}
\ No newline at end of file
+    // Stage 3: store each file's document and chunks in one transaction
+    for mut file in pending {
+        let chunks = &file.prepared.chunks;
+        let embedded = match batched_embeddings.as_mut() {
+            Some(iter) => {
+                let (embeddings, cached) = iter.by_ref().take(chunks.len()).unzip();
+                Ok(embedding::CachedEmbeddings { embeddings, cached })
+            }
+            None => {
+                let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+                embedding::get_embeddings_cached(pool, embedder, &texts).await
+            }
+        };
+
+        let mut embeddings_cached = 0;
+        let outcome = match embedded {
+            Ok(embedded) => {
+                embeddings_cached = embedded.hits();
+                match summarize::prepare_summary(pool, embedder, chunks, &mut file.prepared.warnings).await {
+                    Ok(summary) => {
+                        let model = embedder.model_name();
+                        store_batch_document(pool, &file, &tags, &embedded.embeddings, summary.as_ref(), model).await
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Err(e) => Err(e),
+        };
+
+        let document_id = match outcome {
+            Ok(document_id) => document_id,
+            Err(e) => {
+                error!("Failed to ingest {}: {}", file.filename, e);
+                errors.push(BatchIngestError::new(&file.filename, e));
+                continue;
+            }
+        };
+
+        info!("Ingested document {} with {} chunks", document_id, chunks.len());
+        match build_response(pool, document_id).await {
+            Ok(mut response) => {
+                response.warnings.extend(file.prepared.warnings);
+                results.push(IngestResponse { embeddings_cached, ..response })
+            }
+            Err(e) => errors.push(BatchIngestError::new(&file.filename, e)),
+        }
+    }
+
+    for (filename, sha256) in repeats {
+        match find_document_by_sha(pool, &sha256).await {
+            Ok(Some(doc)) => match build_response(pool, doc.id).await {
+                Ok(mut response) => {
+                    response.warnings.push(duplicate_warning(doc.id));
+                    results.push(IngestResponse { deduplicated: true, ..response })
+                }
+                Err(e) => errors.push(BatchIngestError::new(&filename, e)),
+            },
+            Ok(None) => errors.push(BatchIngestError::new(
+                &filename,
+                "same content as another file in this batch, which failed",
+            )),
+            Err(e) => errors.push(BatchIngestError::new(&filename, e)),
+        }
+    }
+
+    state.query_cache.invalidate();
+
+    let summary = BatchIngestSummary {
+        files_received,
+        files_ingested: results.len(),
+        files_failed: errors.len(),
+        total_chunks: results.iter().map(|r| r.chunks_count).sum(),
+    };
+
+    info!(
+        "Batch ingest finished: {}/{} files, {} chunks",
+        summary.files_ingested, summary.files_received, summary.total_chunks
+    );
+
+    Ok(Json(BatchIngestResponse { results, errors, summary }))
+}
+
+/// A batch file chunked in stage 1, waiting for its embeddings.
+struct PendingFile {
+    filename: String,
+    source: DocumentSource,
+    sha256: String,
+    prepared: PreparedDocument,
+}
+
+/// Inserts a batch file's document row, its chunks and its summary in one transaction.
+async fn store_batch_document(
+    pool: &PgPool,
+    file: &PendingFile,
+    tags: &[String],
+    embeddings: &[Vec<f32>],
+    summary: Option<&DocumentSummary>,
+    embedding_model: &str,
+) -> anyhow::Result<Uuid> {
+    let PreparedDocument { chunks, settings, .. } = &file.prepared;
+    let mut tx = pool.begin().await?;
+    let doc = insert_document(&mut tx, &file.source, &file.sha256, 1, tags, embedding_model, *settings).await?;
+    insert_chunks(&mut tx, doc.id, chunks, 0, embeddings, embedding_model, *settings).await?;
+    if let Some(summary) = summary {
+        summarize::store_summary(&mut tx, doc.id, summary, embedding_model).await?;
+    }
+    tx.commit().await?;
+    Ok(doc.id)
+}
+
+fn parse_tags(text: &str) -> Vec<String> {
+    text.split(',').map(|s| s.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::FromRequest;
+    use axum::http::{header, Request};
+
+    const BOUNDARY: &str = "test-boundary";
+
+    fn file_part(contents: &str) -> String {
+        format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.md\"\r\n\r\n{c}\r\n--{b}--\r\n",
+            b = BOUNDARY,
+            c = contents
+        )
+    }
+
+    async fn multipart(body: String) -> Multipart {
+        let request = Request::post("/api/ingest")
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", BOUNDARY))
+            .body(Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    async fn read_first_file(body: String, limit: usize) -> Result<Upload, ApiError> {
+        let mut multipart = multipart(body).await;
+        let field = multipart.next_field().await?.expect("a file field");
+        read_file_field(field, limit).await
+    }
+
+    #[tokio::test]
+    async fn reads_and_hashes_an_upload_within_the_limit() {
+        let upload = read_first_file(file_part("# Notes\nhello"), 1024).await.unwrap();
+        assert_eq!(&upload.data[..], b"# Notes\nhello");
+        assert_eq!(upload.sha256, format!("{:x}", Sha256::digest(b"# Notes\nhello")));
+    }
+
+    #[tokio::test]
+    async fn oversized_upload_is_payload_too_large() {
+        let error = read_first_file(file_part(&"x".repeat(2048)), 1024).await.err().unwrap();
+        assert_eq!(error.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(error.code(), "payload_too_large");
+        assert!(error.to_string().contains("notes.md"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn truncated_multipart_body_is_a_bad_request() {
+        let mut body = file_part("# Notes\nhello");
+        body.truncate(body.len() - 20);
+        let error = read_first_file(body, 1024).await.err().unwrap();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.code(), "invalid_request");
+    }
+}
@@ -1,3 +1,6 @@
+pub mod chunks;
+pub mod documents;
 pub mod ingest;
 pub mod query;
+pub mod reembed;
 pub mod feedback;
\ No newline at end of file
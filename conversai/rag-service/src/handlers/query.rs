@@ -1,52 +1,410 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::{channel::mpsc, future::join_all, Stream, StreamExt};
+use serde::Serialize;
+use serde_json::json;
 use sqlx::PgPool;
-use std::time::Instant;
-use tracing::info;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::time::{Duration, Instant};
+use tracing::{error, field, info, instrument, warn, Span};
+use uuid::Uuid;
 
+use crate::error::ApiError;
 use crate::models::{
-    ChunkWithScore, Citation, QueryDiagnostics, QueryRequest, QueryResponse,
+    Answer, AnswerCitation, CacheDiagnostics, CacheMode, CacheStatus, Chunk, ChunkWithScore, Citation,
+    Diversity, DocumentChunk, DocumentResult, FusionMethod, QueryDiagnostics, QueryRequest, QueryResponse,
+    RerankerKind, SearchMode, SimilarityMetric,
 };
-use crate::services::{embedding, retrieval};
+use crate::services::chunking::estimate_tokens;
+use crate::services::embedding_provider::EmbeddingProvider;
+use crate::services::{answer, fusion, rerank, retrieval};
+use crate::state::AppState;
+use crate::utils::highlight_terms;
 
-pub async fn handle_query(
-    State(pool): State<PgPool>,
-    Json(request): Json<QueryRequest>,
-) -> Result<Json<QueryResponse>, StatusCode> {
-    let start = Instant::now();
-    
-    // Get query embedding
+const DEFAULT_K: i32 = 10;
+const DEFAULT_MAX_K: i32 = 100;
+const MAX_RRF_K: u32 = 10_000;
+const MAX_EXPAND_NEIGHBORS: usize = 5;
+// Only the best fused candidates have their stored embeddings loaded for reranking
+const RERANK_CANDIDATES: usize = 50;
+const DEFAULT_MAX_QUERY_CHARS: usize = 2000;
+// Rephrasings searched alongside the original query when `expand_queries` is set
+const QUERY_VARIATIONS: usize = 3;
+const DEFAULT_TAG_BOOST_FACTOR: f32 = 1.5;
+
+/// Most results a query may ask for, from `QUERY_MAX_K`.
+fn max_k() -> i32 {
+    env::var("QUERY_MAX_K")
+        .ok()
+        .and_then(|v| v.trim().parse::<i32>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_K)
+}
+
+/// Rejects requests that would otherwise fail opaquely downstream. Runs before
+/// anything is sent to the embedding provider.
+fn validate_query(request: &QueryRequest) -> Result<(), ApiError> {
+    if request.query.trim().is_empty() {
+        return Err(ApiError::Validation("query must not be empty or only whitespace".to_string()));
+    }
+
+    let max_chars = env::var("QUERY_MAX_CHARS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_QUERY_CHARS);
+    let chars = request.query.chars().count();
+    if chars > max_chars {
+        return Err(ApiError::Validation(format!(
+            "query is {} characters long; the limit is {}",
+            chars, max_chars
+        )));
+    }
+
+    // Values over the limit are clamped in `retrieve` rather than rejected
+    if let Some(k) = request.k {
+        if k < 1 {
+            return Err(ApiError::Validation(format!("k must be positive, got {}", k)));
+        }
+    }
+
+    if request.alpha.is_some() && (request.semantic_weight.is_some() || request.lexical_weight.is_some()) {
+        return Err(ApiError::Validation(
+            "use either alpha or semantic_weight/lexical_weight, not both".to_string(),
+        ));
+    }
+
+    if request.semantic_weight.is_some() || request.lexical_weight.is_some() {
+        let semantic = request.semantic_weight.unwrap_or(0.0);
+        let lexical = request.lexical_weight.unwrap_or(0.0);
+        if !(semantic.is_finite() && lexical.is_finite()) || semantic < 0.0 || lexical < 0.0 || semantic + lexical <= 0.0 {
+            return Err(ApiError::Validation(
+                "semantic_weight and lexical_weight must be non-negative and not both zero".to_string(),
+            ));
+        }
+    }
+
+    if let Some(min_score) = request.min_score {
+        if !min_score.is_finite() {
+            return Err(ApiError::Validation("min_score must be a finite number".to_string()));
+        }
+    }
+
+    if let Some(rrf_k) = request.rrf_k {
+        if !(1..=MAX_RRF_K).contains(&rrf_k) {
+            return Err(ApiError::Validation(format!("rrf_k must be between 1 and {}, got {}", MAX_RRF_K, rrf_k)));
+        }
+    }
+
+    if request.expand_context.is_some() && request.context_window.is_some() {
+        return Err(ApiError::Validation("set either expand_context or context_window, not both".to_string()));
+    }
+    if let Some(expand) = request.context_expansion() {
+        if expand.before > MAX_EXPAND_NEIGHBORS || expand.after > MAX_EXPAND_NEIGHBORS {
+            return Err(ApiError::Validation(format!(
+                "expand_context before/after and context_window must be at most {}",
+                MAX_EXPAND_NEIGHBORS
+            )));
+        }
+        if expand.max_tokens == 0 {
+            return Err(ApiError::Validation("expand_context max_tokens must be positive".to_string()));
+        }
+    }
+
+    if request.max_context_tokens == Some(0) {
+        return Err(ApiError::Validation("max_context_tokens must be positive".to_string()));
+    }
+
+    if let Diversity::Mmr { lambda } = request.diversity {
+        if !(0.0..=1.0).contains(&lambda) {
+            return Err(ApiError::Validation(format!("mmr lambda must be between 0 and 1, got {}", lambda)));
+        }
+    }
+
+    if let Some((from, to)) = request.filters.as_ref().and_then(|f| f.date_range) {
+        if from > to {
+            return Err(ApiError::Validation(format!(
+                "date_range start ({}) is after its end ({})",
+                from.to_rfc3339(),
+                to.to_rfc3339()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Effective semantic/lexical blend: explicit weights, else `alpha`, else an even split.
+fn hybrid_weights(request: &QueryRequest) -> retrieval::HybridWeights {
+    if request.semantic_weight.is_some() || request.lexical_weight.is_some() {
+        return retrieval::HybridWeights::normalized(
+            request.semantic_weight.unwrap_or(0.0),
+            request.lexical_weight.unwrap_or(0.0),
+        );
+    }
+
+    let alpha = request.alpha
+        .filter(|a| a.is_finite())
+        .unwrap_or(0.5)
+        .clamp(0.0, 1.0);
+    retrieval::HybridWeights::from_alpha(alpha)
+}
+
+/// `boost_tags` with the factor from `TAG_BOOST_FACTOR` (default 1.5); `None` when no
+/// tags are given.
+fn tag_boost(request: &QueryRequest) -> Option<retrieval::TagBoost> {
+    let tags: Vec<String> = request
+        .boost_tags
+        .iter()
+        .flatten()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if tags.is_empty() {
+        return None;
+    }
+
+    let factor = env::var("TAG_BOOST_FACTOR")
+        .ok()
+        .and_then(|v| v.parse::<f32>().ok())
+        .filter(|f| f.is_finite() && *f > 0.0)
+        .unwrap_or(DEFAULT_TAG_BOOST_FACTOR);
+    Some(retrieval::TagBoost { tags, factor })
+}
+
+// Output of the embedding + hybrid search + rerank phase shared by both query handlers
+struct Retrieval {
+    mode: SearchMode,
+    reranked: Vec<retrieval::ChunkWithScore>,
+    reranker: Option<&'static str>,
+    diversity: Diversity,
+    warnings: Vec<String>,
+    context: Vec<ChunkWithScore>,
+    citations: Vec<Citation>,
+    k: i32,
+    fusion: retrieval::FusionOptions,
+    /// The embedding provider failed and only full-text search was used
+    degraded: bool,
+    query_expansions: usize,
+    dropped_chunks: usize,
+    embedding_model: String,
+    embedding_dimensions: usize,
+    embedding_time: Duration,
+    rerank_time: Duration,
+}
+
+/// Hybrid mode embeds, fuses both branches and reranks. The single-branch modes
+/// return that branch's top `k` in its own order, without reranking or diversity;
+/// semantic mode fails if the query can't be embedded instead of degrading.
+async fn retrieve(
+    pool: &PgPool,
+    embedder: &dyn EmbeddingProvider,
+    similarity: SimilarityMetric,
+    request: &QueryRequest,
+    mode: SearchMode,
+) -> Result<Retrieval, ApiError> {
+    let mut warnings = Vec::new();
+
+    // Get query embedding; without one, hybrid retrieval degrades to full-text search
     let embedding_start = Instant::now();
-    let query_embedding = embedding::get_embeddings(&[&request.query]).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .into_iter()
-        .next()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let query_embedding = match mode {
+        SearchMode::Lexical => None,
+        SearchMode::Semantic => Some(embedder.embed_query(&request.query).await.map_err(ApiError::Embedding)?),
+        SearchMode::Hybrid => match embedder.embed_query(&request.query).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                warn!("Embedding failed, falling back to lexical-only retrieval: {}", e);
+                warnings.push(format!("embedding failed, used lexical-only retrieval: {}", e));
+                None
+            }
+        },
+    };
     let embedding_time = embedding_start.elapsed();
+    let degraded = mode == SearchMode::Hybrid && query_embedding.is_none();
 
     // Perform hybrid search
-    let k = request.k.unwrap_or(10);
-    let chunks = retrieval::hybrid_search(
-        &pool,
-        &query_embedding,
-        &request.query,
-        k,
-        request.filters.as_ref().and_then(|f| f.tags.as_ref()),
-    )
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let max_k = max_k();
+    let k = request.k.unwrap_or(DEFAULT_K).min(max_k);
+    if let Some(requested) = request.k.filter(|&requested| requested > max_k) {
+        warnings.push(format!("k clamped to {}, the QUERY_MAX_K limit; {} was requested", max_k, requested));
+    }
+    let fusion = retrieval::FusionOptions {
+        method: request.fusion,
+        rrf_k: request.rrf_k.unwrap_or(retrieval::DEFAULT_RRF_K),
+        weights: hybrid_weights(request),
+        normalization: request.normalization,
+        min_score: request.min_score,
+        similarity,
+        boost: tag_boost(request),
+    };
+    let mut query_expansions = 0;
+    let mut chunks = match (&query_embedding, mode) {
+        (Some(query_embedding), SearchMode::Semantic) => {
+            retrieval::semantic_search(pool, query_embedding, k, request.filters.as_ref(), &fusion).await?
+        }
+        (Some(query_embedding), _) => {
+            let chunks = retrieval::hybrid_search(
+                pool,
+                query_embedding,
+                &request.query,
+                k,
+                request.filters.as_ref(),
+                &fusion,
+            )
+            .await?;
 
-    // Rerank results
+            if request.expand_queries {
+                // Expansion only adds recall; on any failure the original results stand
+                match expanded_search(pool, embedder, request, k, &fusion, &chunks).await {
+                    Ok((fused, expansions)) => {
+                        query_expansions = expansions;
+                        fused
+                    }
+                    Err(e) => {
+                        warn!("Query expansion failed, using the original query only: {}", e);
+                        warnings.push(format!("query expansion skipped: {}", e));
+                        chunks
+                    }
+                }
+            } else {
+                chunks
+            }
+        }
+        (None, _) => retrieval::lexical_search(pool, &request.query, k, request.filters.as_ref(), &fusion).await?,
+    };
+
+    // Rerank results, keeping the `k` the request asked for
     let rerank_start = Instant::now();
-    let reranked = retrieval::rerank_chunks(&chunks, &query_embedding, 8);
+    let top_k = k as usize;
+
+    // The cross-encoder is opt-in per request; provider errors fall back to cosine
+    let mut cross_encoded = None;
+    if mode == SearchMode::Hybrid && request.reranker == RerankerKind::CrossEncoder {
+        let candidates = &chunks[..RERANK_CANDIDATES.min(chunks.len())];
+        match rerank::cross_encoder_rerank(&request.query, candidates).await {
+            Ok(mut ranked) => {
+                // MMR compares candidates by embedding, which the cross-encoder doesn't provide
+                if let Diversity::Mmr { .. } = request.diversity {
+                    let count = ranked.len();
+                    if let Err(e) = retrieval::fetch_embeddings(pool, &mut ranked, count).await {
+                        warn!("Could not load chunk embeddings for MMR: {}", e);
+                        warnings.push(format!("mmr ran without embeddings: {}", e));
+                    }
+                }
+                cross_encoded = Some(retrieval::diversify(ranked, top_k, request.diversity));
+            }
+            Err(e) => {
+                let fallback = if degraded { "lexical order" } else { "cosine" };
+                warn!("Cross-encoder rerank failed, falling back to {}: {}", fallback, e);
+                warnings.push(format!("cross-encoder rerank failed, used {}: {}", fallback, e));
+            }
+        }
+    }
+
+    let (reranked, reranker) = match (cross_encoded, &query_embedding) {
+        // A single branch is returned as it ranked its results
+        _ if mode != SearchMode::Hybrid => (chunks, None),
+        (Some(reranked), _) => (reranked, Some("cross-encoder")),
+        (None, Some(query_embedding)) => {
+            let reranker = match retrieval::fetch_embeddings(pool, &mut chunks, RERANK_CANDIDATES).await {
+                Ok(attached) if attached > 0 => Some("cosine"),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("Could not load chunk embeddings, skipping rerank: {}", e);
+                    warnings.push(format!("rerank skipped: {}", e));
+                    None
+                }
+            };
+            (retrieval::rerank_chunks(&chunks, query_embedding, top_k, request.diversity, similarity), reranker)
+        }
+        // No query embedding to rerank against: keep the full-text order
+        (None, None) => {
+            if let Diversity::Mmr { .. } = request.diversity {
+                if let Err(e) = retrieval::fetch_embeddings(pool, &mut chunks, RERANK_CANDIDATES).await {
+                    warn!("Could not load chunk embeddings for MMR: {}", e);
+                    warnings.push(format!("mmr ran without embeddings: {}", e));
+                }
+            }
+            (retrieval::diversify(chunks, top_k, request.diversity), Some("lexical-only"))
+        }
+    };
     let rerank_time = rerank_start.elapsed();
 
+    // Neighbour expansion is best-effort; results are still useful without it
+    let mut reranked = reranked;
+    if let Some(expand) = request.context_expansion() {
+        if let Err(e) = retrieval::expand_neighbors(pool, &mut reranked, expand).await {
+            warn!("Could not expand chunks with neighbouring context: {}", e);
+            warnings.push(format!("context expansion skipped: {}", e));
+        }
+    }
+
+    // Vectors from another model aren't comparable with the query embedding
+    let embedding_model = embedder.model_name();
+    let mut other_models: Vec<&str> = reranked
+        .iter()
+        .filter_map(|c| c.embedding_model.as_deref())
+        .filter(|m| *m != embedding_model)
+        .collect();
+    other_models.sort_unstable();
+    other_models.dedup();
+    if !other_models.is_empty() {
+        warn!("Query with {} matched documents embedded with {:?}", embedding_model, other_models);
+        warnings.push(format!(
+            "some results come from documents embedded with {} but queries use {}; re-ingest them to get comparable scores",
+            other_models.join(", "),
+            embedding_model
+        ));
+    }
+
+    // Token budget: keep the best-ranked chunks that fit, stopping at the first that doesn't
+    let mut dropped_chunks = 0;
+    if let Some(budget) = request.max_context_tokens {
+        let mut used = 0;
+        let fitting = reranked
+            .iter()
+            .take_while(|c| {
+                used += c.chunk.content_tokens
+                    .map_or_else(|| estimate_tokens(&c.chunk.content), |t| t as usize);
+                used <= budget
+            })
+            .count();
+        dropped_chunks = reranked.len() - fitting;
+        reranked.truncate(fitting);
+    }
+
+    // Rerank only loaded the top candidates' embeddings; fill in the rest on request
+    if request.include_embeddings {
+        let missing = reranked.iter().any(|c| c.chunk.embedding.is_none());
+        if missing {
+            if let Err(e) = retrieval::fetch_embeddings(pool, &mut reranked, usize::MAX).await {
+                warn!("Could not load chunk embeddings for the response: {}", e);
+                warnings.push(format!("embeddings not included: {}", e));
+            }
+        }
+    }
+
     // Convert to response format
     let context: Vec<ChunkWithScore> = reranked
         .iter()
         .map(|c| ChunkWithScore {
-            chunk: c.chunk.clone(),
+            // Embeddings are only needed for reranking unless the client asked for them
+            chunk: Chunk {
+                embedding: c.chunk.embedding.clone().filter(|_| request.include_embeddings),
+                ..c.chunk.clone()
+            },
             score: c.score,
             source_uri: c.source_uri.clone().unwrap_or_default(),
+            document_tags: c.document_tags.clone(),
+            semantic_score: c.semantic_score,
+            lexical_score: c.lexical_score,
+            semantic_rank: c.semantic_rank,
+            lexical_rank: c.lexical_rank,
+            expansion: c.expansion.clone(),
         })
         .collect();
 
@@ -73,24 +431,543 @@ pub async fn handle_query(
         })
         .collect();
 
+    Ok(Retrieval {
+        mode,
+        reranked,
+        reranker,
+        diversity: request.diversity,
+        warnings,
+        context,
+        citations,
+        k,
+        fusion,
+        degraded,
+        query_expansions,
+        dropped_chunks,
+        embedding_model: embedding_model.to_string(),
+        embedding_dimensions: embedder.dimensions(),
+        embedding_time,
+        rerank_time,
+    })
+}
+
+/// Runs hybrid search for LLM-generated rephrasings of the query and fuses them
+/// with the original results by RRF. A chunk found by several variations keeps
+/// its best-scoring copy. Returns the fused candidates and the variation count.
+async fn expanded_search(
+    pool: &PgPool,
+    embedder: &dyn EmbeddingProvider,
+    request: &QueryRequest,
+    k: i32,
+    fusion: &retrieval::FusionOptions,
+    original: &[retrieval::ChunkWithScore],
+) -> anyhow::Result<(Vec<retrieval::ChunkWithScore>, usize)> {
+    let variations = answer::generate_query_variations(&request.query, QUERY_VARIATIONS).await?;
+    if variations.is_empty() {
+        return Ok((original.to_vec(), 0));
+    }
+
+    let texts: Vec<&str> = variations.iter().map(String::as_str).collect();
+    let embeddings = embedder.embed(&texts).await?;
+    let searches = join_all(variations.iter().zip(&embeddings).map(|(text, embedding)| {
+        retrieval::hybrid_search(pool, embedding, text, k, request.filters.as_ref(), fusion)
+    }))
+    .await;
+
+    let mut lists = vec![original.to_vec()];
+    for search in searches {
+        lists.push(search?);
+    }
+
+    let mut best: HashMap<Uuid, retrieval::ChunkWithScore> = HashMap::new();
+    for chunk in lists.iter().flatten() {
+        match best.get(&chunk.chunk.id) {
+            Some(kept) if retrieval::score_order(kept.score, chunk.score).is_ge() => {}
+            _ => {
+                best.insert(chunk.chunk.id, chunk.clone());
+            }
+        }
+    }
+
+    let ranked: Vec<Vec<(Uuid, f32)>> = lists
+        .iter()
+        .map(|list| list.iter().map(|c| (c.chunk.id, c.score)).collect())
+        .collect();
+    let fused = fusion::rrf(&ranked, retrieval::DEFAULT_RRF_K as f32)
+        .into_iter()
+        .filter_map(|(id, _)| best.remove(&id))
+        .collect();
+
+    info!("Fused results of {} query variations", variations.len());
+    Ok((fused, variations.len()))
+}
+
+fn build_answer(generated: answer::GeneratedAnswer, warnings: &mut Vec<String>) -> Answer {
+    if generated.trimmed_chunks > 0 {
+        warnings.push(format!(
+            "{} lowest-scoring chunks were left out of the answer prompt to fit the model context",
+            generated.trimmed_chunks
+        ));
+    }
+
+    Answer {
+        text: generated.text,
+        citations: generated
+            .cited
+            .into_iter()
+            .map(|i| AnswerCitation {
+                marker: format!("[{}]", i + 1),
+                citation_index: i,
+            })
+            .collect(),
+    }
+}
+
+fn diagnostics(
+    retrieved: &Retrieval,
+    query_time: Duration,
+    answer_time_ms: Option<u64>,
+    warnings: Vec<String>,
+    cache: CacheDiagnostics,
+) -> QueryDiagnostics {
+    // Each branch fetches 2k candidates for fusion; a single branch fetches k
+    let k = retrieved.k as usize;
+    let (ann_k, lexical_k) = match retrieved.mode {
+        SearchMode::Hybrid => (k * 2, k * 2),
+        SearchMode::Lexical => (0, k),
+        SearchMode::Semantic => (k, 0),
+    };
+    QueryDiagnostics {
+        search_mode: retrieved.mode,
+        ann_k,
+        lexical_k,
+        reranker: retrieved.reranker.map(String::from),
+        diversity: retrieved.diversity,
+        alpha: retrieved.fusion.weights.semantic,
+        semantic_weight: retrieved.fusion.weights.semantic,
+        lexical_weight: retrieved.fusion.weights.lexical,
+        min_score: retrieved.fusion.min_score,
+        normalization: retrieved.fusion.normalization,
+        fusion: retrieved.fusion.method,
+        rrf_k: (retrieved.fusion.method == FusionMethod::Rrf).then_some(retrieved.fusion.rrf_k),
+        similarity_metric: retrieved.fusion.similarity,
+        degraded: retrieved.degraded,
+        query_expansions: retrieved.query_expansions,
+        embedding_model: retrieved.embedding_model.clone(),
+        embedding_dimensions: retrieved.embedding_dimensions,
+        included_chunks: retrieved.reranked.len(),
+        dropped_chunks: retrieved.dropped_chunks,
+        tag_boost: retrieved.fusion.boost.as_ref().map(|boost| boost.factor),
+        boosted_chunks: retrieved
+            .reranked
+            .iter()
+            .filter(|c| c.boost.is_some())
+            .map(|c| c.chunk.id)
+            .collect(),
+        cache,
+        query_time_ms: query_time.as_millis() as u64,
+        embedding_time_ms: retrieved.embedding_time.as_millis() as u64,
+        rerank_time_ms: retrieved.rerank_time.as_millis() as u64,
+        answer_time_ms,
+        warnings,
+    }
+}
+
+/// Copies the response diagnostics onto the current span, so traces carry the same
+/// timings and counts clients see in `diagnostics`.
+fn record_diagnostics(diagnostics: &QueryDiagnostics) {
+    let span = Span::current();
+    span.record("cache", field::debug(diagnostics.cache.status));
+    span.record("reranker", diagnostics.reranker.as_deref());
+    span.record("fusion", field::debug(diagnostics.fusion));
+    span.record("metric", diagnostics.similarity_metric.as_str());
+    span.record("degraded", diagnostics.degraded);
+    span.record("query_expansions", diagnostics.query_expansions);
+    span.record("results", diagnostics.included_chunks);
+    span.record("dropped_chunks", diagnostics.dropped_chunks);
+    span.record("boosted_chunks", diagnostics.boosted_chunks.len());
+    span.record("warnings", diagnostics.warnings.len());
+    span.record("embedding_time_ms", diagnostics.embedding_time_ms);
+    span.record("rerank_time_ms", diagnostics.rerank_time_ms);
+    span.record("answer_time_ms", diagnostics.answer_time_ms);
+    span.record("query_time_ms", diagnostics.query_time_ms);
+}
+
+pub async fn handle_query(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryResponse>, ApiError> {
+    run_query(&state, request, SearchMode::Hybrid).await.map(Json)
+}
+
+/// Full-text branch only, for comparing it with `handle_semantic_query` when
+/// debugging retrieval.
+pub async fn handle_lexical_query(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryResponse>, ApiError> {
+    run_query(&state, request, SearchMode::Lexical).await.map(Json)
+}
+
+/// ANN branch only; fails with `embedding_failed` when the query can't be embedded.
+pub async fn handle_semantic_query(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryResponse>, ApiError> {
+    run_query(&state, request, SearchMode::Semantic).await.map(Json)
+}
+
+/// Only hybrid responses are cached; the single-branch modes are for debugging and
+/// would otherwise share cache entries with `/api/query`.
+#[instrument(
+    name = "query",
+    skip_all,
+    fields(
+        mode = ?mode,
+        k = request.k.unwrap_or(DEFAULT_K),
+        answer = request.answer,
+        cache = field::Empty,
+        reranker = field::Empty,
+        fusion = field::Empty,
+        metric = field::Empty,
+        degraded = field::Empty,
+        query_expansions = field::Empty,
+        results = field::Empty,
+        dropped_chunks = field::Empty,
+        boosted_chunks = field::Empty,
+        warnings = field::Empty,
+        embedding_time_ms = field::Empty,
+        rerank_time_ms = field::Empty,
+        answer_time_ms = field::Empty,
+        query_time_ms = field::Empty,
+    )
+)]
+async fn run_query(state: &AppState, request: QueryRequest, mode: SearchMode) -> Result<QueryResponse, ApiError> {
+    let start = Instant::now();
+    validate_query(&request)?;
+    let pool = state.pool()?;
+
+    // Identical requests within the TTL are served from memory until documents change
+    let cache = &state.query_cache;
+    let cache_key = match request.cache {
+        CacheMode::Use if mode == SearchMode::Hybrid => cache.key(&request),
+        _ => None,
+    };
+    if let Some(key) = &cache_key {
+        if let Some(cached) = cache.get(key).await {
+            match serde_json::from_value::<QueryResponse>(cached) {
+                Ok(mut response) => {
+                    let diagnostics = &mut response.diagnostics;
+                    diagnostics.query_time_ms = start.elapsed().as_millis() as u64;
+                    diagnostics.embedding_time_ms = 0;
+                    diagnostics.rerank_time_ms = 0;
+                    diagnostics.answer_time_ms = None;
+                    diagnostics.cache = cache.diagnostics(CacheStatus::Hit);
+                    record_diagnostics(diagnostics);
+                    info!("Query served from cache in {}ms", diagnostics.query_time_ms);
+                    return Ok(response);
+                }
+                Err(e) => warn!("Discarding unreadable cached query response: {}", e),
+            }
+        }
+    }
+
+    let retrieved = retrieve(pool, state.embedder.as_ref(), state.similarity, &request, mode).await?;
+
+    // Answer mode: LLM failures degrade to a retrieve-only response with a warning
+    let mut warnings = retrieved.warnings.clone();
+    let mut answer_time_ms = None;
+    let mut answer_failed = false;
+    let answer = if request.answer {
+        let answer_start = Instant::now();
+        let generated = answer::generate_answer(&request.query, &retrieved.reranked).await;
+        answer_time_ms = Some(answer_start.elapsed().as_millis() as u64);
+
+        match generated {
+            Ok(generated) => Some(build_answer(generated, &mut warnings)),
+            Err(e) => {
+                warn!("Answer generation failed, returning retrieved context only: {}", e);
+                warnings.push(format!("answer generation failed: {}", e));
+                answer_failed = true;
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     let query_time = start.elapsed();
 
     info!(
         "Query processed in {:?} with {} results",
         query_time,
-        context.len()
+        retrieved.context.len()
     );
 
-    Ok(Json(QueryResponse {
+    // Degraded results and failed answers aren't cached, so the next request retries
+    // the embedding provider and the chat model
+    let cache_status = match &cache_key {
+        _ if !cache.enabled() => CacheStatus::Disabled,
+        Some(_) if !retrieved.degraded && !answer_failed => CacheStatus::Miss,
+        _ => CacheStatus::Bypass,
+    };
+    let diagnostics = diagnostics(&retrieved, query_time, answer_time_ms, warnings, cache.diagnostics(cache_status));
+    record_diagnostics(&diagnostics);
+    let mut context = retrieved.context;
+    if request.highlight {
+        for entry in &mut context {
+            entry.chunk.content = highlight_terms(&entry.chunk.content, &request.query);
+        }
+    }
+
+    let (context, documents) = shape_results(context, request.group_by_document);
+    let response = QueryResponse {
         context,
-        citations,
-        diagnostics: QueryDiagnostics {
-            ann_k: k as usize * 2,
-            lexical_k: k as usize * 2,
-            reranker: Some("cosine".to_string()),
-            query_time_ms: query_time.as_millis() as u64,
-            embedding_time_ms: embedding_time.as_millis() as u64,
-            rerank_time_ms: rerank_time.as_millis() as u64,
-        },
-    }))
-}
\ No newline at end of file
+        documents,
+        citations: retrieved.citations,
+        answer,
+        diagnostics,
+    };
+
+    if let (Some(key), CacheStatus::Miss) = (cache_key, cache_status) {
+        match serde_json::to_value(&response) {
+            Ok(value) => cache.insert(key, value).await,
+            Err(e) => warn!("Could not cache query response: {}", e),
+        }
+    }
+
+    Ok(response)
+}
+
+/// Answer mode over server-sent events. Emits one `citations` event with the retrieved
+/// context, `delta` events as the answer streams in, then `done` with the inline citation
+/// mapping and diagnostics. Retrieval failures are reported as an `error` event;
+/// validation errors and a missing database are returned before the stream starts.
+pub async fn handle_query_stream(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    // Checked up front so bad requests and degraded mode get a plain error response
+    validate_query(&request)?;
+    let pool = state.pool()?.clone();
+    let query_cache = state.query_cache.clone();
+    let embedder = state.embedder.clone();
+    let similarity = state.similarity;
+    let (tx, rx) = mpsc::unbounded();
+
+    tokio::spawn(async move {
+        let start = Instant::now();
+
+        let mut retrieved = match retrieve(&pool, embedder.as_ref(), similarity, &request, SearchMode::Hybrid).await {
+            Ok(retrieved) => retrieved,
+            Err(e) => {
+                error!("Streaming query failed during retrieval: {}", e);
+                let _ = tx.unbounded_send(sse_event("error", json!({
+                    "phase": "retrieval",
+                    "code": e.code(),
+                    "message": e.to_string(),
+                })));
+                return;
+            }
+        };
+
+        let citations = match shape_results(std::mem::take(&mut retrieved.context), request.group_by_document) {
+            (_, Some(documents)) => json!({ "documents": documents, "citations": retrieved.citations }),
+            (context, None) => json!({ "context": context, "citations": retrieved.citations }),
+        };
+        let _ = tx.unbounded_send(sse_event("citations", citations));
+
+        // As in `handle_query`, an LLM failure still ends with `done`, carrying a warning
+        let mut warnings = retrieved.warnings.clone();
+        let answer_start = Instant::now();
+        let generated = answer::stream_answer(&request.query, &retrieved.reranked, |delta| {
+            let _ = tx.unbounded_send(sse_event("delta", json!({ "text": delta })));
+        })
+        .await;
+        let answer_time_ms = Some(answer_start.elapsed().as_millis() as u64);
+
+        let answer_citations = match generated {
+            Ok(generated) => build_answer(generated, &mut warnings).citations,
+            Err(e) => {
+                warn!("Streaming answer generation failed: {}", e);
+                warnings.push(format!("answer generation failed: {}", e));
+                Vec::new()
+            }
+        };
+
+        let query_time = start.elapsed();
+        info!("Streaming query processed in {:?}", query_time);
+
+        let _ = tx.unbounded_send(sse_event("done", json!({
+            "answer_citations": answer_citations,
+            "diagnostics": diagnostics(
+                &retrieved,
+                query_time,
+                answer_time_ms,
+                warnings,
+                query_cache.diagnostics(CacheStatus::Bypass),
+            ),
+        })));
+    });
+
+    Ok(Sse::new(rx.map(Ok)).keep_alive(KeepAlive::default()))
+}
+
+/// Flat `context` by default, or the same entries nested per document.
+fn shape_results(
+    context: Vec<ChunkWithScore>,
+    group_by_document: bool,
+) -> (Option<Vec<ChunkWithScore>>, Option<Vec<DocumentResult>>) {
+    if group_by_document {
+        (None, Some(group_results(context)))
+    } else {
+        (Some(context), None)
+    }
+}
+
+/// Documents are ordered by their best chunk score, then by how many chunks matched;
+/// chunks within a document by score. Each chunk keeps the index of its citation.
+fn group_results(context: Vec<ChunkWithScore>) -> Vec<DocumentResult> {
+    let mut documents: Vec<DocumentResult> = Vec::new();
+
+    for (citation_index, result) in context.into_iter().enumerate() {
+        let document_id = result.chunk.document_id;
+        let position = match documents.iter().position(|d| d.document_id == document_id) {
+            Some(position) => position,
+            None => {
+                documents.push(DocumentResult {
+                    document_id,
+                    source_uri: result.source_uri.clone(),
+                    tags: result.document_tags.clone(),
+                    best_score: result.score,
+                    chunks: Vec::new(),
+                });
+                documents.len() - 1
+            }
+        };
+
+        let document = &mut documents[position];
+        if retrieval::score_order(result.score, document.best_score).is_gt() {
+            document.best_score = result.score;
+        }
+        document.chunks.push(DocumentChunk { result, citation_index });
+    }
+
+    for document in &mut documents {
+        document.chunks.sort_by(|a, b| {
+            retrieval::score_order(b.result.score, a.result.score)
+                .then_with(|| a.result.chunk.id.cmp(&b.result.chunk.id))
+        });
+    }
+    documents.sort_by(|a, b| {
+        retrieval::score_order(b.best_score, a.best_score)
+            .then_with(|| b.chunks.len().cmp(&a.chunks.len()))
+            .then_with(|| a.document_id.cmp(&b.document_id))
+    });
+
+    documents
+}
+
+fn sse_event(name: &str, data: impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(overrides: serde_json::Value) -> QueryRequest {
+        let mut body = json!({ "query": "how do embeddings work" });
+        body.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn rejection(request: &QueryRequest) -> String {
+        match validate_query(request) {
+            Err(ApiError::Validation(message)) => message,
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepts_a_plain_query() {
+        assert!(validate_query(&request(json!({}))).is_ok());
+    }
+
+    #[test]
+    fn rejects_an_empty_query() {
+        assert!(rejection(&request(json!({ "query": "  \n\t" }))).contains("must not be empty"));
+    }
+
+    #[test]
+    fn rejects_a_query_over_the_character_limit() {
+        let query = "é".repeat(DEFAULT_MAX_QUERY_CHARS + 1);
+        let message = rejection(&request(json!({ "query": query })));
+        assert!(message.contains("the limit is 2000"), "{}", message);
+        // The limit counts characters, not bytes
+        let query = "é".repeat(DEFAULT_MAX_QUERY_CHARS);
+        assert!(validate_query(&request(json!({ "query": query }))).is_ok());
+    }
+
+    #[test]
+    fn rejects_k_below_one() {
+        assert!(rejection(&request(json!({ "k": 0 }))).contains("k must be positive"));
+        assert!(rejection(&request(json!({ "k": -3 }))).contains("got -3"));
+    }
+
+    #[test]
+    fn rejects_alpha_together_with_weights() {
+        let message = rejection(&request(json!({ "alpha": 0.5, "semantic_weight": 0.7 })));
+        assert!(message.contains("either alpha or"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_a_non_finite_min_score() {
+        for min_score in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let mut request = request(json!({}));
+            request.min_score = Some(min_score);
+            assert!(rejection(&request).contains("min_score must be a finite number"));
+        }
+    }
+
+    #[test]
+    fn rejects_rrf_k_out_of_range() {
+        assert!(rejection(&request(json!({ "rrf_k": 0 }))).contains("rrf_k must be between 1 and 10000"));
+        assert!(rejection(&request(json!({ "rrf_k": 10_001 }))).contains("got 10001"));
+        assert!(validate_query(&request(json!({ "rrf_k": 10_000 }))).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_context_window_above_the_limit() {
+        let message = rejection(&request(json!({ "context_window": MAX_EXPAND_NEIGHBORS + 1 })));
+        assert!(message.contains("at most 5"), "{}", message);
+        assert!(validate_query(&request(json!({ "context_window": MAX_EXPAND_NEIGHBORS }))).is_ok());
+    }
+
+    #[test]
+    fn rejects_zero_max_context_tokens() {
+        let message = rejection(&request(json!({ "max_context_tokens": 0 })));
+        assert!(message.contains("max_context_tokens must be positive"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_an_mmr_lambda_out_of_range() {
+        for lambda in [-0.1, 1.5] {
+            let message = rejection(&request(json!({ "diversity": { "strategy": "mmr", "lambda": lambda } })));
+            assert!(message.contains("mmr lambda must be between 0 and 1"), "{}", message);
+        }
+        let request = request(json!({ "diversity": { "strategy": "mmr", "lambda": 1.0 } }));
+        assert!(validate_query(&request).is_ok());
+    }
+
+    #[test]
+    fn rejects_an_inverted_date_range() {
+        let message = rejection(&request(json!({
+            "filters": { "date_range": ["2024-06-01T00:00:00Z", "2024-01-01T00:00:00Z"] }
+        })));
+        assert!(message.contains("is after its end"), "{}", message);
+    }
+}
@@ -0,0 +1,80 @@
+use axum::{extract::State, Json};
+use tracing::{field, info, instrument, Span};
+
+use crate::error::ApiError;
+use crate::models::{ReembedRequest, ReembedResponse};
+use crate::services::reembed::{self, ReembedScope};
+use crate::state::AppState;
+
+const DEFAULT_REEMBED_DOCUMENTS: i64 = 20;
+const MAX_REEMBED_DOCUMENTS: i64 = 100;
+
+/// Re-embeds stored chunks with the current embedding model, one document per
+/// transaction, in document id order. Each call handles up to `limit` documents
+/// and returns a cursor for the next call.
+#[instrument(name = "reembed", skip_all, fields(documents_updated = field::Empty, chunks_updated = field::Empty))]
+pub async fn handle_reembed(
+    State(state): State<AppState>,
+    Json(request): Json<ReembedRequest>,
+) -> Result<Json<ReembedResponse>, ApiError> {
+    let pool = state.pool()?;
+
+    let limit = request.limit.unwrap_or(DEFAULT_REEMBED_DOCUMENTS);
+    if !(1..=MAX_REEMBED_DOCUMENTS).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "limit must be between 1 and {}, got {}",
+            MAX_REEMBED_DOCUMENTS, limit
+        )));
+    }
+    let scope = ReembedScope {
+        document_ids: request.document_ids.as_deref(),
+        tag: request.tag.as_deref().map(str::trim).filter(|t| !t.is_empty()),
+        force: request.force,
+    };
+    let model = state.embedder.model_name();
+
+    // One extra id tells whether another call is needed
+    let mut documents = reembed::next_documents(pool, &scope, request.cursor, limit + 1, model).await?;
+    let more = documents.len() as i64 > limit;
+    documents.truncate(limit as usize);
+
+    let mut chunks_updated = 0;
+    let mut embeddings_cached = 0;
+    let mut documents_updated = 0;
+    for &document_id in &documents {
+        match reembed::reembed_document(pool, state.embedder.as_ref(), document_id).await {
+            Ok(done) => {
+                chunks_updated += done.chunks;
+                embeddings_cached += done.cached;
+                documents_updated += 1;
+            }
+            Err(e) => {
+                // Finished documents stay re-embedded; drop cached responses ranked on the old vectors
+                if documents_updated > 0 {
+                    state.query_cache.invalidate();
+                }
+                info!(
+                    "Re-embedding stopped at document {} after {} documents: {}",
+                    document_id, documents_updated, e
+                );
+                return Err(e.into());
+            }
+        }
+    }
+    if documents_updated > 0 {
+        state.query_cache.invalidate();
+    }
+
+    let span = Span::current();
+    span.record("documents_updated", documents_updated);
+    span.record("chunks_updated", chunks_updated);
+    info!("Re-embedded {} chunks in {} documents with {}", chunks_updated, documents_updated, model);
+
+    Ok(Json(ReembedResponse {
+        embedding_model: model.to_string(),
+        documents_updated,
+        chunks_updated,
+        embeddings_cached,
+        next_cursor: if more { documents.last().copied() } else { None },
+    }))
+}
@@ -0,0 +1,21 @@
+//! The RAG service as a library, so benchmarks and integration tests can reach
+//! its modules; `main.rs` only wires them into the server.
+
+// Everything behind the database and the embedding provider is part of the `full`
+// feature; without it only `/` and `/health` are served
+#[cfg(feature = "full")]
+pub mod db;
+#[cfg(feature = "full")]
+pub mod error;
+#[cfg(feature = "full")]
+pub mod handlers;
+pub mod middleware;
+#[cfg(feature = "full")]
+pub mod models;
+#[cfg(feature = "full")]
+pub mod services;
+#[cfg(feature = "full")]
+pub mod state;
+pub mod telemetry;
+#[cfg(feature = "full")]
+pub mod utils;
@@ -1,90 +1,42 @@
 use axum::{
-    http::{Method, header, HeaderValue, StatusCode},
-    response::{Json, IntoResponse},
-    routing::{get, post},
+    http::{Method, header, HeaderValue},
+    middleware::from_fn_with_state,
+    response::Json,
+    routing::get,
     Router,
 };
+#[cfg(feature = "full")]
+use axum::{
+    extract::{DefaultBodyLimit, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{patch, post},
+};
 use dotenv::dotenv;
 use serde_json::json;
-use sqlx::postgres::PgPoolOptions;
 use std::env;
 use std::net::SocketAddr;
 use tower_http::cors::CorsLayer;
-use tracing::{info, Level};
-use tracing_subscriber;
+use tracing::info;
 
-mod handlers;
-mod models;
-mod services;
-mod utils;
+#[cfg(feature = "full")]
+use conversai_rag::{db, handlers, models, services};
+use conversai_rag::{middleware, telemetry};
 
+#[cfg(feature = "full")]
 use handlers::{ingest, query};
+use middleware::auth::{self, ApiKeys};
+use middleware::rate_limit::{self, RateLimitConfig, RateLimiter};
+#[cfg(feature = "full")]
+use conversai_rag::state::AppState;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load environment variables
     dotenv().ok();
     
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
-
-    // Database connection - make it optional for health checks
-    let database_url = env::var("CONVERSAI_SUPABASE_DB_URL")
-        .or_else(|_| env::var("DATABASE_URL"))
-        .unwrap_or_else(|_| {
-            info!("WARNING: No database URL found in environment variables");
-            info!("Looking for CONVERSAI_SUPABASE_DB_URL or DATABASE_URL");
-            info!("Service will start with limited functionality (health check only)");
-            "".to_string()
-        });
-    
-    // Try to connect to database if URL is provided
-    let pool = if !database_url.is_empty() {
-        info!("Attempting to connect to database...");
-        
-        match PgPoolOptions::new()
-            .max_connections(5)
-            .acquire_timeout(std::time::Duration::from_secs(10))
-            .connect(&database_url)
-            .await {
-                Ok(pool) => {
-                    info!("Successfully connected to database");
-                    Some(pool)
-                },
-                Err(e) => {
-                    eprintln!("WARNING: Failed to connect to database: {}", e);
-                    eprintln!("Service starting in health-check-only mode");
-                    eprintln!("Add CONVERSAI_SUPABASE_DB_URL or DATABASE_URL to enable full functionality");
-                    None
-                }
-            }
-    } else {
-        info!("No database URL provided - starting in health-check-only mode");
-        None
-    };
-    
-    // For now, if no database is available, we'll still fail but with better error message
-    let pool = match pool {
-        Some(p) => p,
-        None => {
-            // Allow service to start for health checks only
-            info!("Starting service in health-check-only mode");
-            info!("Database endpoints will not be functional");
-            // Create a minimal connection pool that will fail if actually used
-            PgPoolOptions::new()
-                .max_connections(1)
-                .connect("postgresql://localhost/dummy")
-                .await
-                .unwrap_or_else(|_| {
-                    // Return a pool that exists but isn't connected
-                    // This allows the service to start and respond to health checks
-                    sqlx::postgres::PgPool::connect_lazy("postgresql://localhost/dummy")
-                        .expect("Failed to create lazy pool")
-                })
-        }
-    };
+    // Initialize tracing, exporting spans when OTEL_EXPORTER_OTLP_ENDPOINT is set
+    let telemetry = telemetry::init()?;
 
     // Build our application with routes
     // Proper CORS configuration for Vercel frontend
@@ -96,7 +48,7 @@ async fn main() -> anyhow::Result<()> {
             "http://localhost:3000".parse::<HeaderValue>().unwrap(),
             "http://localhost:3001".parse::<HeaderValue>().unwrap(),
         ])
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE, Method::OPTIONS])
         .allow_headers([
             header::CONTENT_TYPE,
             header::AUTHORIZATION,
@@ -105,20 +57,18 @@ async fn main() -> anyhow::Result<()> {
         .expose_headers([header::CONTENT_TYPE])
         .max_age(std::time::Duration::from_secs(3600));
 
-    let app = Router::new()
-        .route("/", get(root_handler))
-        .route("/health", get(health_check))
-        // Handle OPTIONS preflight requests explicitly
-        .route("/api/ingest", post(ingest::handle_ingest).options(handle_options))
-        .route("/api/query", post(query::handle_query).options(handle_options))
-        .route("/api/feedback", post(handlers::feedback::handle_feedback).options(handle_options))
-        // Legacy routes for backward compatibility
-        .route("/ingest", post(ingest::handle_ingest).options(handle_options))
-        .route("/query", post(query::handle_query).options(handle_options))
-        .route("/feedback", post(handlers::feedback::handle_feedback).options(handle_options))
-        // Apply CORS layer BEFORE state (important for OPTIONS to work)
-        .layer(cors)
-        .with_state(pool);
+    // Per-client token bucket rate limiting, off unless RATE_LIMIT_RPS is set
+    let limiter = RateLimiter::new(RateLimitConfig::from_env());
+
+    // Bearer token auth, disabled when CONVERSAI_API_KEYS is unset
+    let api_keys = ApiKeys::from_env();
+
+    let app = build_router()
+        .await?
+        .layer(from_fn_with_state(api_keys, auth::require_api_key))
+        .layer(from_fn_with_state(limiter, rate_limit::rate_limit))
+        // CORS outermost so preflight requests are answered before auth and rate limiting
+        .layer(cors);
 
     // Run server - use PORT env var from Railway or default to 3030
     let port_str = env::var("PORT").unwrap_or_else(|_| {
@@ -144,42 +94,208 @@ async fn main() -> anyhow::Result<()> {
     println!("✅ Server successfully bound to {}:{}", "0.0.0.0", port);
     println!("🔗 Health check available at: http://0.0.0.0:{}/health", port);
     
-    axum::serve(listener, app).await?;
+    // Peer addresses are needed by the rate limiter
+    let served = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await;
+    telemetry.shutdown();
+    served?;
 
     Ok(())
 }
 
+/// All routes; the database is optional and only needed by the API endpoints.
+#[cfg(feature = "full")]
+async fn build_router() -> anyhow::Result<Router> {
+    // A misconfigured provider is a startup error rather than a failure on every request
+    let embedder = services::embedding_provider::from_env()?;
+    let url_fetcher = services::url_fetch::UrlFetcher::from_env()?;
+    let chunk_settings = services::ingest::ChunkSettings::from_env(embedder.as_ref())?;
+
+    // Database connection - make it optional for health checks
+    let database_url = env::var("CONVERSAI_SUPABASE_DB_URL")
+        .or_else(|_| env::var("DATABASE_URL"))
+        .unwrap_or_else(|_| {
+            info!("WARNING: No database URL found in environment variables");
+            info!("Looking for CONVERSAI_SUPABASE_DB_URL or DATABASE_URL");
+            info!("Service will start with limited functionality (health check only)");
+            "".to_string()
+        });
+    
+    // Try to connect to database if URL is provided
+    let pool = if !database_url.is_empty() {
+        info!("Attempting to connect to database...");
+        
+        match db::PoolConfig::from_env().connect(&database_url).await {
+            Ok(pool) => {
+                info!("Successfully connected to database");
+                // Refuse to start rather than fail every insert later
+                services::embedding::validate_dimensions(&pool, embedder.as_ref()).await?;
+                // Background jobs don't outlive the process that ran them
+                match services::jobs::fail_interrupted(&pool).await {
+                    Ok(0) => {}
+                    Ok(n) => info!("Marked {} interrupted ingest job(s) as failed", n),
+                    Err(e) => eprintln!("WARNING: Failed to check for interrupted ingest jobs: {}", e),
+                }
+                Some(pool)
+            },
+            Err(e) => {
+                eprintln!("WARNING: Failed to connect to database: {}", e);
+                eprintln!("Service starting in health-check-only mode");
+                eprintln!("Add CONVERSAI_SUPABASE_DB_URL or DATABASE_URL to enable full functionality");
+                None
+            }
+        }
+    } else {
+        info!("No database URL provided - starting in health-check-only mode");
+        None
+    };
+    
+    // Without a pool the service still starts for health checks; database
+    // endpoints answer 503 instead of failing against a missing connection
+    if pool.is_none() {
+        info!("Starting service in health-check-only mode");
+        info!("Database endpoints will return 503");
+    }
+    let state = AppState::new(pool, embedder, url_fetcher, chunk_settings);
+    // Ingest bodies past INGEST_MAX_UPLOAD_BYTES fail with 413; other routes keep axum's default
+    let upload_limit = DefaultBodyLimit::max(state.max_upload_bytes);
+
+    let router = Router::new()
+        .route("/", get(root_handler))
+        .route("/health", get(health_check))
+        // Handle OPTIONS preflight requests explicitly
+        .route("/api/ingest", post(ingest::handle_ingest).layer(upload_limit).options(handle_options))
+        .route("/api/ingest/batch", post(ingest::handle_ingest_batch).layer(upload_limit).options(handle_options))
+        .route("/api/ingest/url", post(ingest::handle_ingest_url).options(handle_options))
+        .route("/api/ingest/text", post(ingest::handle_ingest_text).layer(upload_limit).options(handle_options))
+        .route("/api/ingest/preview", post(ingest::handle_ingest_preview).layer(upload_limit).options(handle_options))
+        .route("/api/ingest/jobs/:id", get(ingest::handle_ingest_job).options(handle_options))
+        .route("/api/query", post(query::handle_query).options(handle_options))
+        .route("/api/query/stream", post(query::handle_query_stream).options(handle_options))
+        .route("/api/query/lexical", post(query::handle_lexical_query).options(handle_options))
+        .route("/api/query/semantic", post(query::handle_semantic_query).options(handle_options))
+        .route("/api/feedback", post(handlers::feedback::handle_feedback).options(handle_options))
+        .route(
+            "/api/documents",
+            get(handlers::documents::handle_list_documents)
+                .delete(handlers::documents::handle_delete_documents)
+                .options(handle_options),
+        )
+        .route(
+            "/api/documents/:id",
+            get(handlers::documents::handle_get_document)
+                .delete(handlers::documents::handle_delete_document)
+                .options(handle_options),
+        )
+        .route("/api/documents/:id/tags", patch(handlers::documents::handle_update_tags).options(handle_options))
+        .route("/api/documents/:id/summary", get(handlers::documents::handle_get_summary).options(handle_options))
+        .route(
+            "/api/chunks/:id",
+            get(handlers::chunks::handle_get_chunk)
+                .patch(handlers::chunks::handle_update_chunk)
+                .delete(handlers::chunks::handle_delete_chunk)
+                .options(handle_options),
+        )
+        .route("/api/reembed", post(handlers::reembed::handle_reembed).options(handle_options))
+        // Legacy routes for backward compatibility
+        .route("/ingest", post(ingest::handle_ingest).layer(upload_limit).options(handle_options))
+        .route("/query", post(query::handle_query).options(handle_options))
+        .route("/feedback", post(handlers::feedback::handle_feedback).options(handle_options))
+        .with_state(state);
+    Ok(router)
+}
+
+/// Health-check-only build (`--no-default-features --features minimal`).
+#[cfg(not(feature = "full"))]
+async fn build_router() -> anyhow::Result<Router> {
+    info!("Built without the `full` feature: serving / and /health only");
+    let router = Router::new()
+        .route("/", get(root_handler))
+        .route("/health", get(health_check));
+    Ok(router)
+}
+
 async fn root_handler() -> Json<serde_json::Value> {
+    #[cfg(feature = "full")]
+    let endpoints = json!({
+        "health": "/health",
+        "ingest": "/api/ingest",
+        "ingest_batch": "/api/ingest/batch",
+        "ingest_url": "/api/ingest/url",
+        "ingest_text": "/api/ingest/text",
+        "ingest_preview": "/api/ingest/preview",
+        "ingest_job": "/api/ingest/jobs/{id}",
+        "query": "/api/query",
+        "query_stream": "/api/query/stream",
+        "query_lexical": "/api/query/lexical",
+        "query_semantic": "/api/query/semantic",
+        "feedback": "/api/feedback",
+        "documents": "/api/documents",
+        "document": "/api/documents/{id}",
+        "document_tags": "/api/documents/{id}/tags",
+        "document_summary": "/api/documents/{id}/summary",
+        "chunk": "/api/chunks/{id}",
+        "reembed": "/api/reembed"
+    });
+    #[cfg(not(feature = "full"))]
+    let endpoints = json!({ "health": "/health" });
+
     Json(json!({
         "name": "ConversAI RAG Service",
         "status": "online",
-        "endpoints": {
-            "health": "/health",
-            "ingest": "/api/ingest",
-            "query": "/api/query",
-            "feedback": "/api/feedback"
-        },
+        "endpoints": endpoints,
         "documentation": "https://github.com/yourusername/conversai",
         "timestamp": chrono::Utc::now().to_rfc3339()
     }))
 }
 
-async fn health_check() -> Json<serde_json::Value> {
-    let db_connected = env::var("CONVERSAI_SUPABASE_DB_URL")
-        .or_else(|_| env::var("DATABASE_URL"))
-        .is_ok();
+#[cfg(feature = "full")]
+async fn health_check(
+    State(state): State<AppState>,
+    Query(params): Query<models::HealthParams>,
+) -> Json<serde_json::Value> {
+    let db_connected = state.pool.is_some();
     
-    Json(json!({
+    let mut health = json!({
         "status": "healthy",
         "service": "conversai-rag",
         "version": "1.0.1",
         "database_configured": db_connected,
         "mode": if db_connected { "full" } else { "health-check-only" },
+        "embedding_provider": state.embedder.provider_name(),
+        "embedding_model": state.embedder.model_name(),
+        "embedding_dimensions": state.embedder.dimensions(),
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    });
+
+    // Deep check: the database answers and its vector column fits the embeddings
+    if let (true, Some(pool)) = (params.deep, &state.pool) {
+        let reachable = sqlx::query("SELECT 1").execute(pool).await.is_ok();
+        let column = services::embedding::column_dimensions(pool).await.ok().flatten();
+        health["database_reachable"] = json!(reachable);
+        health["column_dimensions"] = json!(column);
+        health["dimensions_match"] = json!(column.map(|width| width == state.embedder.dimensions()));
+        if !reachable {
+            health["status"] = json!("degraded");
+        }
+    }
+
+    Json(health)
+}
+
+#[cfg(not(feature = "full"))]
+async fn health_check() -> Json<serde_json::Value> {
+    Json(json!({
+        "status": "healthy",
+        "service": "conversai-rag",
+        "version": "1.0.1",
+        "database_configured": false,
+        "mode": "minimal",
         "timestamp": chrono::Utc::now().to_rfc3339()
     }))
 }
 
 // Quick OPTIONS handler for CORS preflight requests
+#[cfg(feature = "full")]
 async fn handle_options() -> impl IntoResponse {
     StatusCode::NO_CONTENT
 }
\ No newline at end of file
@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Identity attached to a request once its API key has been accepted.
+/// Handlers can read it with `Extension<ApiKeyInfo>`.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeyInfo {
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+    keys: Arc<HashMap<String, ApiKeyInfo>>,
+}
+
+impl ApiKeys {
+    pub fn from_env() -> Self {
+        let keys: HashMap<String, ApiKeyInfo> = env::var("CONVERSAI_API_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|k| k.trim())
+            .filter(|k| !k.is_empty())
+            .map(|k| (k.to_string(), ApiKeyInfo::default()))
+            .collect();
+
+        if keys.is_empty() {
+            info!("No CONVERSAI_API_KEYS configured - API authentication disabled");
+        } else {
+            info!("API authentication enabled with {} key(s)", keys.len());
+        }
+
+        Self { keys: Arc::new(keys) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn lookup(&self, token: &str) -> Option<&ApiKeyInfo> {
+        self.keys.get(token)
+    }
+}
+
+pub async fn require_api_key(
+    State(keys): State<ApiKeys>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // Health checks and CORS preflights stay unauthenticated
+    let path = request.uri().path();
+    let exempt = path == "/health" || path.starts_with("/health/") || request.method() == Method::OPTIONS;
+
+    if !keys.enabled() || exempt {
+        return next.run(request).await;
+    }
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim());
+
+    match token.and_then(|t| keys.lookup(t)) {
+        Some(info) => {
+            debug!("Authenticated request (tenant: {:?})", info.tenant_id);
+            request.extensions_mut().insert(info.clone());
+            next.run(request).await
+        }
+        None => {
+            warn!("Rejected request to {} with missing or invalid API key", request.uri().path());
+            unauthorized(token.is_some())
+        }
+    }
+}
+
+/// A 401 in the `{"error": {...}}` format `ApiError` renders, which isn't available
+/// to the minimal build.
+fn unauthorized(had_token: bool) -> Response {
+    let request_id = Uuid::new_v4().to_string();
+    let message = if had_token {
+        "invalid API key"
+    } else {
+        "missing API key; send `Authorization: Bearer <key>`"
+    };
+    let body = json!({
+        "error": {
+            "code": "unauthorized",
+            "message": message,
+            "details": null,
+            "request_id": request_id,
+        }
+    });
+
+    let mut response = (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], Json(body)).into_response();
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        let keys = ApiKeys {
+            keys: Arc::new(HashMap::from([("key-one".to_string(), ApiKeyInfo::default())])),
+        };
+        Router::new()
+            .route("/api/documents", get(|| async { "documents" }).options(|| async { "preflight" }))
+            .route("/health", get(|| async { "ok" }))
+            .route("/health/db", get(|| async { "ok" }))
+            .route("/healthcheck", get(|| async { "not exempt" }))
+            .layer(from_fn_with_state(keys, require_api_key))
+    }
+
+    async fn send(method: Method, uri: &str, token: Option<&str>) -> (StatusCode, Response) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        (response.status(), response)
+    }
+
+    async fn error_body(response: Response) -> Value {
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn missing_key_is_a_structured_401() {
+        let (status, response) = send(Method::GET, "/api/documents", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        let request_id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+
+        let body = error_body(response).await;
+        assert_eq!(body["error"]["code"], "unauthorized");
+        assert!(body["error"]["message"].as_str().unwrap().contains("missing API key"));
+        assert_eq!(body["error"]["request_id"], request_id);
+    }
+
+    #[tokio::test]
+    async fn wrong_key_is_rejected() {
+        let (status, response) = send(Method::GET, "/api/documents", Some("key-two")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let body = error_body(response).await;
+        assert_eq!(body["error"]["code"], "unauthorized");
+        assert_eq!(body["error"]["message"], "invalid API key");
+    }
+
+    #[tokio::test]
+    async fn valid_key_reaches_the_handler() {
+        let (status, _) = send(Method::GET, "/api/documents", Some("key-one")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn health_and_preflights_need_no_key() {
+        assert_eq!(send(Method::GET, "/health", None).await.0, StatusCode::OK);
+        assert_eq!(send(Method::GET, "/health/db", None).await.0, StatusCode::OK);
+        assert_eq!(send(Method::OPTIONS, "/api/documents", None).await.0, StatusCode::OK);
+        // Only /health itself and the paths under it are exempt
+        assert_eq!(send(Method::GET, "/healthcheck", None).await.0, StatusCode::UNAUTHORIZED);
+    }
+}
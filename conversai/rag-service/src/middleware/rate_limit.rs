@@ -0,0 +1,229 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{info, warn};
+
+// Prune idle buckets once the map grows past this many clients
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// `RATE_LIMIT_RPS`; 0 (the default) disables rate limiting
+    pub requests_per_second: f64,
+    pub burst: f64,
+    /// Proxies in front of the service that append to `X-Forwarded-For` (`TRUST_PROXY`);
+    /// 0 keys buckets on the peer address
+    pub trusted_proxies: usize,
+}
+
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        let requests_per_second = env::var("RATE_LIMIT_RPS")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0);
+        let burst = env::var("RATE_LIMIT_BURST")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(20.0);
+        // `true` is one proxy, as on Railway and Fly; a number counts chained proxies
+        let trust_proxy = env::var("TRUST_PROXY").ok();
+        let trusted_proxies = match trust_proxy.as_deref().map(str::trim) {
+            Some(v) if v.eq_ignore_ascii_case("true") => 1,
+            Some(v) => v.parse::<usize>().unwrap_or(0),
+            None => 0,
+        };
+
+        if requests_per_second > 0.0 && trust_proxy.is_none() {
+            warn!(
+                "RATE_LIMIT_RPS is set but TRUST_PROXY is not; behind a proxy every client \
+                 shares the proxy's bucket. Set TRUST_PROXY=true (or false when clients connect directly)"
+            );
+        }
+
+        Self {
+            requests_per_second,
+            burst: burst.max(1.0),
+            trusted_proxies,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.requests_per_second > 0.0
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<HashMap<IpAddr, TokenBucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        if config.enabled() {
+            info!(
+                "Rate limiting enabled: {} req/s, burst {}, trusted proxies: {}",
+                config.requests_per_second, config.burst, config.trusted_proxies
+            );
+        } else {
+            info!("Rate limiting disabled");
+        }
+
+        Self {
+            config,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes one token for `ip`, or returns the number of seconds until one is available.
+    fn check(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() > MAX_TRACKED_CLIENTS {
+            let config = self.config;
+            buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens + elapsed * config.requests_per_second < config.burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(TokenBucket {
+            tokens: self.config.burst,
+            last_refill: now,
+        });
+
+        // Refill based on time elapsed since the last request
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.config.requests_per_second)
+            .min(self.config.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.config.requests_per_second;
+            Err(wait.ceil().max(1.0) as u64)
+        }
+    }
+
+    fn client_ip(&self, request: &Request, peer: SocketAddr) -> IpAddr {
+        let hops = self.config.trusted_proxies;
+        if hops > 0 {
+            // Each trusted proxy appends the address it was connected from, so the
+            // entry `hops` from the right is the client; anything left of it is
+            // whatever the client sent and can't be trusted
+            let entries: Vec<&str> = request
+                .headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .collect();
+            let forwarded = entries
+                .get(entries.len().saturating_sub(hops))
+                .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+
+        peer.ip()
+    }
+}
+
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Health checks and CORS preflights never consume tokens
+    let exempt = request.uri().path().starts_with("/health")
+        || request.method() == Method::OPTIONS;
+
+    if !limiter.config.enabled() || exempt {
+        return next.run(request).await;
+    }
+
+    let ip = limiter.client_ip(&request, peer);
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            warn!("Rate limit exceeded for {}", ip);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn limiter(trusted_proxies: usize) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { requests_per_second: 1.0, burst: 1.0, trusted_proxies })
+    }
+
+    fn request(forwarded_for: &[&str]) -> Request {
+        let mut builder = Request::builder().uri("/api/query");
+        for value in forwarded_for {
+            builder = builder.header("x-forwarded-for", *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn peer() -> SocketAddr {
+        "10.0.0.1:4000".parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_header_is_ignored() {
+        let ip = limiter(0).client_ip(&request(&["203.0.113.7"]), peer());
+        assert_eq!(ip, peer().ip());
+    }
+
+    #[test]
+    fn uses_the_entry_the_proxy_appended() {
+        // The client sent a spoofed first entry; the proxy appended the real address
+        let ip = limiter(1).client_ip(&request(&["198.51.100.1, 203.0.113.7"]), peer());
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn counts_trusted_hops_from_the_right() {
+        let request = request(&["198.51.100.1, 203.0.113.7", "10.1.0.2"]);
+        let ip = limiter(2).client_ip(&request, peer());
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn rotating_the_header_does_not_reset_the_bucket() {
+        let limiter = limiter(1);
+        let first = limiter.client_ip(&request(&["198.51.100.1, 203.0.113.7"]), peer());
+        assert!(limiter.check(first).is_ok());
+        let second = limiter.client_ip(&request(&["198.51.100.2, 203.0.113.7"]), peer());
+        assert!(limiter.check(second).is_err());
+    }
+}
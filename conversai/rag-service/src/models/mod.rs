@@ -10,6 +10,10 @@ pub struct Document {
     pub source_uri: String,
     pub content_sha256: String,
     pub document_version: i32,
+    /// Caller-chosen identity shared by all versions of a document
+    pub document_key: Option<String>,
+    /// Set once a newer version with the same key has been ingested
+    pub superseded_at: Option<DateTime<Utc>>,
     pub tags: Option<Vec<String>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -45,6 +49,30 @@ pub struct Fact {
 pub struct IngestRequest {
     pub url: Option<String>,
     pub tags: Option<Vec<String>>,
+    /// Versions the page under this key; defaults to the URL it was served from
+    pub document_key: Option<String>,
+    /// Override `CHUNK_MAX_TOKENS` for this page
+    pub max_tokens: Option<usize>,
+    /// Override `CHUNK_OVERLAP_TOKENS` for this page
+    pub overlap_tokens: Option<usize>,
+}
+
+/// Body of `POST /api/ingest/text`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IngestTextRequest {
+    /// Markdown to ingest
+    pub content: String,
+    /// Defaults to `untitled.md`
+    pub filename: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// Stored as the document's `source_uri`; defaults to `storage://{filename}`
+    pub source_uri: Option<String>,
+    /// Versions the document under this key; defaults to `source_uri` when one is given
+    pub document_key: Option<String>,
+    /// Override `CHUNK_MAX_TOKENS` for this document
+    pub max_tokens: Option<usize>,
+    /// Override `CHUNK_OVERLAP_TOKENS` for this document
+    pub overlap_tokens: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,13 +81,308 @@ pub struct IngestResponse {
     pub chunks_count: usize,
     pub tokens_estimate: usize,
     pub warnings: Vec<String>,
+    /// Chunks whose embedding came from `embedding_cache` instead of the provider
+    #[serde(default)]
+    pub embeddings_cached: usize,
+    /// `document_version` of the returned document
+    #[serde(default)]
+    pub version: i32,
+    /// The content was already stored, so the existing document was returned
+    #[serde(default)]
+    pub deduplicated: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IngestParams {
+    /// Return a job id immediately and ingest on a background task
+    #[serde(default, rename = "async")]
+    pub run_async: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PreviewParams {
+    /// Also embed the chunks with the configured provider
+    #[serde(default)]
+    pub embed: bool,
+}
+
+/// A chunk as `POST /api/ingest/preview` would store it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreviewChunk {
+    pub chunk_index: usize,
+    pub section: String,
+    pub content: String,
+    pub tokens: usize,
+    pub span: serde_json::Value,
+    pub metadata: serde_json::Value,
+    /// Only with `?embed=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IngestPreviewResponse {
+    /// Format the file was read as, as stored in `source_type`
+    pub format: String,
+    pub max_tokens: usize,
+    pub overlap_tokens: usize,
+    pub chunks_count: usize,
+    pub total_tokens: usize,
+    pub warnings: Vec<String>,
+    /// Set when the chunks were embedded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    pub chunks: Vec<PreviewChunk>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HealthParams {
+    /// Also ping the database and compare the embedding size with `chunks.embedding`
+    #[serde(default)]
+    pub deep: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IngestJobAccepted {
+    pub job_id: Uuid,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct IngestJob {
+    #[serde(rename = "job_id")]
+    pub id: Uuid,
+    pub status: String,
+    pub filename: String,
+    pub document_id: Option<Uuid>,
+    pub chunks_done: i32,
+    pub chunks_total: Option<i32>,
+    /// The `IngestResponse` of a completed job
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchIngestResponse {
+    pub results: Vec<IngestResponse>,
+    pub errors: Vec<BatchIngestError>,
+    pub summary: BatchIngestSummary,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchIngestError {
+    pub filename: String,
+    pub error: String,
+}
+
+impl BatchIngestError {
+    pub fn new(filename: &str, error: impl std::fmt::Display) -> Self {
+        Self {
+            filename: filename.to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchIngestSummary {
+    pub files_received: usize,
+    pub files_ingested: usize,
+    pub files_failed: usize,
+    pub total_chunks: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryRequest {
     pub query: String,
     pub filters: Option<QueryFilters>,
+    /// Prefer chunks from documents with any of these tags by multiplying their fused
+    /// score by `TAG_BOOST_FACTOR`; unlike `filters.tags`, other chunks stay eligible
+    pub boost_tags: Option<Vec<String>>,
     pub k: Option<i32>,
+    /// Reranking backend; `cross-encoder` needs `RERANK_PROVIDER` to be configured
+    #[serde(default)]
+    pub reranker: RerankerKind,
+    /// Weight of the semantic score against the lexical one, 0..1 (default 0.5)
+    pub alpha: Option<f32>,
+    /// Alternative to `alpha`: relative weights, normalized to sum to 1
+    pub semantic_weight: Option<f32>,
+    pub lexical_weight: Option<f32>,
+    /// Drop candidates whose fused score is below this before reranking
+    pub min_score: Option<f32>,
+    /// How semantic and lexical scores are rescaled before blending; defaults to min-max
+    #[serde(default)]
+    pub normalization: ScoreNormalization,
+    /// How the ANN and lexical candidates are combined; defaults to the weighted sum
+    #[serde(default)]
+    pub fusion: FusionMethod,
+    /// Rank offset for RRF fusion (default 60)
+    pub rrf_k: Option<u32>,
+    /// How the final results are diversified; defaults to at most 2 chunks per document
+    #[serde(default)]
+    pub diversity: Diversity,
+    /// Wrap query-term matches in returned chunk content with `**...**`
+    #[serde(default)]
+    pub highlight: bool,
+    /// Append neighbouring chunks of the same document to each result
+    pub expand_context: Option<ExpandContext>,
+    /// Shorthand for `expand_context` with this many neighbours on each side and the
+    /// default token budget
+    pub context_window: Option<usize>,
+    /// Keep results in rank order only while their combined `content_tokens` fit this budget
+    pub max_context_tokens: Option<usize>,
+    /// Also search with a few LLM-generated rephrasings of the query and fuse the results with RRF
+    #[serde(default)]
+    pub expand_queries: bool,
+    /// `"bypass"` skips the response cache for this request
+    #[serde(default)]
+    pub cache: CacheMode,
+    /// Return `documents` (results nested per document) instead of the flat `context`
+    #[serde(default)]
+    pub group_by_document: bool,
+    /// Also generate an LLM answer from the retrieved context
+    #[serde(default)]
+    pub answer: bool,
+    /// Return each chunk's stored embedding for client-side reranking or clustering.
+    /// Adds a full vector (1536 floats with the default model) per result, so
+    /// responses grow many times over.
+    #[serde(default)]
+    pub include_embeddings: bool,
+}
+
+impl QueryRequest {
+    /// Neighbour expansion asked for by `expand_context` or `context_window`
+    pub fn context_expansion(&self) -> Option<ExpandContext> {
+        self.expand_context.or_else(|| {
+            self.context_window.map(|window| ExpandContext {
+                before: window,
+                after: window,
+                max_tokens: default_expand_max_tokens(),
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RerankerKind {
+    #[default]
+    Cosine,
+    CrossEncoder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FusionMethod {
+    /// `semantic_weight * semantic + lexical_weight * lexical` over normalized scores
+    #[default]
+    WeightedSum,
+    /// Reciprocal Rank Fusion: sum of `1 / (rrf_k + rank)` over both lists
+    Rrf,
+}
+
+/// Which retrieval branches a query ran: both fused (`/api/query`), or only the
+/// full-text or only the ANN branch (`/api/query/lexical`, `/api/query/semantic`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchMode {
+    #[default]
+    Hybrid,
+    Lexical,
+    Semantic,
+}
+
+/// Vector similarity used by the semantic search and cosine reranking, set with
+/// `SIMILARITY_METRIC`. Every variant scores higher for more similar vectors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SimilarityMetric {
+    /// `1 - cosine distance`; pgvector operator `<=>`
+    #[default]
+    Cosine,
+    /// Inner product; pgvector operator `<#>` (which returns its negation)
+    Dot,
+    /// `1 / (1 + L2 distance)`; pgvector operator `<->`
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Name passed to the SQL search functions' `metric` argument.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SimilarityMetric::Cosine => "cosine",
+            SimilarityMetric::Dot => "dot",
+            SimilarityMetric::Euclidean => "euclidean",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+    #[default]
+    Use,
+    Bypass,
+}
+
+/// Rescaling applied to each score distribution over the candidate set before
+/// the weighted blend, so cosine and `ts_rank_cd` scores are comparable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScoreNormalization {
+    /// `(x - min) / (max - min)`, into 0..1
+    #[default]
+    MinMax,
+    /// `(x - mean) / stddev`
+    ZScore,
+    /// Blend the raw scores
+    None,
+}
+
+/// `{"strategy": "per-document"}` or `{"strategy": "mmr", "lambda": 0.7}`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "kebab-case")]
+pub enum Diversity {
+    #[default]
+    PerDocument,
+    Mmr {
+        #[serde(default = "default_mmr_lambda")]
+        lambda: f32,
+    },
+}
+
+fn default_mmr_lambda() -> f32 {
+    0.7
+}
+
+/// `{"before": 1, "after": 1}`; neighbours are added nearest first until `max_tokens`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ExpandContext {
+    #[serde(default)]
+    pub before: usize,
+    #[serde(default)]
+    pub after: usize,
+    #[serde(default = "default_expand_max_tokens")]
+    pub max_tokens: usize,
+}
+
+fn default_expand_max_tokens() -> usize {
+    1500
+}
+
+/// Neighbouring chunks merged into a result by `expand_context`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextExpansion {
+    pub chunk_ids: Vec<Uuid>,
+    pub before: usize,
+    pub after: usize,
+    pub tokens: usize,
+    /// Some requested neighbours were left out to stay within `max_tokens`
+    pub truncated: bool,
+    /// Neighbours left out because a higher-ranked result already contains them
+    pub shared: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -67,20 +390,101 @@ pub struct QueryFilters {
     pub tags: Option<Vec<String>>,
     pub document_ids: Option<Vec<Uuid>>,
     pub date_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Also search documents replaced by a newer version
+    #[serde(default)]
+    pub include_superseded: bool,
+    /// Only chunks from code block sections, only document summaries, or only the rest
+    pub content_kind: Option<ContentKind>,
+}
+
+/// Chunk kinds `QueryFilters.content_kind` selects, read from the chunk metadata's
+/// `kind`; code block sections only exist with `CHUNK_SPLIT_CODE_BLOCKS`, and
+/// summary chunks with `DOCUMENT_SUMMARIES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentKind {
+    Code,
+    Prose,
+    Summary,
+}
+
+impl ContentKind {
+    /// Value passed to the SQL search functions' `filter_content_kind` argument.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentKind::Code => "code",
+            ContentKind::Prose => "prose",
+            ContentKind::Summary => "summary",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryResponse {
-    pub context: Vec<ChunkWithScore>,
+    /// Flat results; omitted when `group_by_document` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<Vec<ChunkWithScore>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents: Option<Vec<DocumentResult>>,
     pub citations: Vec<Citation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<Answer>,
     pub diagnostics: QueryDiagnostics,
 }
 
+/// One document's results, ordered by best chunk score
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentResult {
+    pub document_id: Uuid,
+    pub source_uri: String,
+    pub tags: Vec<String>,
+    pub best_score: f32,
+    pub chunks: Vec<DocumentChunk>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentChunk {
+    #[serde(flatten)]
+    pub result: ChunkWithScore,
+    /// Index of this chunk's entry in `QueryResponse.citations`
+    pub citation_index: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Answer {
+    pub text: String,
+    pub citations: Vec<AnswerCitation>,
+}
+
+/// Maps an inline marker such as `[1]` to an entry of `QueryResponse.citations`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnswerCitation {
+    pub marker: String,
+    pub citation_index: usize,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChunkWithScore {
     pub chunk: Chunk,
     pub score: f32,
     pub source_uri: String,
+    pub document_tags: Vec<String>,
+    pub semantic_score: ComponentScore,
+    pub lexical_score: ComponentScore,
+    /// Positions in the ANN and lexical lists, reported with RRF fusion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub semantic_rank: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lexical_rank: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expansion: Option<ContextExpansion>,
+}
+
+/// One side of the hybrid score, as returned by SQL and after normalization
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComponentScore {
+    pub raw: f32,
+    pub normalized: f32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,12 +498,219 @@ pub struct Citation {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryDiagnostics {
+    #[serde(default)]
+    pub search_mode: SearchMode,
     pub ann_k: usize,
     pub lexical_k: usize,
     pub reranker: Option<String>,
+    pub diversity: Diversity,
+    pub alpha: f32,
+    pub semantic_weight: f32,
+    pub lexical_weight: f32,
+    pub min_score: Option<f32>,
+    pub normalization: ScoreNormalization,
+    pub fusion: FusionMethod,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rrf_k: Option<u32>,
+    pub similarity_metric: SimilarityMetric,
+    /// Set when the embedding provider failed and results come from full-text search only
+    #[serde(default)]
+    pub degraded: bool,
+    /// Query variations searched in addition to the original (`expand_queries`)
+    #[serde(default)]
+    pub query_expansions: usize,
+    /// Embedding model and vector size the query was embedded with
+    pub embedding_model: String,
+    pub embedding_dimensions: usize,
+    pub cache: CacheDiagnostics,
+    /// Results returned, and results cut to fit `max_context_tokens`
+    pub included_chunks: usize,
+    pub dropped_chunks: usize,
+    /// Factor applied for `boost_tags`, and the returned chunks it was applied to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_boost: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boosted_chunks: Vec<Uuid>,
     pub query_time_ms: u64,
     pub embedding_time_ms: u64,
     pub rerank_time_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_time_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Whether this response came from the query cache, with process-wide counters
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheDiagnostics {
+    pub status: CacheStatus,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    /// Skipped by the request, or not cacheable (streaming, degraded results)
+    Bypass,
+    Disabled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateTagsRequest {
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentTagsResponse {
+    pub document_id: Uuid,
+    pub tags: Vec<String>,
+}
+
+/// A document's generated summary, from its `metadata.summary`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SummaryResponse {
+    pub document_id: Uuid,
+    pub summary: String,
+    /// Chat model that wrote it
+    pub model: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListDocumentsParams {
+    /// Page size, 1–200 (default 50)
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: i64,
+    pub tag: Option<String>,
+    pub source_type: Option<String>,
+}
+
+/// A stored document with its chunk count and token total.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct DocumentSummary {
+    pub id: Uuid,
+    pub source_type: String,
+    pub source_uri: String,
+    pub document_key: Option<String>,
+    pub version: i32,
+    pub tags: Vec<String>,
+    pub chunk_count: i64,
+    pub total_tokens: i64,
+    pub created_at: DateTime<Utc>,
+    pub superseded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentListResponse {
+    pub documents: Vec<DocumentSummary>,
+    /// Documents matching the filters, across all pages
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Offset of the next page, absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkPreview {
+    pub id: Uuid,
+    pub chunk_index: Option<i32>,
+    pub section: Option<String>,
+    pub content_tokens: Option<i32>,
+    /// The start of the chunk's content, cut at a word boundary
+    pub preview: String,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentDetailResponse {
+    #[serde(flatten)]
+    pub document: DocumentSummary,
+    pub chunks: Vec<ChunkPreview>,
+}
+
+/// A stored chunk without its embedding.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ChunkDetail {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub chunk_index: Option<i32>,
+    pub content: String,
+    pub content_tokens: Option<i32>,
+    pub section: Option<String>,
+    pub span: Option<serde_json::Value>,
+    pub metadata: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateChunkRequest {
+    /// Replaces the content; the chunk is re-embedded and its tokens recounted
+    pub content: Option<String>,
+    /// Merged into the stored metadata, replacing keys it shares with it
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteChunkResponse {
+    pub chunk_id: Uuid,
+    pub document_id: Uuid,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReembedRequest {
+    /// Only these documents; combined with `tag` when both are set
+    pub document_ids: Option<Vec<Uuid>>,
+    pub tag: Option<String>,
+    /// `next_cursor` of the previous call: continue after this document id
+    pub cursor: Option<Uuid>,
+    /// Documents to re-embed in this call, 1–100 (default 20)
+    pub limit: Option<i64>,
+    /// Also re-embed documents already embedded with the current model
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReembedResponse {
+    pub embedding_model: String,
+    pub documents_updated: usize,
+    pub chunks_updated: usize,
+    pub embeddings_cached: usize,
+    /// Pass as `cursor` to continue; absent once every matching document is done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteDocumentResponse {
+    pub document_id: Uuid,
+    pub chunks_deleted: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteDocumentsParams {
+    /// Required; every document carrying this tag is deleted
+    pub tag: Option<String>,
+    /// Report what would be deleted without deleting it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteDocumentsResponse {
+    pub document_ids: Vec<Uuid>,
+    pub documents_deleted: usize,
+    pub chunks_deleted: u64,
+    pub dry_run: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
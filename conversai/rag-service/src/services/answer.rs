@@ -0,0 +1,356 @@
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::env;
+use tracing::info;
+
+use crate::services::chunking::estimate_tokens;
+use crate::services::embedding_provider::openai_base_url;
+use crate::services::retrieval::{score_order, ChunkWithScore};
+
+const SYSTEM_PROMPT: &str = "You answer questions using only the numbered context passages provided. \
+Cite the passages you use inline with their markers, e.g. [1] or [2][3]. \
+If the context does not contain the answer, say so.";
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    max_tokens: usize,
+    temperature: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChunk {
+    choices: Vec<ChatStreamChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChoice {
+    delta: ChatDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatDelta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnswerConfig {
+    pub model: String,
+    pub context_tokens: usize,
+    pub max_answer_tokens: usize,
+}
+
+impl AnswerConfig {
+    pub fn from_env() -> Self {
+        let model = env::var("CHAT_MODEL_NAME")
+            .unwrap_or_else(|_| "gpt-4o-mini".to_string());
+        let context_tokens = env::var("CHAT_MODEL_CONTEXT_TOKENS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(16_000);
+        let max_answer_tokens = env::var("ANSWER_MAX_TOKENS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(512);
+
+        Self {
+            model,
+            context_tokens,
+            max_answer_tokens,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GeneratedAnswer {
+    pub text: String,
+    /// Indices into the chunks passed to `generate_answer` that the answer cites
+    pub cited: Vec<usize>,
+    /// Chunks dropped to fit the prompt into the model context
+    pub trimmed_chunks: usize,
+}
+
+struct PreparedPrompt {
+    api_key: String,
+    request: ChatRequest,
+    included: Vec<usize>,
+    trimmed_chunks: usize,
+}
+
+/// Generates an answer from the reranked chunks. Passages are numbered by their
+/// position in `chunks`, so marker `[n]` refers to `chunks[n - 1]` (and to the
+/// citation built from it).
+pub async fn generate_answer(query: &str, chunks: &[ChunkWithScore]) -> Result<GeneratedAnswer> {
+    let prompt = prepare_prompt(query, chunks, false)?;
+
+    let response = reqwest::Client::new()
+        .post(chat_completions_url())
+        .header("Authorization", format!("Bearer {}", prompt.api_key))
+        .json(&prompt.request)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let chat_response: ChatResponse = response.json().await?;
+    let text = chat_response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .ok_or_else(|| anyhow!("chat completion returned no choices"))?;
+
+    Ok(finish(prompt, text))
+}
+
+/// Streaming variant of `generate_answer`: `on_delta` is called with each token
+/// fragment as the model produces it, and the complete answer is returned at the end.
+pub async fn stream_answer(
+    query: &str,
+    chunks: &[ChunkWithScore],
+    mut on_delta: impl FnMut(&str),
+) -> Result<GeneratedAnswer> {
+    let prompt = prepare_prompt(query, chunks, true)?;
+
+    let response = reqwest::Client::new()
+        .post(chat_completions_url())
+        .header("Authorization", format!("Bearer {}", prompt.api_key))
+        .json(&prompt.request)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // The completion arrives as server-sent events: `data: {json}` lines ending with `data: [DONE]`
+    let mut body = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut text = String::new();
+
+    'read: while let Some(bytes) = body.next().await {
+        buffer.extend_from_slice(&bytes?);
+
+        while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else { continue };
+            let data = data.trim();
+
+            if data == "[DONE]" {
+                break 'read;
+            }
+
+            let chunk: ChatStreamChunk = serde_json::from_str(data)?;
+            if let Some(delta) = chunk.choices.into_iter().next().and_then(|c| c.delta.content) {
+                on_delta(&delta);
+                text.push_str(&delta);
+            }
+        }
+    }
+
+    Ok(finish(prompt, text))
+}
+
+/// Asks the chat model for up to `count` alternative phrasings of `query`, used to
+/// widen retrieval recall. The original query is never among the returned variations.
+pub async fn generate_query_variations(query: &str, count: usize) -> Result<Vec<String>> {
+    let system = format!(
+        "Rewrite the user's search query into {} alternative phrasings that use different \
+         wording but ask for the same information. Reply with one phrasing per line and nothing else.",
+        count
+    );
+    let text = complete(&system, query, 200, 0.7).await?;
+
+    // Models sometimes number or bullet the lines despite the instructions
+    let mut variations: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*'))
+            .trim()
+            .trim_matches('"');
+        let duplicate = line.eq_ignore_ascii_case(query.trim())
+            || variations.iter().any(|v| v.eq_ignore_ascii_case(line));
+        if !line.is_empty() && !duplicate {
+            variations.push(line.to_string());
+        }
+    }
+    variations.truncate(count);
+
+    info!("Generated {} query variations", variations.len());
+    Ok(variations)
+}
+
+/// One non-streaming completion of `user` under `system` with the configured chat model.
+pub async fn complete(system: &str, user: &str, max_tokens: usize, temperature: f32) -> Result<String> {
+    let api_key = env::var("OPENAI_API_KEY")?;
+    let config = AnswerConfig::from_env();
+
+    let request = ChatRequest {
+        model: config.model,
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: system.to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: user.to_string(),
+            },
+        ],
+        max_tokens,
+        temperature,
+        stream: false,
+    };
+
+    let response = reqwest::Client::new()
+        .post(chat_completions_url())
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let chat_response: ChatResponse = response.json().await?;
+    chat_response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .ok_or_else(|| anyhow!("chat completion returned no choices"))
+}
+
+fn chat_completions_url() -> String {
+    format!("{}/chat/completions", openai_base_url())
+}
+
+fn prepare_prompt(query: &str, chunks: &[ChunkWithScore], stream: bool) -> Result<PreparedPrompt> {
+    let api_key = env::var("OPENAI_API_KEY")?;
+    let config = AnswerConfig::from_env();
+
+    let included = select_within_budget(query, chunks, &config);
+    let trimmed_chunks = chunks.len() - included.len();
+    if included.is_empty() && !chunks.is_empty() {
+        return Err(anyhow!("no context passage fits the model context of {} tokens", config.context_tokens));
+    }
+
+    let passages: String = included
+        .iter()
+        .map(|&i| format_passage(i, &chunks[i]))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let request = ChatRequest {
+        model: config.model,
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: SYSTEM_PROMPT.to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: format!("Context:\n\n{}\n\nQuestion: {}", passages, query),
+            },
+        ],
+        max_tokens: config.max_answer_tokens,
+        temperature: 0.2,
+        stream,
+    };
+
+    Ok(PreparedPrompt {
+        api_key,
+        request,
+        included,
+        trimmed_chunks,
+    })
+}
+
+fn finish(prompt: PreparedPrompt, text: String) -> GeneratedAnswer {
+    let cited = cited_passages(&text, &prompt.included);
+
+    info!(
+        "Generated answer with {} ({} passages, {} trimmed, {} cited)",
+        prompt.request.model,
+        prompt.included.len(),
+        prompt.trimmed_chunks,
+        cited.len()
+    );
+
+    GeneratedAnswer {
+        text,
+        cited,
+        trimmed_chunks: prompt.trimmed_chunks,
+    }
+}
+
+fn format_passage(index: usize, chunk: &ChunkWithScore) -> String {
+    match chunk.chunk.section.as_deref() {
+        Some(section) if !section.is_empty() => {
+            format!("[{}] ({})\n{}", index + 1, section, chunk.chunk.content)
+        }
+        _ => format!("[{}]\n{}", index + 1, chunk.chunk.content),
+    }
+}
+
+/// Picks the chunks that fit the prompt budget, dropping the lowest-scoring first.
+/// Returned indices keep the original order so passage numbers stay stable.
+fn select_within_budget(query: &str, chunks: &[ChunkWithScore], config: &AnswerConfig) -> Vec<usize> {
+    let overhead = estimate_tokens(SYSTEM_PROMPT) + estimate_tokens(query) + 32;
+    let budget = config
+        .context_tokens
+        .saturating_sub(config.max_answer_tokens)
+        .saturating_sub(overhead);
+
+    let mut by_score: Vec<usize> = (0..chunks.len()).collect();
+    by_score.sort_by(|&a, &b| score_order(chunks[b].score, chunks[a].score));
+
+    let mut used = 0;
+    let mut included = Vec::new();
+    for i in by_score {
+        let cost = estimate_tokens(&format_passage(i, &chunks[i])) + 2;
+        if used + cost <= budget {
+            used += cost;
+            included.push(i);
+        }
+    }
+
+    included.sort_unstable();
+    included
+}
+
+/// Passage indices referenced as `[n]` in the answer, in order of first appearance.
+/// Markers pointing at passages that were not in the prompt are ignored.
+fn cited_passages(text: &str, included: &[usize]) -> Vec<usize> {
+    let mut cited = Vec::new();
+
+    for (start, _) in text.match_indices('[') {
+        let rest = &text[start + 1..];
+        let Some(end) = rest.find(']') else { continue };
+        let Ok(marker) = rest[..end].trim().parse::<usize>() else { continue };
+
+        if let Some(index) = marker.checked_sub(1) {
+            if included.contains(&index) && !cited.contains(&index) {
+                cited.push(index);
+            }
+        }
+    }
+
+    cited
+}
@@ -1,8 +1,21 @@
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tiktoken_rs::p50k_base;
+use std::collections::HashSet;
+use std::env;
+use std::ops::Range;
+use tiktoken_rs::{p50k_base, CoreBPE};
+use tracing::info;
 
 use crate::services::markdown::MarkdownSection;
+use crate::utils::{calculate_sha256, truncate_text};
+
+/// Upper bound on `context_prefix`, so a run-on first sentence doesn't bloat metadata
+const MAX_CONTEXT_PREFIX_CHARS: usize = 200;
+/// A heading prefix may take at most this share of the chunk budget; deeper paths go without
+const MAX_HEADING_PREFIX_SHARE: usize = 4;
+
+/// The tiktoken encoding every chunk's tokens are counted with
+pub const TOKENIZER: &str = "p50k_base";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
@@ -13,70 +26,111 @@ pub struct Chunk {
     pub metadata: serde_json::Value,
 }
 
-pub fn chunk_sections(sections: &[MarkdownSection], max_tokens: usize, overlap_tokens: usize) -> Vec<Chunk> {
+/// Chunks of a document, with the sections worth warning about.
+pub struct ChunkedSections {
+    pub chunks: Vec<Chunk>,
+    /// Index and token count of each section over `max_tokens`, cut into token windows
+    pub split_sections: Vec<(usize, usize)>,
+    /// Indices of sections whose chunks were all dropped as duplicates
+    pub empty_sections: Vec<usize>,
+}
+
+/// Splits sections into token-bounded chunks. With `dedup`, chunks whose normalized
+/// content repeats an earlier chunk of the same document (boilerplate, overlap
+/// artifacts) are dropped before they cost an embedding call. With `contextual`,
+/// each chunk's metadata also records its neighbours and a lead-in from the
+/// previous chunk; only `content` is ever embedded. With `heading_prefix`, chunks
+/// of headed sections start with their heading path (`A > B:` and a line break),
+/// so text that only says "it" still embeds near its topic. The prefix counts
+/// against `max_tokens` and is recorded as `"heading_prefix"` in the metadata, so
+/// it can be stripped for display.
+pub fn chunk_sections(
+    sections: &[MarkdownSection],
+    max_tokens: usize,
+    overlap_tokens: usize,
+    dedup: bool,
+    contextual: bool,
+    heading_prefix: bool,
+) -> ChunkedSections {
     let tokenizer = p50k_base().unwrap();
     let mut chunks = Vec::new();
+    // Index of the section each chunk came from
+    let mut origins = Vec::new();
+    let mut split_sections = Vec::new();
 
-    for section in sections {
+    for (index, section) in sections.iter().enumerate() {
         let tokens = tokenizer.encode_with_special_tokens(&section.content);
         let token_count = tokens.len();
 
-        if token_count <= max_tokens {
+        let (prefix, prefix_tokens) = heading_prefix
+            .then(|| section_prefix(section))
+            .flatten()
+            .map(|prefix| {
+                let tokens = tokenizer.encode_with_special_tokens(&prefix).len();
+                (prefix, tokens)
+            })
+            .filter(|&(_, tokens)| tokens <= max_tokens / MAX_HEADING_PREFIX_SHARE)
+            .unwrap_or_default();
+        let budget = max_tokens - prefix_tokens;
+
+        if token_count <= budget {
             // Section fits in one chunk
+            origins.push(index);
             chunks.push(Chunk {
-                content: section.content.clone(),
+                content: format!("{}{}", prefix, section.content),
                 section: section.heading_path.join(" > "),
-                tokens: token_count,
-                span: json!({
+                tokens: prefix_tokens + token_count,
+                span: with_location(json!({
                     "start_char": section.start_offset,
                     "end_char": section.end_offset
-                }),
-                metadata: json!({
+                }), section),
+                metadata: with_prefix(with_code(with_location(json!({
                     "heading_path": section.heading_path,
                     "level": section.level
-                }),
+                }), section), section), &prefix),
             });
         } else {
             // Split section into multiple chunks
+            split_sections.push((index, token_count));
+            let offsets = token_offsets(&tokenizer, &tokens);
+            let overlap_tokens = overlap_tokens.min(budget - 1);
             let mut start = 0;
             while start < tokens.len() {
-                let end = (start + max_tokens).min(tokens.len());
-                
-                // Try to find a sentence boundary near the end
+                let end = (start + budget).min(tokens.len());
                 let chunk_tokens = &tokens[start..end];
-                let chunk_text = tokenizer.decode(chunk_tokens.to_vec()).unwrap();
-                
-                // Calculate character offsets
-                let char_start = if start == 0 {
-                    section.start_offset
-                } else {
-                    section.start_offset + (start * 3) // Rough approximation
-                };
-                
-                let char_end = if end == tokens.len() {
-                    section.end_offset
-                } else {
-                    section.start_offset + (end * 3) // Rough approximation
-                };
 
+                // A token can end mid-character; the window widens to whole characters
+                let mut from = offsets[start];
+                while !section.content.is_char_boundary(from) {
+                    from -= 1;
+                }
+                let mut to = offsets[end];
+                while !section.content.is_char_boundary(to) {
+                    to += 1;
+                }
+                let chunk_text = format!("{}{}", prefix, &section.content[from..to]);
+                let char_start = section.source_offset(from);
+                let char_end = section.source_offset(to);
+
+                origins.push(index);
                 chunks.push(Chunk {
                     content: chunk_text,
                     section: section.heading_path.join(" > "),
-                    tokens: chunk_tokens.len(),
-                    span: json!({
+                    tokens: prefix_tokens + chunk_tokens.len(),
+                    span: with_location(json!({
                         "start_char": char_start,
                         "end_char": char_end
-                    }),
-                    metadata: json!({
+                    }), section),
+                    metadata: with_prefix(with_code(with_location(json!({
                         "heading_path": section.heading_path,
                         "level": section.level,
                         "chunk_index": chunks.len()
-                    }),
+                    }), section), section), &prefix),
                 });
 
                 // Move to next chunk with overlap
                 if end < tokens.len() {
-                    start = end - overlap_tokens;
+                    start = overlap_start(&tokenizer, &tokens, start, end, overlap_tokens);
                 } else {
                     break;
                 }
@@ -84,10 +138,409 @@ pub fn chunk_sections(sections: &[MarkdownSection], max_tokens: usize, overlap_t
         }
     }
 
-    chunks
+    let (mut chunks, origins) = if dedup { dedup_chunks(chunks, origins) } else { (chunks, origins) };
+    if contextual {
+        add_context_metadata(&mut chunks);
+    }
+
+    let kept: HashSet<usize> = origins.into_iter().collect();
+    let empty_sections = (0..sections.len()).filter(|index| !kept.contains(index)).collect();
+    ChunkedSections {
+        chunks,
+        split_sections,
+        empty_sections,
+    }
+}
+
+/// Byte offset in the encoded text where each token starts, followed by the
+/// text's length, so `offsets[i]..offsets[j]` holds tokens `i..j`.
+fn token_offsets(tokenizer: &CoreBPE, tokens: &[usize]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for &token in tokens {
+        offset += tokenizer._decode_native(&[token]).len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Boundaries `split_plain_text` cuts at, coarsest first
+const SEPARATORS: [Separator; 4] = [Separator::Paragraph, Separator::Line, Separator::Sentence, Separator::Word];
+
+#[derive(Debug, Clone, Copy)]
+enum Separator {
+    /// A blank line
+    Paragraph,
+    /// Any line break
+    Line,
+    /// Whitespace after `.`, `!` or `?`, or a line break
+    Sentence,
+    /// Any whitespace
+    Word,
+}
+
+/// Sections for plain text, which has no headings to split on. Paragraphs are packed
+/// together up to `max_tokens`; a paragraph too long for one section is split at
+/// line breaks, then sentences, then words, and the pieces are packed the same way.
+/// A single word longer than `max_tokens` is left whole for `chunk_sections` to cut
+/// by tokens. Sections have no heading path, and their offsets are byte offsets
+/// into `text`.
+pub fn split_plain_text(text: &str, max_tokens: usize) -> Vec<MarkdownSection> {
+    let tokenizer = p50k_base().unwrap();
+    let fits = |range: &Range<usize>| tokenizer.encode_with_special_tokens(&text[range.clone()]).len() <= max_tokens;
+
+    let mut pieces = Vec::new();
+    split_recursive(text, 0..text.len(), 0, &fits, &mut pieces);
+
+    // Packed pieces keep the original separators between them
+    let mut packed: Vec<Range<usize>> = Vec::new();
+    for piece in pieces {
+        match packed.last_mut() {
+            Some(last) if fits(&(last.start..piece.end)) => last.end = piece.end,
+            _ => packed.push(piece),
+        }
+    }
+
+    packed
+        .into_iter()
+        .filter_map(|range| {
+            let piece = &text[range.clone()];
+            let start = range.start + (piece.len() - piece.trim_start().len());
+            let end = range.end - (piece.len() - piece.trim_end().len());
+            (start < end).then(|| MarkdownSection {
+                content: text[start..end].to_string(),
+                heading_path: Vec::new(),
+                level: 0,
+                start_offset: start,
+                end_offset: end,
+                page: None,
+                rows: None,
+                code: None,
+                source_map: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Splits `text[range]` at `SEPARATORS[level]`, recursing with the next separator
+/// into pieces that don't fit, and appends the resulting pieces in order.
+fn split_recursive(
+    text: &str,
+    range: Range<usize>,
+    level: usize,
+    fits: &dyn Fn(&Range<usize>) -> bool,
+    out: &mut Vec<Range<usize>>,
+) {
+    let Some(&separator) = SEPARATORS.get(level) else {
+        out.push(range);
+        return;
+    };
+
+    for piece in split_at(text, range, separator) {
+        if fits(&piece) {
+            out.push(piece);
+        } else {
+            split_recursive(text, piece, level + 1, fits, out);
+        }
+    }
+}
+
+/// Byte ranges of the non-empty pieces of `text[range]` between `separator` boundaries.
+fn split_at(text: &str, range: Range<usize>, separator: Separator) -> Vec<Range<usize>> {
+    let slice = &text[range.clone()];
+    let mut pieces = Vec::new();
+    let mut piece_start = 0;
+    let mut previous = None;
+    let mut chars = slice.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if !c.is_whitespace() {
+            previous = Some(c);
+            continue;
+        }
+
+        // Take the whole whitespace run as one potential boundary
+        let mut end = i + c.len_utf8();
+        let mut newlines = usize::from(c == '\n');
+        while let Some(&(j, next)) = chars.peek().filter(|(_, next)| next.is_whitespace()) {
+            newlines += usize::from(next == '\n');
+            end = j + next.len_utf8();
+            chars.next();
+        }
+
+        let is_boundary = match separator {
+            Separator::Paragraph => newlines >= 2,
+            Separator::Line => newlines >= 1,
+            Separator::Sentence => newlines >= 1 || matches!(previous, Some('.' | '!' | '?')),
+            Separator::Word => true,
+        };
+        if is_boundary {
+            if i > piece_start {
+                pieces.push(range.start + piece_start..range.start + i);
+            }
+            piece_start = end;
+        }
+        previous = Some(c);
+    }
+
+    if piece_start < slice.len() {
+        pieces.push(range.start + piece_start..range.end);
+    }
+    pieces
+}
+
+/// Adds `"page"` and `"row_start"`/`"row_end"` to a span or metadata object when
+/// the section has them; citations read them back from the span.
+fn with_location(mut value: serde_json::Value, section: &MarkdownSection) -> serde_json::Value {
+    if let serde_json::Value::Object(fields) = &mut value {
+        if let Some(page) = section.page {
+            fields.insert("page".to_string(), json!(page));
+        }
+        if let Some((first, last)) = section.rows {
+            fields.insert("row_start".to_string(), json!(first));
+            fields.insert("row_end".to_string(), json!(last));
+        }
+    }
+    value
+}
+
+/// Adds `"kind": "code"` and the block's `"language"` to the metadata of chunks
+/// from a code block section; `kind` is what the `content_kind` query filter reads.
+fn with_code(mut metadata: serde_json::Value, section: &MarkdownSection) -> serde_json::Value {
+    if let (serde_json::Value::Object(fields), Some(code)) = (&mut metadata, &section.code) {
+        fields.insert("kind".to_string(), json!("code"));
+        if let Some(language) = &code.language {
+            fields.insert("language".to_string(), json!(language));
+        }
+    }
+    metadata
+}
+
+/// The heading path line chunks of `section` start with. Sections named after
+/// their page or row range have nothing worth embedding in their heading.
+fn section_prefix(section: &MarkdownSection) -> Option<String> {
+    let synthetic = section.page.is_some() || section.rows.is_some();
+    (!section.heading_path.is_empty() && !synthetic).then(|| format!("{}:\n", section.heading_path.join(" > ")))
+}
+
+fn with_prefix(mut metadata: serde_json::Value, prefix: &str) -> serde_json::Value {
+    if !prefix.is_empty() {
+        metadata["heading_prefix"] = json!(prefix);
+    }
+    metadata
+}
+
+/// A chunk's content without its heading prefix.
+fn body(chunk: &Chunk) -> &str {
+    chunk
+        .metadata
+        .get("heading_prefix")
+        .and_then(|prefix| prefix.as_str())
+        .and_then(|prefix| chunk.content.strip_prefix(prefix))
+        .unwrap_or(&chunk.content)
+}
+
+/// Reads `CHUNK_CONTEXT_METADATA` (default off).
+pub fn context_metadata_enabled() -> bool {
+    env::var("CHUNK_CONTEXT_METADATA")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Adds `prev_chunk_index`/`next_chunk_index` and `context_prefix` (the first
+/// sentence of the preceding chunk). Indices are positions after dedup, matching
+/// the `chunk_index` column chunks are stored with.
+fn add_context_metadata(chunks: &mut [Chunk]) {
+    let prefixes: Vec<String> = chunks
+        .iter()
+        .map(|chunk| truncate_text(first_sentence(body(chunk)), MAX_CONTEXT_PREFIX_CHARS))
+        .collect();
+    let last = chunks.len().saturating_sub(1);
+
+    for (i, chunk) in chunks.iter_mut().enumerate() {
+        let serde_json::Value::Object(metadata) = &mut chunk.metadata else {
+            continue;
+        };
+        metadata.insert("prev_chunk_index".to_string(), json!(i.checked_sub(1)));
+        metadata.insert("next_chunk_index".to_string(), json!((i < last).then_some(i + 1)));
+        if let Some(prefix) = i.checked_sub(1).map(|prev| &prefixes[prev]) {
+            metadata.insert("context_prefix".to_string(), json!(prefix));
+        }
+    }
+}
+
+/// Text up to and including the first `.`, `!` or `?` followed by whitespace,
+/// or up to the first line break, whichever comes first.
+fn first_sentence(text: &str) -> &str {
+    let text = text.trim_start();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\n' {
+            return text[..i].trim_end();
+        }
+        if matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|(_, next)| next.is_whitespace()) {
+            return &text[..i + c.len_utf8()];
+        }
+    }
+    text.trim_end()
+}
+
+/// Where the chunk after `start..end` begins. Within the last `overlap_tokens`
+/// tokens it prefers the earliest sentence start, so the overlap is whole
+/// sentences, then the earliest word start; the window never reaches back to
+/// `start`, so chunking always advances.
+fn overlap_start(tokenizer: &CoreBPE, tokens: &[usize], start: usize, end: usize, overlap_tokens: usize) -> usize {
+    let window = end.saturating_sub(overlap_tokens).max(start + 1)..end;
+    if window.is_empty() {
+        return end;
+    }
+
+    // Tokens that split a multi-byte character don't decode alone and are never boundaries
+    let decoded = |i: usize| tokenizer.decode(vec![tokens[i]]).ok();
+    let sentence_start = window.clone().find(|&i| {
+        decoded(i - 1).is_some_and(|prev| {
+            prev.contains('\n') || prev.trim_end().ends_with(['.', '!', '?'])
+        })
+    });
+    let word_start = || {
+        window
+            .clone()
+            .find(|&i| decoded(i).is_some_and(|text| text.starts_with(char::is_whitespace)))
+    };
+
+    sentence_start.or_else(word_start).unwrap_or(window.start)
+}
+
+/// Keeps the first chunk for each distinct normalized content, along with the
+/// matching entries of `origins`.
+fn dedup_chunks(chunks: Vec<Chunk>, origins: Vec<usize>) -> (Vec<Chunk>, Vec<usize>) {
+    let total = chunks.len();
+    let mut seen = HashSet::new();
+
+    let kept: (Vec<Chunk>, Vec<usize>) = chunks
+        .into_iter()
+        .zip(origins)
+        .filter(|(chunk, _)| seen.insert(calculate_sha256(normalize_content(&chunk.content).as_bytes())))
+        .unzip();
+
+    let skipped = total - kept.0.len();
+    if skipped > 0 {
+        info!("Skipped {} duplicate chunks out of {}", skipped, total);
+    }
+
+    kept
+}
+
+/// Lowercased with all whitespace runs collapsed to single spaces.
+fn normalize_content(content: &str) -> String {
+    content
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Exact token count with `TOKENIZER`, as stored in `chunks.content_tokens`.
+pub fn count_tokens(text: &str) -> usize {
+    p50k_base().unwrap().encode_with_special_tokens(text).len()
 }
 
 pub fn estimate_tokens(text: &str) -> usize {
     // Simple estimation: ~1 token per 4 characters
     text.len() / 4
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::markdown::parse_markdown;
+
+    fn chunk(markdown: &str, max_tokens: usize, overlap_tokens: usize, heading_prefix: bool) -> Vec<Chunk> {
+        let sections = parse_markdown(markdown, false);
+        chunk_sections(&sections, max_tokens, overlap_tokens, false, false, heading_prefix).chunks
+    }
+
+    #[test]
+    fn overlap_repeats_a_whole_sentence() {
+        let text: String = (1..=30)
+            .map(|i| format!("Sentence {} covers topic number {}. ", i, i * 7))
+            .collect();
+        let chunks = chunk(&format!("# Notes\n\n{}", text), 40, 15, false);
+        assert!(chunks.len() > 2, "expected several chunks, got {}", chunks.len());
+
+        for pair in chunks.windows(2) {
+            let (previous, next) = (&pair[0].content, &pair[1].content);
+            let shared = first_sentence(next);
+            assert!(shared.starts_with("Sentence ") && shared.ends_with('.'), "{:?} starts mid-sentence", next);
+            assert!(previous.contains(shared), "{:?} is not repeated from {:?}", shared, previous);
+        }
+    }
+
+    #[test]
+    fn heading_prefix_is_stripped_by_body_and_counted_in_tokens() {
+        let markdown = "# Projects\n\n## ConversAI\n\n### Deployment\n\nIt runs on Railway behind a proxy.\n";
+        let prefixed = chunk(markdown, 200, 20, true);
+        let plain = chunk(markdown, 200, 20, false);
+        let find = |chunks: &[Chunk]| chunks.iter().find(|c| c.content.contains("Railway")).cloned().unwrap();
+        let (prefixed, plain) = (find(&prefixed), find(&plain));
+
+        let prefix = "Projects > ConversAI > Deployment:\n";
+        assert!(prefixed.content.starts_with(prefix), "{:?}", prefixed.content);
+        assert_eq!(prefixed.metadata["heading_prefix"], prefix);
+        assert_eq!(body(&prefixed), plain.content);
+        assert_eq!(body(&plain), plain.content);
+
+        let prefix_tokens = p50k_base().unwrap().encode_with_special_tokens(prefix).len();
+        assert!(prefix_tokens > 0);
+        assert_eq!(prefixed.tokens, plain.tokens + prefix_tokens);
+    }
+
+    #[test]
+    fn heading_prefix_counts_against_the_chunk_budget() {
+        let text: String = (1..=40).map(|i| format!("It handles request number {}. ", i)).collect();
+        let chunks: Vec<Chunk> = chunk(&format!("# Service\n\n## Deployment\n\n{}", text), 40, 5, true)
+            .into_iter()
+            .filter(|chunk| chunk.section == "Service > Deployment")
+            .collect();
+        assert!(chunks.len() > 2, "expected several chunks, got {}", chunks.len());
+
+        let tokenizer = p50k_base().unwrap();
+        for chunk in &chunks {
+            assert!(chunk.content.starts_with("Service > Deployment:\n"), "{:?}", chunk.content);
+            assert!(chunk.tokens <= 40, "{} tokens in {:?}", chunk.tokens, chunk.content);
+            assert!(!body(chunk).starts_with("Service > Deployment"));
+            let body_tokens = tokenizer.encode_with_special_tokens(body(chunk)).len();
+            assert!(chunk.tokens > body_tokens, "prefix tokens missing from {:?}", chunk.content);
+        }
+    }
+
+    #[test]
+    fn spans_point_at_the_source_of_each_chunk() {
+        let paragraph = "Die **Größe** des `Straßen`-Netzes wächst _schnell_; 東京 und Zürich planen mehr. ";
+        let source = format!("# Städte
+
+{}
+
+## Verkehr
+
+{}
+", paragraph.repeat(6), paragraph.repeat(4));
+        let chunks = chunk(&source, 30, 5, false);
+        assert!(chunks.len() > 3, "expected the sections to be split, got {}", chunks.len());
+
+        // Markup is in the source but not the chunk, and the chunk may space inline
+        // elements differently, so compare with both removed
+        let words = |text: &str| -> String {
+            text.chars().filter(|c| !c.is_whitespace() && !matches!(c, '*' | '_' | '`')).collect()
+        };
+        for chunk in &chunks {
+            let start = chunk.span["start_char"].as_u64().unwrap() as usize;
+            let end = chunk.span["end_char"].as_u64().unwrap() as usize;
+            assert!(source.is_char_boundary(start) && source.is_char_boundary(end));
+            let passage = words(&source[start..end]);
+            let body = words(&chunk.content);
+            assert!(passage.contains(&body), "{:?} not found in {:?}", body, passage);
+        }
+    }
+}
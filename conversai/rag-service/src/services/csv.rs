@@ -0,0 +1,209 @@
+use csv::{ReaderBuilder, StringRecord};
+use tiktoken_rs::p50k_base;
+
+use crate::services::ingest::DocumentError;
+use crate::services::markdown::MarkdownSection;
+
+/// Rows of a CSV file grouped into sections that fit the token budget, each
+/// starting with the header row so a chunk can be read on its own.
+pub struct CsvText {
+    pub sections: Vec<MarkdownSection>,
+    pub columns: Vec<String>,
+    /// Rows with the wrong number of fields or invalid UTF-8, skipped
+    pub malformed_rows: usize,
+}
+
+/// Reads the first row as the header and packs the data rows that follow into
+/// sections of at most `max_tokens`, header included. Rows are written as
+/// `value | value` lines under a `column | column` header line. A single row too
+/// long for one section gets a section of its own, for `chunk_sections` to cut by
+/// tokens. Sections are headed `Rows N–M` and record their 1-based data row range;
+/// their offsets are byte offsets into the file.
+pub fn extract_sections(data: &[u8], max_tokens: usize) -> Result<CsvText, DocumentError> {
+    let mut reader = ReaderBuilder::new().has_headers(true).from_reader(data);
+    let columns: Vec<String> = reader
+        .headers()
+        .map_err(|e| DocumentError::Corrupt(format!("unreadable CSV header row: {}", e)))?
+        .iter()
+        .map(single_line)
+        .collect();
+    if columns.iter().all(|column| column.is_empty()) {
+        return Err(DocumentError::Corrupt("CSV file has no header row".to_string()));
+    }
+
+    let tokenizer = p50k_base().unwrap();
+    let header = columns.join(" | ");
+    let header_tokens = tokenizer.encode_with_special_tokens(&header).len() + 1;
+
+    let mut sections = Vec::new();
+    let mut malformed_rows = 0;
+    let mut window = RowWindow::default();
+    let mut record = StringRecord::new();
+    let mut row = 0;
+
+    loop {
+        let start = reader.position().byte() as usize;
+        match reader.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => {
+                return Err(DocumentError::Corrupt(format!("unreadable CSV: {}", e)));
+            }
+            // Wrong field count or invalid UTF-8; the reader resumes at the next row
+            Err(_) => {
+                row += 1;
+                malformed_rows += 1;
+                continue;
+            }
+        }
+        row += 1;
+        let end = reader.position().byte() as usize;
+
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let line = record.iter().map(single_line).collect::<Vec<_>>().join(" | ");
+        let tokens = tokenizer.encode_with_special_tokens(&line).len() + 1;
+
+        if !window.lines.is_empty() && header_tokens + window.tokens + tokens > max_tokens {
+            sections.push(window.section(&header));
+            window = RowWindow::default();
+        }
+        window.push(row, start..end, line, tokens);
+    }
+    if !window.lines.is_empty() {
+        sections.push(window.section(&header));
+    }
+
+    Ok(CsvText {
+        sections,
+        columns,
+        malformed_rows,
+    })
+}
+
+/// Consecutive rows collected for one section.
+#[derive(Default)]
+struct RowWindow {
+    lines: Vec<String>,
+    /// Byte offset of each row in the file
+    row_offsets: Vec<usize>,
+    tokens: usize,
+    first_row: usize,
+    last_row: usize,
+    start_offset: usize,
+    end_offset: usize,
+}
+
+impl RowWindow {
+    fn push(&mut self, row: usize, bytes: std::ops::Range<usize>, line: String, tokens: usize) {
+        if self.lines.is_empty() {
+            self.first_row = row;
+            self.start_offset = bytes.start;
+        }
+        self.last_row = row;
+        self.end_offset = bytes.end;
+        self.lines.push(line);
+        self.row_offsets.push(bytes.start);
+        self.tokens += tokens;
+    }
+
+    fn section(&self, header: &str) -> MarkdownSection {
+        let heading = if self.first_row == self.last_row {
+            format!("Row {}", self.first_row)
+        } else {
+            format!("Rows {}–{}", self.first_row, self.last_row)
+        };
+        // Each row line maps to its row in the file; the header to the first row
+        let mut source_map = vec![(0, self.start_offset)];
+        let mut offset = header.len() + 1;
+        for (line, &row_offset) in self.lines.iter().zip(&self.row_offsets) {
+            source_map.push((offset, row_offset));
+            offset += line.len() + 1;
+        }
+        MarkdownSection {
+            content: format!("{}\n{}", header, self.lines.join("\n")),
+            heading_path: vec![heading],
+            level: 0,
+            start_offset: self.start_offset,
+            end_offset: self.end_offset,
+            page: None,
+            rows: Some((self.first_row, self.last_row)),
+            code: None,
+            source_map,
+        }
+    }
+}
+
+/// Field text with its whitespace, including quoted line breaks, collapsed so each
+/// row stays on one line.
+fn single_line(field: &str) -> String {
+    field.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ingest::{prepare_document, ChunkSettings, DocumentSource};
+
+    /// A header and 30 customers, with a short row at data row 5.
+    fn customers() -> String {
+        let mut csv = String::from("name,city,plan\n");
+        for i in 1..=30 {
+            if i == 5 {
+                csv.push_str("Broken row,Oslo\n");
+            } else {
+                csv.push_str(&format!("Customer {},City {},pro\n", i, i));
+            }
+        }
+        csv
+    }
+
+    #[test]
+    fn every_section_starts_with_the_header_row() {
+        let text = extract_sections(customers().as_bytes(), 40).unwrap();
+
+        assert_eq!(text.columns, ["name", "city", "plan"]);
+        assert_eq!(text.malformed_rows, 1);
+        assert!(text.sections.len() > 2, "{} sections", text.sections.len());
+        for section in &text.sections {
+            assert!(section.content.starts_with("name | city | plan\n"), "{:?}", section.content);
+        }
+        assert!(text.sections[0].content.contains("Customer 1 | City 1 | pro"));
+        assert!(!text.sections.iter().any(|section| section.content.contains("Broken row")));
+
+        // Row numbers count the skipped row, so ranges stay true to the file
+        let rows: Vec<(usize, usize)> = text.sections.iter().map(|section| section.rows.unwrap()).collect();
+        assert_eq!(rows[0], (1, 4));
+        assert_eq!(rows[1].0, 6);
+        assert_eq!(rows.last().unwrap().1, 30);
+        assert!(rows[1..].windows(2).all(|pair| pair[1].0 == pair[0].1 + 1), "{:?}", rows);
+    }
+
+    #[tokio::test]
+    async fn chunks_record_their_rows_and_skipped_rows_are_reported() {
+        let data = customers();
+        let source = DocumentSource::upload("customers.csv", data.as_bytes());
+        let settings = ChunkSettings { max_tokens: 40, overlap_tokens: 0, ..ChunkSettings::default() };
+        let prepared = prepare_document(&source, data.as_bytes(), settings).await.unwrap();
+
+        assert_eq!(
+            prepared.warnings,
+            ["csv_rows_skipped: 1 malformed rows (wrong number of fields or invalid UTF-8) were skipped"]
+        );
+        assert!(prepared.chunks.len() > 2);
+        for chunk in &prepared.chunks {
+            assert!(chunk.content.contains("name | city | plan\n"), "{:?}", chunk.content);
+            let row_start = chunk.metadata["row_start"].as_u64().unwrap();
+            let row_end = chunk.metadata["row_end"].as_u64().unwrap();
+            let heading = if row_start == row_end {
+                format!("Row {}", row_start)
+            } else {
+                format!("Rows {}–{}", row_start, row_end)
+            };
+            assert_eq!(chunk.section, heading, "{}", chunk.metadata);
+        }
+        assert_eq!(prepared.chunks[0].metadata["row_start"], 1);
+        assert_eq!(prepared.chunks.last().unwrap().metadata["row_end"], 30);
+    }
+}
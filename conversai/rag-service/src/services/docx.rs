@@ -0,0 +1,293 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+use crate::services::ingest::DocumentError;
+use crate::services::markdown::MarkdownSection;
+
+// Encrypted OOXML isn't a zip at all but an OLE compound file, like legacy .doc
+const COMPOUND_FILE_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+// Stream name (UTF-16LE) that only encrypted OOXML compound files contain
+const ENCRYPTION_INFO: &[u8] = b"E\0n\0c\0r\0y\0p\0t\0i\0o\0n\0I\0n\0f\0o\0";
+
+/// Text of a Word document as sections split at Heading 1–6 paragraphs, with
+/// heading paths like markdown. Images and embedded objects (OLE: spreadsheets,
+/// equations, ...) aren't extracted, only counted.
+pub struct DocxText {
+    pub sections: Vec<MarkdownSection>,
+    pub images: usize,
+    pub objects: usize,
+}
+
+/// Extracts paragraph text from `word/document.xml`. Headings are recognized by
+/// style (Heading 1–6, by name from `word/styles.xml` or by id) or by outline level.
+/// List items become `- item` lines indented by level, and table rows become
+/// `cell | cell` lines. Offsets are byte offsets into the extracted text.
+pub fn extract_sections(data: &[u8]) -> Result<DocxText, DocumentError> {
+    if data.starts_with(COMPOUND_FILE_MAGIC) {
+        return Err(if contains(data, ENCRYPTION_INFO) {
+            DocumentError::Encrypted("password-protected .docx files are not supported".to_string())
+        } else {
+            DocumentError::Corrupt("legacy .doc files are not supported; save the document as .docx".to_string())
+        });
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| DocumentError::Corrupt(format!("not a readable .docx archive: {}", e)))?;
+    let document = read_entry(&mut archive, "word/document.xml")?
+        .ok_or_else(|| DocumentError::Corrupt("not a Word document: word/document.xml is missing".to_string()))?;
+    // Without styles, headings are still found by style id or outline level
+    let heading_styles = match read_entry(&mut archive, "word/styles.xml")? {
+        Some(styles) => heading_styles(&styles)?,
+        None => HashMap::new(),
+    };
+
+    let paragraphs = read_paragraphs(&document, &heading_styles)?;
+    Ok(DocxText {
+        sections: build_sections(paragraphs.paragraphs),
+        images: paragraphs.images,
+        objects: paragraphs.objects,
+    })
+}
+
+struct Paragraph {
+    text: String,
+    /// 1–6 for headings
+    heading_level: Option<usize>,
+}
+
+struct Paragraphs {
+    paragraphs: Vec<Paragraph>,
+    images: usize,
+    objects: usize,
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Option<String>, DocumentError> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(DocumentError::Corrupt(format!("could not open {}: {}", name, e))),
+    };
+    let mut xml = String::new();
+    entry
+        .read_to_string(&mut xml)
+        .map_err(|e| DocumentError::Corrupt(format!("could not read {}: {}", name, e)))?;
+    Ok(Some(xml))
+}
+
+/// Style id → heading level for paragraph styles named "heading 1" … "heading 6".
+fn heading_styles(xml: &str) -> Result<HashMap<String, usize>, DocumentError> {
+    let mut reader = Reader::from_str(xml);
+    let mut styles = HashMap::new();
+    let mut current_id = None;
+
+    loop {
+        match reader.read_event().map_err(|e| malformed("word/styles.xml", e))? {
+            Event::Start(e) if e.local_name().as_ref() == b"style" => current_id = attribute(&e, b"styleId"),
+            Event::End(e) if e.local_name().as_ref() == b"style" => current_id = None,
+            Event::Empty(e) if e.local_name().as_ref() == b"name" => {
+                if let (Some(id), Some(level)) = (&current_id, attribute(&e, b"val").and_then(|n| heading_level(&n))) {
+                    styles.insert(id.clone(), level);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(styles)
+}
+
+fn read_paragraphs(xml: &str, heading_styles: &HashMap<String, usize>) -> Result<Paragraphs, DocumentError> {
+    let mut reader = Reader::from_str(xml);
+    let mut paragraphs = Vec::new();
+    let mut images = 0;
+    let mut objects = 0;
+
+    // Text boxes nest paragraphs inside a paragraph; their lines join the outer one
+    let mut depth = 0;
+    let mut text = String::new();
+    let mut heading = None;
+    let mut list_level: Option<usize> = None;
+    let mut in_text = false;
+    // OLE objects carry a preview picture, which isn't counted as an image too
+    let mut object_depth = 0;
+
+    // Each row of a top-level table becomes one paragraph of `|`-separated cells;
+    // nested tables are flattened into their cell
+    let mut table_depth = 0;
+    let mut cell = String::new();
+    let mut row: Vec<String> = Vec::new();
+
+    loop {
+        let event = reader.read_event().map_err(|e| malformed("word/document.xml", e))?;
+        match event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"p" => {
+                    if depth > 0 {
+                        text.push('\n');
+                    }
+                    depth += 1;
+                }
+                b"t" => in_text = true,
+                b"numPr" => list_level = Some(0),
+                b"tbl" => table_depth += 1,
+                b"object" => {
+                    objects += 1;
+                    object_depth += 1;
+                }
+                b"drawing" | b"pict" if object_depth == 0 => images += 1,
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"pStyle" => {
+                    if let Some(style) = attribute(&e, b"val") {
+                        heading = heading_styles.get(&style).copied().or_else(|| heading_level(&style)).or(heading);
+                    }
+                }
+                // Outline levels are 0-based; 9 means body text
+                b"outlineLvl" => {
+                    if let Some(level) = attribute(&e, b"val").and_then(|v| v.parse::<usize>().ok()) {
+                        if level < 6 {
+                            heading = heading.or(Some(level + 1));
+                        }
+                    }
+                }
+                b"ilvl" if list_level.is_some() => {
+                    list_level = attribute(&e, b"val").and_then(|v| v.parse().ok()).or(list_level);
+                }
+                b"tab" => text.push('\t'),
+                b"br" | b"cr" => text.push('\n'),
+                _ => {}
+            },
+            Event::Text(t) if in_text => {
+                text.push_str(&t.unescape().map_err(|e| malformed("word/document.xml", e))?);
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"object" => object_depth -= 1,
+                b"p" => {
+                    depth -= 1;
+                    if depth > 0 {
+                        continue;
+                    }
+                    let heading_level = heading.take();
+                    let mut paragraph = std::mem::take(&mut text).trim().to_string();
+                    // Numbered headings stay headings; other numbered paragraphs are list items
+                    if let (Some(level), None) = (list_level.take(), heading_level) {
+                        if !paragraph.is_empty() {
+                            paragraph = format!("{}- {}", "  ".repeat(level), paragraph);
+                        }
+                    }
+
+                    if table_depth > 0 {
+                        let paragraph = paragraph.trim();
+                        if !paragraph.is_empty() {
+                            if !cell.is_empty() {
+                                cell.push(' ');
+                            }
+                            cell.push_str(paragraph);
+                        }
+                    } else {
+                        paragraphs.push(Paragraph { text: paragraph, heading_level });
+                    }
+                }
+                b"tc" if table_depth == 1 => row.push(std::mem::take(&mut cell)),
+                b"tr" if table_depth == 1 => {
+                    if row.iter().any(|cell| !cell.is_empty()) {
+                        paragraphs.push(Paragraph {
+                            text: row.join(" | "),
+                            heading_level: None,
+                        });
+                    }
+                    row.clear();
+                }
+                b"tbl" => table_depth -= 1,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(Paragraphs { paragraphs, images, objects })
+}
+
+/// One section per heading with the paragraphs under it, like `parse_markdown`:
+/// the heading text opens the section's content and ends its heading path.
+fn build_sections(paragraphs: Vec<Paragraph>) -> Vec<MarkdownSection> {
+    let mut sections = Vec::new();
+    let mut heading_path: Vec<String> = Vec::new();
+    let mut level = 0;
+    let mut content = String::new();
+    let mut start_offset = 0;
+    let mut offset = 0;
+
+    for paragraph in paragraphs {
+        // Paragraphs come trimmed except for list indentation
+        let text = paragraph.text.as_str();
+        if text.is_empty() {
+            continue;
+        }
+
+        if let Some(heading_level) = paragraph.heading_level {
+            if !content.trim().is_empty() {
+                sections.push(section(&content, &heading_path, level, start_offset, offset));
+            }
+            content.clear();
+            start_offset = offset;
+            level = heading_level;
+            heading_path.truncate(heading_level - 1);
+            heading_path.push(text.to_string());
+        }
+
+        content.push_str(text);
+        content.push('\n');
+        offset += text.len() + 1;
+    }
+
+    if !content.trim().is_empty() {
+        sections.push(section(&content, &heading_path, level, start_offset, offset));
+    }
+    sections
+}
+
+fn section(content: &str, heading_path: &[String], level: usize, start_offset: usize, end_offset: usize) -> MarkdownSection {
+    MarkdownSection {
+        content: content.trim_end().to_string(),
+        heading_path: heading_path.to_vec(),
+        level,
+        start_offset,
+        end_offset,
+        page: None,
+        rows: None,
+        code: None,
+        source_map: Vec::new(),
+    }
+}
+
+/// Level for a style named or identified like "heading 2" / "Heading2".
+fn heading_level(style: &str) -> Option<usize> {
+    let rest = style.to_ascii_lowercase();
+    let level = rest.strip_prefix("heading")?.trim().parse::<usize>().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+/// Value of the attribute with this local name, whatever its namespace prefix.
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+fn malformed(entry: &str, error: quick_xml::Error) -> DocumentError {
+    DocumentError::Corrupt(format!("{} is malformed: {}", entry, error))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
@@ -1,67 +1,157 @@
-use anyhow::Result;
-use reqwest;
-use serde::{Deserialize, Serialize};
-use std::env;
-use tracing::info;
-
-#[derive(Debug, Serialize)]
-struct EmbeddingRequest {
-    input: Vec<String>,
-    model: String,
-}
+use anyhow::{anyhow, Result};
+use pgvector::Vector;
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use std::collections::HashMap;
+use tracing::{error, field, info, instrument, warn, Span};
+
+use crate::services::embedding_provider::EmbeddingProvider;
+use crate::utils::calculate_sha256;
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    data: Vec<EmbeddingData>,
+// Rows per INSERT when writing back to `embedding_cache`, well under the bind limit
+const CACHE_WRITE_BATCH: usize = 1000;
+
+/// Result of `get_embeddings_cached`; `cached[i]` is true when `embeddings[i]`
+/// came from `embedding_cache` rather than the provider.
+pub struct CachedEmbeddings {
+    pub embeddings: Vec<Vec<f32>>,
+    pub cached: Vec<bool>,
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingData {
-    embedding: Vec<f32>,
+impl CachedEmbeddings {
+    pub fn hits(&self) -> usize {
+        self.cached.iter().filter(|&&c| c).count()
+    }
 }
 
-pub async fn get_embeddings(texts: &[&str]) -> Result<Vec<Vec<f32>>> {
-    let api_key = env::var("OPENAI_API_KEY")?;
-    let model = env::var("EMBEDDING_MODEL_NAME")
-        .unwrap_or_else(|_| "text-embedding-ada-002".to_string());
-
-    let client = reqwest::Client::new();
-    
-    // Batch texts for efficiency
-    let mut all_embeddings = Vec::new();
-    
-    for chunk in texts.chunks(100) {
-        let request = EmbeddingRequest {
-            input: chunk.iter().map(|s| s.to_string()).collect(),
-            model: model.clone(),
-        };
-
-        let response = client
-            .post("https://api.openai.com/v1/embeddings")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .json(&request)
-            .send()
-            .await?;
-
-        let embedding_response: EmbeddingResponse = response.json().await?;
-        
-        for data in embedding_response.data {
-            all_embeddings.push(data.embedding);
+/// Embeds `texts` with `provider`, looking every text up in `embedding_cache` first
+/// (one query for the whole slice) and only sending the misses to the provider, each
+/// distinct text once. New embeddings are written back. Cache errors are logged and
+/// never fail the call.
+#[instrument(skip_all, fields(model = provider.model_name(), texts = texts.len(), cache_hits = field::Empty))]
+pub async fn get_embeddings_cached(
+    pool: &PgPool,
+    provider: &dyn EmbeddingProvider,
+    texts: &[&str],
+) -> Result<CachedEmbeddings> {
+    let model = cache_model_key(provider);
+    let hashes: Vec<String> = texts.iter().map(|t| calculate_sha256(t.as_bytes())).collect();
+
+    let mut known: HashMap<String, Vec<f32>> = match sqlx::query(
+        "SELECT content_sha256, embedding FROM embedding_cache WHERE model = $1 AND content_sha256 = ANY($2)"
+    )
+    .bind(&model)
+    .bind(&hashes)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|row| (row.get("content_sha256"), row.get::<Vector, _>("embedding").to_vec()))
+            .collect(),
+        Err(e) => {
+            warn!("Embedding cache lookup failed, embedding everything: {}", e);
+            HashMap::new()
+        }
+    };
+    let cached: Vec<bool> = hashes.iter().map(|h| known.contains_key(h)).collect();
+
+    // Embed each missing text once, even if it repeats within the slice
+    let mut missing: Vec<(&str, &str)> = Vec::new();
+    for (text, hash) in texts.iter().zip(&hashes) {
+        if !known.contains_key(hash) && !missing.iter().any(|(h, _)| *h == hash.as_str()) {
+            missing.push((hash, text));
+        }
+    }
+
+    if !missing.is_empty() {
+        let missing_texts: Vec<&str> = missing.iter().map(|(_, text)| *text).collect();
+        let computed = provider.embed(&missing_texts).await?;
+        if computed.len() != missing.len() {
+            return Err(anyhow!(
+                "embedding provider returned {} embeddings for {} texts",
+                computed.len(),
+                missing.len()
+            ));
+        }
+
+        let new_entries: Vec<(&str, &Vec<f32>)> = missing.iter().map(|(hash, _)| *hash).zip(&computed).collect();
+        if let Err(e) = store_cached(pool, &model, &new_entries).await {
+            warn!("Could not write {} embeddings to the cache: {}", new_entries.len(), e);
+        }
+
+        for ((hash, _), embedding) in missing.iter().zip(computed) {
+            known.insert(hash.to_string(), embedding);
         }
     }
 
-    info!("Generated {} embeddings", all_embeddings.len());
-    Ok(all_embeddings)
+    let embeddings = hashes.iter().map(|h| known[h].clone()).collect();
+    let result = CachedEmbeddings { embeddings, cached };
+    info!("Embedding cache: {} hits, {} texts embedded", result.hits(), missing.len());
+    Span::current().record("cache_hits", result.hits());
+    Ok(result)
 }
 
-pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-    
-    if norm_a == 0.0 || norm_b == 0.0 {
-        0.0
-    } else {
-        dot_product / (norm_a * norm_b)
+async fn store_cached(pool: &PgPool, model: &str, entries: &[(&str, &Vec<f32>)]) -> Result<()> {
+    for batch in entries.chunks(CACHE_WRITE_BATCH) {
+        let mut insert: QueryBuilder<Postgres> =
+            QueryBuilder::new("INSERT INTO embedding_cache (content_sha256, model, embedding) ");
+        insert.push_values(batch, |mut row, (hash, embedding)| {
+            row.push_bind(*hash)
+                .push_bind(model)
+                .push_bind(Vector::from((*embedding).clone()));
+        });
+        insert.push(" ON CONFLICT DO NOTHING");
+        insert.build().execute(pool).await?;
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Cache entries are only reusable for the same model at the same output size.
+fn cache_model_key(provider: &dyn EmbeddingProvider) -> String {
+    format!("{}:{}", provider.model_name(), provider.dimensions())
+}
+
+/// Declared width of the `chunks.embedding` column, or `None` when it can't be
+/// determined (unsized column or missing table).
+pub async fn column_dimensions(pool: &PgPool) -> Result<Option<usize>> {
+    // For pgvector columns, atttypmod holds the declared dimension
+    let width = sqlx::query_scalar::<_, i32>(
+        r#"
+        SELECT atttypmod FROM pg_attribute
+        WHERE attrelid = to_regclass('chunks') AND attname = 'embedding'
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(width.filter(|&w| w > 0).map(|w| w as usize))
+}
+
+/// Compares the provider's output size with the `chunks.embedding` column. A
+/// mismatch is an error, since every insert would fail; a column that can't be
+/// read is only logged.
+pub async fn validate_dimensions(pool: &PgPool, provider: &dyn EmbeddingProvider) -> Result<()> {
+    let expected = provider.dimensions();
+    match column_dimensions(pool).await {
+        Ok(Some(width)) if width != expected => Err(anyhow!(
+            "embedding model {} ({}) produces {}-dimensional vectors but chunks.embedding is vector({}); \
+             set EMBEDDING_DIMENSIONS, choose a matching model or migrate the column",
+            provider.model_name(),
+            provider.provider_name(),
+            expected,
+            width
+        )),
+        Ok(Some(_)) => {
+            info!("Embedding dimensions ({}) match the chunks.embedding column", expected);
+            Ok(())
+        }
+        Ok(None) => {
+            info!("Could not determine chunks.embedding width; skipping dimension check");
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to read chunks.embedding width: {}", e);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,842 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use rand::Rng;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{field, info, instrument, warn, Span};
+
+// Input limits of the hosted models; the APIs cut or reject longer texts
+const OPENAI_MAX_INPUT_TOKENS: usize = 8191;
+const COHERE_MAX_INPUT_TOKENS: usize = 512;
+
+/// A source of text embeddings. One provider is built from the environment at
+/// startup (`from_env`) and shared through `AppState`.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embeds documents; the result has one vector per text, in input order.
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+
+    /// Embeds a search query. Providers that distinguish queries from documents
+    /// override this.
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed(&[text])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("embedding provider returned no embedding"))
+    }
+
+    /// Size of the vectors this provider returns.
+    fn dimensions(&self) -> usize;
+
+    /// Model recorded with every document and reported in diagnostics.
+    fn model_name(&self) -> &str;
+
+    /// Short provider id, as accepted by `EMBEDDING_PROVIDER`.
+    fn provider_name(&self) -> &'static str;
+
+    /// Longest input, in the model's own tokens, that is embedded in full; `None`
+    /// when unknown.
+    fn max_input_tokens(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Builds the provider named by `EMBEDDING_PROVIDER` (`openai` by default, `azure`,
+/// `ollama`, `cohere`, or `local` with the `local-embeddings` feature). Missing API
+/// keys only fail when embedding, so the service still starts in health-check-only
+/// setups; a local model that can't be loaded fails startup.
+pub fn from_env() -> Result<Arc<dyn EmbeddingProvider>> {
+    let kind = env::var("EMBEDDING_PROVIDER")
+        .unwrap_or_else(|_| "openai".to_string())
+        .trim()
+        .to_lowercase();
+    let http = HttpSettings::from_env();
+
+    let provider: Arc<dyn EmbeddingProvider> = match kind.as_str() {
+        "openai" => Arc::new(OpenAiProvider::from_env(http)),
+        "azure" | "azure-openai" => Arc::new(AzureOpenAiProvider::from_env(http)?),
+        "ollama" => Arc::new(OllamaProvider::from_env(http)),
+        "cohere" => Arc::new(CohereProvider::from_env(http)),
+        #[cfg(feature = "local-embeddings")]
+        "local" => Arc::new(super::local_embedding::LocalModelProvider::from_env()?),
+        #[cfg(not(feature = "local-embeddings"))]
+        "local" => return Err(anyhow!("EMBEDDING_PROVIDER=local needs a build with the `local-embeddings` feature")),
+        other => {
+            return Err(anyhow!(
+                "unknown EMBEDDING_PROVIDER '{}' (expected openai, azure, ollama, cohere or local)",
+                other
+            ))
+        }
+    };
+
+    info!(
+        "Embedding provider: {} with {} ({} dimensions)",
+        provider.provider_name(),
+        provider.model_name(),
+        provider.dimensions()
+    );
+    Ok(provider)
+}
+
+/// Output dimension set via `EMBEDDING_DIMENSIONS`, if any. Sent to OpenAI and
+/// Azure (text-embedding-3 models only); for other providers it declares the
+/// size of a model this service doesn't know.
+pub fn configured_dimensions() -> Option<usize> {
+    env::var("EMBEDDING_DIMENSIONS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&d| d > 0)
+}
+
+/// Requests in flight at once, from `EMBEDDING_CONCURRENCY` (default 4).
+pub fn configured_concurrency() -> usize {
+    env::var("EMBEDDING_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(4)
+        .max(1)
+}
+
+fn model_from_env(default: &str) -> String {
+    env::var("EMBEDDING_MODEL_NAME").unwrap_or_else(|_| default.to_string())
+}
+
+/// OpenAI API root from `OPENAI_BASE_URL` (default `https://api.openai.com/v1`), for
+/// proxies and compatible servers. Shared by embeddings and chat completions.
+pub fn openai_base_url() -> String {
+    env::var("OPENAI_BASE_URL")
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| "https://api.openai.com/v1".to_string())
+}
+
+/// `requested` (from `EMBEDDING_DIMENSIONS`) if `model` accepts the `dimensions`
+/// parameter. Older models reject it, so it is dropped with a warning instead of
+/// failing every request.
+fn openai_requested_dimensions(model: &str, requested: Option<usize>) -> Option<usize> {
+    let requested = requested?;
+    if model.starts_with("text-embedding-3") {
+        Some(requested)
+    } else {
+        warn!(
+            "EMBEDDING_DIMENSIONS={} ignored: {} does not support the dimensions parameter",
+            requested, model
+        );
+        None
+    }
+}
+
+fn openai_native_dimensions(model: &str) -> usize {
+    match model {
+        "text-embedding-3-large" => 3072,
+        _ => 1536,
+    }
+}
+
+/// Client and request policy shared by every provider.
+#[derive(Debug, Clone)]
+struct HttpSettings {
+    client: reqwest::Client,
+    retry: RetryConfig,
+    concurrency: usize,
+}
+
+impl HttpSettings {
+    fn from_env() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            retry: RetryConfig::from_env(),
+            concurrency: configured_concurrency(),
+        }
+    }
+
+    /// Splits `texts` into batches and sends them `concurrency` at a time. `join_all`
+    /// returns them in input order, so the vectors still line up with `texts`. Every
+    /// batch runs to completion (with its own retries) before the first failure is
+    /// reported. The result is checked against `provider`'s declared dimension.
+    #[instrument(
+        name = "embed",
+        skip_all,
+        fields(
+            provider = provider.provider_name(),
+            model = provider.model_name(),
+            texts = texts.len(),
+            batches = field::Empty,
+            duration_ms = field::Empty,
+        )
+    )]
+    async fn embed_in_batches<'a, F, Fut>(
+        &self,
+        provider: &dyn EmbeddingProvider,
+        texts: &'a [&'a str],
+        batch_size: usize,
+        embed_batch: F,
+    ) -> Result<Vec<Vec<f32>>>
+    where
+        F: Fn(&'a [&'a str]) -> Fut,
+        Fut: Future<Output = Result<Vec<Vec<f32>>>>,
+    {
+        let started = Instant::now();
+        let permits = Semaphore::new(self.concurrency);
+        let batches = join_all(texts.chunks(batch_size).map(|batch| {
+            let request = embed_batch(batch);
+            let permits = &permits;
+            async move {
+                let _permit = permits.acquire().await?;
+                request.await
+            }
+        }))
+        .await;
+
+        let batch_count = batches.len();
+        let span = Span::current();
+        span.record("batches", batch_count);
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        let mut all_embeddings = Vec::with_capacity(texts.len());
+        for batch in batches {
+            all_embeddings.extend(batch?);
+        }
+
+        check_output(provider, texts.len(), &all_embeddings)?;
+        info!(
+            "Generated {} embeddings in {} batches ({} concurrent) in {} ms",
+            all_embeddings.len(),
+            batch_count,
+            self.concurrency,
+            started.elapsed().as_millis()
+        );
+        Ok(all_embeddings)
+    }
+}
+
+// Vectors of the wrong size would otherwise fail deep inside the insert
+fn check_output(provider: &dyn EmbeddingProvider, expected_count: usize, embeddings: &[Vec<f32>]) -> Result<()> {
+    if embeddings.len() != expected_count {
+        return Err(anyhow!(
+            "embedding model {} returned {} embeddings for {} texts",
+            provider.model_name(),
+            embeddings.len(),
+            expected_count
+        ));
+    }
+
+    let expected = provider.dimensions();
+    if let Some(wrong) = embeddings.iter().find(|e| e.len() != expected) {
+        return Err(anyhow!(
+            "embedding model {} returned a {}-dimensional embedding, expected {}; \
+             check EMBEDDING_MODEL_NAME and EMBEDDING_DIMENSIONS",
+            provider.model_name(),
+            wrong.len(),
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// Retry policy for embedding requests.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    /// Total tries per batch, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Per-request timeout; a timed out request is retried
+    pub timeout: Duration,
+}
+
+impl RetryConfig {
+    /// Reads `EMBEDDING_MAX_ATTEMPTS` (default 4), `EMBEDDING_RETRY_BASE_MS` (default 500),
+    /// `EMBEDDING_RETRY_MAX_MS` (default 30000) and `EMBEDDING_TIMEOUT_SECS` (default 60).
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            max_attempts: var("EMBEDDING_MAX_ATTEMPTS", 4).max(1) as u32,
+            base_delay: Duration::from_millis(var("EMBEDDING_RETRY_BASE_MS", 500)),
+            max_delay: Duration::from_millis(var("EMBEDDING_RETRY_MAX_MS", 30_000)),
+            timeout: Duration::from_secs(var("EMBEDDING_TIMEOUT_SECS", 60).max(1)),
+        }
+    }
+
+    /// Exponential backoff with jitter: somewhere between half and all of
+    /// `base_delay * 2^(attempt - 1)`, capped at `max_delay`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(1 << (attempt - 1).min(16));
+        let capped = exponential.min(self.max_delay);
+        capped.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+// Sends the request built by `request` until it succeeds. 429s, 5xx, timeouts and
+// connection errors are retried (honouring `Retry-After` when the provider sends one);
+// any other status fails immediately with the provider's error body, since retrying a
+// bad key or request can't succeed.
+async fn send_with_retries(
+    retry: &RetryConfig,
+    request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let outcome = request().timeout(retry.timeout).send().await;
+
+        let (failure, retry_after) = match outcome {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let status = response.status();
+                let retry_after = retry_after(&response);
+                let body = response.text().await.unwrap_or_default();
+                let failure = anyhow!("embedding request failed with {}: {}", status, body.trim());
+                if !is_retryable_status(status) {
+                    return Err(failure.context("non-retryable embedding error"));
+                }
+                (failure, retry_after)
+            }
+            Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => {
+                (anyhow!(e).context("embedding request did not complete"), None)
+            }
+            Err(e) => return Err(anyhow!(e).context("non-retryable embedding error")),
+        };
+
+        if attempt >= retry.max_attempts {
+            return Err(failure.context(format!(
+                "retryable embedding error, giving up after {} attempts",
+                attempt
+            )));
+        }
+
+        let delay = retry_after.unwrap_or_else(|| retry.backoff(attempt)).min(retry.max_delay);
+        warn!(
+            "Embedding attempt {}/{} failed ({:#}), retrying in {:?}",
+            attempt, retry.max_attempts, failure, delay
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// `Retry-After` in whole seconds; the HTTP-date form isn't used by these providers
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+async fn read_json<T: for<'de> Deserialize<'de>>(response: reqwest::Response) -> Result<T> {
+    response
+        .json::<T>()
+        .await
+        .context("embedding provider returned an unreadable response")
+}
+
+// OpenAI and Azure OpenAI share the request and response shapes
+
+#[derive(Debug, Serialize)]
+struct OpenAiRequest<'a> {
+    input: &'a [&'a str],
+    // Azure picks the model from the deployment in the URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    // Only supported by text-embedding-3 models, so omitted for anything else
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbedding {
+    embedding: Vec<f32>,
+}
+
+/// api.openai.com (or `OPENAI_BASE_URL`), configured with `OPENAI_API_KEY` and
+/// `EMBEDDING_MODEL_NAME`.
+pub struct OpenAiProvider {
+    http: HttpSettings,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    requested_dimensions: Option<usize>,
+}
+
+impl OpenAiProvider {
+    fn from_env(http: HttpSettings) -> Self {
+        let model = model_from_env("text-embedding-ada-002");
+        Self {
+            http,
+            base_url: openai_base_url(),
+            api_key: env::var("OPENAI_API_KEY").ok(),
+            requested_dimensions: openai_requested_dimensions(&model, configured_dimensions()),
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let api_key = self.api_key.as_deref().ok_or_else(|| anyhow!("OPENAI_API_KEY is not set"))?;
+
+        self.http
+            .embed_in_batches(self, texts, 100, |batch| async move {
+                let request = OpenAiRequest {
+                    input: batch,
+                    model: Some(&self.model),
+                    dimensions: self.requested_dimensions,
+                };
+                let url = format!("{}/embeddings", self.base_url);
+                let response = send_with_retries(&self.http.retry, || {
+                    self.http
+                        .client
+                        .post(&url)
+                        .bearer_auth(api_key)
+                        .json(&request)
+                })
+                .await?;
+                let parsed: OpenAiResponse = read_json(response).await?;
+                Ok(parsed.data.into_iter().map(|d| d.embedding).collect())
+            })
+            .await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.requested_dimensions
+            .unwrap_or_else(|| openai_native_dimensions(&self.model))
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        Some(OPENAI_MAX_INPUT_TOKENS)
+    }
+}
+
+/// Azure OpenAI deployment, addressed as
+/// `{AZURE_OPENAI_ENDPOINT}/openai/deployments/{AZURE_OPENAI_DEPLOYMENT}/embeddings`
+/// and authenticated with the `api-key` header. `EMBEDDING_MODEL_NAME` names the
+/// model behind the deployment.
+pub struct AzureOpenAiProvider {
+    http: HttpSettings,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+    requested_dimensions: Option<usize>,
+}
+
+impl AzureOpenAiProvider {
+    /// Reads `AZURE_OPENAI_ENDPOINT`, `AZURE_OPENAI_DEPLOYMENT` (both required),
+    /// `AZURE_OPENAI_API_KEY` and `AZURE_OPENAI_API_VERSION` (default `2024-02-01`).
+    fn from_env(http: HttpSettings) -> Result<Self> {
+        let endpoint = env::var("AZURE_OPENAI_ENDPOINT")
+            .map_err(|_| anyhow!("AZURE_OPENAI_ENDPOINT must be set for EMBEDDING_PROVIDER=azure"))?;
+        let deployment = env::var("AZURE_OPENAI_DEPLOYMENT")
+            .map_err(|_| anyhow!("AZURE_OPENAI_DEPLOYMENT must be set for EMBEDDING_PROVIDER=azure"))?;
+        let api_version = env::var("AZURE_OPENAI_API_VERSION").unwrap_or_else(|_| "2024-02-01".to_string());
+        let model = model_from_env("text-embedding-ada-002");
+
+        Ok(Self {
+            http,
+            url: format!(
+                "{}/openai/deployments/{}/embeddings?api-version={}",
+                endpoint.trim_end_matches('/'),
+                deployment,
+                api_version
+            ),
+            api_key: env::var("AZURE_OPENAI_API_KEY").ok(),
+            requested_dimensions: openai_requested_dimensions(&model, configured_dimensions()),
+            model,
+        })
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for AzureOpenAiProvider {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let api_key = self
+            .api_key
+            .as_deref()
+            .ok_or_else(|| anyhow!("AZURE_OPENAI_API_KEY is not set"))?;
+
+        self.http
+            .embed_in_batches(self, texts, 100, |batch| async move {
+                let request = OpenAiRequest {
+                    input: batch,
+                    model: None,
+                    dimensions: self.requested_dimensions,
+                };
+                let response = send_with_retries(&self.http.retry, || {
+                    self.http.client.post(&self.url).header("api-key", api_key).json(&request)
+                })
+                .await?;
+                let parsed: OpenAiResponse = read_json(response).await?;
+                Ok(parsed.data.into_iter().map(|d| d.embedding).collect())
+            })
+            .await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.requested_dimensions
+            .unwrap_or_else(|| openai_native_dimensions(&self.model))
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        Some(OPENAI_MAX_INPUT_TOKENS)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    embedding: Vec<f32>,
+}
+
+/// Local Ollama server at `OLLAMA_URL` (default `http://localhost:11434`), using
+/// `EMBEDDING_MODEL_NAME` (default `nomic-embed-text`).
+pub struct OllamaProvider {
+    http: HttpSettings,
+    base_url: String,
+    model: String,
+    dimensions: usize,
+}
+
+impl OllamaProvider {
+    fn from_env(http: HttpSettings) -> Self {
+        let model = model_from_env("nomic-embed-text");
+        // Native sizes of the common embedding models; others need EMBEDDING_DIMENSIONS
+        let dimensions = configured_dimensions().unwrap_or_else(|| {
+            match model.split(':').next().unwrap_or_default() {
+                "mxbai-embed-large" | "snowflake-arctic-embed" => 1024,
+                "all-minilm" => 384,
+                _ => 768,
+            }
+        });
+
+        Self {
+            http,
+            base_url: env::var("OLLAMA_URL")
+                .unwrap_or_else(|_| "http://localhost:11434".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            model,
+            dimensions,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaProvider {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/api/embeddings", self.base_url);
+
+        // `/api/embeddings` takes one prompt per call
+        self.http
+            .embed_in_batches(self, texts, 1, |batch| {
+                let url = &url;
+                async move {
+                    let mut embeddings = Vec::with_capacity(batch.len());
+                    for prompt in batch {
+                        let request = OllamaRequest { model: &self.model, prompt };
+                        let response =
+                            send_with_retries(&self.http.retry, || self.http.client.post(url).json(&request)).await?;
+                        let parsed: OllamaResponse = read_json(response).await?;
+                        embeddings.push(parsed.embedding);
+                    }
+                    Ok(embeddings)
+                }
+            })
+            .await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "ollama"
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CohereRequest<'a> {
+    model: &'a str,
+    texts: &'a [&'a str],
+    input_type: &'a str,
+    embedding_types: [&'a str; 1],
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereResponse {
+    embeddings: CohereEmbeddings,
+}
+
+// Only the float variant is requested; int8 and binary would appear as sibling fields
+#[derive(Debug, Deserialize)]
+struct CohereEmbeddings {
+    float: Vec<Vec<f32>>,
+}
+
+/// Cohere `/v1/embed` with `COHERE_API_KEY` and `EMBEDDING_MODEL_NAME` (default
+/// `embed-english-v3.0`). Documents and queries are embedded with their own
+/// `input_type`, as the v3 models expect.
+pub struct CohereProvider {
+    http: HttpSettings,
+    api_key: Option<String>,
+    model: String,
+    dimensions: usize,
+}
+
+impl CohereProvider {
+    fn from_env(http: HttpSettings) -> Self {
+        let model = model_from_env("embed-english-v3.0");
+        let dimensions = configured_dimensions()
+            .unwrap_or(if model.contains("light") { 384 } else { 1024 });
+
+        Self {
+            http,
+            api_key: env::var("COHERE_API_KEY").ok(),
+            model,
+            dimensions,
+        }
+    }
+
+    async fn embed_as(&self, texts: &[&str], input_type: &str) -> Result<Vec<Vec<f32>>> {
+        let api_key = self.api_key.as_deref().ok_or_else(|| anyhow!("COHERE_API_KEY is not set"))?;
+
+        // Cohere accepts at most 96 texts per call
+        self.http
+            .embed_in_batches(self, texts, 96, |batch| async move {
+                let request = CohereRequest {
+                    model: &self.model,
+                    texts: batch,
+                    input_type,
+                    embedding_types: ["float"],
+                };
+                let response = send_with_retries(&self.http.retry, || {
+                    self.http
+                        .client
+                        .post("https://api.cohere.com/v1/embed")
+                        .bearer_auth(api_key)
+                        .json(&request)
+                })
+                .await?;
+                let parsed: CohereResponse = read_json(response).await?;
+                Ok(parsed.embeddings.float)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for CohereProvider {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.embed_as(texts, "search_document").await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_as(&[text], "search_query")
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("embedding provider returned no embedding"))
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "cohere"
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        Some(COHERE_MAX_INPUT_TOKENS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use wiremock::matchers::{header as header_is, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn retry(max_attempts: u32) -> RetryConfig {
+        RetryConfig {
+            max_attempts,
+            // Backoff alone would retry within milliseconds
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(5),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    async fn send(server: &MockServer, retry: &RetryConfig) -> Result<reqwest::Response> {
+        let client = reqwest::Client::new();
+        let url = format!("{}/v1/embeddings", server.uri());
+        send_with_retries(retry, || client.post(&url).body("{}")).await
+    }
+
+    #[tokio::test]
+    async fn retries_a_429_after_the_retry_after_delay() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let started = Instant::now();
+        let response = send(&server, &retry(3)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        assert!(
+            started.elapsed() >= Duration::from_secs(1),
+            "retried after {:?}, before Retry-After elapsed",
+            started.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("overloaded"))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let error = send(&server, &retry(3)).await.unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("giving up after 3 attempts"), "{}", message);
+        assert!(message.contains("overloaded"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_401() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("invalid api key"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let error = send(&server, &retry(3)).await.unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.starts_with("non-retryable embedding error"), "{}", message);
+        assert!(message.contains("invalid api key"), "{}", message);
+    }
+
+    /// Embeds one text with an OpenAI provider for `model` pointed at a mock server,
+    /// returning the provider's dimensions and the JSON body it sent.
+    async fn openai_request(model: &str, configured: Option<usize>) -> (usize, Value) {
+        let server = MockServer::start().await;
+        let provider = OpenAiProvider {
+            http: HttpSettings {
+                client: reqwest::Client::new(),
+                retry: retry(1),
+                concurrency: 1,
+            },
+            base_url: format!("{}/v1", server.uri()),
+            api_key: Some("sk-test".to_string()),
+            requested_dimensions: openai_requested_dimensions(model, configured),
+            model: model.to_string(),
+        };
+        let embedding = vec![0.5; provider.dimensions()];
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(header_is("authorization", "Bearer sk-test"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "data": [{ "embedding": embedding }] })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let embeddings = provider.embed(&["hello"]).await.unwrap();
+        assert_eq!(embeddings[0].len(), provider.dimensions());
+        let requests = server.received_requests().await.unwrap();
+        (provider.dimensions(), requests[0].body_json().unwrap())
+    }
+
+    #[tokio::test]
+    async fn sends_dimensions_for_text_embedding_3_models() {
+        let (dimensions, body) = openai_request("text-embedding-3-small", Some(512)).await;
+        assert_eq!(dimensions, 512);
+        assert_eq!(body["model"], "text-embedding-3-small");
+        assert_eq!(body["dimensions"], 512);
+        assert_eq!(body["input"], json!(["hello"]));
+    }
+
+    #[tokio::test]
+    async fn omits_dimensions_for_ada_002() {
+        let (dimensions, body) = openai_request("text-embedding-ada-002", Some(512)).await;
+        assert_eq!(dimensions, 1536);
+        assert_eq!(body["model"], "text-embedding-ada-002");
+        assert!(body.get("dimensions").is_none(), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn omits_dimensions_when_none_are_configured() {
+        let (dimensions, body) = openai_request("text-embedding-3-large", None).await;
+        assert_eq!(dimensions, 3072);
+        assert!(body.get("dimensions").is_none(), "{}", body);
+    }
+}
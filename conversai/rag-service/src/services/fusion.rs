@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::services::retrieval::score_order;
+
+/// How several ranked result lists are combined into one. Each list holds
+/// `(id, score)` ordered best first; an id missing from a list gets no
+/// contribution from it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FusionStrategy {
+    /// Reciprocal Rank Fusion: sum of `1 / (k + rank)` with 1-based ranks
+    Rrf { k: f32 },
+    /// Sum of `weight * score` per list; lists without a weight count as 1.0
+    WeightedSum { weights: Vec<f32> },
+    /// Highest score any list gave the id
+    Max,
+}
+
+pub fn fuse(result_lists: &[Vec<(Uuid, f32)>], strategy: &FusionStrategy) -> Vec<(Uuid, f32)> {
+    match strategy {
+        FusionStrategy::Rrf { k } => rrf(result_lists, *k),
+        FusionStrategy::WeightedSum { weights } => weighted_sum(result_lists, weights),
+        FusionStrategy::Max => max(result_lists),
+    }
+}
+
+/// Scores depend only on positions, so lists on different scales fuse cleanly.
+pub fn rrf(result_lists: &[Vec<(Uuid, f32)>], k: f32) -> Vec<(Uuid, f32)> {
+    combine(result_lists, |_, rank, _| 1.0 / (k + rank as f32), |total, score| total + score)
+}
+
+pub fn weighted_sum(result_lists: &[Vec<(Uuid, f32)>], weights: &[f32]) -> Vec<(Uuid, f32)> {
+    combine(
+        result_lists,
+        |list, _, score| weights.get(list).copied().unwrap_or(1.0) * score,
+        |total, score| total + score,
+    )
+}
+
+pub fn max(result_lists: &[Vec<(Uuid, f32)>]) -> Vec<(Uuid, f32)> {
+    combine(result_lists, |_, _, score| score, f32::max)
+}
+
+// Folds per-list contributions by id, then sorts best first with ties broken by
+// id. `contribution` gets the list index, 1-based rank and the original score.
+fn combine(
+    result_lists: &[Vec<(Uuid, f32)>],
+    contribution: impl Fn(usize, usize, f32) -> f32,
+    merge: impl Fn(f32, f32) -> f32,
+) -> Vec<(Uuid, f32)> {
+    let mut fused: HashMap<Uuid, f32> = HashMap::new();
+    for (list, results) in result_lists.iter().enumerate() {
+        for (position, &(id, score)) in results.iter().enumerate() {
+            let value = contribution(list, position + 1, score);
+            fused
+                .entry(id)
+                .and_modify(|total| *total = merge(*total, value))
+                .or_insert(value);
+        }
+    }
+
+    let mut fused: Vec<(Uuid, f32)> = fused.into_iter().collect();
+    fused.sort_by(|a, b| score_order(b.1, a.1).then_with(|| a.0.cmp(&b.0)));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    fn ids(fused: &[(Uuid, f32)]) -> Vec<u128> {
+        fused.iter().map(|(id, _)| id.as_u128()).collect()
+    }
+
+    #[test]
+    fn rrf_sums_reciprocal_ranks() {
+        let semantic = vec![(id(1), 0.9), (id(2), 0.8), (id(3), 0.7)];
+        let lexical = vec![(id(3), 12.0), (id(1), 4.0)];
+
+        let fused = rrf(&[semantic, lexical], 60.0);
+
+        // 1: 1/61 + 1/62, 3: 1/63 + 1/61, 2: 1/62 only
+        assert_eq!(ids(&fused), [1, 3, 2]);
+        let expected = [1.0 / 61.0 + 1.0 / 62.0, 1.0 / 63.0 + 1.0 / 61.0, 1.0 / 62.0];
+        for ((_, score), expected) in fused.iter().zip(expected) {
+            assert!((score - expected).abs() < 1e-6, "{} vs {}", score, expected);
+        }
+    }
+
+    #[test]
+    fn rrf_and_weighted_sum_can_disagree() {
+        // 2 is good in both lists; 3 is first lexically but nearly irrelevant semantically
+        let semantic = vec![(id(1), 0.95), (id(2), 0.90), (id(3), 0.10)];
+        let lexical = vec![(id(3), 0.99), (id(2), 0.20)];
+        let lists = [semantic, lexical];
+
+        // Weighted: 2 = 0.55, 3 = 0.545, 1 = 0.475
+        let weighted = weighted_sum(&lists, &[0.5, 0.5]);
+        assert_eq!(ids(&weighted), [2, 3, 1]);
+
+        // RRF only sees ranks: 3 = 1/63 + 1/61 beats 2 = 1/62 + 1/62
+        let reciprocal = rrf(&lists, 60.0);
+        assert_eq!(ids(&reciprocal), [3, 2, 1]);
+    }
+}
@@ -0,0 +1,862 @@
+use anyhow::anyhow;
+use chrono::Utc;
+use pgvector::Vector;
+use serde_json::json;
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use std::borrow::Cow;
+use std::env;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::{Document, IngestResponse};
+use crate::services::embedding_provider::{self, EmbeddingProvider};
+use crate::services::{chunking, csv, docx, embedding, jobs, markdown, pdf, summarize};
+use crate::utils::calculate_sha256;
+
+// Chunks per embedding request; each ingest step sends `EMBEDDING_CONCURRENCY` of
+// these at once and reports progress after storing them
+const INGEST_BATCH_SIZE: usize = 100;
+
+// Chunk rows per INSERT statement; 8 parameters each, far below Postgres' 65535 limit
+const INSERT_BATCH_SIZE: usize = 100;
+
+// Default token bounds for every chunk, whatever the format; overridable with
+// CHUNK_MAX_TOKENS and CHUNK_OVERLAP_TOKENS, and per request
+const CHUNK_MAX_TOKENS: usize = 500;
+const CHUNK_OVERLAP_TOKENS: usize = 50;
+// Smallest chunk size allowed; tinier chunks carry too little meaning to retrieve
+const MIN_CHUNK_TOKENS: usize = 32;
+
+// Markdown and HTML with more of their text in code blocks than this get a warning
+const MOSTLY_CODE_SHARE: f32 = 0.5;
+// Sections named in a single warning before the rest are only counted
+const MAX_WARNED_SECTIONS: usize = 5;
+
+/// Token bounds chunks are cut to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSettings {
+    /// Chunk size ceiling; longer sections are cut into windows of this size
+    pub max_tokens: usize,
+    /// Tokens each window repeats from the end of the previous one
+    pub overlap_tokens: usize,
+    /// Give each markdown code block a section of its own (`CHUNK_SPLIT_CODE_BLOCKS`)
+    pub split_code_blocks: bool,
+    /// Start chunks with their heading path (`CHUNK_HEADING_PREFIX`)
+    pub heading_prefix: bool,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        Self {
+            max_tokens: CHUNK_MAX_TOKENS,
+            overlap_tokens: CHUNK_OVERLAP_TOKENS,
+            split_code_blocks: false,
+            heading_prefix: true,
+        }
+    }
+}
+
+impl ChunkSettings {
+    /// Defaults from `CHUNK_MAX_TOKENS` and `CHUNK_OVERLAP_TOKENS`, checked like
+    /// per-request overrides so a bad value fails startup. Without `CHUNK_MAX_TOKENS`
+    /// the size is capped at the model's input limit.
+    pub fn from_env(embedder: &dyn EmbeddingProvider) -> anyhow::Result<Self> {
+        let read = |name: &str| -> anyhow::Result<Option<usize>> {
+            match env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) {
+                None => Ok(None),
+                Some(value) => value
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| anyhow!("{} must be a non-negative integer, got {:?}", name, value)),
+            }
+        };
+        let max_tokens = read("CHUNK_MAX_TOKENS")?;
+        let overlap_tokens = read("CHUNK_OVERLAP_TOKENS")?;
+
+        let mut defaults = Self {
+            split_code_blocks: env::var("CHUNK_SPLIT_CODE_BLOCKS")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            heading_prefix: env::var("CHUNK_HEADING_PREFIX")
+                .map(|v| !(v == "0" || v.eq_ignore_ascii_case("false")))
+                .unwrap_or(true),
+            ..Self::default()
+        };
+        if let Some(limit) = embedder.max_input_tokens() {
+            defaults.max_tokens = defaults.max_tokens.min(limit);
+        }
+        let settings = defaults
+            .with_overrides(max_tokens, overlap_tokens, embedder)
+            .map_err(|e| anyhow!("invalid chunk settings: {}", e))?;
+        info!(
+            "Chunking at {} tokens with {} overlap{}",
+            settings.max_tokens,
+            settings.overlap_tokens,
+            if settings.split_code_blocks { ", code blocks in their own sections" } else { "" }
+        );
+        Ok(settings)
+    }
+
+    /// These settings with `max_tokens`/`overlap_tokens` replaced where given. Fails
+    /// with a message when the size is below `MIN_CHUNK_TOKENS` or past the model's
+    /// input limit, or the overlap isn't smaller than the size.
+    pub fn with_overrides(
+        self,
+        max_tokens: Option<usize>,
+        overlap_tokens: Option<usize>,
+        embedder: &dyn EmbeddingProvider,
+    ) -> Result<Self, String> {
+        let settings = Self {
+            max_tokens: max_tokens.unwrap_or(self.max_tokens),
+            overlap_tokens: overlap_tokens.unwrap_or(self.overlap_tokens),
+            ..self
+        };
+
+        if settings.max_tokens < MIN_CHUNK_TOKENS {
+            return Err(format!(
+                "max_tokens must be at least {}, got {}",
+                MIN_CHUNK_TOKENS, settings.max_tokens
+            ));
+        }
+        if let Some(limit) = embedder.max_input_tokens() {
+            if settings.max_tokens > limit {
+                return Err(format!(
+                    "max_tokens must be at most {}, the input limit of {}, got {}",
+                    limit,
+                    embedder.model_name(),
+                    settings.max_tokens
+                ));
+            }
+        }
+        if settings.overlap_tokens >= settings.max_tokens {
+            return Err(format!(
+                "overlap_tokens ({}) must be smaller than max_tokens ({})",
+                settings.overlap_tokens, settings.max_tokens
+            ));
+        }
+        Ok(settings)
+    }
+
+    /// Recorded in document and chunk metadata, so re-chunking knows what was applied
+    fn metadata(&self) -> serde_json::Value {
+        json!({
+            "tokenizer": chunking::TOKENIZER,
+            "max_tokens": self.max_tokens,
+            "overlap_tokens": self.overlap_tokens,
+            "split_code_blocks": self.split_code_blocks,
+            "heading_prefix": self.heading_prefix,
+        })
+    }
+}
+
+/// How a document's bytes are turned into sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Markdown,
+    /// No headings; split into paragraphs and sentences
+    Text,
+    /// Converted to markdown first, keeping its headings
+    Html,
+    /// Text extracted per page; chunks record their page
+    Pdf,
+    /// Paragraph text, split at Heading 1–6 styles
+    Docx,
+    /// Windows of rows under the header row; chunks record their row range
+    Csv,
+}
+
+impl DocumentFormat {
+    /// Recognizes PDF and HTML by their content first, then by extension (`.pdf`,
+    /// `.docx`, `.csv`, `.html`/`.htm`/`.xhtml`, `.txt`/`.text`); anything else is
+    /// read as markdown.
+    pub fn detect(filename: &str, data: &[u8]) -> Self {
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+        if pdf::is_pdf(data) {
+            return DocumentFormat::Pdf;
+        }
+        if looks_like_html(data) {
+            return DocumentFormat::Html;
+        }
+        match extension.as_deref() {
+            Some("pdf") => DocumentFormat::Pdf,
+            Some("docx") => DocumentFormat::Docx,
+            Some("csv") => DocumentFormat::Csv,
+            Some("html" | "htm" | "xhtml") => DocumentFormat::Html,
+            Some("txt" | "text") => DocumentFormat::Text,
+            _ => DocumentFormat::Markdown,
+        }
+    }
+
+    /// Stored as `source_type` for uploads and as `metadata.format`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DocumentFormat::Markdown => "md",
+            DocumentFormat::Text => "txt",
+            DocumentFormat::Html => "html",
+            DocumentFormat::Pdf => "pdf",
+            DocumentFormat::Docx => "docx",
+            DocumentFormat::Csv => "csv",
+        }
+    }
+}
+
+/// True when the data opens with a doctype or `<html>` tag, after any BOM and whitespace.
+fn looks_like_html(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(512)]).to_ascii_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with("<!doctype html") || head.starts_with("<html")
+}
+
+/// Where a document came from, stored as its `source_type` and `source_uri`.
+pub struct DocumentSource {
+    pub source_type: &'static str,
+    pub uri: String,
+    pub format: DocumentFormat,
+    /// Media type the content was served with, for fetched URLs
+    pub content_type: Option<String>,
+    /// Identity across versions; new content under a known key supersedes its
+    /// current version instead of becoming an unrelated document
+    pub key: Option<String>,
+    /// `content_sha256` of the content when the caller already hashed it, as
+    /// streamed uploads are
+    pub sha256: Option<String>,
+    /// An upload whose extension wasn't recognized, read as markdown
+    pub assumed_markdown: bool,
+}
+
+impl DocumentSource {
+    /// An uploaded file, typed by its content and name
+    pub fn upload(filename: &str, data: &[u8]) -> Self {
+        let format = DocumentFormat::detect(filename, data);
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+        // Upload to Supabase Storage (placeholder for now)
+        Self {
+            source_type: format.as_str(),
+            uri: format!("storage://{}", filename),
+            format,
+            content_type: None,
+            key: None,
+            sha256: None,
+            assumed_markdown: format == DocumentFormat::Markdown
+                && !matches!(extension.as_deref(), Some("md" | "markdown")),
+        }
+    }
+
+    /// A fetched page, already converted to markdown by the fetcher
+    pub fn url(url: &str, content_type: &str) -> Self {
+        Self {
+            source_type: "url",
+            uri: url.to_string(),
+            format: DocumentFormat::Markdown,
+            content_type: Some(content_type.to_string()),
+            key: None,
+            sha256: None,
+            assumed_markdown: false,
+        }
+    }
+
+    /// Markdown supplied directly, identified by a caller-chosen URI
+    pub fn text(uri: &str) -> Self {
+        Self {
+            source_type: "md",
+            uri: uri.to_string(),
+            format: DocumentFormat::Markdown,
+            content_type: None,
+            key: None,
+            sha256: None,
+            assumed_markdown: false,
+        }
+    }
+
+    /// Versions the document under `key`; blank keys are ignored
+    pub fn with_key(mut self, key: Option<&str>) -> Self {
+        self.key = key.map(str::trim).filter(|key| !key.is_empty()).map(String::from);
+        self
+    }
+
+    /// Skips hashing the content again in `ingest_document`
+    pub fn with_sha256(mut self, sha256: String) -> Self {
+        self.sha256 = Some(sha256);
+        self
+    }
+
+    /// Stored as the document's `metadata`
+    fn metadata(&self) -> serde_json::Value {
+        let mut metadata = json!({ "format": self.format.as_str() });
+        if let Some(content_type) = &self.content_type {
+            metadata["content_type"] = json!(content_type);
+        }
+        metadata
+    }
+}
+
+/// What happens to the previous version's chunks when a new version is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supersede {
+    /// Keep them; searches skip them unless `include_superseded` is set
+    Mark,
+    /// Delete them, keeping only the document row
+    Delete,
+}
+
+/// Reads `INGEST_SUPERSEDE` (`mark` or `delete`; default mark). Unknown values
+/// fall back to mark with a warning.
+pub fn supersede_mode() -> Supersede {
+    match env::var("INGEST_SUPERSEDE").ok().as_deref().map(str::trim) {
+        None | Some("") | Some("mark") => Supersede::Mark,
+        Some("delete") => Supersede::Delete,
+        Some(other) => {
+            warn!("Unknown INGEST_SUPERSEDE {:?}, using mark", other);
+            Supersede::Mark
+        }
+    }
+}
+
+/// A document's chunks, plus anything about the extraction worth telling the caller.
+pub struct PreparedDocument {
+    pub chunks: Vec<chunking::Chunk>,
+    pub warnings: Vec<String>,
+    /// The bounds the chunks were cut to
+    pub settings: ChunkSettings,
+}
+
+/// Why a file couldn't be read as its detected format.
+#[derive(Debug, Error)]
+pub enum DocumentError {
+    /// Damaged, truncated or not actually in that format
+    #[error("{0}")]
+    Corrupt(String),
+
+    #[error("{0}")]
+    Encrypted(String),
+}
+
+/// Why `ingest_document` failed. Embedding failures are kept apart so handlers
+/// can report the provider as the culprit.
+#[derive(Debug, Error)]
+pub enum IngestError {
+    #[error("embedding provider request failed: {0}")]
+    Embedding(anyhow::Error),
+
+    #[error("invalid {} document: {error}", format.as_str())]
+    InvalidDocument { format: DocumentFormat, error: DocumentError },
+
+    /// Another ingest stored this version of the key first
+    #[error("version {version} of {key:?} already exists")]
+    VersionConflict { key: String, version: i32 },
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Dedup, chunk, embed and store one document. All chunks are embedded before the
+/// document and its chunks are inserted in one transaction, so a failure at any
+/// point stores nothing. When `job_id` is set, progress is written to the job row
+/// after every batch of embeddings.
+///
+/// Without a key, content already stored anywhere returns that document. With one,
+/// only the key's latest version counts as a duplicate; other content is stored as
+/// the next version, and the older versions are superseded as `INGEST_SUPERSEDE`
+/// says in the same transaction.
+pub async fn ingest_document(
+    pool: &PgPool,
+    embedder: &dyn EmbeddingProvider,
+    source: &DocumentSource,
+    data: &[u8],
+    tags: &[String],
+    settings: ChunkSettings,
+    job_id: Option<Uuid>,
+) -> Result<IngestResponse, IngestError> {
+    let sha256 = source.sha256.clone().unwrap_or_else(|| calculate_sha256(data));
+
+    // Check if document already exists
+    let (existing, previous) = match &source.key {
+        Some(key) => match find_latest_version(pool, key).await? {
+            Some(latest) if latest.content_sha256 == sha256 => (Some(latest), None),
+            latest => (None, latest),
+        },
+        None => (find_document_by_sha(pool, &sha256).await?, None),
+    };
+    let deduplicated = existing.is_some();
+
+    let mut embeddings_cached = 0;
+    let mut warnings = Vec::new();
+    let document_id = if let Some(doc) = existing {
+        info!("Document already exists with ID: {}", doc.id);
+        warnings.push(duplicate_warning(doc.id));
+        doc.id
+    } else {
+        // Extract first so an unreadable file doesn't leave an empty document row behind
+        let prepared = prepare_document(source, data, settings).await?;
+        warnings = prepared.warnings;
+        let chunks = prepared.chunks;
+        warnings.extend(input_limit_warning(embedder, &chunks));
+
+        // Embed everything before writing anything, so a failed embedding call
+        // leaves no trace and a retry isn't mistaken for a duplicate
+        let total = chunks.len();
+        let step = INGEST_BATCH_SIZE * embedding_provider::configured_concurrency();
+        let mut embedding_time = Duration::ZERO;
+        let mut embeddings = Vec::with_capacity(total);
+
+        for (step_index, batch) in chunks.chunks(step).enumerate() {
+            if let Some(job_id) = job_id {
+                jobs::set_progress(pool, job_id, step_index * step, total).await?;
+            }
+
+            let texts: Vec<&str> = batch.iter().map(|c| c.content.as_str()).collect();
+            let started = Instant::now();
+            let embedded = embedding::get_embeddings_cached(pool, embedder, &texts).await
+                .map_err(IngestError::Embedding)?;
+            embedding_time += started.elapsed();
+            embeddings_cached += embedded.hits();
+            embeddings.extend(embedded.embeddings);
+        }
+        let summary = summarize::prepare_summary(pool, embedder, &chunks, &mut warnings).await?;
+
+        // The document, its chunks and the superseding of older versions commit
+        // together; any error rolls all of them back
+        let version = previous.as_ref().map_or(1, |doc| doc.document_version + 1);
+        let mut tx = pool.begin().await.map_err(anyhow::Error::from)?;
+        let doc = insert_document(&mut tx, source, &sha256, version, tags, embedder.model_name(), settings)
+            .await
+            .map_err(|e| version_conflict(e, source, version))?;
+        if let Err(e) = insert_chunks(&mut tx, doc.id, &chunks, 0, &embeddings, embedder.model_name(), settings).await {
+            error!("Failed to insert chunk: {}", e);
+            return Err(e.into());
+        }
+        if let Some(summary) = &summary {
+            summarize::store_summary(&mut tx, doc.id, summary, embedder.model_name()).await?;
+        }
+        if let (Some(key), Some(_)) = (&source.key, &previous) {
+            let superseded = supersede_versions(&mut tx, key, doc.id, supersede_mode()).await?;
+            info!("Document {} is version {} of {:?}, superseding {:?}", doc.id, version, key, superseded);
+        }
+        tx.commit().await.map_err(anyhow::Error::from)?;
+
+        info!(
+            "Ingested document {} with {} chunks (embedding took {} ms)",
+            doc.id,
+            total,
+            embedding_time.as_millis()
+        );
+        doc.id
+    };
+
+    let mut response = build_response(pool, document_id).await?;
+    response.embeddings_cached = embeddings_cached;
+    response.deduplicated = deduplicated;
+    response.warnings.extend(warnings);
+    Ok(response)
+}
+
+/// Turns a violation of `documents_key_version_idx` (migration 017) into
+/// `VersionConflict`; other errors pass through.
+fn version_conflict(error: anyhow::Error, source: &DocumentSource, version: i32) -> IngestError {
+    let conflict = error
+        .downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .is_some_and(|e| e.constraint() == Some("documents_key_version_idx"));
+    match &source.key {
+        Some(key) if conflict => IngestError::VersionConflict { key: key.clone(), version },
+        _ => error.into(),
+    }
+}
+
+pub async fn find_document_by_sha(pool: &PgPool, sha256: &str) -> anyhow::Result<Option<Document>> {
+    let existing = sqlx::query_as::<_, Document>(
+        // A current version wins over a superseded one with the same content
+        "SELECT * FROM documents WHERE content_sha256 = $1 ORDER BY superseded_at IS NOT NULL, created_at DESC LIMIT 1"
+    )
+    .bind(sha256)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(existing)
+}
+
+/// Highest version stored under `key`, superseded or not.
+pub async fn find_latest_version(pool: &PgPool, key: &str) -> anyhow::Result<Option<Document>> {
+    let latest = sqlx::query_as::<_, Document>(
+        "SELECT * FROM documents WHERE document_key = $1 ORDER BY document_version DESC, created_at DESC LIMIT 1"
+    )
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(latest)
+}
+
+pub async fn insert_document(
+    conn: &mut PgConnection,
+    source: &DocumentSource,
+    sha256: &str,
+    version: i32,
+    tags: &[String],
+    embedding_model: &str,
+    settings: ChunkSettings,
+) -> anyhow::Result<Document> {
+    let mut metadata = source.metadata();
+    metadata["chunking"] = settings.metadata();
+
+    let doc = sqlx::query_as::<_, Document>(
+        r#"
+        INSERT INTO documents (source_type, source_uri, content_sha256, tags, embedding_model, metadata, document_key, document_version)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#
+    )
+    .bind(source.source_type)
+    .bind(&source.uri)
+    .bind(sha256)
+    .bind(tags)
+    .bind(embedding_model)
+    .bind(metadata)
+    .bind(&source.key)
+    .bind(version)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(doc)
+}
+
+/// Marks every current version under `key` other than `current` as superseded, and
+/// with `Supersede::Delete` drops their chunks. Returns the superseded document ids.
+/// Runs on the caller's transaction, so it commits with the new version.
+pub async fn supersede_versions(
+    conn: &mut PgConnection,
+    key: &str,
+    current: Uuid,
+    mode: Supersede,
+) -> anyhow::Result<Vec<Uuid>> {
+    let superseded: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        UPDATE documents
+        SET superseded_at = now(), updated_at = now()
+        WHERE document_key = $1 AND id <> $2 AND superseded_at IS NULL
+        RETURNING id
+        "#
+    )
+    .bind(key)
+    .bind(current)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if mode == Supersede::Delete && !superseded.is_empty() {
+        sqlx::query("DELETE FROM chunks WHERE document_id = ANY($1)")
+            .bind(&superseded)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(superseded)
+}
+
+/// Parses `data` as `format` and chunks it. HTML is converted to markdown and then
+/// split by headings like markdown; plain text is split by paragraphs and sentences.
+/// PDF and DOCX extraction run on a blocking thread; a PDF without any extractable
+/// text yields no chunks and a warning, and skipped DOCX images and objects are
+/// counted in warnings. CSV rows are chunked in windows under their header row,
+/// with the column names in every chunk's metadata; malformed rows are skipped and
+/// counted in a warning. Text formats are read as UTF-8, invalid bytes replaced.
+/// Nothing is stored or embedded, so this also backs `POST /api/ingest/preview`.
+pub async fn prepare_document(
+    source: &DocumentSource,
+    data: &[u8],
+    settings: ChunkSettings,
+) -> Result<PreparedDocument, IngestError> {
+    let format = source.format;
+    let mut warnings = Vec::new();
+    if source.assumed_markdown {
+        warnings.push(warning("unknown_extension", "the file extension isn't recognized, so it was read as markdown"));
+    }
+
+    let mut columns = None;
+    let mut code_share = 0.0;
+    let sections = match format {
+        DocumentFormat::Markdown => {
+            let text = decode_text(data, &mut warnings);
+            code_share = markdown::code_block_share(&text);
+            markdown::parse_markdown(&text, settings.split_code_blocks)
+        }
+        DocumentFormat::Text => chunking::split_plain_text(&decode_text(data, &mut warnings), settings.max_tokens),
+        DocumentFormat::Html => {
+            let text = markdown::html_to_markdown(&decode_text(data, &mut warnings));
+            code_share = markdown::code_block_share(&text);
+            markdown::parse_markdown(&text, settings.split_code_blocks)
+        }
+        DocumentFormat::Pdf => {
+            let text = extract_blocking(format, data, pdf::extract_sections).await?;
+
+            if text.sections.is_empty() {
+                warnings.push(warning("pdf_no_text", format!(
+                    "no extractable text in any of the {} pages; scanned PDFs need OCR before ingesting",
+                    text.pages
+                )));
+            } else if text.empty_pages > 0 {
+                warnings.push(warning("pdf_pages_skipped", format!(
+                    "{} of {} pages had no extractable text and were skipped",
+                    text.empty_pages, text.pages
+                )));
+            }
+            text.sections
+        }
+        DocumentFormat::Docx => {
+            let text = extract_blocking(format, data, docx::extract_sections).await?;
+            if text.images > 0 {
+                warnings.push(warning("docx_images_skipped", format!("{} embedded images were skipped", text.images)));
+            }
+            if text.objects > 0 {
+                warnings.push(warning("docx_objects_skipped", format!(
+                    "{} embedded objects (spreadsheets, equations, ...) could not be extracted",
+                    text.objects
+                )));
+            }
+            text.sections
+        }
+        DocumentFormat::Csv => {
+            let max_tokens = settings.max_tokens;
+            let text = extract_blocking(format, data, move |data| csv::extract_sections(data, max_tokens)).await?;
+            if text.malformed_rows > 0 {
+                warnings.push(warning("csv_rows_skipped", format!(
+                    "{} malformed rows (wrong number of fields or invalid UTF-8) were skipped",
+                    text.malformed_rows
+                )));
+            }
+            columns = Some(text.columns);
+            text.sections
+        }
+    };
+
+    let chunked = chunking::chunk_sections(
+        &sections,
+        settings.max_tokens,
+        settings.overlap_tokens,
+        true,
+        chunking::context_metadata_enabled(),
+        settings.heading_prefix,
+    );
+    warnings.extend(section_warnings(format, &sections, &chunked, settings.max_tokens, code_share));
+
+    let mut chunks = chunked.chunks;
+    if let Some(columns) = columns {
+        for chunk in &mut chunks {
+            chunk.metadata["columns"] = json!(columns);
+        }
+    }
+    Ok(PreparedDocument { chunks, warnings, settings })
+}
+
+/// Warnings about how the document was sectioned that can explain poor retrieval:
+/// sections hard-split at the token ceiling, sections left without chunks, mostly
+/// code, and headed formats without a single heading. Sections are named by their
+/// heading path.
+fn section_warnings(
+    format: DocumentFormat,
+    sections: &[markdown::MarkdownSection],
+    chunked: &chunking::ChunkedSections,
+    max_tokens: usize,
+    code_share: f32,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if !chunked.split_sections.is_empty() {
+        let names = chunked
+            .split_sections
+            .iter()
+            .map(|&(index, tokens)| format!("{} ({} tokens)", section_name(&sections[index]), tokens));
+        warnings.push(warning("section_split", format!(
+            "{} sections exceeded {} tokens and were cut into token windows mid-text: {}",
+            chunked.split_sections.len(),
+            max_tokens,
+            name_list(names)
+        )));
+    }
+    if !chunked.empty_sections.is_empty() {
+        let names = chunked.empty_sections.iter().map(|&index| section_name(&sections[index]));
+        warnings.push(warning("section_empty", format!(
+            "{} sections produced no chunks because their content repeats earlier sections: {}",
+            chunked.empty_sections.len(),
+            name_list(names)
+        )));
+    }
+    if code_share > MOSTLY_CODE_SHARE {
+        warnings.push(warning("mostly_code", format!(
+            "{:.0}% of the text is in code blocks, which match natural-language queries poorly",
+            code_share * 100.0
+        )));
+    }
+    // A single chunk loses nothing to the missing headings
+    let headed = matches!(format, DocumentFormat::Markdown | DocumentFormat::Html | DocumentFormat::Docx);
+    if headed && chunked.chunks.len() > 1 && sections.iter().all(|section| section.heading_path.is_empty()) {
+        warnings.push(warning("no_headings", format!(
+            "no headings found, so all {} chunks landed in one untitled section; headings give chunks context",
+            chunked.chunks.len()
+        )));
+    }
+    warnings
+}
+
+/// A warning for `IngestResponse.warnings`: a snake_case code clients can match on,
+/// then the human-readable message.
+pub fn warning(code: &str, message: impl std::fmt::Display) -> String {
+    format!("{}: {}", code, message)
+}
+
+/// The response warning for content that was already stored.
+pub fn duplicate_warning(document_id: Uuid) -> String {
+    warning(
+        "duplicate_document",
+        format!("the same content is already stored as document {}, so nothing new was ingested", document_id),
+    )
+}
+
+/// Warns about chunks longer than the embedding model reads, whose embeddings only
+/// cover their beginning. Chunk tokens are counted with `chunking::TOKENIZER`, so
+/// for other tokenizers this is an approximation.
+pub fn input_limit_warning(embedder: &dyn EmbeddingProvider, chunks: &[chunking::Chunk]) -> Option<String> {
+    let limit = embedder.max_input_tokens()?;
+    let over = chunks.iter().filter(|chunk| chunk.tokens > limit).count();
+    (over > 0).then(|| {
+        warning("chunk_truncated", format!(
+            "{} chunks are longer than the {}-token input of {} and were embedded from their beginning only",
+            over,
+            limit,
+            embedder.model_name()
+        ))
+    })
+}
+
+/// `data` as UTF-8 with invalid byte sequences replaced by U+FFFD, warning with how
+/// many there were.
+fn decode_text<'a>(data: &'a [u8], warnings: &mut Vec<String>) -> Cow<'a, str> {
+    let invalid = data.utf8_chunks().filter(|chunk| !chunk.invalid().is_empty()).count();
+    if invalid > 0 {
+        warnings.push(warning("invalid_utf8", format!(
+            "{} invalid UTF-8 byte sequences were replaced with U+FFFD",
+            invalid
+        )));
+    }
+    String::from_utf8_lossy(data)
+}
+
+fn section_name(section: &markdown::MarkdownSection) -> String {
+    if section.heading_path.is_empty() {
+        format!("untitled section at offset {}", section.start_offset)
+    } else {
+        format!("\"{}\"", section.heading_path.join(" > "))
+    }
+}
+
+/// The first `MAX_WARNED_SECTIONS` names, then how many more there are.
+fn name_list(names: impl ExactSizeIterator<Item = String>) -> String {
+    let total = names.len();
+    let mut list = names.take(MAX_WARNED_SECTIONS).collect::<Vec<_>>().join(", ");
+    if total > MAX_WARNED_SECTIONS {
+        list.push_str(&format!(" and {} more", total - MAX_WARNED_SECTIONS));
+    }
+    list
+}
+
+/// Runs a CPU-bound extractor off the async workers.
+async fn extract_blocking<T: Send + 'static>(
+    format: DocumentFormat,
+    data: &[u8],
+    extract: impl FnOnce(&[u8]) -> Result<T, DocumentError> + Send + 'static,
+) -> Result<T, IngestError> {
+    let data = data.to_vec();
+    tokio::task::spawn_blocking(move || extract(&data))
+        .await
+        .map_err(|e| anyhow!("{} extraction task failed: {}", format.as_str(), e))?
+        .map_err(|error| IngestError::InvalidDocument { format, error })
+}
+
+/// `first_index` is the position of `chunks[0]` within the document, stored as
+/// `chunk_index` so neighbouring chunks can be found again at query time. Each
+/// chunk's metadata also records how it was made: the tokenizer, the chunk
+/// `settings`, `embedding_model` and when it was stored (`ingested_at`), so
+/// chunks made under old settings can be found and reprocessed. Rows are inserted
+/// `INSERT_BATCH_SIZE` at a time, one statement per batch. Fails without
+/// writing anything if there isn't exactly one embedding per chunk.
+pub async fn insert_chunks(
+    conn: &mut PgConnection,
+    document_id: Uuid,
+    chunks: &[chunking::Chunk],
+    first_index: usize,
+    embeddings: &[Vec<f32>],
+    embedding_model: &str,
+    settings: ChunkSettings,
+) -> anyhow::Result<()> {
+    if chunks.len() != embeddings.len() {
+        return Err(anyhow!(
+            "got {} embeddings for {} chunks; refusing to store chunks without their embedding",
+            embeddings.len(),
+            chunks.len()
+        ));
+    }
+    let ingested_at = Utc::now().to_rfc3339();
+    let rows: Vec<(usize, &chunking::Chunk, &Vec<f32>)> = chunks
+        .iter()
+        .zip(embeddings)
+        .enumerate()
+        .map(|(i, (chunk, embedding))| (first_index + i, chunk, embedding))
+        .collect();
+
+    for batch in rows.chunks(INSERT_BATCH_SIZE) {
+        let mut insert = QueryBuilder::<Postgres>::new(
+            "INSERT INTO chunks (document_id, content, content_tokens, section, span, metadata, embedding, chunk_index) ",
+        );
+        insert.push_values(batch, |mut row, &(index, chunk, embedding)| {
+            let mut metadata = chunk.metadata.clone();
+            metadata["tokenizer"] = json!(chunking::TOKENIZER);
+            metadata["max_tokens"] = json!(settings.max_tokens);
+            metadata["overlap_tokens"] = json!(settings.overlap_tokens);
+            metadata["embedding_model"] = json!(embedding_model);
+            metadata["ingested_at"] = json!(ingested_at);
+
+            row.push_bind(document_id)
+                .push_bind(&chunk.content)
+                .push_bind(chunk.tokens as i32)
+                .push_bind(&chunk.section)
+                .push_bind(&chunk.span)
+                .push_bind(metadata)
+                .push_bind(Vector::from(embedding.clone()))
+                .push_bind(index as i32);
+        });
+        insert.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn build_response(pool: &PgPool, document_id: Uuid) -> anyhow::Result<IngestResponse> {
+    // Chunk count, their tokens (estimated for rows stored without a count) and version;
+    // the summary chunk is derived from the others, so it isn't counted
+    let (chunk_count, tokens, version) = sqlx::query_as::<_, (i64, i64, i32)>(
+        r#"
+        SELECT
+            COUNT(c.id),
+            COALESCE(SUM(COALESCE(c.content_tokens, octet_length(c.content) / 4)), 0)::bigint,
+            COALESCE(d.document_version, 1)
+        FROM documents d
+        LEFT JOIN chunks c ON c.document_id = d.id AND c.metadata->>'kind' IS DISTINCT FROM 'summary'
+        WHERE d.id = $1
+        GROUP BY d.id
+        "#
+    )
+    .bind(document_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(IngestResponse {
+        document_id,
+        chunks_count: chunk_count as usize,
+        tokens_estimate: tokens as usize,
+        warnings: vec![],
+        embeddings_cached: 0,
+        version,
+        deduplicated: false,
+    })
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::{IngestJob, IngestResponse};
+
+pub async fn create_job(pool: &PgPool, filename: &str) -> Result<Uuid> {
+    let id = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO ingest_jobs (filename) VALUES ($1) RETURNING id"
+    )
+    .bind(filename)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(id)
+}
+
+pub async fn get_job(pool: &PgPool, job_id: Uuid) -> Result<Option<IngestJob>> {
+    let job = sqlx::query_as::<_, IngestJob>(
+        r#"
+        SELECT id, status, filename, document_id, chunks_done, chunks_total, result, error, created_at, updated_at
+        FROM ingest_jobs WHERE id = $1
+        "#
+    )
+    .bind(job_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+pub async fn mark_processing(pool: &PgPool, job_id: Uuid) -> Result<()> {
+    sqlx::query("UPDATE ingest_jobs SET status = 'processing' WHERE id = $1")
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Chunks embedded so far. The document id is only recorded on completion, since
+/// the document row isn't committed before then.
+pub async fn set_progress(pool: &PgPool, job_id: Uuid, chunks_done: usize, chunks_total: usize) -> Result<()> {
+    sqlx::query(
+        "UPDATE ingest_jobs SET chunks_done = $2, chunks_total = $3 WHERE id = $1"
+    )
+    .bind(job_id)
+    .bind(chunks_done as i32)
+    .bind(chunks_total as i32)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn mark_completed(pool: &PgPool, job_id: Uuid, response: &IngestResponse) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE ingest_jobs
+        SET status = 'completed', document_id = $2, result = $3, chunks_done = COALESCE(chunks_total, chunks_done)
+        WHERE id = $1
+        "#
+    )
+    .bind(job_id)
+    .bind(response.document_id)
+    .bind(serde_json::to_value(response)?)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn mark_failed(pool: &PgPool, job_id: Uuid, error: &str) -> Result<()> {
+    sqlx::query("UPDATE ingest_jobs SET status = 'failed', error = $2 WHERE id = $1")
+        .bind(job_id)
+        .bind(error)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Fails jobs left `queued` or `processing` by a previous process. Their tasks died
+/// with it, and since a document is stored in one transaction at the end, nothing
+/// of theirs was kept; the upload has to be sent again.
+pub async fn fail_interrupted(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        UPDATE ingest_jobs
+        SET status = 'failed', error = 'interrupted by a service restart'
+        WHERE status IN ('queued', 'processing')
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
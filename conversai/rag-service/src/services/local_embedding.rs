@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+use tracing::info;
+
+use crate::services::embedding_provider::EmbeddingProvider;
+
+// Texts per forward pass; padding makes a batch cost as much as its longest text
+const LOCAL_BATCH_SIZE: usize = 32;
+// sentence-transformers models are trained on inputs up to this many tokens
+const DEFAULT_MAX_SEQUENCE_TOKENS: usize = 256;
+
+/// A sentence-transformers BERT model (all-MiniLM-L6-v2 and the like) run
+/// in-process on the CPU, for deployments that can't reach an embedding API.
+/// The model is loaded once at startup from `EMBEDDING_LOCAL_MODEL_DIR`, which
+/// must hold the `config.json`, `tokenizer.json` and `model.safetensors` of a
+/// Hugging Face checkout; nothing is downloaded. Vectors are mean-pooled over the
+/// tokens and L2-normalized, and their size is the model's `hidden_size`.
+pub struct LocalModelProvider {
+    inner: Arc<LocalModel>,
+    model_name: String,
+    dimensions: usize,
+    max_tokens: usize,
+}
+
+struct LocalModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl LocalModelProvider {
+    pub fn from_env() -> Result<Self> {
+        let dir = env::var("EMBEDDING_LOCAL_MODEL_DIR")
+            .map(PathBuf::from)
+            .map_err(|_| anyhow!("EMBEDDING_PROVIDER=local needs EMBEDDING_LOCAL_MODEL_DIR"))?;
+        let max_tokens = env::var("EMBEDDING_LOCAL_MAX_TOKENS")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_MAX_SEQUENCE_TOKENS);
+
+        let config_json = std::fs::read_to_string(dir.join("config.json"))
+            .with_context(|| format!("reading {}", dir.join("config.json").display()))?;
+        let config: Config = serde_json::from_str(&config_json).context("parsing config.json as a BERT config")?;
+
+        let max_tokens = max_tokens.min(config.max_position_embeddings);
+
+        let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| anyhow!("loading {}: {}", dir.join("tokenizer.json").display(), e))?;
+        tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..PaddingParams::default()
+        }));
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: max_tokens,
+                ..TruncationParams::default()
+            }))
+            .map_err(|e| anyhow!("configuring tokenizer truncation: {}", e))?;
+
+        let device = Device::Cpu;
+        let weights = dir.join("model.safetensors");
+        // Safety: the weights file is memory-mapped and must not change while the service runs
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[&weights], DTYPE, &device) }
+            .with_context(|| format!("loading {}", weights.display()))?;
+        let model = BertModel::load(vb, &config).context("building the BERT model from its weights")?;
+
+        let model_name = env::var("EMBEDDING_MODEL_NAME").unwrap_or_else(|_| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "local".to_string())
+        });
+        info!("Loaded local embedding model {} from {}", model_name, dir.display());
+
+        Ok(Self {
+            inner: Arc::new(LocalModel { model, tokenizer, device }),
+            model_name,
+            dimensions: config.hidden_size,
+            max_tokens,
+        })
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalModelProvider {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let inner = self.inner.clone();
+        let texts: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
+
+        // Inference is CPU-bound; keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            let mut embeddings = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(LOCAL_BATCH_SIZE) {
+                embeddings.extend(inner.embed_batch(batch)?);
+            }
+            Ok(embeddings)
+        })
+        .await
+        .map_err(|e| anyhow!("local embedding task failed: {}", e))?
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "local"
+    }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        Some(self.max_tokens)
+    }
+}
+
+impl LocalModel {
+    fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow!("tokenizing: {}", e))?;
+
+        let rows = |field: fn(&tokenizers::Encoding) -> &[u32]| -> Result<Tensor> {
+            let rows = encodings
+                .iter()
+                .map(|encoding| Tensor::new(field(encoding), &self.device))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            Ok(Tensor::stack(&rows, 0)?)
+        };
+        let input_ids = rows(|e| e.get_ids())?;
+        let type_ids = rows(|e| e.get_type_ids())?;
+        let attention_mask = rows(|e| e.get_attention_mask())?;
+
+        let hidden = self.model.forward(&input_ids, &type_ids, Some(&attention_mask))?;
+
+        // Mean over the real tokens only, then unit length so cosine and dot agree
+        let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let counts = mask.sum(1)?.clamp(1e-9, f64::MAX)?;
+        let pooled = summed.broadcast_div(&counts)?;
+        let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?.clamp(1e-12, f64::MAX)?;
+        Ok(pooled.broadcast_div(&norms)?.to_vec2::<f32>()?)
+    }
+}
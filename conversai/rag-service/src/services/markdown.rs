@@ -1,5 +1,7 @@
-use pulldown_cmark::{Event, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarkdownSection {
@@ -8,16 +10,71 @@ pub struct MarkdownSection {
     pub level: usize,
     pub start_offset: usize,
     pub end_offset: usize,
+    /// 1-based page the section came from, for paginated formats such as PDF
+    #[serde(default)]
+    pub page: Option<u32>,
+    /// 1-based first and last data row it holds, for tabular formats such as CSV
+    #[serde(default)]
+    pub rows: Option<(usize, usize)>,
+    /// Set for a code block split into its own section; the language comes from a
+    /// fenced block's info string
+    #[serde(default)]
+    pub code: Option<CodeInfo>,
+    /// `(content offset, source offset)` byte pairs where a run of `content` copied
+    /// from the source starts, for content rebuilt from markup. Empty when the
+    /// content is the source text between the offsets as it is.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub source_map: Vec<(usize, usize)>,
 }
 
-pub fn parse_markdown(content: &str) -> Vec<MarkdownSection> {
+impl MarkdownSection {
+    /// Source offset of a byte offset into `content`, kept within the section.
+    /// Rebuilt content maps through the last `source_map` run starting at or
+    /// before it, stopping short of where the next run's source begins.
+    pub fn source_offset(&self, offset: usize) -> usize {
+        let run = self.source_map.partition_point(|&(content, _)| content <= offset);
+        let mapped = match run.checked_sub(1).map(|i| self.source_map[i]) {
+            Some((content, source)) => {
+                let next = self.source_map.get(run).map_or(self.end_offset, |&(_, source)| source);
+                (source + (offset - content)).min(next.max(source))
+            }
+            None => self.start_offset + offset,
+        };
+        mapped.clamp(self.start_offset, self.end_offset.max(self.start_offset))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CodeInfo {
+    pub language: Option<String>,
+}
+
+/// First word of a fence's info string, so "rust,ignore" and "python title=x" give the language.
+fn fence_language(kind: &CodeBlockKind) -> Option<String> {
+    match kind {
+        CodeBlockKind::Fenced(info) => info
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+            .next()
+            .filter(|language| !language.is_empty())
+            .map(str::to_lowercase),
+        CodeBlockKind::Indented => None,
+    }
+}
+
+/// Splits markdown into one section per heading. With `split_code_blocks`, each
+/// code block also becomes a section of its own under the current heading path,
+/// so code and the prose around it are chunked and embedded separately.
+pub fn parse_markdown(content: &str, split_code_blocks: bool) -> Vec<MarkdownSection> {
     let parser = Parser::new(content);
     let mut sections = Vec::new();
     let mut current_heading_path = Vec::new();
     let mut current_content = String::new();
+    let mut current_map = Vec::new();
     let mut current_level = 0;
     let mut start_offset = 0;
     let mut in_code_block = false;
+    // Set while inside a code block that becomes its own section
+    let mut code_language: Option<Option<String>> = None;
 
     for (event, range) in parser.into_offset_iter() {
         match event {
@@ -30,10 +87,15 @@ pub fn parse_markdown(content: &str) -> Vec<MarkdownSection> {
                         level: current_level,
                         start_offset,
                         end_offset: range.start,
+                        page: None,
+                        rows: None,
+                        code: None,
+                        source_map: current_map.clone(),
                     });
                 }
                 
                 current_content.clear();
+                current_map.clear();
                 current_level = level as usize;
                 start_offset = range.start;
                 
@@ -50,22 +112,74 @@ pub fn parse_markdown(content: &str) -> Vec<MarkdownSection> {
                     // This is heading text
                     current_heading_path.push(text.to_string());
                 }
+                current_map.push((current_content.len(), range.start));
                 current_content.push_str(&text);
-                current_content.push(' ');
+                // Code block text keeps its own line breaks
+                if !in_code_block {
+                    current_content.push(' ');
+                }
             }
             Event::Code(code) => {
+                current_map.push((current_content.len(), range.start));
                 current_content.push_str("`");
                 current_content.push_str(&code);
                 current_content.push_str("`");
                 current_content.push(' ');
             }
-            Event::Start(Tag::CodeBlock(_)) => {
+            Event::Start(Tag::CodeBlock(kind)) => {
                 in_code_block = true;
-                current_content.push_str("```\n");
+                if split_code_blocks {
+                    // Prose before the block stays a section of its own
+                    if !current_content.trim().is_empty() {
+                        sections.push(MarkdownSection {
+                            content: current_content.clone(),
+                            heading_path: current_heading_path.clone(),
+                            level: current_level,
+                            start_offset,
+                            end_offset: range.start,
+                            page: None,
+                            rows: None,
+                            code: None,
+                            source_map: current_map.clone(),
+                        });
+                    }
+                    current_content.clear();
+                    current_map.clear();
+                    start_offset = range.start;
+                    current_map.push((0, range.start));
+                    code_language = Some(fence_language(&kind));
+                    current_content.push_str("```");
+                    current_content.push_str(code_language.as_ref().and_then(|l| l.as_deref()).unwrap_or(""));
+                    current_content.push('\n');
+                } else {
+                    current_content.push_str("```\n");
+                }
             }
             Event::End(Tag::CodeBlock(_)) => {
                 in_code_block = false;
-                current_content.push_str("```\n");
+                if let Some(language) = code_language.take() {
+                    // Past the opening fence line; empty blocks aren't worth a section
+                    let has_code = current_content.lines().skip(1).any(|line| !line.trim().is_empty());
+                    current_content.push_str("```\n");
+                    if has_code {
+                        sections.push(MarkdownSection {
+                            content: current_content.clone(),
+                            heading_path: current_heading_path.clone(),
+                            level: current_level,
+                            start_offset,
+                            end_offset: range.end,
+                            page: None,
+                            rows: None,
+                            code: Some(CodeInfo { language }),
+                            source_map: current_map.clone(),
+                        });
+                    }
+                    current_content.clear();
+                    current_map.clear();
+                    start_offset = range.end;
+                } else {
+                    current_content.push_str("```\n");
+                }
             }
             Event::SoftBreak | Event::HardBreak => {
                 if !in_code_block {
@@ -86,8 +200,40 @@ pub fn parse_markdown(content: &str) -> Vec<MarkdownSection> {
             level: current_level,
             start_offset,
             end_offset: content.len(),
+            page: None,
+            rows: None,
+            code: None,
+            source_map: current_map,
         });
     }
 
     sections
-}
\ No newline at end of file
+}
+
+/// Share of the document's non-whitespace characters that sit in code blocks.
+pub fn code_block_share(content: &str) -> f32 {
+    let visible = |text: &str| text.chars().filter(|c| !c.is_whitespace()).count();
+    let total = visible(content);
+    if total == 0 {
+        return 0.0;
+    }
+
+    let code: usize = Parser::new(content)
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Start(Tag::CodeBlock(_))))
+        .map(|(_, range)| visible(&content[range]))
+        .sum();
+    code as f32 / total as f32
+}
+
+/// Converts an HTML page to markdown, keeping its headings so `parse_markdown` can
+/// split on them. The head (title, meta), scripts, styles and `<noscript>` blocks
+/// are dropped first; html2md would otherwise keep their text as content.
+pub fn html_to_markdown(html: &str) -> String {
+    static NON_CONTENT: OnceLock<Regex> = OnceLock::new();
+    let non_content = NON_CONTENT.get_or_init(|| {
+        Regex::new(r"(?is)<head\b.*?</head\s*>|<script\b.*?</script\s*>|<style\b.*?</style\s*>|<noscript\b.*?</noscript\s*>")
+            .unwrap()
+    });
+    html2md::parse_html(&non_content.replace_all(html, ""))
+}
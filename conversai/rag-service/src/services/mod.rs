@@ -1,4 +1,20 @@
+pub mod answer;
 pub mod chunking;
+pub mod csv;
+pub mod docx;
 pub mod embedding;
+pub mod embedding_provider;
+pub mod fusion;
+pub mod ingest;
+pub mod jobs;
+#[cfg(feature = "local-embeddings")]
+pub mod local_embedding;
 pub mod markdown;
-pub mod retrieval;
\ No newline at end of file
+pub mod pdf;
+pub mod query_cache;
+pub mod reembed;
+pub mod rerank;
+pub mod retrieval;
+pub mod summarize;
+pub mod url_fetch;
+pub mod vector_math;
\ No newline at end of file
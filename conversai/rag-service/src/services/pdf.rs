@@ -0,0 +1,61 @@
+use lopdf::Document;
+
+use crate::services::ingest::DocumentError;
+use crate::services::markdown::MarkdownSection;
+
+/// Text of a PDF as one section per page, in page order. Pages without
+/// extractable text (scans, images) are skipped and counted.
+pub struct PdfText {
+    pub sections: Vec<MarkdownSection>,
+    pub pages: usize,
+    pub empty_pages: usize,
+}
+
+/// True for data starting with the `%PDF-` header.
+pub fn is_pdf(data: &[u8]) -> bool {
+    data.starts_with(b"%PDF-")
+}
+
+/// Extracts each page's text. Fails for data lopdf can't parse and for
+/// password-protected files.
+pub fn extract_sections(data: &[u8]) -> Result<PdfText, DocumentError> {
+    let document = Document::load_mem(data).map_err(|e| DocumentError::Corrupt(format!("not a readable PDF: {}", e)))?;
+    if document.is_encrypted() {
+        return Err(DocumentError::Encrypted("password-protected PDFs are not supported".to_string()));
+    }
+
+    let pages = document.get_pages();
+    let mut sections = Vec::new();
+    let mut empty_pages = 0;
+    let mut offset = 0;
+
+    for &page in pages.keys() {
+        // A page whose content stream can't be decoded counts as empty rather than failing the file
+        let text = document.extract_text(&[page]).unwrap_or_default();
+        let text = text.trim();
+        if text.is_empty() {
+            empty_pages += 1;
+            continue;
+        }
+
+        let length = text.len();
+        sections.push(MarkdownSection {
+            content: text.to_string(),
+            heading_path: vec![format!("Page {}", page)],
+            level: 0,
+            start_offset: offset,
+            end_offset: offset + length,
+            page: Some(page),
+            rows: None,
+            code: None,
+            source_map: Vec::new(),
+        });
+        offset += length;
+    }
+
+    Ok(PdfText {
+        sections,
+        pages: pages.len(),
+        empty_pages,
+    })
+}
@@ -0,0 +1,152 @@
+use moka::future::Cache;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::info;
+
+use crate::models::{CacheDiagnostics, CacheStatus, QueryRequest};
+
+/// In-process cache of `/api/query` responses. Keys cover every request field that
+/// shapes the response plus a corpus generation, which ingest and document edits
+/// bump so results from before the change are never served.
+pub struct QueryCache {
+    /// `None` when disabled with `QUERY_CACHE_MAX_ENTRIES=0`
+    entries: Option<Cache<String, Value>>,
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl QueryCache {
+    /// Reads `QUERY_CACHE_MAX_ENTRIES` (default 1000, 0 disables) and
+    /// `QUERY_CACHE_TTL_SECS` (default 300).
+    pub fn from_env() -> Self {
+        let max_entries = env::var("QUERY_CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(1000);
+        let ttl = env::var("QUERY_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(300);
+
+        info!("Query cache: max_entries={} ttl={}s", max_entries, ttl);
+
+        let entries = (max_entries > 0).then(|| {
+            Cache::builder()
+                .max_capacity(max_entries)
+                .time_to_live(Duration::from_secs(ttl))
+                .build()
+        });
+
+        Self {
+            entries,
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.entries.is_some()
+    }
+
+    /// Hash of the request with whitespace and case in the query normalized,
+    /// taken against the current generation.
+    pub fn key(&self, request: &QueryRequest) -> Option<String> {
+        let Value::Object(mut fields) = serde_json::to_value(request).ok()? else {
+            return None;
+        };
+        fields.remove("cache");
+        let query = request.query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        fields.insert("query".to_string(), Value::String(query));
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.generation.load(Ordering::Acquire).to_le_bytes());
+        hasher.update(Value::Object(fields).to_string().as_bytes());
+        Some(hex::encode(hasher.finalize()))
+    }
+
+    /// Looks up a cached response, counting the hit or miss.
+    pub async fn get(&self, key: &str) -> Option<Value> {
+        let entries = self.entries.as_ref()?;
+        let cached = entries.get(key).await;
+        let counter = if cached.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    pub async fn insert(&self, key: String, response: Value) {
+        if let Some(entries) = &self.entries {
+            entries.insert(key, response).await;
+        }
+    }
+
+    /// Drops every cached response; called whenever documents change.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(entries) = &self.entries {
+            entries.invalidate_all();
+        }
+    }
+
+    pub fn diagnostics(&self, status: CacheStatus) -> CacheDiagnostics {
+        CacheDiagnostics {
+            status,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(body: Value) -> QueryRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn key_ignores_the_cache_mode_and_query_formatting() {
+        let cache = QueryCache::from_env();
+        let key = cache.key(&request(json!({ "query": "Borrow  checker", "k": 5 }))).unwrap();
+
+        let same = request(json!({ "query": "  borrow\tCHECKER\n", "k": 5, "cache": "bypass" }));
+        assert_eq!(cache.key(&same).unwrap(), key);
+        let other_k = request(json!({ "query": "borrow checker", "k": 6 }));
+        assert_ne!(cache.key(&other_k).unwrap(), key);
+        let other_query = request(json!({ "query": "borrow checkers", "k": 5 }));
+        assert_ne!(cache.key(&other_query).unwrap(), key);
+    }
+
+    #[tokio::test]
+    async fn invalidate_makes_earlier_keys_miss() {
+        let cache = QueryCache::from_env();
+        let query = request(json!({ "query": "borrow checker" }));
+        let key = cache.key(&query).unwrap();
+        cache.insert(key.clone(), json!({ "answer": "cached" })).await;
+        assert_eq!(cache.get(&key).await, Some(json!({ "answer": "cached" })));
+
+        cache.invalidate();
+        assert_eq!(cache.get(&key).await, None);
+        // The same request hashes differently after a change, so nothing stale can be re-read
+        assert_ne!(cache.key(&query).unwrap(), key);
+    }
+
+    #[tokio::test]
+    async fn diagnostics_count_hits_and_misses() {
+        let cache = QueryCache::from_env();
+        cache.get("absent").await;
+        cache.insert("present".to_string(), json!({})).await;
+        cache.get("present").await;
+        cache.get("present").await;
+
+        let diagnostics = cache.diagnostics(CacheStatus::Hit);
+        assert_eq!((diagnostics.hits, diagnostics.misses), (2, 1));
+        assert_eq!(diagnostics.status, CacheStatus::Hit);
+    }
+}
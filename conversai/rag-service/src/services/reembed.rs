@@ -0,0 +1,123 @@
+use pgvector::Vector;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::services::embedding;
+use crate::services::embedding_provider::EmbeddingProvider;
+use crate::services::ingest::IngestError;
+
+// Chunks per embedding request while re-embedding
+const REEMBED_BATCH_SIZE: usize = 100;
+
+/// Which documents a re-embed run covers.
+pub struct ReembedScope<'a> {
+    pub document_ids: Option<&'a [Uuid]>,
+    pub tag: Option<&'a str>,
+    /// Include documents already embedded with the current model
+    pub force: bool,
+}
+
+/// Ids of the next `limit` documents in scope after `cursor`, in id order.
+pub async fn next_documents(
+    pool: &PgPool,
+    scope: &ReembedScope<'_>,
+    cursor: Option<Uuid>,
+    limit: i64,
+    model: &str,
+) -> anyhow::Result<Vec<Uuid>> {
+    let ids = sqlx::query_scalar(
+        r#"
+        SELECT id FROM documents
+        WHERE ($1::uuid[] IS NULL OR id = ANY($1))
+          AND ($2::text IS NULL OR $2 = ANY(tags))
+          AND ($3::uuid IS NULL OR id > $3)
+          AND ($4 OR embedding_model IS DISTINCT FROM $5)
+        ORDER BY id
+        LIMIT $6
+        "#
+    )
+    .bind(scope.document_ids)
+    .bind(scope.tag)
+    .bind(cursor)
+    .bind(scope.force)
+    .bind(model)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(ids)
+}
+
+/// Embedding calls a document's chunks needed.
+pub struct Reembedded {
+    pub chunks: usize,
+    pub cached: usize,
+}
+
+/// Re-embeds every chunk of a document, in chunk id order, from its stored
+/// content. All embeddings are fetched first and then written in one transaction
+/// with the document's `embedding_model`, so a failure leaves the document
+/// entirely on its old vectors.
+pub async fn reembed_document(
+    pool: &PgPool,
+    embedder: &dyn EmbeddingProvider,
+    document_id: Uuid,
+) -> Result<Reembedded, IngestError> {
+    let chunks: Vec<(Uuid, String)> = sqlx::query_as("SELECT id, content FROM chunks WHERE document_id = $1 ORDER BY id")
+        .bind(document_id)
+        .fetch_all(pool)
+        .await
+        .map_err(anyhow::Error::from)?;
+
+    let mut embeddings = Vec::with_capacity(chunks.len());
+    let mut cached = 0;
+    for batch in chunks.chunks(REEMBED_BATCH_SIZE) {
+        let texts: Vec<&str> = batch.iter().map(|(_, content)| content.as_str()).collect();
+        let embedded = embedding::get_embeddings_cached(pool, embedder, &texts)
+            .await
+            .map_err(IngestError::Embedding)?;
+        cached += embedded.hits();
+        embeddings.extend(embedded.embeddings);
+    }
+
+    let ids: Vec<Uuid> = chunks.iter().map(|(id, _)| *id).collect();
+    store_embeddings(pool, document_id, &ids, embeddings, embedder.model_name()).await?;
+
+    Ok(Reembedded {
+        chunks: chunks.len(),
+        cached,
+    })
+}
+
+async fn store_embeddings(
+    pool: &PgPool,
+    document_id: Uuid,
+    chunk_ids: &[Uuid],
+    embeddings: Vec<Vec<f32>>,
+    model: &str,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    for (chunk_id, embedding) in chunk_ids.iter().zip(embeddings) {
+        sqlx::query(
+            r#"
+            UPDATE chunks
+            SET embedding = $2, metadata = COALESCE(metadata, '{}'::jsonb) || $3
+            WHERE id = $1
+            "#
+        )
+        .bind(chunk_id)
+        .bind(Vector::from(embedding))
+        .bind(json!({ "embedding_model": model }))
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query("UPDATE documents SET embedding_model = $2, updated_at = now() WHERE id = $1")
+        .bind(document_id)
+        .bind(model)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
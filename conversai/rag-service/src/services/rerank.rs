@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use tracing::info;
+
+use crate::services::retrieval::{boosted, by_score_desc, ChunkWithScore};
+
+/// Hosted cross-encoder rerank APIs. Both accept the same request shape and
+/// return `{ results: [{ index, relevance_score }] }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RerankProvider {
+    Cohere,
+    Jina,
+}
+
+impl RerankProvider {
+    fn default_base_url(self) -> &'static str {
+        match self {
+            RerankProvider::Cohere => "https://api.cohere.com/v1",
+            RerankProvider::Jina => "https://api.jina.ai/v1",
+        }
+    }
+
+    fn default_model(self) -> &'static str {
+        match self {
+            RerankProvider::Cohere => "rerank-multilingual-v3.0",
+            RerankProvider::Jina => "jina-reranker-v2-base-multilingual",
+        }
+    }
+
+    fn api_key_var(self) -> &'static str {
+        match self {
+            RerankProvider::Cohere => "COHERE_API_KEY",
+            RerankProvider::Jina => "JINA_API_KEY",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RerankConfig {
+    pub provider: RerankProvider,
+    pub model: String,
+    pub api_key: String,
+    /// API root the `/rerank` path is appended to
+    pub base_url: String,
+}
+
+impl RerankConfig {
+    /// Reads `RERANK_PROVIDER` (`cohere` or `jina`), `RERANK_MODEL`, and the API key
+    /// from `RERANK_API_KEY` or the provider's own variable. `RERANK_BASE_URL`
+    /// replaces the provider's API root, for proxies and compatible servers.
+    pub fn from_env() -> Result<Self> {
+        let provider = match env::var("RERANK_PROVIDER")
+            .map_err(|_| anyhow!("RERANK_PROVIDER is not set"))?
+            .to_lowercase()
+            .as_str()
+        {
+            "cohere" => RerankProvider::Cohere,
+            "jina" => RerankProvider::Jina,
+            other => return Err(anyhow!("unknown RERANK_PROVIDER '{}'", other)),
+        };
+
+        let model = env::var("RERANK_MODEL")
+            .unwrap_or_else(|_| provider.default_model().to_string());
+        let api_key = env::var("RERANK_API_KEY")
+            .or_else(|_| env::var(provider.api_key_var()))
+            .map_err(|_| anyhow!("RERANK_API_KEY or {} must be set", provider.api_key_var()))?;
+
+        let base_url = env::var("RERANK_BASE_URL")
+            .ok()
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| provider.default_base_url().to_string());
+
+        Ok(Self {
+            provider,
+            model,
+            api_key,
+            base_url,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: Vec<&'a str>,
+    top_n: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+/// Scores every chunk against the query with the configured cross-encoder and
+/// returns them ordered by relevance, with `score` replaced by the provider's score
+/// (tag-boosted like the fused score was).
+pub async fn cross_encoder_rerank(query: &str, chunks: &[ChunkWithScore]) -> Result<Vec<ChunkWithScore>> {
+    if chunks.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let config = RerankConfig::from_env()?;
+    let request = RerankRequest {
+        model: &config.model,
+        query,
+        documents: chunks.iter().map(|c| c.chunk.content.as_str()).collect(),
+        top_n: chunks.len(),
+    };
+
+    let response: RerankResponse = reqwest::Client::new()
+        .post(format!("{}/rerank", config.base_url))
+        .header("Authorization", format!("Bearer {}", config.api_key))
+        .json(&request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut reranked = Vec::with_capacity(response.results.len());
+    for result in response.results {
+        let mut chunk = chunks
+            .get(result.index)
+            .cloned()
+            .ok_or_else(|| anyhow!("rerank result index {} out of range", result.index))?;
+        chunk.score = boosted(result.relevance_score, chunk.boost);
+        reranked.push(chunk);
+    }
+    reranked.sort_by(by_score_desc);
+
+    info!("Reranked {} chunks with {:?} {}", reranked.len(), config.provider, config.model);
+    Ok(reranked)
+}
@@ -1,99 +1,749 @@
 use anyhow::Result;
-use sqlx::{PgPool, Row};
-use std::collections::HashMap;
-use tracing::info;
+use sqlx::{postgres::PgRow, PgPool, Row};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use tracing::{field, info, instrument, Span};
 use pgvector::Vector;
 
-use crate::models::Chunk;
-use crate::services::embedding::cosine_similarity;
+use crate::models::{
+    Chunk, ComponentScore, ContentKind, ContextExpansion, Diversity, ExpandContext, FusionMethod, QueryFilters,
+    ScoreNormalization, SimilarityMetric,
+};
+use crate::services::chunking::estimate_tokens;
+use crate::services::vector_math;
 
 #[derive(Debug, Clone)]
 pub struct ChunkWithScore {
     pub chunk: Chunk,
     pub score: f32,
     pub source_uri: Option<String>,
+    pub document_tags: Vec<String>,
+    pub semantic_score: ComponentScore,
+    pub lexical_score: ComponentScore,
+    /// 1-based positions in the ANN and lexical lists; only reported with RRF fusion
+    pub semantic_rank: Option<u32>,
+    pub lexical_rank: Option<u32>,
+    pub expansion: Option<ContextExpansion>,
+    /// Model the chunk's document was embedded with; `None` for documents ingested before it was recorded
+    pub embedding_model: Option<String>,
+    /// Factor the fused score was boosted by for a `boost_tags` match; rerankers apply it to their scores too
+    pub boost: Option<f32>,
 }
 
+/// Rank offset used by Reciprocal Rank Fusion unless the request sets `rrf_k`
+pub const DEFAULT_RRF_K: u32 = 60;
+
+/// Relative weights of the semantic and lexical scores, normalized to sum to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HybridWeights {
+    pub semantic: f32,
+    pub lexical: f32,
+}
+
+impl HybridWeights {
+    pub fn from_alpha(alpha: f32) -> Self {
+        Self {
+            semantic: alpha,
+            lexical: 1.0 - alpha,
+        }
+    }
+
+    /// Callers must ensure the weights are non-negative and not both zero.
+    pub fn normalized(semantic: f32, lexical: f32) -> Self {
+        let total = semantic + lexical;
+        Self {
+            semantic: semantic / total,
+            lexical: lexical / total,
+        }
+    }
+}
+
+/// Boost for chunks whose document carries any of `tags`.
+#[derive(Debug, Clone)]
+pub struct TagBoost {
+    pub tags: Vec<String>,
+    pub factor: f32,
+}
+
+impl TagBoost {
+    fn factor_for(&self, document_tags: &[String]) -> Option<f32> {
+        document_tags
+            .iter()
+            .any(|tag| self.tags.contains(tag))
+            .then_some(self.factor)
+    }
+}
+
+/// Applies a tag boost to a score. Scores are scaled by their magnitude, so a boost
+/// above 1 also raises negative (z-score normalized) scores instead of lowering them.
+pub fn boosted(score: f32, boost: Option<f32>) -> f32 {
+    match boost {
+        Some(factor) => score + score.abs() * (factor - 1.0),
+        None => score,
+    }
+}
+
+/// How the semantic and lexical scores of each candidate are combined.
+#[derive(Debug, Clone)]
+pub struct FusionOptions {
+    pub method: FusionMethod,
+    /// Only used by `FusionMethod::Rrf`
+    pub rrf_k: u32,
+    /// Only used by `FusionMethod::WeightedSum`
+    pub weights: HybridWeights,
+    pub normalization: ScoreNormalization,
+    /// Candidates scoring below this after fusion are dropped before reranking
+    pub min_score: Option<f32>,
+    /// Orders the semantic list and scores it; passed to the SQL function as `metric`
+    pub similarity: SimilarityMetric,
+    /// Raises the fused score of chunks from documents with these tags, before `min_score`
+    pub boost: Option<TagBoost>,
+}
+
+/// With weighted-sum fusion, semantic and lexical scores are normalized over the
+/// candidate set before blending. With RRF, each chunk scores
+/// `1 / (rrf_k + rank)` for every list it appears in. A tag boost then applies to
+/// the fused score; it only reorders the candidates the SQL function returned.
+#[instrument(
+    skip_all,
+    fields(k, fusion = ?fusion.method, metric = fusion.similarity.as_str(), candidates = field::Empty, results = field::Empty, duration_ms = field::Empty)
+)]
 pub async fn hybrid_search(
     pool: &PgPool,
     query_embedding: &[f32],
     query_text: &str,
     k: i32,
-    filter_tags: Option<&Vec<String>>,
+    filters: Option<&QueryFilters>,
+    fusion: &FusionOptions,
 ) -> Result<Vec<ChunkWithScore>> {
+    let started = Instant::now();
+    let FusionOptions { method, rrf_k, weights, normalization, min_score, similarity, ref boost } = *fusion;
     // Convert embedding to pgvector::Vector
     let vector = Vector::from(query_embedding.to_vec());
     
-    // Use the hybrid_search function we defined in SQL
-    let rows = sqlx::query(
+    // Unset filters bind as NULL, which the SQL function treats as "no restriction"
+    let filter_tags = filters.and_then(|f| f.tags.as_ref());
+    let filter_document_ids = filters.and_then(|f| f.document_ids.as_ref());
+    let (created_from, created_to) = filters
+        .and_then(|f| f.date_range)
+        .map_or((None, None), |(from, to)| (Some(from), Some(to)));
+    let include_superseded = filters.is_some_and(|f| f.include_superseded);
+    let content_kind = filters.and_then(|f| f.content_kind).map(ContentKind::as_str);
+    
+    // Use the search function we defined in SQL, joining chunk and document
+    // columns in the same query so sources don't need a lookup per result
+    let (search, ranks) = match method {
+        FusionMethod::WeightedSum => (
+            "hybrid_search($1::vector, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+            "NULL::bigint AS semantic_rank, NULL::bigint AS lexical_rank",
+        ),
+        FusionMethod::Rrf => (
+            "rrf_search($1::vector, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            "h.semantic_rank, h.lexical_rank",
+        ),
+    };
+    let sql = format!(
         r#"
         SELECT 
-            chunk_id,
-            document_id,
-            content,
-            section,
-            metadata,
+            h.chunk_id,
+            h.document_id,
+            h.content,
+            h.section,
+            h.metadata,
+            h.semantic_score,
+            h.lexical_score,
+            {ranks},
+            c.content_tokens,
+            c.span,
+            c.created_at,
+            d.source_uri,
+            d.tags,
+            d.embedding_model
+        FROM {search} h
+        JOIN chunks c ON c.id = h.chunk_id
+        JOIN documents d ON d.id = h.document_id
+        "#
+    );
+
+    let query = sqlx::query(&sql)
+        .bind(vector)
+        .bind(query_text)
+        .bind(k)
+        .bind(filter_tags)
+        .bind(filter_document_ids)
+        .bind(created_from)
+        .bind(created_to);
+    let query = match method {
+        FusionMethod::WeightedSum => query.bind(weights.semantic as f64).bind(weights.lexical as f64),
+        FusionMethod::Rrf => query.bind(rrf_k as i32),
+    };
+    let query = query.bind(similarity.as_str()).bind(include_superseded).bind(content_kind);
+    let rows = query.fetch_all(pool).await?;
+
+    let semantic: Vec<f32> = rows.iter().map(|r| r.get::<f64, _>("semantic_score") as f32).collect();
+    let lexical: Vec<f32> = rows.iter().map(|r| r.get::<f64, _>("lexical_score") as f32).collect();
+    let semantic_normalized = normalize_scores(&semantic, normalization);
+    let lexical_normalized = normalize_scores(&lexical, normalization);
+
+    let mut results = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        let semantic_score = ComponentScore {
+            raw: semantic[i],
+            normalized: semantic_normalized[i],
+        };
+        let lexical_score = ComponentScore {
+            raw: lexical[i],
+            normalized: lexical_normalized[i],
+        };
+        let semantic_rank = row.get::<Option<i64>, _>("semantic_rank").map(|r| r as u32);
+        let lexical_rank = row.get::<Option<i64>, _>("lexical_rank").map(|r| r as u32);
+
+        // The SQL function used the same fusion to pick candidates; score again here in f32
+        let score = match method {
+            FusionMethod::WeightedSum => {
+                weights.semantic * semantic_score.normalized + weights.lexical * lexical_score.normalized
+            }
+            FusionMethod::Rrf => [semantic_rank, lexical_rank]
+                .into_iter()
+                .flatten()
+                .map(|rank| 1.0 / (rrf_k + rank) as f32)
+                .sum(),
+        };
+        let document_tags = row.get::<Option<Vec<String>>, _>("tags").unwrap_or_default();
+        let boost = boost.as_ref().and_then(|b| b.factor_for(&document_tags));
+
+        results.push(ChunkWithScore {
+            chunk: chunk_from_row(&row),
+            score: boosted(score, boost),
+            source_uri: Some(row.get("source_uri")),
+            document_tags,
             semantic_score,
             lexical_score,
-            combined_score
-        FROM hybrid_search($1::vector, $2, $3, $4)
+            semantic_rank,
+            lexical_rank,
+            expansion: None,
+            embedding_model: row.get("embedding_model"),
+            boost,
+        });
+    }
+
+    let candidates = results.len();
+    if let Some(min_score) = min_score {
+        results.retain(|c| c.score >= min_score);
+    }
+    results.sort_by(by_score_desc);
+
+    match method {
+        FusionMethod::WeightedSum => info!(
+            "Hybrid search returned {} results ({} below min_score, weights {:.2}/{:.2}, {:?} normalization)",
+            results.len(),
+            candidates - results.len(),
+            weights.semantic,
+            weights.lexical,
+            normalization
+        ),
+        FusionMethod::Rrf => info!(
+            "Hybrid search returned {} results ({} below min_score, RRF k={})",
+            results.len(),
+            candidates - results.len(),
+            rrf_k
+        ),
+    }
+
+    let span = Span::current();
+    span.record("candidates", candidates);
+    span.record("results", results.len());
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    Ok(results)
+}
+
+/// Full-text-only retrieval for when no query embedding is available. Uses the
+/// same filters and tag boost as `hybrid_search`; the score is the normalized `ts_rank_cd`.
+pub async fn lexical_search(
+    pool: &PgPool,
+    query_text: &str,
+    k: i32,
+    filters: Option<&QueryFilters>,
+    fusion: &FusionOptions,
+) -> Result<Vec<ChunkWithScore>> {
+    let filter_tags = filters.and_then(|f| f.tags.as_ref());
+    let filter_document_ids = filters.and_then(|f| f.document_ids.as_ref());
+    let (created_from, created_to) = filters
+        .and_then(|f| f.date_range)
+        .map_or((None, None), |(from, to)| (Some(from), Some(to)));
+    let include_superseded = filters.is_some_and(|f| f.include_superseded);
+    let content_kind = filters.and_then(|f| f.content_kind).map(ContentKind::as_str);
+
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            c.id AS chunk_id,
+            c.document_id,
+            c.content,
+            c.section,
+            c.metadata,
+            c.content_tokens,
+            c.span,
+            c.created_at,
+            d.source_uri,
+            d.tags,
+            d.embedding_model,
+            ts_rank_cd(to_tsvector('simple', c.content), plainto_tsquery('simple', $1))::double precision AS lexical_score
+        FROM chunks c
+        JOIN documents d ON d.id = c.document_id
+        WHERE to_tsvector('simple', c.content) @@ plainto_tsquery('simple', $1)
+            AND ($3::text[] IS NULL OR d.tags && $3)
+            AND ($4::uuid[] IS NULL OR d.id = ANY($4))
+            AND ($5::timestamptz IS NULL OR d.created_at >= $5)
+            AND ($6::timestamptz IS NULL OR d.created_at <= $6)
+            AND ($7 OR d.superseded_at IS NULL)
+            AND ($8::text IS NULL OR COALESCE(c.metadata->>'kind', 'prose') = $8)
+        ORDER BY lexical_score DESC, c.id
+        LIMIT $2
         "#
     )
-    .bind(vector)
     .bind(query_text)
     .bind(k)
     .bind(filter_tags)
+    .bind(filter_document_ids)
+    .bind(created_from)
+    .bind(created_to)
+    .bind(include_superseded)
+    .bind(content_kind)
     .fetch_all(pool)
     .await?;
 
-    let mut results = Vec::new();
-    for row in rows {
-        let chunk = Chunk {
-            id: row.get("chunk_id"),
-            document_id: row.get("document_id"),
-            content: row.get("content"),
-            content_tokens: None,
-            section: row.get("section"),
-            span: row.get("metadata"),
-            metadata: row.get("metadata"),
-            embedding: None,
-            created_at: chrono::Utc::now(),
-        };
+    let lexical: Vec<f32> = rows.iter().map(|r| r.get::<f64, _>("lexical_score") as f32).collect();
+    let lexical_normalized = normalize_scores(&lexical, fusion.normalization);
 
-        results.push(ChunkWithScore {
-            chunk,
-            score: row.get("combined_score"),
-            source_uri: None,
-        });
+    let mut results: Vec<ChunkWithScore> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let document_tags = row.get::<Option<Vec<String>>, _>("tags").unwrap_or_default();
+            let boost = fusion.boost.as_ref().and_then(|b| b.factor_for(&document_tags));
+            ChunkWithScore {
+                chunk: chunk_from_row(row),
+                score: boosted(lexical_normalized[i], boost),
+                source_uri: Some(row.get("source_uri")),
+                document_tags,
+                semantic_score: ComponentScore::default(),
+                lexical_score: ComponentScore {
+                    raw: lexical[i],
+                    normalized: lexical_normalized[i],
+                },
+                semantic_rank: None,
+                lexical_rank: None,
+                expansion: None,
+                embedding_model: row.get("embedding_model"),
+                boost,
+            }
+        })
+        .collect();
+
+    let candidates = results.len();
+    if let Some(min_score) = fusion.min_score {
+        results.retain(|c| c.score >= min_score);
     }
+    results.sort_by(by_score_desc);
 
-    info!("Hybrid search returned {} results", results.len());
+    info!(
+        "Lexical-only search returned {} results ({} below min_score)",
+        results.len(),
+        candidates - results.len()
+    );
     Ok(results)
 }
 
-pub fn rerank_chunks(chunks: &[ChunkWithScore], query_embedding: &[f32], top_k: usize) -> Vec<ChunkWithScore> {
-    let mut reranked = chunks.to_vec();
-    
-    // Simple cosine similarity reranking for now
-    // In production, use a cross-encoder model
-    if let Some(first_chunk) = chunks.first() {
-        if let Some(ref _embedding) = first_chunk.chunk.embedding {
-            for chunk in &mut reranked {
-                if let Some(ref chunk_embedding) = chunk.chunk.embedding {
-                    chunk.score = cosine_similarity(query_embedding, chunk_embedding);
-                }
+/// ANN-only retrieval: the semantic branch of `hybrid_search` on its own, with
+/// the same filters and tag boost. The score is the normalized similarity.
+pub async fn semantic_search(
+    pool: &PgPool,
+    query_embedding: &[f32],
+    k: i32,
+    filters: Option<&QueryFilters>,
+    fusion: &FusionOptions,
+) -> Result<Vec<ChunkWithScore>> {
+    let filter_tags = filters.and_then(|f| f.tags.as_ref());
+    let filter_document_ids = filters.and_then(|f| f.document_ids.as_ref());
+    let (created_from, created_to) = filters
+        .and_then(|f| f.date_range)
+        .map_or((None, None), |(from, to)| (Some(from), Some(to)));
+    let include_superseded = filters.is_some_and(|f| f.include_superseded);
+    let content_kind = filters.and_then(|f| f.content_kind).map(ContentKind::as_str);
+
+    // Same expressions as the SQL `similarity_sql` helper, so scores match hybrid search
+    let (distance, score) = match fusion.similarity {
+        SimilarityMetric::Cosine => ("c.embedding <=> $1", "1 - (c.embedding <=> $1)"),
+        SimilarityMetric::Dot => ("c.embedding <#> $1", "-(c.embedding <#> $1)"),
+        SimilarityMetric::Euclidean => ("c.embedding <-> $1", "1 / (1 + (c.embedding <-> $1))"),
+    };
+    let sql = format!(
+        r#"
+        SELECT
+            c.id AS chunk_id,
+            c.document_id,
+            c.content,
+            c.section,
+            c.metadata,
+            c.content_tokens,
+            c.span,
+            c.created_at,
+            d.source_uri,
+            d.tags,
+            d.embedding_model,
+            ({score})::double precision AS semantic_score
+        FROM chunks c
+        JOIN documents d ON d.id = c.document_id
+        WHERE c.embedding IS NOT NULL
+            AND ($3::text[] IS NULL OR d.tags && $3)
+            AND ($4::uuid[] IS NULL OR d.id = ANY($4))
+            AND ($5::timestamptz IS NULL OR d.created_at >= $5)
+            AND ($6::timestamptz IS NULL OR d.created_at <= $6)
+            AND ($7 OR d.superseded_at IS NULL)
+            AND ($8::text IS NULL OR COALESCE(c.metadata->>'kind', 'prose') = $8)
+        ORDER BY {distance}
+        LIMIT $2
+        "#
+    );
+
+    let rows = sqlx::query(&sql)
+        .bind(Vector::from(query_embedding.to_vec()))
+        .bind(k)
+        .bind(filter_tags)
+        .bind(filter_document_ids)
+        .bind(created_from)
+        .bind(created_to)
+        .bind(include_superseded)
+        .bind(content_kind)
+        .fetch_all(pool)
+        .await?;
+
+    let semantic: Vec<f32> = rows.iter().map(|r| r.get::<f64, _>("semantic_score") as f32).collect();
+    let semantic_normalized = normalize_scores(&semantic, fusion.normalization);
+
+    let mut results: Vec<ChunkWithScore> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let document_tags = row.get::<Option<Vec<String>>, _>("tags").unwrap_or_default();
+            let boost = fusion.boost.as_ref().and_then(|b| b.factor_for(&document_tags));
+            ChunkWithScore {
+                chunk: chunk_from_row(row),
+                score: boosted(semantic_normalized[i], boost),
+                source_uri: Some(row.get("source_uri")),
+                document_tags,
+                semantic_score: ComponentScore {
+                    raw: semantic[i],
+                    normalized: semantic_normalized[i],
+                },
+                lexical_score: ComponentScore::default(),
+                semantic_rank: None,
+                lexical_rank: None,
+                expansion: None,
+                embedding_model: row.get("embedding_model"),
+                boost,
             }
+        })
+        .collect();
+
+    let candidates = results.len();
+    if let Some(min_score) = fusion.min_score {
+        results.retain(|c| c.score >= min_score);
+    }
+    // The SQL orders by distance alone so the vector index can serve it; equal
+    // distances get their chunk id order here
+    results.sort_by(by_score_desc);
+
+    info!(
+        "Semantic-only search returned {} results ({} below min_score, {} similarity)",
+        results.len(),
+        candidates - results.len(),
+        fusion.similarity.as_str()
+    );
+    Ok(results)
+}
+
+fn chunk_from_row(row: &PgRow) -> Chunk {
+    Chunk {
+        id: row.get("chunk_id"),
+        document_id: row.get("document_id"),
+        content: row.get("content"),
+        content_tokens: row.get("content_tokens"),
+        section: row.get("section"),
+        span: row.get("span"),
+        metadata: row.get("metadata"),
+        embedding: None,
+        created_at: row.get("created_at"),
+    }
+}
+
+/// Rescales one score distribution over the candidate set. A constant
+/// distribution carries no ranking signal, so it maps to 0 rather than dividing by zero.
+fn normalize_scores(scores: &[f32], normalization: ScoreNormalization) -> Vec<f32> {
+    match normalization {
+        ScoreNormalization::None => scores.to_vec(),
+        ScoreNormalization::MinMax => {
+            let (min, max) = scores.iter().fold((f32::MAX, f32::MIN), |(min, max), &s| {
+                (min.min(s), max.max(s))
+            });
+            let range = max - min;
+            scores
+                .iter()
+                .map(|&s| if range > 0.0 { (s - min) / range } else { 0.0 })
+                .collect()
+        }
+        ScoreNormalization::ZScore => {
+            let n = scores.len().max(1) as f32;
+            let mean = scores.iter().sum::<f32>() / n;
+            let stddev = (scores.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n).sqrt();
+            scores
+                .iter()
+                .map(|&s| if stddev > 0.0 { (s - mean) / stddev } else { 0.0 })
+                .collect()
         }
     }
+}
 
-    // Sort by score descending
-    reranked.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+/// Total order on scores with NaN below everything, so sorting never panics.
+pub fn score_order(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).unwrap(),
+    }
+}
 
+/// Highest score first; equal scores are ordered by chunk id so results are
+/// stable between runs.
+pub fn by_score_desc(a: &ChunkWithScore, b: &ChunkWithScore) -> Ordering {
+    score_order(b.score, a.score).then_with(|| a.chunk.id.cmp(&b.chunk.id))
+}
+
+/// Loads stored embeddings for the first `top_n` chunks in a single query so they
+/// can be reranked. Returns how many chunks received an embedding.
+pub async fn fetch_embeddings(pool: &PgPool, chunks: &mut [ChunkWithScore], top_n: usize) -> Result<usize> {
+    let top_n = top_n.min(chunks.len());
+    let candidates = &mut chunks[..top_n];
+    if candidates.is_empty() {
+        return Ok(0);
+    }
+
+    let ids: Vec<uuid::Uuid> = candidates.iter().map(|c| c.chunk.id).collect();
+    let rows = sqlx::query(
+        "SELECT id, embedding FROM chunks WHERE id = ANY($1) AND embedding IS NOT NULL"
+    )
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut embeddings: HashMap<uuid::Uuid, Vec<f32>> = rows
+        .into_iter()
+        .map(|row| (row.get("id"), row.get::<Vector, _>("embedding").to_vec()))
+        .collect();
+
+    let mut attached = 0;
+    for chunk in candidates.iter_mut() {
+        if let Some(embedding) = embeddings.remove(&chunk.chunk.id) {
+            chunk.chunk.embedding = Some(embedding);
+            attached += 1;
+        }
+    }
+
+    Ok(attached)
+}
+
+/// A chunk adjacent to a result, `offset` positions away from it in its document.
+struct Neighbor {
+    offset: i32,
+    id: uuid::Uuid,
+    content: String,
+    tokens: usize,
+    span: Option<serde_json::Value>,
+}
+
+/// Merges up to `before`/`after` neighbouring chunks (by `chunk_index`) into each
+/// chunk's content, nearest first, while the total stays within `max_tokens`.
+/// The span is widened to cover the merged text so citations point at all of it.
+///
+/// Each chunk's text appears once in the results: results are expanded in rank
+/// order, and a side stops growing at a chunk that is itself a result or was
+/// already merged into a higher-ranked one.
+pub async fn expand_neighbors(pool: &PgPool, chunks: &mut [ChunkWithScore], expand: ExpandContext) -> Result<()> {
+    if chunks.is_empty() || (expand.before == 0 && expand.after == 0) {
+        return Ok(());
+    }
+
+    let ids: Vec<uuid::Uuid> = chunks.iter().map(|c| c.chunk.id).collect();
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            t.id AS target_id,
+            n.id,
+            n.content,
+            n.content_tokens,
+            n.span,
+            n.chunk_index - t.chunk_index AS relative_index
+        FROM chunks t
+        JOIN chunks n ON n.document_id = t.document_id
+            AND n.chunk_index BETWEEN t.chunk_index - $2 AND t.chunk_index + $3
+            AND n.id <> t.id
+        WHERE t.id = ANY($1)
+        "#
+    )
+    .bind(&ids)
+    .bind(expand.before as i32)
+    .bind(expand.after as i32)
+    .fetch_all(pool)
+    .await?;
+
+    let mut neighbors: HashMap<uuid::Uuid, Vec<Neighbor>> = HashMap::new();
+    for row in rows {
+        let content: String = row.get("content");
+        let tokens = row
+            .get::<Option<i32>, _>("content_tokens")
+            .map_or_else(|| estimate_tokens(&content), |t| t as usize);
+        neighbors.entry(row.get("target_id")).or_default().push(Neighbor {
+            offset: row.get("relative_index"),
+            id: row.get("id"),
+            content,
+            tokens,
+            span: row.get("span"),
+        });
+    }
+
+    // Chunks whose text is already in the results
+    let mut claimed: HashSet<uuid::Uuid> = ids.iter().copied().collect();
+    let mut expanded = 0;
+    for chunk in chunks.iter_mut() {
+        let Some(mut candidates) = neighbors.remove(&chunk.chunk.id) else { continue };
+
+        // Nearest first, the preceding chunk winning ties
+        candidates.sort_by_key(|n| (n.offset.abs(), n.offset > 0));
+
+        let mut tokens = chunk
+            .chunk
+            .content_tokens
+            .map_or_else(|| estimate_tokens(&chunk.chunk.content), |t| t as usize);
+        let mut included = Vec::new();
+        let mut truncated = false;
+        let mut shared = 0;
+        let (mut before_blocked, mut after_blocked) = (false, false);
+        for candidate in candidates {
+            let blocked = if candidate.offset < 0 { &mut before_blocked } else { &mut after_blocked };
+            if claimed.contains(&candidate.id) {
+                // Past a shared chunk the text wouldn't be contiguous with this one
+                *blocked = true;
+                shared += 1;
+                continue;
+            }
+            if *blocked {
+                continue;
+            }
+            if tokens + candidate.tokens > expand.max_tokens {
+                truncated = true;
+                continue;
+            }
+            tokens += candidate.tokens;
+            claimed.insert(candidate.id);
+            included.push(candidate);
+        }
+        if included.is_empty() {
+            continue;
+        }
+        included.sort_by_key(|n| n.offset);
+
+        let spans: Vec<&serde_json::Value> = included
+            .iter()
+            .filter_map(|n| n.span.as_ref())
+            .chain(chunk.chunk.span.as_ref())
+            .collect();
+        let start = spans.iter().filter_map(|s| s.get("start_char").and_then(|v| v.as_u64())).min();
+        let end = spans.iter().filter_map(|s| s.get("end_char").and_then(|v| v.as_u64())).max();
+        if let (Some(start), Some(end), Some(serde_json::Value::Object(span))) =
+            (start, end, chunk.chunk.span.as_mut())
+        {
+            span.insert("start_char".to_string(), start.into());
+            span.insert("end_char".to_string(), end.into());
+        }
+
+        let (before, after): (Vec<_>, Vec<_>) = included.into_iter().partition(|n| n.offset < 0);
+        let mut parts: Vec<String> = before.iter().map(|n| n.content.clone()).collect();
+        parts.push(std::mem::take(&mut chunk.chunk.content));
+        parts.extend(after.iter().map(|n| n.content.clone()));
+        chunk.chunk.content = parts.join("\n\n");
+        chunk.chunk.content_tokens = Some(tokens as i32);
+
+        chunk.expansion = Some(ContextExpansion {
+            chunk_ids: before.iter().chain(&after).map(|n| n.id).collect(),
+            before: before.len(),
+            after: after.len(),
+            tokens,
+            truncated,
+            shared,
+        });
+        expanded += 1;
+    }
+
+    info!("Expanded {} of {} chunks with neighbouring context", expanded, chunks.len());
+    Ok(())
+}
+
+#[instrument(
+    skip_all,
+    fields(candidates = chunks.len(), top_k, metric = similarity.as_str(), scored = field::Empty, results = field::Empty, duration_ms = field::Empty)
+)]
+pub fn rerank_chunks(
+    chunks: &[ChunkWithScore],
+    query_embedding: &[f32],
+    top_k: usize,
+    diversity: Diversity,
+    similarity: SimilarityMetric,
+) -> Vec<ChunkWithScore> {
+    let started = Instant::now();
+    // Simple vector similarity reranking for now
+    // In production, use a cross-encoder model
+    let (mut reranked, rest): (Vec<ChunkWithScore>, Vec<ChunkWithScore>) = chunks
+        .iter()
+        .cloned()
+        .partition(|c| c.chunk.embedding.is_some());
+
+    let embeddings: Vec<&[f32]> = reranked.iter().filter_map(|c| c.chunk.embedding.as_deref()).collect();
+    let scores = vector_math::similarity_many(similarity, query_embedding, &embeddings);
+    for (chunk, score) in reranked.iter_mut().zip(scores) {
+        chunk.score = boosted(score, chunk.boost);
+    }
+
+    // Sort by score descending; chunks without an embedding keep their fused order after them
+    reranked.sort_by(by_score_desc);
+    let scored = reranked.len();
+    reranked.extend(rest);
+
+    let results = diversify(reranked, top_k, diversity);
+    let span = Span::current();
+    span.record("scored", scored);
+    span.record("results", results.len());
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    results
+}
+
+/// Picks `top_k` of the already-ordered chunks using the requested diversity strategy.
+pub fn diversify(ranked: Vec<ChunkWithScore>, top_k: usize, diversity: Diversity) -> Vec<ChunkWithScore> {
+    match diversity {
+        Diversity::PerDocument => per_document_cap(ranked, top_k),
+        Diversity::Mmr { lambda } => maximal_marginal_relevance(ranked, top_k, lambda),
+    }
+}
+
+/// Keeps the best `top_k` chunks, at most 2 per document.
+fn per_document_cap(ranked: Vec<ChunkWithScore>, top_k: usize) -> Vec<ChunkWithScore> {
     // Enforce diversity: max 2 chunks per document
     let mut seen_docs = HashMap::new();
     let mut diverse_results = Vec::new();
     
-    for chunk in reranked {
+    for chunk in ranked {
         let count = seen_docs.entry(chunk.chunk.document_id).or_insert(0);
         if *count < 2 {
             *count += 1;
@@ -107,13 +757,162 @@ pub fn rerank_chunks(chunks: &[ChunkWithScore], query_embedding: &[f32], top_k:
     diverse_results
 }
 
-pub async fn get_document_source_uri(pool: &PgPool, document_id: uuid::Uuid) -> Result<String> {
-    let source_uri: String = sqlx::query_scalar(
-        "SELECT source_uri FROM documents WHERE id = $1"
-    )
-    .bind(document_id)
-    .fetch_one(pool)
-    .await?;
+/// Greedy MMR: repeatedly takes the chunk maximizing
+/// `lambda * relevance - (1 - lambda) * max_similarity_to_selected`.
+/// Relevance is the min-max normalized score so it is on the same 0..1 scale as
+/// cosine similarity, which measures redundancy whatever `SIMILARITY_METRIC` is;
+/// chunks without an embedding count as dissimilar to everything.
+fn maximal_marginal_relevance(ranked: Vec<ChunkWithScore>, top_k: usize, lambda: f32) -> Vec<ChunkWithScore> {
+    let (min, max) = ranked.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+        (min.min(c.score), max.max(c.score))
+    });
+    let range = max - min;
+    let relevance: Vec<f32> = ranked
+        .iter()
+        .map(|c| if range > 0.0 { (c.score - min) / range } else { 1.0 })
+        .collect();
+
+    // Normalized once so each pairwise similarity is a plain dot product
+    let units: Vec<Option<Vec<f32>>> = ranked
+        .iter()
+        .map(|c| c.chunk.embedding.as_deref().map(vector_math::normalize))
+        .collect();
+
+    let mut remaining: Vec<usize> = (0..ranked.len()).collect();
+    let mut selected: Vec<usize> = Vec::new();
+
+    while selected.len() < top_k && !remaining.is_empty() {
+        let marginal = |i: usize| {
+            let redundancy = selected
+                .iter()
+                .filter_map(|&j| match (&units[i], &units[j]) {
+                    (Some(a), Some(b)) => Some(vector_math::dot(a, b)),
+                    _ => None,
+                })
+                .fold(0.0f32, f32::max);
+            lambda * relevance[i] - (1.0 - lambda) * redundancy
+        };
+
+        // Ties keep the incoming order
+        let (position, _) = remaining
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| (position, marginal(i)))
+            .fold((0, f32::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+
+        selected.push(remaining.remove(position));
+    }
+
+    let mut ranked: Vec<Option<ChunkWithScore>> = ranked.into_iter().map(Some).collect();
+    selected.into_iter().filter_map(|i| ranked[i].take()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
 
-    Ok(source_uri)
-}
\ No newline at end of file
+    /// A scored chunk with a fixed id, so tie-breaks are predictable.
+    fn scored(id: u128, document: u128, score: f32, embedding: Option<Vec<f32>>) -> ChunkWithScore {
+        ChunkWithScore {
+            chunk: Chunk {
+                id: Uuid::from_u128(id),
+                document_id: Uuid::from_u128(document),
+                content: format!("chunk {}", id),
+                content_tokens: None,
+                section: None,
+                span: None,
+                metadata: None,
+                embedding,
+                created_at: chrono::Utc::now(),
+            },
+            score,
+            source_uri: None,
+            document_tags: Vec::new(),
+            semantic_score: ComponentScore::default(),
+            lexical_score: ComponentScore::default(),
+            semantic_rank: None,
+            lexical_rank: None,
+            expansion: None,
+            embedding_model: None,
+            boost: None,
+        }
+    }
+
+    fn ids(chunks: &[ChunkWithScore]) -> Vec<u128> {
+        chunks.iter().map(|c| c.chunk.id.as_u128()).collect()
+    }
+
+    #[test]
+    fn rerank_orders_by_query_similarity() {
+        let query = [1.0, 0.0];
+        // Fused order is 1, 2, 3, 4; the embeddings rank them 3, 2, 1, and 4 has none
+        let chunks = vec![
+            scored(1, 1, 0.9, Some(vec![0.0, 1.0])),
+            scored(2, 2, 0.8, Some(vec![1.0, 1.0])),
+            scored(3, 3, 0.7, Some(vec![1.0, 0.0])),
+            scored(4, 4, 0.6, None),
+        ];
+
+        let reranked = rerank_chunks(&chunks, &query, 10, Diversity::PerDocument, SimilarityMetric::Cosine);
+        assert_eq!(ids(&reranked), [3, 2, 1, 4]);
+        assert!((reranked[0].score - 1.0).abs() < 1e-6);
+        // Unscored chunks keep their fused score
+        assert_eq!(reranked[3].score, 0.6);
+    }
+
+    #[test]
+    fn rerank_returns_the_requested_top_k() {
+        let query = [1.0, 0.0];
+        let chunks: Vec<ChunkWithScore> = (1..=12)
+            .map(|i| scored(i, i, 1.0 / i as f32, Some(vec![1.0, i as f32])))
+            .collect();
+
+        let reranked = rerank_chunks(&chunks, &query, 10, Diversity::PerDocument, SimilarityMetric::Cosine);
+        assert_eq!(ids(&reranked), (1..=10).collect::<Vec<_>>());
+        let reranked = rerank_chunks(&chunks, &query, 3, Diversity::PerDocument, SimilarityMetric::Cosine);
+        assert_eq!(ids(&reranked), [1, 2, 3]);
+    }
+
+    #[test]
+    fn mmr_demotes_near_duplicates_that_top_k_keeps() {
+        // 2 is nearly the same vector as 1 and almost as relevant; 3 is less relevant but different
+        let ranked = vec![
+            scored(1, 1, 0.90, Some(vec![1.0, 0.0])),
+            scored(2, 2, 0.89, Some(vec![1.0, 0.01])),
+            scored(3, 3, 0.80, Some(vec![0.0, 1.0])),
+        ];
+
+        let top_k = diversify(ranked.clone(), 2, Diversity::PerDocument);
+        assert_eq!(ids(&top_k), [1, 2]);
+
+        let mmr = diversify(ranked.clone(), 2, Diversity::Mmr { lambda: 0.5 });
+        assert_eq!(ids(&mmr), [1, 3]);
+
+        // With lambda = 1 only relevance counts, so MMR is plain top-k again
+        let relevance_only = diversify(ranked, 2, Diversity::Mmr { lambda: 1.0 });
+        assert_eq!(ids(&relevance_only), [1, 2]);
+    }
+
+    #[test]
+    fn equal_scores_are_ordered_by_chunk_id() {
+        let query = [1.0, 0.0];
+        // Same embedding, so every candidate reranks to the same score; one scores NaN
+        let chunks = vec![
+            scored(5, 5, 0.9, Some(vec![1.0, 1.0])),
+            scored(7, 7, 0.8, Some(vec![f32::NAN, 1.0])),
+            scored(2, 2, 0.7, Some(vec![1.0, 1.0])),
+            scored(9, 9, 0.6, Some(vec![1.0, 1.0])),
+        ];
+
+        for _ in 0..3 {
+            let reranked = rerank_chunks(&chunks, &query, 10, Diversity::PerDocument, SimilarityMetric::Cosine);
+            assert_eq!(ids(&reranked), [2, 5, 9, 7]);
+        }
+
+        let mut fused = chunks.clone();
+        fused.iter_mut().for_each(|c| c.score = 0.5);
+        fused.sort_by(by_score_desc);
+        assert_eq!(ids(&fused), [2, 5, 7, 9]);
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::Utc;
+use pgvector::Vector;
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::services::answer::{self, AnswerConfig};
+use crate::services::chunking::{self, Chunk};
+use crate::services::embedding;
+use crate::services::embedding_provider::EmbeddingProvider;
+use crate::services::ingest::{warning, IngestError};
+
+const SUMMARY_PROMPT: &str = "Summarize the document the user sends in one paragraph of at most five \
+sentences. Name its subject and main points, and reply with the summary only.";
+const DEFAULT_MIN_TOKENS: usize = 1000;
+const DEFAULT_MAX_INPUT_TOKENS: usize = 8000;
+const DEFAULT_MAX_SUMMARY_TOKENS: usize = 200;
+/// Section name of the stored summary chunk
+const SUMMARY_SECTION: &str = "Summary";
+
+/// When and how much of a document is summarized during ingest.
+#[derive(Debug, Clone)]
+pub struct SummaryConfig {
+    /// `DOCUMENT_SUMMARIES` (default off)
+    pub enabled: bool,
+    /// Documents with fewer chunk tokens than this are skipped (`SUMMARY_MIN_TOKENS`)
+    pub min_tokens: usize,
+    /// Chunk tokens sent to the model; longer documents are summarized from their beginning
+    pub max_input_tokens: usize,
+    pub max_summary_tokens: usize,
+}
+
+impl SummaryConfig {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: usize| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            enabled: env::var("DOCUMENT_SUMMARIES")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            min_tokens: number("SUMMARY_MIN_TOKENS", DEFAULT_MIN_TOKENS),
+            max_input_tokens: number("SUMMARY_MAX_INPUT_TOKENS", DEFAULT_MAX_INPUT_TOKENS).max(1),
+            max_summary_tokens: number("SUMMARY_MAX_TOKENS", DEFAULT_MAX_SUMMARY_TOKENS).max(1),
+        }
+    }
+}
+
+/// A generated summary with its embedding, ready to store with its document.
+pub struct DocumentSummary {
+    pub text: String,
+    pub model: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Summarizes and embeds a document from its chunks when summaries are enabled and
+/// the document is long enough. A failed summary only adds a `summary_failed`
+/// warning; a failed embedding fails like a chunk embedding would.
+pub async fn prepare_summary(
+    pool: &PgPool,
+    embedder: &dyn EmbeddingProvider,
+    chunks: &[Chunk],
+    warnings: &mut Vec<String>,
+) -> Result<Option<DocumentSummary>, IngestError> {
+    let config = SummaryConfig::from_env();
+    let tokens: usize = chunks.iter().map(|chunk| chunk.tokens).sum();
+    if !config.enabled || tokens < config.min_tokens {
+        return Ok(None);
+    }
+
+    let text = match summarize(chunks, &config).await {
+        Ok(text) if !text.is_empty() => text,
+        Ok(_) => {
+            warnings.push(warning("summary_failed", "the chat model returned an empty summary"));
+            return Ok(None);
+        }
+        Err(e) => {
+            warn!("Document summary failed: {}", e);
+            warnings.push(warning("summary_failed", format!("no summary was generated: {}", e)));
+            return Ok(None);
+        }
+    };
+
+    let embedding = embedding::get_embeddings_cached(pool, embedder, &[text.as_str()])
+        .await
+        .map_err(IngestError::Embedding)?
+        .embeddings
+        .remove(0);
+    Ok(Some(DocumentSummary {
+        text,
+        model: AnswerConfig::from_env().model,
+        embedding,
+    }))
+}
+
+/// Asks the chat model for a one-paragraph summary of the chunks, in document order,
+/// up to `max_input_tokens` of them.
+async fn summarize(chunks: &[Chunk], config: &SummaryConfig) -> Result<String> {
+    let mut used = 0;
+    let mut passages = Vec::new();
+    for chunk in chunks {
+        if used + chunk.tokens > config.max_input_tokens && !passages.is_empty() {
+            break;
+        }
+        used += chunk.tokens;
+        passages.push(chunk.content.as_str());
+    }
+
+    let summary = answer::complete(SUMMARY_PROMPT, &passages.join("\n\n"), config.max_summary_tokens, 0.2).await?;
+    info!("Summarized {} of {} chunks ({} tokens)", passages.len(), chunks.len(), used);
+    Ok(summary.trim().to_string())
+}
+
+/// Records the summary in the document's `metadata.summary` and stores it as an
+/// extra chunk with `"kind": "summary"`, so it is searchable as a coarse match for
+/// the whole document. The chunk has no `chunk_index`, so context expansion never
+/// merges it. Runs on the caller's transaction.
+pub async fn store_summary(
+    conn: &mut PgConnection,
+    document_id: Uuid,
+    summary: &DocumentSummary,
+    embedding_model: &str,
+) -> Result<()> {
+    let generated_at = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE documents
+        SET metadata = COALESCE(metadata, '{}'::jsonb) || jsonb_build_object('summary', $2::jsonb)
+        WHERE id = $1
+        "#,
+    )
+    .bind(document_id)
+    .bind(json!({ "text": summary.text, "model": summary.model, "generated_at": generated_at }))
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO chunks (document_id, content, content_tokens, section, metadata, embedding)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(document_id)
+    .bind(&summary.text)
+    .bind(chunking::count_tokens(&summary.text) as i32)
+    .bind(SUMMARY_SECTION)
+    .bind(json!({
+        "kind": "summary",
+        "summary_model": summary.model,
+        "tokenizer": chunking::TOKENIZER,
+        "embedding_model": embedding_model,
+        "ingested_at": generated_at,
+    }))
+    .bind(Vector::from(summary.embedding.clone()))
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
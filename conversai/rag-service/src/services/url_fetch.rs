@@ -0,0 +1,409 @@
+use anyhow::Result;
+use reqwest::{header, redirect, Url};
+use std::env;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::services::markdown;
+
+const USER_AGENT: &str = concat!("conversai-rag/", env!("CARGO_PKG_VERSION"));
+const MAX_REDIRECTS: usize = 5;
+
+/// Why a URL could not be fetched for ingestion. Each variant has its own API
+/// error code, so clients can tell a bad URL from an unreachable or unsuitable page.
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("invalid URL {url}: {reason}")]
+    InvalidUrl { url: String, reason: String },
+
+    #[error("{url} resolves to the non-public address {address}")]
+    BlockedAddress { url: String, address: IpAddr },
+
+    #[error("{url} redirected more than {max} times")]
+    TooManyRedirects { url: String, max: usize },
+
+    #[error("{url} did not respond within {secs}s")]
+    Timeout { url: String, secs: u64 },
+
+    #[error("{url} returned HTTP {status}")]
+    Status { url: String, status: u16 },
+
+    #[error("{url} has unsupported content type {content_type:?}; expected HTML, markdown or plain text")]
+    UnsupportedContentType { url: String, content_type: String },
+
+    #[error("{url} exceeds the maximum size of {limit} bytes")]
+    TooLarge { url: String, limit: usize },
+
+    #[error("could not resolve {url}: {source}")]
+    Resolve {
+        url: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("could not fetch {url}: {source}")]
+    Request {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+}
+
+impl FetchError {
+    pub fn url(&self) -> &str {
+        match self {
+            FetchError::InvalidUrl { url, .. }
+            | FetchError::BlockedAddress { url, .. }
+            | FetchError::TooManyRedirects { url, .. }
+            | FetchError::Timeout { url, .. }
+            | FetchError::Status { url, .. }
+            | FetchError::UnsupportedContentType { url, .. }
+            | FetchError::TooLarge { url, .. }
+            | FetchError::Resolve { url, .. }
+            | FetchError::Request { url, .. } => url,
+        }
+    }
+}
+
+/// A fetched page, converted to markdown for the usual parse and chunk pipeline.
+pub struct FetchedDocument {
+    /// Where the content was served from after following redirects
+    pub final_url: String,
+    pub content_type: String,
+    pub markdown: String,
+}
+
+enum ContentKind {
+    Html,
+    Text,
+}
+
+impl ContentKind {
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "text/html" | "application/xhtml+xml" => Some(ContentKind::Html),
+            "text/markdown" | "text/x-markdown" | "text/plain" => Some(ContentKind::Text),
+            _ => None,
+        }
+    }
+}
+
+/// HTTP client for `POST /api/ingest/url`.
+pub struct UrlFetcher {
+    client: reqwest::Client,
+    timeout: Duration,
+    allow_private: bool,
+}
+
+impl UrlFetcher {
+    /// Reads `URL_FETCH_TIMEOUT_SECS` (default 30), covering the whole fetch
+    /// including redirects and the body, and `URL_FETCH_ALLOW_PRIVATE` (default
+    /// false), which lets URLs reach loopback, private and link-local addresses.
+    pub fn from_env() -> Result<Self> {
+        let timeout = env::var("URL_FETCH_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(30)
+            .max(1);
+        let allow_private = env::var("URL_FETCH_ALLOW_PRIVATE")
+            .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+
+        Self::new(Duration::from_secs(timeout), allow_private)
+    }
+
+    pub fn new(timeout: Duration, allow_private: bool) -> Result<Self> {
+        info!(
+            "URL fetcher: timeout={}s max_redirects={} allow_private={}",
+            timeout.as_secs(),
+            MAX_REDIRECTS,
+            allow_private
+        );
+        if allow_private {
+            warn!("URL_FETCH_ALLOW_PRIVATE is set; ingested URLs can reach internal addresses");
+        }
+
+        Ok(Self {
+            client: client_builder(allow_private).build()?,
+            timeout,
+            allow_private,
+        })
+    }
+
+    /// Fetches an http(s) URL, reading at most `max_bytes` of body. HTML is
+    /// converted to markdown; markdown and plain text pass through unchanged.
+    /// Bodies are decoded as UTF-8, replacing invalid sequences.
+    ///
+    /// Unless private addresses are allowed, the URL and every redirect target
+    /// must resolve to public addresses only, and the request connects to the
+    /// addresses that were checked.
+    pub async fn fetch(&self, url: &str, max_bytes: usize) -> Result<FetchedDocument, FetchError> {
+        match tokio::time::timeout(self.timeout, self.fetch_with_redirects(url, max_bytes)).await {
+            Ok(result) => result,
+            Err(_) => Err(FetchError::Timeout {
+                url: url.to_string(),
+                secs: self.timeout.as_secs(),
+            }),
+        }
+    }
+
+    async fn fetch_with_redirects(&self, url: &str, max_bytes: usize) -> Result<FetchedDocument, FetchError> {
+        let mut current = http_url(url)?;
+        let mut redirects = 0;
+
+        // Redirects are followed here rather than by reqwest, so each hop is checked before connecting
+        let mut response = loop {
+            let client = self.client_for(&current).await?;
+            let response = client
+                .get(current.clone())
+                .send()
+                .await
+                .map_err(|e| self.request_error(current.as_str(), e))?;
+
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .filter(|_| response.status().is_redirection());
+            let Some(location) = location else { break response };
+
+            if redirects == MAX_REDIRECTS {
+                return Err(FetchError::TooManyRedirects {
+                    url: url.to_string(),
+                    max: MAX_REDIRECTS,
+                });
+            }
+            redirects += 1;
+            let next = current.join(location).map_err(|e| FetchError::InvalidUrl {
+                url: location.to_string(),
+                reason: e.to_string(),
+            })?;
+            current = http_url(next.as_str())?;
+        };
+        let final_url = response.url().to_string();
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(FetchError::Status {
+                url: final_url,
+                status: status.as_u16(),
+            });
+        }
+
+        // Parameters such as charset are ignored; only the media type decides the handling
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        let Some(kind) = ContentKind::from_mime(&content_type) else {
+            return Err(FetchError::UnsupportedContentType { url: final_url, content_type });
+        };
+
+        if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+            return Err(FetchError::TooLarge { url: final_url, limit: max_bytes });
+        }
+
+        // Content-Length may be absent or wrong, so the limit is enforced while reading too
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| self.request_error(&final_url, e))? {
+            if body.len() + chunk.len() > max_bytes {
+                return Err(FetchError::TooLarge { url: final_url, limit: max_bytes });
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let text = String::from_utf8_lossy(&body);
+        let markdown = match kind {
+            ContentKind::Html => markdown::html_to_markdown(&text),
+            ContentKind::Text => text.into_owned(),
+        };
+
+        Ok(FetchedDocument {
+            final_url,
+            content_type,
+            markdown,
+        })
+    }
+
+    /// Checks where `url` points and returns a client that connects only there.
+    /// Hostnames are resolved once and pinned, so a second lookup during the
+    /// request cannot swap in an internal address.
+    async fn client_for(&self, url: &Url) -> Result<reqwest::Client, FetchError> {
+        if self.allow_private {
+            return Ok(self.client.clone());
+        }
+
+        let Some(host) = url.host_str() else {
+            return Err(FetchError::InvalidUrl {
+                url: url.to_string(),
+                reason: "the URL has no host".to_string(),
+            });
+        };
+        // IPv6 literals keep their brackets in the host string
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            check_addresses(url, &[ip])?;
+            return Ok(self.client.clone());
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let resolve_error = |source| FetchError::Resolve {
+            url: url.to_string(),
+            source,
+        };
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(resolve_error)?
+            .collect();
+        if addrs.is_empty() {
+            return Err(resolve_error(io::Error::new(io::ErrorKind::NotFound, "no addresses found")));
+        }
+        check_addresses(url, &addrs.iter().map(SocketAddr::ip).collect::<Vec<_>>())?;
+
+        client_builder(false)
+            .resolve_to_addrs(host, &addrs)
+            .build()
+            .map_err(|e| self.request_error(url.as_str(), e))
+    }
+
+    fn request_error(&self, url: &str, error: reqwest::Error) -> FetchError {
+        if error.is_timeout() {
+            FetchError::Timeout {
+                url: url.to_string(),
+                secs: self.timeout.as_secs(),
+            }
+        } else {
+            FetchError::Request {
+                url: url.to_string(),
+                source: error,
+            }
+        }
+    }
+}
+
+fn client_builder(allow_private: bool) -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .redirect(redirect::Policy::none());
+    if allow_private {
+        builder
+    } else {
+        // A proxy would resolve the host itself, past the address check
+        builder.no_proxy()
+    }
+}
+
+fn http_url(url: &str) -> Result<Url, FetchError> {
+    let parsed = Url::parse(url).map_err(|e| FetchError::InvalidUrl {
+        url: url.to_string(),
+        reason: e.to_string(),
+    })?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(FetchError::InvalidUrl {
+            url: url.to_string(),
+            reason: "only http and https URLs are supported".to_string(),
+        });
+    }
+    Ok(parsed)
+}
+
+/// Fails if any address is not publicly routable; a host with one internal
+/// address could be steered to it.
+fn check_addresses(url: &Url, addresses: &[IpAddr]) -> Result<(), FetchError> {
+    match addresses.iter().find(|ip| !is_public(**ip)) {
+        Some(&address) => Err(FetchError::BlockedAddress {
+            url: url.to_string(),
+            address,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            // 0.0.0.0/8 ("this network") and 100.64.0.0/10 (carrier-grade NAT)
+            let reserved = a == 0 || (a == 100 && (64..128).contains(&b));
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || reserved)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                // fc00::/7 (unique local) and fe80::/10 (link-local)
+                let local = (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80;
+                !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast() || local)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn blocked(fetcher: &UrlFetcher, url: &str) -> Option<IpAddr> {
+        match fetcher.fetch(url, 1024).await {
+            Err(FetchError::BlockedAddress { address, .. }) => Some(address),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} should be blocked", ip);
+        }
+        for ip in ["1.1.1.1", "93.184.216.34", "100.128.0.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public(ip.parse().unwrap()), "{} should be allowed", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_hosts_are_rejected_before_connecting() {
+        let fetcher = UrlFetcher::new(Duration::from_secs(5), false).unwrap();
+
+        // Nothing listens on port 1, so only the address check can produce these errors
+        assert_eq!(blocked(&fetcher, "http://127.0.0.1:1/").await, Some("127.0.0.1".parse().unwrap()));
+        assert_eq!(blocked(&fetcher, "http://[::1]:1/").await, Some("::1".parse().unwrap()));
+        assert_eq!(
+            blocked(&fetcher, "http://169.254.169.254/latest/meta-data/").await,
+            Some("169.254.169.254".parse().unwrap())
+        );
+        assert!(blocked(&fetcher, "http://localhost:1/").await.is_some_and(|ip| ip.is_loopback()));
+    }
+
+    #[tokio::test]
+    async fn allow_private_skips_the_address_check() {
+        let fetcher = UrlFetcher::new(Duration::from_secs(5), true).unwrap();
+        let error = fetcher.fetch("http://127.0.0.1:1/", 1024).await.err().unwrap();
+        assert!(matches!(error, FetchError::Request { .. }), "{:?}", error);
+    }
+}
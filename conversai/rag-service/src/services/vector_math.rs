@@ -0,0 +1,229 @@
+//! Similarity math for embeddings held in memory (reranking, MMR).
+//!
+//! Sums are accumulated in eight independent lanes: explicit SIMD via `wide` with
+//! the `simd` feature, otherwise plain arrays the compiler can vectorize. Lanes add
+//! up in a different order than a sequential loop, so results match a naive
+//! implementation to within float rounding rather than bit-for-bit.
+
+use std::env;
+use tracing::{info, warn};
+
+use crate::models::SimilarityMetric;
+
+const LANES: usize = 8;
+
+/// Reads `SIMILARITY_METRIC` (`cosine`, `dot` or `euclidean`; default cosine).
+/// Unknown values fall back to cosine with a warning.
+pub fn configured_metric() -> SimilarityMetric {
+    let metric = match env::var("SIMILARITY_METRIC").ok().as_deref().map(str::trim) {
+        None | Some("") | Some("cosine") => SimilarityMetric::Cosine,
+        Some("dot") => SimilarityMetric::Dot,
+        Some("euclidean") => SimilarityMetric::Euclidean,
+        Some(other) => {
+            warn!("Unknown SIMILARITY_METRIC {:?}, using cosine", other);
+            SimilarityMetric::Cosine
+        }
+    };
+    info!("Similarity metric: {}", metric.as_str());
+    metric
+}
+
+/// Dot product over the shorter of the two slices.
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    lane_dot(&a[..len], &b[..len])
+}
+
+/// Euclidean length of the whole slice.
+pub fn norm(v: &[f32]) -> f32 {
+    lane_dot(v, v).sqrt()
+}
+
+/// Cosine similarity; 0.0 when either vector has zero length. The dot product
+/// stops at the shorter slice while each norm covers its whole slice.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot(a, b) / (norm_a * norm_b)
+    }
+}
+
+/// Straight-line distance over the shorter of the two slices.
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// Similarity of two vectors under `metric`, higher meaning more alike. Matches
+/// the semantic score the SQL search functions compute for the same metric.
+pub fn similarity(metric: SimilarityMetric, a: &[f32], b: &[f32]) -> f32 {
+    match metric {
+        SimilarityMetric::Cosine => cosine(a, b),
+        SimilarityMetric::Dot => dot(a, b),
+        SimilarityMetric::Euclidean => 1.0 / (1.0 + euclidean_distance(a, b)),
+    }
+}
+
+/// `similarity` of `query` against each candidate, in order; cosine normalizes
+/// the query only once.
+pub fn similarity_many<V: AsRef<[f32]>>(metric: SimilarityMetric, query: &[f32], candidates: &[V]) -> Vec<f32> {
+    match metric {
+        SimilarityMetric::Cosine => cosine_many(query, candidates),
+        _ => candidates
+            .iter()
+            .map(|candidate| similarity(metric, query, candidate.as_ref()))
+            .collect(),
+    }
+}
+
+/// `v` scaled to unit length, or all zeros if `v` has zero length.
+pub fn normalize(v: &[f32]) -> Vec<f32> {
+    let length = norm(v);
+    if length == 0.0 {
+        vec![0.0; v.len()]
+    } else {
+        v.iter().map(|x| x / length).collect()
+    }
+}
+
+/// `cosine` for a query already passed through `normalize`, saving its norm on
+/// every comparison.
+pub fn cosine_normalized(unit_query: &[f32], b: &[f32]) -> f32 {
+    let norm_b = norm(b);
+    if norm_b == 0.0 {
+        0.0
+    } else {
+        dot(unit_query, b) / norm_b
+    }
+}
+
+/// Cosine similarity of `query` against each candidate, in order.
+pub fn cosine_many<V: AsRef<[f32]>>(query: &[f32], candidates: &[V]) -> Vec<f32> {
+    let unit_query = normalize(query);
+    candidates
+        .iter()
+        .map(|candidate| cosine_normalized(&unit_query, candidate.as_ref()))
+        .collect()
+}
+
+// Both slices have the same length here
+fn lane_dot(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(feature = "simd")]
+    return simd_lane_dot(a, b);
+    #[cfg(not(feature = "simd"))]
+    return scalar_lane_dot(a, b);
+}
+
+// Also compiled for tests with `simd`, so the SIMD path can be checked against it
+#[cfg(any(test, not(feature = "simd")))]
+fn scalar_lane_dot(a: &[f32], b: &[f32]) -> f32 {
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(x, y)| x * y).sum();
+
+    let mut lanes = [0.0f32; LANES];
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for ((lane, x), y) in lanes.iter_mut().zip(x).zip(y) {
+            *lane += x * y;
+        }
+    }
+
+    lanes.iter().sum::<f32>() + tail
+}
+
+#[cfg(feature = "simd")]
+fn simd_lane_dot(a: &[f32], b: &[f32]) -> f32 {
+    use wide::f32x8;
+
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(x, y)| x * y).sum();
+
+    let mut lanes = f32x8::ZERO;
+    for (x, y) in a_chunks.zip(b_chunks) {
+        let x = f32x8::from(<[f32; LANES]>::try_from(x).unwrap());
+        let y = f32x8::from(<[f32; LANES]>::try_from(y).unwrap());
+        lanes = x.mul_add(y, lanes);
+    }
+
+    lanes.reduce_add() + tail
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Sequential reference the lane-chunked sums must agree with.
+    fn naive_dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    fn close(a: f32, b: f32, scale: f32) -> bool {
+        (a - b).abs() <= 1e-4 * scale.max(1.0)
+    }
+
+    proptest! {
+        #[test]
+        fn dot_matches_a_sequential_loop(
+            pairs in prop::collection::vec((-1.0f32..1.0, -1.0f32..1.0), 0..2048)
+        ) {
+            let (a, b): (Vec<f32>, Vec<f32>) = pairs.into_iter().unzip();
+            let scale = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum();
+            prop_assert!(close(dot(&a, &b), naive_dot(&a, &b), scale));
+        }
+
+        #[test]
+        fn cosine_matches_a_sequential_loop(
+            pairs in prop::collection::vec((-1.0f32..1.0, -1.0f32..1.0), 1..2048)
+        ) {
+            let (a, b): (Vec<f32>, Vec<f32>) = pairs.into_iter().unzip();
+            let (norm_a, norm_b) = (naive_dot(&a, &a).sqrt(), naive_dot(&b, &b).sqrt());
+            prop_assume!(norm_a > 1e-3 && norm_b > 1e-3);
+            let expected = naive_dot(&a, &b) / (norm_a * norm_b);
+            prop_assert!((cosine(&a, &b) - expected).abs() <= 1e-4);
+            prop_assert!((cosine_many(&a, &[&b])[0] - expected).abs() <= 1e-4);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn scalar_cosine(a: &[f32], b: &[f32]) -> f32 {
+        let (norm_a, norm_b) = (scalar_lane_dot(a, a).sqrt(), scalar_lane_dot(b, b).sqrt());
+        scalar_lane_dot(a, b) / (norm_a * norm_b)
+    }
+
+    #[cfg(feature = "simd")]
+    proptest! {
+        #[test]
+        fn simd_dot_matches_scalar(
+            pairs in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0), 0..2048)
+        ) {
+            let (a, b): (Vec<f32>, Vec<f32>) = pairs.into_iter().unzip();
+            let scale = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum();
+            prop_assert!(close(simd_lane_dot(&a, &b), scalar_lane_dot(&a, &b), scale));
+        }
+
+        #[test]
+        fn simd_cosine_matches_scalar(
+            pairs in prop::collection::vec((-1.0f32..1.0, -1.0f32..1.0), 1..2048)
+        ) {
+            let (a, b): (Vec<f32>, Vec<f32>) = pairs.into_iter().unzip();
+            prop_assume!(norm(&a) > 1e-3 && norm(&b) > 1e-3);
+            prop_assert!((cosine(&a, &b) - scalar_cosine(&a, &b)).abs() <= 1e-4);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn simd_handles_lengths_off_the_lane_width() {
+        // Every length around one and two full lanes, so the tail path is exercised
+        for len in (0..=2 * LANES + 1).chain([1535, 1536, 1537]) {
+            let a: Vec<f32> = (0..len).map(|i| (i as f32 * 0.37).sin()).collect();
+            let b: Vec<f32> = (0..len).map(|i| (i as f32 * 0.71).cos()).collect();
+            let scale = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum();
+            let (simd, scalar) = (simd_lane_dot(&a, &b), scalar_lane_dot(&a, &b));
+            assert!(close(simd, scalar, scale), "len {}: simd {} vs scalar {}", len, simd, scalar);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use sqlx::PgPool;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::error::ApiError;
+use crate::models::SimilarityMetric;
+use crate::services::embedding_provider::EmbeddingProvider;
+use crate::services::ingest::ChunkSettings;
+use crate::services::query_cache::QueryCache;
+use crate::services::url_fetch::UrlFetcher;
+use crate::services::vector_math;
+
+// 50 MiB, overridable with INGEST_MAX_UPLOAD_BYTES
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+
+/// Shared handler state. `pool` is `None` in health-check-only mode, when no
+/// database URL was configured or the initial connection failed.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: Option<PgPool>,
+    /// Caps how many background ingest jobs run at once; the rest wait as `queued`
+    pub ingest_jobs: Arc<Semaphore>,
+    pub query_cache: Arc<QueryCache>,
+    /// Built once at startup from `EMBEDDING_PROVIDER`
+    pub embedder: Arc<dyn EmbeddingProvider>,
+    pub url_fetcher: Arc<UrlFetcher>,
+    /// From `SIMILARITY_METRIC`; used by semantic search and reranking
+    pub similarity: SimilarityMetric,
+    /// Largest accepted ingest request body, and the cap on any single uploaded file
+    /// or fetched page
+    pub max_upload_bytes: usize,
+    /// Chunk bounds from `CHUNK_MAX_TOKENS`/`CHUNK_OVERLAP_TOKENS`, before per-request overrides
+    pub chunk_settings: ChunkSettings,
+}
+
+impl AppState {
+    pub fn new(
+        pool: Option<PgPool>,
+        embedder: Arc<dyn EmbeddingProvider>,
+        url_fetcher: UrlFetcher,
+        chunk_settings: ChunkSettings,
+    ) -> Self {
+        let max_jobs = env::var("INGEST_MAX_CONCURRENT_JOBS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(2)
+            .max(1);
+        let max_upload_bytes = env::var("INGEST_MAX_UPLOAD_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|&bytes| bytes > 0)
+            .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES);
+
+        Self {
+            pool,
+            ingest_jobs: Arc::new(Semaphore::new(max_jobs)),
+            query_cache: Arc::new(QueryCache::from_env()),
+            embedder,
+            url_fetcher: Arc::new(url_fetcher),
+            similarity: vector_math::configured_metric(),
+            max_upload_bytes,
+            chunk_settings,
+        }
+    }
+
+    /// The database pool, or a 503 for handlers that need one in degraded mode.
+    pub fn pool(&self) -> Result<&PgPool, ApiError> {
+        self.pool.as_ref().ok_or(ApiError::DatabaseUnavailable)
+    }
+}
@@ -0,0 +1,102 @@
+//! Log output, plus span export to an OpenTelemetry collector (Jaeger, Tempo,
+//! the OTel Collector, ...) when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+
+use std::env;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+#[cfg(feature = "full")]
+use opentelemetry::trace::TracerProvider;
+#[cfg(feature = "full")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "full")]
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+#[cfg(feature = "full")]
+use tracing::info;
+#[cfg(not(feature = "full"))]
+use tracing::warn;
+
+#[cfg(feature = "full")]
+const DEFAULT_SERVICE_NAME: &str = "conversai-rag";
+
+/// Keeps the span exporter alive for the life of the process.
+pub struct Telemetry {
+    #[cfg(feature = "full")]
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Flushes spans still queued for export.
+    pub fn shutdown(self) {
+        #[cfg(feature = "full")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("WARNING: Failed to flush OpenTelemetry spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber: logs to stdout filtered by `RUST_LOG` (default
+/// `info`, e.g. `info,conversai_rag=debug`), and with the `full` feature an
+/// OTLP/gRPC span exporter when `OTEL_EXPORTER_OTLP_ENDPOINT` is set
+/// (e.g. `http://localhost:4317`). Spans are reported as `OTEL_SERVICE_NAME`,
+/// default `conversai-rag`. The collector is connected to lazily, so one that is
+/// down only costs the spans, never a request.
+pub fn init() -> anyhow::Result<Telemetry> {
+    let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    let registry = tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "full")]
+    {
+        let service_name = env::var("OTEL_SERVICE_NAME")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+        let provider = endpoint
+            .as_deref()
+            .map(|endpoint| otlp_provider(endpoint, &service_name))
+            .transpose()?;
+        let otel = provider
+            .as_ref()
+            .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME)));
+        registry.with(otel).init();
+
+        match &endpoint {
+            Some(endpoint) => info!("Exporting spans over OTLP to {} as {}", endpoint, service_name),
+            None => info!("OTEL_EXPORTER_OTLP_ENDPOINT not set, span export disabled"),
+        }
+        Ok(Telemetry { provider })
+    }
+
+    #[cfg(not(feature = "full"))]
+    {
+        registry.init();
+        if endpoint.is_some() {
+            warn!("OTEL_EXPORTER_OTLP_ENDPOINT ignored: span export needs the `full` feature");
+        }
+        Ok(Telemetry {})
+    }
+}
+
+#[cfg(feature = "full")]
+fn otlp_provider(endpoint: &str, service_name: &str) -> anyhow::Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| anyhow::anyhow!("invalid OTEL_EXPORTER_OTLP_ENDPOINT {:?}: {}", endpoint, e))?;
+
+    let resource = Resource::builder()
+        .with_service_name(service_name.to_string())
+        .build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
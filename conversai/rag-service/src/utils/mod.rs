@@ -1,4 +1,8 @@
 use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub use text_truncate::{truncate_with, TruncateBoundary, TruncateOptions};
 
 pub fn calculate_sha256(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -6,12 +10,64 @@ pub fn calculate_sha256(data: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Wraps every case-insensitive whole-word occurrence of a query term in `**...**`.
+/// Single-character terms are ignored so they don't light up every other word.
+pub fn highlight_terms(text: &str, query: &str) -> String {
+    let terms: HashSet<String> = query
+        .unicode_words()
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_lowercase())
+        .collect();
+
+    if terms.is_empty() {
+        return text.to_string();
+    }
+
+    let mut highlighted = String::with_capacity(text.len());
+    for (_, segment) in text.split_word_bound_indices() {
+        if terms.contains(&segment.to_lowercase()) {
+            highlighted.push_str("**");
+            highlighted.push_str(segment);
+            highlighted.push_str("**");
+        } else {
+            highlighted.push_str(segment);
+        }
+    }
+    highlighted
+}
+
+/// Truncates to at most `max_chars` characters (including the `...` suffix)
+/// without splitting a grapheme cluster. Lengths are counted in chars, never
+/// bytes. With `max_chars <= 3` there is no room for the suffix, so the text is
+/// cut to `max_chars` characters without one.
 pub fn truncate_text(text: &str, max_chars: usize) -> String {
-    if text.len() <= max_chars {
-        text.to_string()
-    } else {
-        let mut truncated = text.chars().take(max_chars - 3).collect::<String>();
-        truncated.push_str("...");
-        truncated
+    truncate_with(text, max_chars, &TruncateOptions::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_text_keeps_emoji_with_their_modifiers() {
+        // Waving hand plus a skin tone modifier: two chars, one grapheme
+        let text = "hi \u{1F44B}\u{1F3FD} there";
+        // Trailing whitespace before the suffix is dropped
+        assert_eq!(truncate_text(text, 7), "hi...");
+        assert_eq!(truncate_text(text, 8), "hi \u{1F44B}\u{1F3FD}...");
+        assert_eq!(truncate_text("\u{1F680}\u{1F680}\u{1F680}\u{1F680}\u{1F680}", 4), "\u{1F680}...");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn truncate_text_keeps_combining_accents_with_their_letter() {
+        // Decomposed "Zoë Ångström"
+        let text = "Zoe\u{308} A\u{30A}ngstro\u{308}m";
+        assert_eq!(truncate_text(text, 6), "Zo...");
+        assert_eq!(truncate_text(text, 7), "Zoe\u{308}...");
+        for max_chars in 0..text.chars().count() {
+            let truncated = truncate_text(text, max_chars);
+            assert!(truncated.chars().count() <= max_chars, "{:?} for {}", truncated, max_chars);
+            assert!(!truncated.starts_with('\u{308}') && !truncated.contains(" \u{30A}"));
+        }
+    }
+}
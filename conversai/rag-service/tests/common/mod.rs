@@ -0,0 +1,252 @@
+//! Shared fixtures for the integration tests: stub embedding providers, a
+//! migrated scratch database, and helpers for driving routers without a
+//! listening socket.
+
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use axum::routing::{get, post};
+use axum::Router;
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use conversai_rag::handlers::{chunks, documents, ingest, query};
+use conversai_rag::services::embedding_provider::EmbeddingProvider;
+use conversai_rag::services::ingest::ChunkSettings;
+use conversai_rag::services::url_fetch::UrlFetcher;
+use conversai_rag::state::AppState;
+
+/// Width of the `chunks.embedding` column.
+pub const DIMENSIONS: usize = 1536;
+
+/// Deterministic bag-of-words embeddings: every word adds weight to one hashed
+/// dimension, so texts sharing words are close and unrelated texts are not.
+#[derive(Default)]
+pub struct StubEmbedder {
+    calls: AtomicUsize,
+}
+
+impl StubEmbedder {
+    /// Number of `embed` calls so far, queries included.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+pub fn embed_text(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; DIMENSIONS];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        // FNV-1a, so the vectors don't depend on the std hasher's seed
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+        vector[(hash % DIMENSIONS as u64) as usize] += 1.0;
+    }
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    } else {
+        // Cosine distance is undefined for the zero vector
+        vector[0] = 1.0;
+    }
+    vector
+}
+
+#[async_trait]
+impl EmbeddingProvider for StubEmbedder {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(texts.iter().map(|text| embed_text(text)).collect())
+    }
+
+    fn dimensions(&self) -> usize {
+        DIMENSIONS
+    }
+
+    fn model_name(&self) -> &str {
+        "stub-bag-of-words"
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "stub"
+    }
+}
+
+/// A provider that is down: every call fails.
+#[derive(Default)]
+pub struct FailingEmbedder {
+    calls: AtomicUsize,
+}
+
+impl FailingEmbedder {
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for FailingEmbedder {
+    async fn embed(&self, _texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(anyhow!("embedding provider unavailable"))
+    }
+
+    fn dimensions(&self) -> usize {
+        DIMENSIONS
+    }
+
+    fn model_name(&self) -> &str {
+        "failing"
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "stub"
+    }
+}
+
+pub fn state(pool: Option<PgPool>, embedder: Arc<dyn EmbeddingProvider>) -> AppState {
+    // Mock servers listen on loopback, which the fetcher otherwise refuses
+    let url_fetcher = UrlFetcher::new(Duration::from_secs(30), true).expect("url fetcher");
+    AppState::new(pool, embedder, url_fetcher, ChunkSettings::default())
+}
+
+pub fn post_json(uri: &str, body: Value) -> Request<Body> {
+    Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// A `multipart/form-data` POST with one `file` part and any number of text fields.
+pub fn post_multipart(uri: &str, filename: &str, contents: &[u8], fields: &[(&str, &str)]) -> Request<Body> {
+    const BOUNDARY: &str = "test-boundary";
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", BOUNDARY, name, value).bytes());
+    }
+    body.extend(
+        format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n", BOUNDARY, filename)
+            .bytes(),
+    );
+    body.extend_from_slice(contents);
+    body.extend(format!("\r\n--{}--\r\n", BOUNDARY).bytes());
+
+    Request::post(uri)
+        .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", BOUNDARY))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Sends one request through `router` and returns the status with the body as
+/// JSON (`Null` for an empty body).
+pub async fn send(router: Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = router.oneshot(request).await.expect("router is infallible");
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+    };
+    (status, body)
+}
+
+/// A new database with the numbered migrations in `supabase/migrations` applied,
+/// or `None` when `DATABASE_URL` isn't set, in which case the caller skips its
+/// test. The server behind `DATABASE_URL` needs the pgvector extension.
+pub async fn test_pool() -> Option<PgPool> {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set; skipping database test");
+        return None;
+    };
+    let options: PgConnectOptions = url.parse().expect("DATABASE_URL is not a Postgres URL");
+
+    // One database per test, so tests run in parallel without seeing each other's rows
+    let name = format!("conversai_test_{}", Uuid::new_v4().simple());
+    let mut admin = PgConnection::connect_with(&options).await.expect("connect to DATABASE_URL");
+    admin.execute(format!("CREATE DATABASE {}", name).as_str()).await.unwrap();
+    admin.close().await.unwrap();
+
+    let pool = PgPoolOptions::new()
+        .max_connections(4)
+        .connect_with(options.database(&name))
+        .await
+        .unwrap();
+    for (file, sql) in migrations() {
+        pool.execute(sql.as_str())
+            .await
+            .unwrap_or_else(|e| panic!("migration {} failed: {}", file, e));
+    }
+    Some(pool)
+}
+
+/// `NNN_name.sql` files in order; the rest of the directory predates the service.
+fn migrations() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../supabase/migrations");
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("reading {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| {
+            let bytes = name.as_bytes();
+            name.ends_with(".sql") && bytes.len() > 4 && bytes[..3].iter().all(u8::is_ascii_digit) && bytes[3] == b'_'
+        })
+        .collect();
+    files.sort();
+    files
+        .into_iter()
+        .map(|name| {
+            let sql = std::fs::read_to_string(dir.join(&name)).unwrap();
+            (name, sql)
+        })
+        .collect()
+}
+
+/// The API routes the tests drive, wired as in `main.rs`.
+pub fn app(state: AppState) -> Router {
+    Router::new()
+        .route("/api/ingest/url", post(ingest::handle_ingest_url))
+        .route("/api/ingest/text", post(ingest::handle_ingest_text))
+        .route("/api/ingest/preview", post(ingest::handle_ingest_preview))
+        .route("/api/query", post(query::handle_query))
+        .route("/api/query/stream", post(query::handle_query_stream))
+        .route("/api/query/lexical", post(query::handle_lexical_query))
+        .route("/api/query/semantic", post(query::handle_semantic_query))
+        .route(
+            "/api/documents",
+            get(documents::handle_list_documents).delete(documents::handle_delete_documents),
+        )
+        .route(
+            "/api/documents/:id",
+            get(documents::handle_get_document).delete(documents::handle_delete_document),
+        )
+        .route(
+            "/api/chunks/:id",
+            get(chunks::handle_get_chunk)
+                .patch(chunks::handle_update_chunk)
+                .delete(chunks::handle_delete_chunk),
+        )
+        .with_state(state)
+}
+
+/// Ingests markdown through `POST /api/ingest/text` and returns the response body.
+pub async fn ingest_text(router: &Router, request: Value) -> Value {
+    let (status, body) = send(router.clone(), post_json("/api/ingest/text", request)).await;
+    assert_eq!(status, StatusCode::OK, "ingest failed: {}", body);
+    body
+}
+
+pub fn uuid(value: &Value) -> Uuid {
+    value.as_str().and_then(|id| id.parse().ok()).unwrap_or_else(|| panic!("not a uuid: {}", value))
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use common::{post_json, send, StubEmbedder};
+
+async fn query(router: &Router, query: &str) -> Vec<Value> {
+    let (status, body) = send(router.clone(), post_json("/api/query", json!({ "query": query, "k": 10 }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["context"].as_array().unwrap().clone()
+}
+
+fn chunk_ids(context: &[Value]) -> BTreeSet<Uuid> {
+    context.iter().map(|result| common::uuid(&result["chunk"]["id"])).collect()
+}
+
+async fn delete(router: &Router, uri: &str) -> Value {
+    let request = Request::delete(uri).body(Body::empty()).unwrap();
+    let (status, body) = send(router.clone(), request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+#[tokio::test]
+async fn deleted_chunks_and_documents_are_no_longer_retrieved() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool), Arc::new(StubEmbedder::default())));
+    let ownership = common::ingest_text(
+        &router,
+        json!({
+            "content": "# Ownership\n\nThe borrow checker enforces ownership.\n\n# Lifetimes\n\nThe borrow checker also tracks lifetimes.",
+            "source_uri": "notes://rust",
+        }),
+    )
+    .await;
+    let deploy = common::ingest_text(
+        &router,
+        json!({ "content": "# Deploy\n\nThe borrow checker runs again when Railway builds.", "source_uri": "notes://deploy" }),
+    )
+    .await;
+
+    // Queried first so the deletes also have to invalidate the cached response
+    let before = query(&router, "borrow checker").await;
+    assert_eq!(before.len(), 3);
+
+    let deleted = before
+        .iter()
+        .find(|result| result["chunk"]["section"] == "Lifetimes")
+        .map(|result| common::uuid(&result["chunk"]["id"]))
+        .unwrap();
+    let body = delete(&router, &format!("/api/chunks/{}", deleted)).await;
+    assert_eq!(common::uuid(&body["document_id"]), common::uuid(&ownership["document_id"]));
+
+    let after_chunk = query(&router, "borrow checker").await;
+    assert_eq!(after_chunk.len(), 2);
+    assert!(!chunk_ids(&after_chunk).contains(&deleted));
+
+    let (status, _) = send(router.clone(), Request::get(format!("/api/chunks/{}", deleted)).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    delete(&router, &format!("/api/documents/{}", ownership["document_id"].as_str().unwrap())).await;
+
+    let after_document = query(&router, "borrow checker").await;
+    assert_eq!(after_document.len(), 1);
+    assert_eq!(after_document[0]["chunk"]["document_id"], deploy["document_id"]);
+}
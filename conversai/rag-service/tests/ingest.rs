@@ -0,0 +1,202 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use sqlx::{Executor, PgPool};
+
+use tokio::sync::Barrier;
+
+use common::{post_json, send, StubEmbedder};
+use conversai_rag::services::embedding_provider::EmbeddingProvider;
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT count(*) FROM {}", table)).fetch_one(pool).await.unwrap()
+}
+
+#[tokio::test]
+async fn failed_chunk_insert_stores_nothing() {
+    let Some(pool) = common::test_pool().await else { return };
+    // Fails the chunk insert after the document row is already written
+    pool.execute(
+        "CREATE FUNCTION reject_poison() RETURNS trigger AS $$
+         BEGIN
+             IF NEW.content LIKE '%poison%' THEN RAISE EXCEPTION 'poisoned chunk'; END IF;
+             RETURN NEW;
+         END $$ LANGUAGE plpgsql;
+         CREATE TRIGGER reject_poison BEFORE INSERT ON chunks FOR EACH ROW EXECUTE FUNCTION reject_poison();",
+    )
+    .await
+    .unwrap();
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+
+    let sections: Vec<String> = (1..=6).map(|i| format!("# Part {}\n\nSection {} of the runbook.", i, i)).collect();
+    let content = format!("{}\n\n# Last\n\nA poison paragraph.", sections.join("\n\n"));
+    let (status, body) = send(
+        router.clone(),
+        post_json("/api/ingest/text", json!({ "content": content, "source_uri": "notes://runbook" })),
+    )
+    .await;
+
+    assert!(status.is_server_error(), "{}: {}", status, body);
+    assert_eq!(count(&pool, "documents").await, 0);
+    assert_eq!(count(&pool, "chunks").await, 0);
+
+    // Nothing half-stored for the retry to be deduplicated against
+    pool.execute("DROP TRIGGER reject_poison ON chunks").await.unwrap();
+    let body = common::ingest_text(&router, json!({ "content": content, "source_uri": "notes://runbook" })).await;
+    assert_eq!(body["deduplicated"], false);
+    assert_eq!(body["version"], 1);
+    assert_eq!(count(&pool, "chunks").await, body["chunks_count"].as_i64().unwrap());
+}
+
+#[tokio::test]
+async fn stored_chunks_start_with_their_heading_path() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+    let body = common::ingest_text(
+        &router,
+        json!({
+            "content": "# Service\n\n## Deployment\n\nIt restarts on every push.",
+            "source_uri": "notes://service",
+        }),
+    )
+    .await;
+
+    let (content, prefix): (String, Option<String>) = sqlx::query_as(
+        "SELECT content, metadata->>'heading_prefix' FROM chunks WHERE document_id = $1 AND section = $2",
+    )
+    .bind(common::uuid(&body["document_id"]))
+    .bind("Service > Deployment")
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(prefix.as_deref(), Some("Service > Deployment:\n"));
+    assert!(content.starts_with("Service > Deployment:\n"), "{:?}", content);
+    assert!(content.contains("It restarts on every push."));
+}
+
+#[tokio::test]
+async fn edited_chunks_keep_their_heading_path() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+    let body = common::ingest_text(
+        &router,
+        json!({ "content": "# Service\n\n## Deployment\n\nIt restarts on evry push.", "source_uri": "notes://service" }),
+    )
+    .await;
+    let chunk_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM chunks WHERE document_id = $1 AND section = $2")
+        .bind(common::uuid(&body["document_id"]))
+        .bind("Service > Deployment")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let patch = |content: &str| {
+        Request::patch(format!("/api/chunks/{}", chunk_id))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({ "content": content }).to_string()))
+            .unwrap()
+    };
+    let expected = "Service > Deployment:\nIt restarts on every push.";
+    for content in ["It restarts on every push.", expected] {
+        let (status, chunk) = send(router.clone(), patch(content)).await;
+        assert_eq!(status, StatusCode::OK, "{}", chunk);
+        assert_eq!(chunk["content"], expected);
+        assert_eq!(chunk["metadata"]["heading_prefix"], "Service > Deployment:\n");
+    }
+
+    // The embedding is of the prefixed content, as at ingestion
+    let embedding: pgvector::Vector = sqlx::query_scalar("SELECT embedding FROM chunks WHERE id = $1")
+        .bind(chunk_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(embedding.to_vec(), common::embed_text(expected));
+}
+
+#[tokio::test]
+async fn chunks_past_one_insert_batch_are_all_stored_in_order() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+    let content: String = (0..250).map(|i| format!("# Part {}\n\nStep {} of the runbook.\n\n", i, i)).collect();
+    let body = common::ingest_text(&router, json!({ "content": content })).await;
+
+    let indexes: Vec<i32> = sqlx::query_scalar("SELECT chunk_index FROM chunks WHERE document_id = $1 ORDER BY chunk_index")
+        .bind(common::uuid(&body["document_id"]))
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert!(indexes.len() > 200, "{} chunks", indexes.len());
+    assert_eq!(body["chunks_count"], indexes.len());
+    assert_eq!(indexes, (0..indexes.len() as i32).collect::<Vec<_>>());
+}
+
+/// Holds every embedding call until `Barrier` many are waiting, so concurrent
+/// ingests have all read the latest version before any of them stores one.
+struct BarrierEmbedder(Barrier);
+
+#[async_trait]
+impl EmbeddingProvider for BarrierEmbedder {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.0.wait().await;
+        Ok(texts.iter().map(|text| common::embed_text(text)).collect())
+    }
+
+    fn dimensions(&self) -> usize {
+        common::DIMENSIONS
+    }
+
+    fn model_name(&self) -> &str {
+        "stub-bag-of-words"
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "stub"
+    }
+}
+
+#[tokio::test]
+async fn concurrent_versions_of_a_key_conflict_instead_of_duplicating() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+    common::ingest_text(&router, json!({ "content": "# Runbook\n\nFirst draft.", "document_key": "runbook" })).await;
+
+    let racing = common::app(common::state(Some(pool.clone()), Arc::new(BarrierEmbedder(Barrier::new(2)))));
+    let ingest = |content: &str| {
+        send(
+            racing.clone(),
+            post_json("/api/ingest/text", json!({ "content": content, "document_key": "runbook" })),
+        )
+    };
+    let drafts = ["# Runbook\n\nSecond draft.", "# Runbook\n\nThird draft."];
+    let (first, second) = tokio::join!(ingest(drafts[0]), ingest(drafts[1]));
+
+    let mut statuses = [first.0, second.0];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT], "{} / {}", first.1, second.1);
+    let (stored, conflict, lost) =
+        if first.0 == StatusCode::OK { (first.1, second.1, drafts[1]) } else { (second.1, first.1, drafts[0]) };
+    assert_eq!(stored["version"], 2);
+    assert_eq!(conflict["error"]["code"], "version_conflict");
+    assert_eq!(conflict["error"]["details"], json!({ "document_key": "runbook", "version": 2 }));
+
+    let versions: Vec<i32> =
+        sqlx::query_scalar("SELECT document_version FROM documents WHERE document_key = 'runbook' ORDER BY 1")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(versions, [1, 2]);
+
+    // Sent again, the losing draft becomes the next version
+    let retried = common::ingest_text(&router, json!({ "content": lost, "document_key": "runbook" })).await;
+    assert_eq!(retried["version"], 3);
+    assert_eq!(retried["deduplicated"], false);
+}
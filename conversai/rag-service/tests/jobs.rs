@@ -0,0 +1,37 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use conversai_rag::services::jobs;
+
+#[tokio::test]
+async fn restart_fails_queued_and_processing_jobs_only() {
+    let Some(pool) = common::test_pool().await else { return };
+    let queued = jobs::create_job(&pool, "queued.md").await.unwrap();
+    let processing = jobs::create_job(&pool, "processing.md").await.unwrap();
+    jobs::mark_processing(&pool, processing).await.unwrap();
+    let failed = jobs::create_job(&pool, "failed.md").await.unwrap();
+    jobs::mark_failed(&pool, failed, "unsupported file type").await.unwrap();
+
+    assert_eq!(jobs::fail_interrupted(&pool).await.unwrap(), 2);
+
+    for id in [queued, processing] {
+        let job = jobs::get_job(&pool, id).await.unwrap().unwrap();
+        assert_eq!(job.status, "failed");
+        assert_eq!(job.error.as_deref(), Some("interrupted by a service restart"));
+    }
+    let job = jobs::get_job(&pool, failed).await.unwrap().unwrap();
+    assert_eq!(job.error.as_deref(), Some("unsupported file type"));
+
+    // A second startup has nothing left to fail
+    assert_eq!(jobs::fail_interrupted(&pool).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn job_status_is_limited_to_the_documented_states() {
+    let Some(pool) = common::test_pool().await else { return };
+    let id = jobs::create_job(&pool, "notes.md").await.unwrap();
+
+    let running = sqlx::query("UPDATE ingest_jobs SET status = 'running' WHERE id = $1").bind(id).execute(&pool).await;
+    assert!(running.is_err(), "`running` was replaced by `processing`");
+}
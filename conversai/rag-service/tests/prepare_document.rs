@@ -0,0 +1,155 @@
+#![cfg(feature = "full")]
+
+use axum::http::StatusCode;
+
+use conversai_rag::error::ApiError;
+use conversai_rag::services::chunking::Chunk;
+use conversai_rag::services::ingest::{prepare_document, ChunkSettings, DocumentFormat, DocumentSource};
+
+async fn prepare(filename: &str, data: &[u8], max_tokens: usize) -> (DocumentSource, Vec<Chunk>) {
+    let source = DocumentSource::upload(filename, data);
+    let settings = ChunkSettings { max_tokens, overlap_tokens: 0, ..ChunkSettings::default() };
+    let prepared = prepare_document(&source, data, settings).await.unwrap();
+    assert!(prepared.warnings.is_empty(), "{:?}", prepared.warnings);
+    (source, prepared.chunks)
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split_whitespace().collect()
+}
+
+#[tokio::test]
+async fn plain_text_is_split_at_paragraphs_and_sentences() {
+    let data = include_bytes!("fixtures/notes.txt");
+    let (source, chunks) = prepare("notes.txt", data, 60).await;
+    assert_eq!(source.format, DocumentFormat::Text);
+    assert_eq!(source.source_type, "txt");
+
+    // The long paragraph is cut between sentences and its neighbours packed around it
+    assert_eq!(chunks.len(), 3);
+    for chunk in &chunks {
+        assert!(chunk.section.is_empty(), "plain text has no headings: {:?}", chunk.section);
+        assert!(chunk.metadata.get("heading_prefix").is_none());
+        assert!(chunk.tokens <= 60, "{} tokens in {:?}", chunk.tokens, chunk.content);
+        assert!(chunk.content.ends_with('.'), "{:?} ends mid-sentence", chunk.content);
+        assert!(chunk.content.starts_with(char::is_uppercase), "{:?} starts mid-sentence", chunk.content);
+    }
+    // Without overlap the chunks are the whole text, in order
+    let text = std::str::from_utf8(data).unwrap();
+    let joined: Vec<&str> = chunks.iter().flat_map(|chunk| words(&chunk.content)).collect();
+    assert_eq!(joined, words(text));
+}
+
+#[tokio::test]
+async fn html_is_chunked_by_its_headings_without_markup() {
+    let (source, chunks) = prepare("guide.html", include_bytes!("fixtures/guide.html"), 200).await;
+    assert_eq!(source.format, DocumentFormat::Html);
+    assert_eq!(source.source_type, "html");
+
+    let sections: Vec<&str> = chunks.iter().map(|chunk| chunk.section.as_str()).collect();
+    assert_eq!(sections, ["Deploy guide", "Deploy guide > Railway", "Deploy guide > Fly"]);
+    for chunk in &chunks {
+        assert!(!chunk.content.contains('<'), "markup left in {:?}", chunk.content);
+        assert!(chunk.content.starts_with(&format!("{}:\n", chunk.section)));
+    }
+    assert!(chunks[1].content.contains("DATABASE_URL") && chunks[1].content.contains("OPENAI_API_KEY"));
+    assert!(chunks[2].content.contains("fly deploy") && chunks[2].content.contains("Fly docs"));
+    assert!(!chunks[2].content.contains("https://fly.io"));
+}
+
+#[tokio::test]
+async fn pdf_pages_become_chunks_that_record_their_page() {
+    let data = include_bytes!("fixtures/sample.pdf");
+    let source = DocumentSource::upload("sample.pdf", data);
+    assert_eq!(source.format, DocumentFormat::Pdf);
+    assert_eq!(source.source_type, "pdf");
+
+    let prepared = prepare_document(&source, data, ChunkSettings::default()).await.unwrap();
+    // The fixture's second page has no text
+    assert_eq!(prepared.warnings.len(), 1);
+    assert!(prepared.warnings[0].starts_with("pdf_pages_skipped: 1 of 3 pages"), "{:?}", prepared.warnings);
+
+    let chunks = &prepared.chunks;
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].section, "Page 1");
+    assert_eq!(chunks[0].span["page"], 1);
+    assert_eq!(chunks[0].metadata["page"], 1);
+    assert!(chunks[0].content.contains("Railway deploys the service on every push."));
+    assert_eq!(chunks[1].section, "Page 3");
+    assert_eq!(chunks[1].span["page"], 3);
+    assert!(chunks[1].content.contains("Fly runs it closer to users."));
+}
+
+#[tokio::test]
+async fn docx_headings_come_from_style_names_ids_and_outline_levels() {
+    let data = include_bytes!("fixtures/guide.docx");
+    let source = DocumentSource::upload("guide.docx", data);
+    assert_eq!(source.format, DocumentFormat::Docx);
+    assert_eq!(source.source_type, "docx");
+
+    let prepared = prepare_document(&source, data, ChunkSettings::default()).await.unwrap();
+    assert_eq!(prepared.warnings, ["docx_images_skipped: 2 embedded images were skipped"]);
+
+    // "Titre1" is a heading by its style name, "Heading2" by its id, "Rollbacks" by outline level
+    let chunks = &prepared.chunks;
+    let sections: Vec<&str> = chunks.iter().map(|chunk| chunk.section.as_str()).collect();
+    assert_eq!(
+        sections,
+        ["Deploy guide", "Deploy guide > Railway", "Deploy guide > Railway > Rollbacks", "Deploy guide > Fly"]
+    );
+    for chunk in chunks {
+        assert!(chunk.content.starts_with(&format!("{}:\n", chunk.section)));
+    }
+    assert!(chunks[1].content.contains("Railway builds the image on every push.\n- Set DATABASE_URL"));
+    assert!(chunks[3].content.contains("Fly runs it closer to users."));
+}
+
+async fn pdf_error(data: &[u8]) -> ApiError {
+    let source = DocumentSource::upload("upload.pdf", data);
+    let error = prepare_document(&source, data, ChunkSettings::default()).await.err().expect("an unreadable PDF");
+    error.into()
+}
+
+#[tokio::test]
+async fn encrypted_pdf_is_reported_as_encrypted() {
+    let error = pdf_error(include_bytes!("fixtures/encrypted.pdf")).await;
+    assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.code(), "encrypted_document");
+}
+
+#[tokio::test]
+async fn truncated_pdf_is_reported_as_corrupt() {
+    let data = include_bytes!("fixtures/sample.pdf");
+    let error = pdf_error(&data[..data.len() / 2]).await;
+    assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.code(), "corrupt_document");
+}
+
+async fn docx_error(data: &[u8]) -> ApiError {
+    let source = DocumentSource::upload("upload.docx", data);
+    let error = prepare_document(&source, data, ChunkSettings::default()).await.err().expect("an unreadable .docx");
+    error.into()
+}
+
+#[tokio::test]
+async fn encrypted_docx_is_reported_as_encrypted() {
+    let error = docx_error(include_bytes!("fixtures/encrypted.docx")).await;
+    assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.code(), "encrypted_document");
+}
+
+#[tokio::test]
+async fn truncated_docx_is_reported_as_corrupt() {
+    let data = include_bytes!("fixtures/guide.docx");
+    let error = docx_error(&data[..data.len() / 2]).await;
+    assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.code(), "corrupt_document");
+}
+
+#[tokio::test]
+async fn zip_without_a_word_document_is_reported_as_corrupt() {
+    let error = docx_error(include_bytes!("fixtures/not_word.docx")).await;
+    assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.code(), "corrupt_document");
+    assert!(error.to_string().contains("word/document.xml is missing"), "{}", error);
+}
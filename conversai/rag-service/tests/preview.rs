@@ -0,0 +1,90 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+
+use common::{post_multipart, send, FailingEmbedder, StubEmbedder, DIMENSIONS};
+
+const MARKDOWN: &str = "# Setup\n\nInstall the CLI and log in.\n\n# Deploy\n\nPush to main and Railway builds the image.";
+
+#[tokio::test]
+async fn preview_needs_neither_a_database_nor_an_embedding_provider() {
+    let embedder = Arc::new(FailingEmbedder::default());
+    let router = common::app(common::state(None, embedder.clone()));
+
+    let request = post_multipart("/api/ingest/preview", "guide.md", MARKDOWN.as_bytes(), &[]);
+    let (status, body) = send(router, request).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["format"], "md");
+    let chunks = body["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(body["chunks_count"], 2);
+    for chunk in chunks {
+        assert!(chunk["tokens"].as_u64().unwrap() > 0);
+        let span = &chunk["span"];
+        assert!(span["start_char"].as_u64().unwrap() < span["end_char"].as_u64().unwrap(), "{}", span);
+        assert!(chunk["embedding"].is_null());
+    }
+    assert!(body.get("embedding_model").is_none());
+    assert_eq!(embedder.calls(), 0);
+}
+
+#[tokio::test]
+async fn token_overrides_change_the_chunking() {
+    let router = common::app(common::state(None, Arc::new(StubEmbedder::default())));
+    let paragraph = "Every deploy runs the migrations before the new image takes traffic. ".repeat(20);
+    let markdown = format!("# Deploy\n\n{}", paragraph);
+
+    let preview = |fields: &'static [(&'static str, &'static str)]| {
+        send(router.clone(), post_multipart("/api/ingest/preview", "deploy.md", markdown.as_bytes(), fields))
+    };
+    let (status, default) = preview(&[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", default);
+    assert_eq!(default["chunks_count"], 1);
+
+    let (status, small) = preview(&[("max_tokens", "64"), ("overlap_tokens", "8")]).await;
+    assert_eq!(status, StatusCode::OK, "{}", small);
+    assert_eq!(small["max_tokens"], 64);
+    assert_eq!(small["overlap_tokens"], 8);
+    let chunks = small["chunks"].as_array().unwrap();
+    assert!(chunks.len() > 3, "{} chunks", chunks.len());
+    assert!(chunks.iter().all(|chunk| chunk["tokens"].as_u64().unwrap() <= 64));
+}
+
+#[tokio::test]
+async fn invalid_token_overrides_are_rejected() {
+    let router = common::app(common::state(None, Arc::new(StubEmbedder::default())));
+
+    for fields in [
+        [("max_tokens", "100"), ("overlap_tokens", "100")],
+        [("max_tokens", "8"), ("overlap_tokens", "0")],
+        [("max_tokens", "many"), ("overlap_tokens", "0")],
+    ] {
+        let request = post_multipart("/api/ingest/preview", "guide.md", MARKDOWN.as_bytes(), &fields);
+        let (status, body) = send(router.clone(), request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}: {}", fields, body);
+        assert_eq!(body["error"]["code"], "invalid_request");
+    }
+}
+
+#[tokio::test]
+async fn embed_attaches_each_chunks_embedding_and_the_model() {
+    let embedder = Arc::new(StubEmbedder::default());
+    let router = common::app(common::state(None, embedder.clone()));
+
+    let request = post_multipart("/api/ingest/preview?embed=true", "guide.md", MARKDOWN.as_bytes(), &[]);
+    let (status, body) = send(router, request).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["embedding_model"], "stub-bag-of-words");
+    for chunk in body["chunks"].as_array().unwrap() {
+        let embedding: Vec<f32> = serde_json::from_value(chunk["embedding"].clone()).unwrap();
+        assert_eq!(embedding.len(), DIMENSIONS);
+        assert_eq!(embedding, common::embed_text(chunk["content"].as_str().unwrap()));
+    }
+    assert_eq!(embedder.calls(), 1);
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use serde_json::json;
+use uuid::Uuid;
+
+use common::{post_json, send, FailingEmbedder, StubEmbedder};
+use conversai_rag::handlers::query::handle_query;
+
+#[tokio::test]
+async fn empty_query_is_rejected_before_embedding() {
+    let embedder = Arc::new(StubEmbedder::default());
+    // No pool either: validation has to answer before the 503 for a missing database
+    let router = Router::new()
+        .route("/api/query", post(handle_query))
+        .with_state(common::state(None, embedder.clone()));
+
+    for query in ["", "   ", "\n\t "] {
+        let (status, body) = send(router.clone(), post_json("/api/query", json!({ "query": query }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}: {}", query, body);
+        assert_eq!(body["error"]["code"], "invalid_request");
+        assert!(body["error"]["message"].as_str().unwrap().contains("must not be empty"));
+    }
+    assert_eq!(embedder.calls(), 0);
+}
+
+#[tokio::test]
+async fn embedding_failure_falls_back_to_lexical_search() {
+    let Some(pool) = common::test_pool().await else { return };
+    let ingest = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+    for (uri, content) in [
+        ("notes://ownership", "# Ownership\n\nThe borrow checker enforces ownership of every value."),
+        ("notes://deploy", "# Deploy\n\nRailway builds the Dockerfile on every push."),
+    ] {
+        common::ingest_text(&ingest, json!({ "content": content, "source_uri": uri })).await;
+    }
+
+    // The provider goes down after ingestion
+    let router = common::app(common::state(Some(pool), Arc::new(FailingEmbedder::default())));
+    let (status, body) = send(router, post_json("/api/query", json!({ "query": "dockerfile" }))).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["diagnostics"]["degraded"], true);
+    assert_eq!(body["diagnostics"]["reranker"], "lexical-only");
+    let context = body["context"].as_array().unwrap();
+    assert_eq!(context.len(), 1);
+    assert_eq!(context[0]["source_uri"], "notes://deploy");
+    assert!(context[0]["lexical_score"]["raw"].as_f64().unwrap() > 0.0);
+}
+
+#[tokio::test]
+async fn citations_carry_each_documents_source_uri() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool), Arc::new(StubEmbedder::default())));
+    // Two documents share a URI under different keys, so neither supersedes the other
+    let mut ids = Vec::new();
+    for (uri, key, content) in [
+        ("wiki://rust", "rust-ownership", "# Ownership\n\nThe borrow checker enforces ownership."),
+        ("wiki://rust", "rust-lifetimes", "# Lifetimes\n\nThe borrow checker tracks lifetimes."),
+        ("notes://deploy", "deploy", "# Deploy\n\nThe borrow checker runs on every Railway build."),
+    ] {
+        let body = common::ingest_text(&router, json!({ "content": content, "source_uri": uri, "document_key": key })).await;
+        ids.push(common::uuid(&body["document_id"]));
+    }
+
+    let (status, body) = send(router, post_json("/api/query", json!({ "query": "borrow checker", "k": 10 }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let context = body["context"].as_array().unwrap();
+    let citations = body["citations"].as_array().unwrap();
+    assert_eq!(citations.len(), 3);
+
+    for (result, citation) in context.iter().zip(citations) {
+        assert_eq!(citation["document_id"], result["chunk"]["document_id"]);
+        assert_eq!(citation["source_uri"], result["source_uri"]);
+        let expected = if common::uuid(&citation["document_id"]) == ids[2] { "notes://deploy" } else { "wiki://rust" };
+        assert_eq!(citation["source_uri"], expected);
+    }
+    let cited: BTreeSet<Uuid> = citations.iter().map(|citation| common::uuid(&citation["document_id"])).collect();
+    assert_eq!(cited, ids.into_iter().collect());
+}
+
+#[tokio::test]
+async fn bypassed_queries_neither_read_nor_fill_the_cache() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool), Arc::new(StubEmbedder::default())));
+    common::ingest_text(&router, json!({ "content": "# Ownership\n\nThe borrow checker enforces ownership." })).await;
+    let query = |cache: &str| post_json("/api/query", json!({ "query": "borrow checker", "cache": cache }));
+    let cache_diagnostics = |body: &serde_json::Value| body["diagnostics"]["cache"].clone();
+
+    let (status, body) = send(router.clone(), query("bypass")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(cache_diagnostics(&body), json!({ "status": "bypass", "hits": 0, "misses": 0 }));
+
+    // The bypassed response wasn't stored, so this is a miss
+    let (_, body) = send(router.clone(), query("use")).await;
+    assert_eq!(cache_diagnostics(&body), json!({ "status": "miss", "hits": 0, "misses": 1 }));
+    let (_, cached) = send(router.clone(), query("use")).await;
+    assert_eq!(cache_diagnostics(&cached), json!({ "status": "hit", "hits": 1, "misses": 1 }));
+    assert_eq!(cached["context"], body["context"]);
+
+    // With an entry present, bypass still doesn't read it or move the counters
+    let (_, body) = send(router, query("bypass")).await;
+    assert_eq!(cache_diagnostics(&body), json!({ "status": "bypass", "hits": 1, "misses": 1 }));
+}
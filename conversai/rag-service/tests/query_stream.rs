@@ -0,0 +1,105 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::to_bytes;
+use axum::http::{header, StatusCode};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::StubEmbedder;
+
+/// Reads the whole event stream and returns `(event, data)` pairs in order.
+async fn events(router: axum::Router, query: Value) -> Vec<(String, Value)> {
+    let response = router.oneshot(common::post_json("/api/query/stream", query)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(bytes.to_vec())
+        .unwrap()
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut name = None;
+            let mut data = None;
+            for line in block.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    name = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data = Some(serde_json::from_str(value.trim()).unwrap());
+                }
+            }
+            Some((name?, data?))
+        })
+        .collect()
+}
+
+fn names(events: &[(String, Value)]) -> Vec<&str> {
+    events.iter().map(|(name, _)| name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn retrieval_failure_is_reported_as_an_error_event() {
+    // Nothing listens on port 1, so the search fails once the stream has started
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(1))
+        .connect_lazy("postgres://localhost:1/none")
+        .unwrap();
+    let router = common::app(common::state(Some(pool), Arc::new(StubEmbedder::default())));
+
+    let events = events(router, json!({ "query": "borrow checker" })).await;
+    assert_eq!(names(&events), ["error"]);
+    assert_eq!(events[0].1["phase"], "retrieval");
+    assert_eq!(events[0].1["code"], "database_error");
+}
+
+#[tokio::test]
+async fn answer_streams_citations_then_deltas_then_done() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool), Arc::new(StubEmbedder::default())));
+    common::ingest_text(
+        &router,
+        json!({
+            "content": "# Ownership\n\nThe borrow checker enforces ownership of every value.",
+            "source_uri": "notes://ownership",
+        }),
+    )
+    .await;
+
+    let llm = MockServer::start().await;
+    let completion = [
+        r#"data: {"choices":[{"delta":{"content":"The borrow checker "}}]}"#,
+        r#"data: {"choices":[{"delta":{"content":"enforces ownership [1]."}}]}"#,
+        "data: [DONE]",
+    ]
+    .map(|line| format!("{}\n\n", line))
+    .concat();
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(completion, "text/event-stream"))
+        .expect(1)
+        .mount(&llm)
+        .await;
+    std::env::set_var("OPENAI_BASE_URL", llm.uri());
+    std::env::set_var("OPENAI_API_KEY", "test-key");
+
+    let events = events(router, json!({ "query": "what does the borrow checker enforce" })).await;
+    assert_eq!(names(&events), ["citations", "delta", "delta", "done"]);
+
+    let citations = &events[0].1;
+    assert_eq!(citations["citations"][0]["source_uri"], "notes://ownership");
+    assert!(citations["context"][0]["chunk"]["content"].as_str().unwrap().contains("borrow checker"));
+
+    let answer: String = events[1..3].iter().map(|(_, data)| data["text"].as_str().unwrap()).collect();
+    assert_eq!(answer, "The borrow checker enforces ownership [1].");
+
+    let done = &events[3].1;
+    assert_eq!(done["answer_citations"], json!([{ "marker": "[1]", "citation_index": 0 }]));
+    assert!(done["diagnostics"].is_object());
+}
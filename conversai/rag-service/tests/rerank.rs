@@ -0,0 +1,94 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use common::{post_json, send, StubEmbedder};
+
+/// Scores the Railway passage highest and the rest in request order, so the
+/// cross-encoder's ranking differs from the cosine one.
+fn relevance(request: &Request) -> ResponseTemplate {
+    let body: Value = serde_json::from_slice(&request.body).unwrap();
+    let results: Vec<Value> = body["documents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let score = if document.as_str().unwrap().contains("Railway") { 0.9 } else { 0.1 / (index + 1) as f64 };
+            json!({ "index": index, "relevance_score": score })
+        })
+        .collect();
+    ResponseTemplate::new(200)
+        .set_body_json(json!({ "results": results }))
+        .set_delay(Duration::from_millis(20))
+}
+
+// One test, since the provider is configured through process-wide variables
+#[tokio::test]
+async fn cross_encoder_reorders_results_and_falls_back_to_cosine() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool), Arc::new(StubEmbedder::default())));
+    for (uri, content) in [
+        ("notes://ownership", "# Ownership\n\nThe borrow checker enforces ownership of every value."),
+        ("notes://lifetimes", "# Lifetimes\n\nThe borrow checker tracks lifetimes."),
+        ("notes://deploy", "# Deploy\n\nRailway builds run the borrow checker on every push."),
+    ] {
+        common::ingest_text(&router, json!({ "content": content, "source_uri": uri })).await;
+    }
+
+    let provider = MockServer::start().await;
+    std::env::set_var("RERANK_PROVIDER", "cohere");
+    std::env::set_var("RERANK_API_KEY", "test-key");
+    std::env::set_var("RERANK_BASE_URL", provider.uri());
+    let query = |reranker: &str| {
+        post_json(
+            "/api/query",
+            json!({ "query": "borrow checker ownership", "k": 3, "reranker": reranker, "cache": "bypass" }),
+        )
+    };
+
+    let (status, cosine) = send(router.clone(), query("cosine")).await;
+    assert_eq!(status, StatusCode::OK, "{}", cosine);
+    assert_ne!(cosine["context"][0]["source_uri"], "notes://deploy");
+
+    let rerank = Mock::given(method("POST"))
+        .and(path("/rerank"))
+        .and(header("authorization", "Bearer test-key"))
+        .respond_with(relevance)
+        .expect(1)
+        .mount_as_scoped(&provider)
+        .await;
+    let (status, body) = send(router.clone(), query("cross-encoder")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    drop(rerank);
+
+    assert_eq!(body["diagnostics"]["reranker"], "cross-encoder");
+    assert!(body["diagnostics"]["rerank_time_ms"].as_u64().unwrap() >= 20, "{}", body["diagnostics"]);
+    let context = body["context"].as_array().unwrap();
+    assert_eq!(context.len(), 3);
+    assert_eq!(context[0]["source_uri"], "notes://deploy");
+    assert!((context[0]["score"].as_f64().unwrap() - 0.9).abs() < 1e-6);
+    let scores: Vec<f64> = context.iter().map(|result| result["score"].as_f64().unwrap()).collect();
+    assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", scores);
+
+    Mock::given(method("POST"))
+        .and(path("/rerank"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&provider)
+        .await;
+    let (status, body) = send(router, query("cross-encoder")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["diagnostics"]["reranker"], "cosine");
+    assert_eq!(body["context"], cosine["context"]);
+    let warnings = body["diagnostics"]["warnings"].to_string();
+    assert!(warnings.contains("cross-encoder rerank failed, used cosine"), "{}", warnings);
+}
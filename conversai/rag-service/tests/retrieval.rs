@@ -0,0 +1,156 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use common::{embed_text, StubEmbedder};
+use conversai_rag::models::{FusionMethod, QueryFilters, ScoreNormalization, SimilarityMetric};
+use conversai_rag::services::retrieval::{
+    self, ChunkWithScore, FusionOptions, HybridWeights, DEFAULT_RRF_K,
+};
+
+struct Corpus {
+    pool: PgPool,
+    ownership: Uuid,
+    deploy: Uuid,
+    lifetimes: Uuid,
+}
+
+/// Three documents: two tagged `rust`, one of them created in 2020, and one
+/// tagged `deploy`.
+async fn seed() -> Option<Corpus> {
+    let pool = common::test_pool().await?;
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+
+    let mut ids = Vec::new();
+    for (uri, tags, content) in [
+        ("notes://ownership", json!(["rust"]), "# Ownership\n\nThe borrow checker enforces ownership of every value."),
+        ("notes://deploy", json!(["deploy"]), "# Deploy\n\nRailway builds the Dockerfile on every push."),
+        ("notes://lifetimes", json!(["rust", "old"]), "# Lifetimes\n\nLifetimes tell the borrow checker how long references live."),
+    ] {
+        let body = common::ingest_text(&router, json!({ "content": content, "source_uri": uri, "tags": tags })).await;
+        ids.push(common::uuid(&body["document_id"]));
+    }
+    sqlx::query("UPDATE documents SET created_at = $1 WHERE id = $2")
+        .bind(Utc.with_ymd_and_hms(2020, 6, 1, 0, 0, 0).unwrap())
+        .bind(ids[2])
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    Some(Corpus { pool, ownership: ids[0], deploy: ids[1], lifetimes: ids[2] })
+}
+
+fn fusion(method: FusionMethod) -> FusionOptions {
+    FusionOptions {
+        method,
+        rrf_k: DEFAULT_RRF_K,
+        weights: HybridWeights::from_alpha(0.7),
+        normalization: ScoreNormalization::MinMax,
+        min_score: None,
+        similarity: SimilarityMetric::Cosine,
+        boost: None,
+    }
+}
+
+fn filters() -> QueryFilters {
+    QueryFilters {
+        tags: None,
+        document_ids: None,
+        date_range: None,
+        include_superseded: false,
+        content_kind: None,
+    }
+}
+
+fn documents(results: &[ChunkWithScore]) -> BTreeSet<Uuid> {
+    results.iter().map(|result| result.chunk.document_id).collect()
+}
+
+#[tokio::test]
+async fn hybrid_search_applies_every_filter_together() {
+    let Some(corpus) = seed().await else { return };
+    let query = "borrow checker";
+    let embedding = embed_text(query);
+
+    for method in [FusionMethod::WeightedSum, FusionMethod::Rrf] {
+        let search = |filters: QueryFilters| {
+            let (pool, embedding, fusion) = (&corpus.pool, &embedding, fusion(method));
+            async move { retrieval::hybrid_search(pool, embedding, query, 10, Some(&filters), &fusion).await.unwrap() }
+        };
+
+        let unfiltered = retrieval::hybrid_search(&corpus.pool, &embedding, query, 10, None, &fusion(method))
+            .await
+            .unwrap();
+        assert!(documents(&unfiltered).contains(&corpus.ownership), "{:?}", method);
+
+        let rust = search(QueryFilters { tags: Some(vec!["rust".into()]), ..filters() }).await;
+        assert_eq!(documents(&rust), BTreeSet::from([corpus.ownership, corpus.lifetimes]), "{:?}", method);
+
+        let rust_in_ids = search(QueryFilters {
+            tags: Some(vec!["rust".into()]),
+            document_ids: Some(vec![corpus.ownership, corpus.deploy]),
+            ..filters()
+        })
+        .await;
+        assert_eq!(documents(&rust_in_ids), BTreeSet::from([corpus.ownership]), "{:?}", method);
+
+        let from = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2020, 12, 31, 0, 0, 0).unwrap();
+        let rust_in_2020 = search(QueryFilters {
+            tags: Some(vec!["rust".into()]),
+            date_range: Some((from, to)),
+            ..filters()
+        })
+        .await;
+        assert_eq!(documents(&rust_in_2020), BTreeSet::from([corpus.lifetimes]), "{:?}", method);
+
+        // Conjunctive: the deploy document is in the id list but not tagged rust
+        let none = search(QueryFilters {
+            tags: Some(vec!["rust".into()]),
+            document_ids: Some(vec![corpus.deploy]),
+            ..filters()
+        })
+        .await;
+        assert!(none.is_empty(), "{:?}: {:?}", method, documents(&none));
+    }
+}
+
+#[tokio::test]
+async fn lexical_search_matches_terms_within_filters() {
+    let Some(corpus) = seed().await else { return };
+    let fusion = fusion(FusionMethod::WeightedSum);
+
+    let dockerfile = retrieval::lexical_search(&corpus.pool, "dockerfile", 10, None, &fusion).await.unwrap();
+    assert_eq!(documents(&dockerfile), BTreeSet::from([corpus.deploy]));
+    assert!(dockerfile[0].lexical_score.raw > 0.0);
+
+    let borrow = retrieval::lexical_search(&corpus.pool, "borrow checker", 10, None, &fusion).await.unwrap();
+    assert_eq!(documents(&borrow), BTreeSet::from([corpus.ownership, corpus.lifetimes]));
+
+    let rust_only = QueryFilters { tags: Some(vec!["rust".into()]), ..filters() };
+    let filtered = retrieval::lexical_search(&corpus.pool, "dockerfile", 10, Some(&rust_only), &fusion).await.unwrap();
+    assert!(filtered.is_empty());
+}
+
+#[tokio::test]
+async fn semantic_search_ranks_the_nearest_chunk_first() {
+    let Some(corpus) = seed().await else { return };
+    let fusion = fusion(FusionMethod::WeightedSum);
+
+    let embedding = embed_text("Railway Dockerfile push");
+    let results = retrieval::semantic_search(&corpus.pool, &embedding, 10, None, &fusion).await.unwrap();
+    assert_eq!(results[0].chunk.document_id, corpus.deploy);
+    assert!(results.windows(2).all(|pair| pair[0].semantic_score.raw >= pair[1].semantic_score.raw));
+
+    let elsewhere = QueryFilters { document_ids: Some(vec![corpus.ownership]), ..filters() };
+    let results = retrieval::semantic_search(&corpus.pool, &embedding, 10, Some(&elsewhere), &fusion).await.unwrap();
+    assert_eq!(documents(&results), BTreeSet::from([corpus.ownership]));
+}
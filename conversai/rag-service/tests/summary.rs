@@ -0,0 +1,63 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{send, StubEmbedder};
+
+// One test, since summaries are configured through process-wide variables
+#[tokio::test]
+async fn summary_chunk_is_stored_but_not_counted() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+
+    let llm = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "How the service is set up and deployed." } }]
+        })))
+        .expect(1)
+        .mount(&llm)
+        .await;
+    std::env::set_var("OPENAI_BASE_URL", llm.uri());
+    std::env::set_var("OPENAI_API_KEY", "test-key");
+    std::env::set_var("DOCUMENT_SUMMARIES", "true");
+    std::env::set_var("SUMMARY_MIN_TOKENS", "1");
+
+    let body = common::ingest_text(
+        &router,
+        json!({ "content": "# Setup\n\nInstall the CLI.\n\n# Deploy\n\nPush to main and Railway builds the image." }),
+    )
+    .await;
+    let document_id = common::uuid(&body["document_id"]);
+
+    let kinds: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT metadata->>'kind' FROM chunks WHERE document_id = $1 ORDER BY chunk_index")
+            .bind(document_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(kinds, [None, None, Some("summary".to_string())]);
+    assert_eq!(body["chunks_count"], 2);
+    let tokens: i64 = sqlx::query_scalar(
+        "SELECT sum(content_tokens) FROM chunks WHERE document_id = $1 AND metadata->>'kind' IS NULL",
+    )
+    .bind(document_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(body["tokens_estimate"], tokens);
+
+    let (status, list) = send(router, Request::get("/api/documents").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK, "{}", list);
+    assert_eq!(list["documents"][0]["chunk_count"], 2);
+    assert_eq!(list["documents"][0]["total_tokens"], tokens);
+}
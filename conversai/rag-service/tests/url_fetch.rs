@@ -0,0 +1,130 @@
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Router;
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{post_json, send, StubEmbedder};
+
+/// A router whose fetches fail before anything is stored, so no database is needed.
+fn router(max_upload_bytes: usize) -> Router {
+    // Nothing listens on port 1; handle_ingest_url only needs a pool to exist
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(1))
+        .connect_lazy("postgres://localhost:1/none")
+        .unwrap();
+    let mut state = common::state(Some(pool), Arc::new(StubEmbedder::default()));
+    state.max_upload_bytes = max_upload_bytes;
+    common::app(state)
+}
+
+async fn ingest_url(router: Router, url: &str) -> (StatusCode, Value) {
+    send(router, post_json("/api/ingest/url", json!({ "url": url }))).await
+}
+
+#[tokio::test]
+async fn unsupported_content_type_is_rejected() {
+    let site = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/logo.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0x89, b'P', b'N', b'G'], "image/png"))
+        .mount(&site)
+        .await;
+
+    let url = format!("{}/logo.png", site.uri());
+    let (status, body) = ingest_url(router(1024), &url).await;
+
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{}", body);
+    assert_eq!(body["error"]["code"], "unsupported_content_type");
+    assert_eq!(body["error"]["details"]["content_type"], "image/png");
+    assert_eq!(body["error"]["details"]["url"], url);
+}
+
+#[tokio::test]
+async fn pages_over_the_upload_limit_are_rejected() {
+    let site = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/big.md"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("# Big\n\n".repeat(1000), "text/markdown"))
+        .mount(&site)
+        .await;
+
+    let (status, body) = ingest_url(router(1024), &format!("{}/big.md", site.uri())).await;
+
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{}", body);
+    assert_eq!(body["error"]["code"], "payload_too_large");
+}
+
+#[tokio::test]
+async fn error_statuses_are_reported_with_the_upstream_status() {
+    let site = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&site)
+        .await;
+
+    let url = format!("{}/missing", site.uri());
+    let (status, body) = ingest_url(router(1024), &url).await;
+
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+    assert_eq!(body["error"]["code"], "upstream_status");
+    assert_eq!(body["error"]["details"], json!({ "url": url, "status": 404 }));
+}
+
+#[tokio::test]
+async fn redirect_loops_stop_after_the_limit() {
+    let site = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/loop"))
+        .respond_with(ResponseTemplate::new(302).insert_header("location", "/loop"))
+        .expect(6)
+        .mount(&site)
+        .await;
+
+    let (status, body) = ingest_url(router(1024), &format!("{}/loop", site.uri())).await;
+
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+    assert_eq!(body["error"]["code"], "too_many_redirects");
+}
+
+#[tokio::test]
+async fn redirects_are_followed_and_reported() {
+    let Some(pool) = common::test_pool().await else { return };
+    let router = common::app(common::state(Some(pool.clone()), Arc::new(StubEmbedder::default())));
+    let site = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/old"))
+        .respond_with(ResponseTemplate::new(301).insert_header("location", "/docs/deploy.md"))
+        .mount(&site)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/docs/deploy.md"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("# Deploy\n\nPush to main.", "text/markdown"))
+        .mount(&site)
+        .await;
+
+    let url = format!("{}/old", site.uri());
+    let final_url = format!("{}/docs/deploy.md", site.uri());
+    let (status, body) = ingest_url(router, &url).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body["chunks_count"].as_u64().unwrap() > 0);
+    let warning = format!("redirected: {} redirected to {}", url, final_url);
+    assert!(body["warnings"].as_array().unwrap().contains(&json!(warning)), "{}", body["warnings"]);
+
+    let source_uri: String = sqlx::query_scalar("SELECT source_uri FROM documents WHERE id = $1")
+        .bind(common::uuid(&body["document_id"]))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(source_uri, final_url);
+}
@@ -0,0 +1,20 @@
+-- Background ingestion jobs created by POST /api/ingest?async=true
+
+CREATE TABLE IF NOT EXISTS ingest_jobs (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    status text NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'running', 'completed', 'failed')),
+    filename text NOT NULL,
+    document_id uuid REFERENCES documents(id) ON DELETE SET NULL,
+    chunks_done int NOT NULL DEFAULT 0,
+    chunks_total int,
+    error text,
+    created_at timestamptz DEFAULT now(),
+    updated_at timestamptz DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS ingest_jobs_status_idx ON ingest_jobs (status);
+
+CREATE TRIGGER ingest_jobs_updated_at
+BEFORE UPDATE ON ingest_jobs
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at();
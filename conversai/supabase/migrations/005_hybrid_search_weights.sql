@@ -0,0 +1,80 @@
+-- Make the semantic/lexical blend of hybrid_search configurable per call
+-- The weights decide which candidates survive the final LIMIT; defaults keep the previous 0.7/0.3
+
+DROP FUNCTION IF EXISTS hybrid_search(vector(1536), text, int, text[], uuid[], timestamptz, timestamptz);
+
+CREATE OR REPLACE FUNCTION hybrid_search(
+    query_embedding vector(1536),
+    query_text text,
+    match_count int DEFAULT 10,
+    filter_tags text[] DEFAULT NULL,
+    filter_document_ids uuid[] DEFAULT NULL,
+    filter_created_from timestamptz DEFAULT NULL,
+    filter_created_to timestamptz DEFAULT NULL,
+    semantic_weight double precision DEFAULT 0.7,
+    lexical_weight double precision DEFAULT 0.3
+)
+RETURNS TABLE (
+    chunk_id uuid,
+    document_id uuid,
+    content text,
+    section text,
+    metadata jsonb,
+    semantic_score double precision,
+    lexical_score double precision,
+    combined_score double precision
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RETURN QUERY
+    WITH filtered_documents AS (
+        SELECT d.id
+        FROM documents d
+        WHERE (filter_tags IS NULL OR d.tags && filter_tags)
+            AND (filter_document_ids IS NULL OR d.id = ANY(filter_document_ids))
+            AND (filter_created_from IS NULL OR d.created_at >= filter_created_from)
+            AND (filter_created_to IS NULL OR d.created_at <= filter_created_to)
+    ),
+    semantic_search AS (
+        SELECT 
+            c.id,
+            c.document_id,
+            c.content,
+            c.section,
+            c.metadata,
+            (1 - (c.embedding <=> query_embedding))::double precision AS score
+        FROM chunks c
+        JOIN filtered_documents d ON c.document_id = d.id
+        ORDER BY c.embedding <=> query_embedding
+        LIMIT match_count * 2
+    ),
+    lexical_search AS (
+        SELECT 
+            c.id,
+            c.document_id,
+            c.content,
+            c.section,
+            c.metadata,
+            ts_rank_cd(to_tsvector('simple', c.content), plainto_tsquery('simple', query_text))::double precision AS score
+        FROM chunks c
+        JOIN filtered_documents d ON c.document_id = d.id
+        WHERE to_tsvector('simple', c.content) @@ plainto_tsquery('simple', query_text)
+        ORDER BY score DESC
+        LIMIT match_count * 2
+    )
+    SELECT 
+        COALESCE(s.id, l.id) AS chunk_id,
+        COALESCE(s.document_id, l.document_id) AS document_id,
+        COALESCE(s.content, l.content) AS content,
+        COALESCE(s.section, l.section) AS section,
+        COALESCE(s.metadata, l.metadata) AS metadata,
+        COALESCE(s.score, 0::double precision) AS semantic_score,
+        COALESCE(l.score, 0::double precision) AS lexical_score,
+        (COALESCE(s.score, 0::double precision) * semantic_weight + COALESCE(l.score, 0::double precision) * lexical_weight) AS combined_score
+    FROM semantic_search s
+    FULL OUTER JOIN lexical_search l ON s.id = l.id
+    ORDER BY combined_score DESC
+    LIMIT match_count;
+END;
+$$;
@@ -0,0 +1,93 @@
+-- Reciprocal Rank Fusion variant of hybrid_search, used for queries with "fusion": "rrf"
+-- Each list is ranked on its own and fused with 1 / (rrf_k + rank), so the scales of
+-- cosine similarity and ts_rank_cd no longer matter
+
+CREATE OR REPLACE FUNCTION rrf_search(
+    query_embedding vector(1536),
+    query_text text,
+    match_count int DEFAULT 10,
+    filter_tags text[] DEFAULT NULL,
+    filter_document_ids uuid[] DEFAULT NULL,
+    filter_created_from timestamptz DEFAULT NULL,
+    filter_created_to timestamptz DEFAULT NULL,
+    rrf_k int DEFAULT 60
+)
+RETURNS TABLE (
+    chunk_id uuid,
+    document_id uuid,
+    content text,
+    section text,
+    metadata jsonb,
+    semantic_score double precision,
+    lexical_score double precision,
+    semantic_rank bigint,
+    lexical_rank bigint,
+    rrf_score double precision
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RETURN QUERY
+    WITH filtered_documents AS (
+        SELECT d.id
+        FROM documents d
+        WHERE (filter_tags IS NULL OR d.tags && filter_tags)
+            AND (filter_document_ids IS NULL OR d.id = ANY(filter_document_ids))
+            AND (filter_created_from IS NULL OR d.created_at >= filter_created_from)
+            AND (filter_created_to IS NULL OR d.created_at <= filter_created_to)
+    ),
+    semantic_search AS (
+        SELECT
+            c.id,
+            c.document_id,
+            c.content,
+            c.section,
+            c.metadata,
+            (1 - (c.embedding <=> query_embedding))::double precision AS score,
+            ROW_NUMBER() OVER (ORDER BY c.embedding <=> query_embedding, c.id) AS rank
+        FROM chunks c
+        JOIN filtered_documents d ON c.document_id = d.id
+        ORDER BY c.embedding <=> query_embedding, c.id
+        LIMIT match_count * 2
+    ),
+    lexical_search AS (
+        SELECT
+            ranked.id,
+            ranked.document_id,
+            ranked.content,
+            ranked.section,
+            ranked.metadata,
+            ranked.score,
+            ROW_NUMBER() OVER (ORDER BY ranked.score DESC, ranked.id) AS rank
+        FROM (
+            SELECT
+                c.id,
+                c.document_id,
+                c.content,
+                c.section,
+                c.metadata,
+                ts_rank_cd(to_tsvector('simple', c.content), plainto_tsquery('simple', query_text))::double precision AS score
+            FROM chunks c
+            JOIN filtered_documents d ON c.document_id = d.id
+            WHERE to_tsvector('simple', c.content) @@ plainto_tsquery('simple', query_text)
+        ) ranked
+        ORDER BY ranked.score DESC, ranked.id
+        LIMIT match_count * 2
+    )
+    SELECT
+        COALESCE(s.id, l.id) AS chunk_id,
+        COALESCE(s.document_id, l.document_id) AS document_id,
+        COALESCE(s.content, l.content) AS content,
+        COALESCE(s.section, l.section) AS section,
+        COALESCE(s.metadata, l.metadata) AS metadata,
+        COALESCE(s.score, 0::double precision) AS semantic_score,
+        COALESCE(l.score, 0::double precision) AS lexical_score,
+        s.rank AS semantic_rank,
+        l.rank AS lexical_rank,
+        (COALESCE(1.0 / (rrf_k + s.rank), 0) + COALESCE(1.0 / (rrf_k + l.rank), 0))::double precision AS rrf_score
+    FROM semantic_search s
+    FULL OUTER JOIN lexical_search l ON s.id = l.id
+    ORDER BY rrf_score DESC
+    LIMIT match_count;
+END;
+$$;
@@ -0,0 +1,20 @@
+-- Position of each chunk within its document, so neighbouring chunks can be fetched
+-- for context expansion ("expand_context" in query requests)
+
+ALTER TABLE chunks ADD COLUMN IF NOT EXISTS chunk_index int;
+
+-- Backfill existing documents in reading order
+UPDATE chunks c
+SET chunk_index = ordered.position
+FROM (
+    SELECT
+        id,
+        (ROW_NUMBER() OVER (
+            PARTITION BY document_id
+            ORDER BY (span->>'start_char')::int NULLS LAST, created_at, id
+        ) - 1)::int AS position
+    FROM chunks
+) ordered
+WHERE c.id = ordered.id AND c.chunk_index IS NULL;
+
+CREATE INDEX IF NOT EXISTS chunks_document_chunk_index ON chunks (document_id, chunk_index);
@@ -0,0 +1,4 @@
+-- Embedding model each document's chunks were embedded with, so queries can warn
+-- when they mix vectors from a different model than the one currently configured
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS embedding_model text;
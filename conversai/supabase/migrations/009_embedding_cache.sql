@@ -0,0 +1,11 @@
+-- Embeddings keyed by content hash and model, so re-ingesting unchanged chunks
+-- doesn't pay for the same embedding twice. The vector column is unsized because
+-- the cache can hold vectors from several models.
+
+CREATE TABLE IF NOT EXISTS embedding_cache (
+    content_sha256 text NOT NULL,
+    model text NOT NULL,
+    embedding vector NOT NULL,
+    created_at timestamptz DEFAULT now(),
+    PRIMARY KEY (content_sha256, model)
+);
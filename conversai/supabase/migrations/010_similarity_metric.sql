@@ -0,0 +1,212 @@
+-- Rank the semantic half of hybrid_search and rrf_search by a chosen vector metric
+-- `metric` is 'cosine' (<=>), 'dot' (<#>) or 'euclidean' (<->); the service passes SIMILARITY_METRIC.
+-- Semantic scores stay higher-is-better for every metric: 1 - cosine distance, the inner
+-- product, or 1 / (1 + L2 distance). An ANN index only serves the operator it was built with.
+-- Defaults keep the previous cosine behaviour for callers that don't pass a metric.
+
+DROP FUNCTION IF EXISTS hybrid_search(vector(1536), text, int, text[], uuid[], timestamptz, timestamptz, double precision, double precision);
+DROP FUNCTION IF EXISTS rrf_search(vector(1536), text, int, text[], uuid[], timestamptz, timestamptz, int);
+
+-- SQL fragments for ordering by distance and computing the semantic score against $1
+CREATE OR REPLACE FUNCTION similarity_sql(metric text, OUT distance text, OUT score text)
+LANGUAGE plpgsql
+IMMUTABLE
+AS $$
+BEGIN
+    CASE metric
+        WHEN 'cosine' THEN
+            distance := 'c.embedding <=> $1';
+            score := '1 - (c.embedding <=> $1)';
+        WHEN 'dot' THEN
+            distance := 'c.embedding <#> $1';
+            score := '-(c.embedding <#> $1)';
+        WHEN 'euclidean' THEN
+            distance := 'c.embedding <-> $1';
+            score := '1 / (1 + (c.embedding <-> $1))';
+        ELSE
+            RAISE EXCEPTION 'unknown similarity metric: %', metric;
+    END CASE;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION hybrid_search(
+    query_embedding vector(1536),
+    query_text text,
+    match_count int DEFAULT 10,
+    filter_tags text[] DEFAULT NULL,
+    filter_document_ids uuid[] DEFAULT NULL,
+    filter_created_from timestamptz DEFAULT NULL,
+    filter_created_to timestamptz DEFAULT NULL,
+    semantic_weight double precision DEFAULT 0.7,
+    lexical_weight double precision DEFAULT 0.3,
+    metric text DEFAULT 'cosine'
+)
+RETURNS TABLE (
+    chunk_id uuid,
+    document_id uuid,
+    content text,
+    section text,
+    metadata jsonb,
+    semantic_score double precision,
+    lexical_score double precision,
+    combined_score double precision
+)
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    sim record;
+BEGIN
+    SELECT * INTO sim FROM similarity_sql(metric);
+
+    RETURN QUERY EXECUTE format($query$
+        WITH filtered_documents AS (
+            SELECT d.id
+            FROM documents d
+            WHERE ($4 IS NULL OR d.tags && $4)
+                AND ($5 IS NULL OR d.id = ANY($5))
+                AND ($6 IS NULL OR d.created_at >= $6)
+                AND ($7 IS NULL OR d.created_at <= $7)
+        ),
+        semantic_search AS (
+            SELECT
+                c.id,
+                c.document_id,
+                c.content,
+                c.section,
+                c.metadata,
+                (%2$s)::double precision AS score
+            FROM chunks c
+            JOIN filtered_documents d ON c.document_id = d.id
+            ORDER BY %1$s
+            LIMIT $3 * 2
+        ),
+        lexical_search AS (
+            SELECT
+                c.id,
+                c.document_id,
+                c.content,
+                c.section,
+                c.metadata,
+                ts_rank_cd(to_tsvector('simple', c.content), plainto_tsquery('simple', $2))::double precision AS score
+            FROM chunks c
+            JOIN filtered_documents d ON c.document_id = d.id
+            WHERE to_tsvector('simple', c.content) @@ plainto_tsquery('simple', $2)
+            ORDER BY score DESC
+            LIMIT $3 * 2
+        )
+        SELECT
+            COALESCE(s.id, l.id) AS chunk_id,
+            COALESCE(s.document_id, l.document_id) AS document_id,
+            COALESCE(s.content, l.content) AS content,
+            COALESCE(s.section, l.section) AS section,
+            COALESCE(s.metadata, l.metadata) AS metadata,
+            COALESCE(s.score, 0::double precision) AS semantic_score,
+            COALESCE(l.score, 0::double precision) AS lexical_score,
+            (COALESCE(s.score, 0::double precision) * $8 + COALESCE(l.score, 0::double precision) * $9) AS combined_score
+        FROM semantic_search s
+        FULL OUTER JOIN lexical_search l ON s.id = l.id
+        ORDER BY combined_score DESC
+        LIMIT $3
+    $query$, sim.distance, sim.score)
+    USING query_embedding, query_text, match_count, filter_tags, filter_document_ids,
+        filter_created_from, filter_created_to, semantic_weight, lexical_weight;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION rrf_search(
+    query_embedding vector(1536),
+    query_text text,
+    match_count int DEFAULT 10,
+    filter_tags text[] DEFAULT NULL,
+    filter_document_ids uuid[] DEFAULT NULL,
+    filter_created_from timestamptz DEFAULT NULL,
+    filter_created_to timestamptz DEFAULT NULL,
+    rrf_k int DEFAULT 60,
+    metric text DEFAULT 'cosine'
+)
+RETURNS TABLE (
+    chunk_id uuid,
+    document_id uuid,
+    content text,
+    section text,
+    metadata jsonb,
+    semantic_score double precision,
+    lexical_score double precision,
+    semantic_rank bigint,
+    lexical_rank bigint,
+    rrf_score double precision
+)
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    sim record;
+BEGIN
+    SELECT * INTO sim FROM similarity_sql(metric);
+
+    RETURN QUERY EXECUTE format($query$
+        WITH filtered_documents AS (
+            SELECT d.id
+            FROM documents d
+            WHERE ($4 IS NULL OR d.tags && $4)
+                AND ($5 IS NULL OR d.id = ANY($5))
+                AND ($6 IS NULL OR d.created_at >= $6)
+                AND ($7 IS NULL OR d.created_at <= $7)
+        ),
+        semantic_search AS (
+            SELECT
+                c.id,
+                c.document_id,
+                c.content,
+                c.section,
+                c.metadata,
+                (%2$s)::double precision AS score,
+                ROW_NUMBER() OVER (ORDER BY %1$s, c.id) AS rank
+            FROM chunks c
+            JOIN filtered_documents d ON c.document_id = d.id
+            ORDER BY %1$s, c.id
+            LIMIT $3 * 2
+        ),
+        lexical_search AS (
+            SELECT
+                ranked.id,
+                ranked.document_id,
+                ranked.content,
+                ranked.section,
+                ranked.metadata,
+                ranked.score,
+                ROW_NUMBER() OVER (ORDER BY ranked.score DESC, ranked.id) AS rank
+            FROM (
+                SELECT
+                    c.id,
+                    c.document_id,
+                    c.content,
+                    c.section,
+                    c.metadata,
+                    ts_rank_cd(to_tsvector('simple', c.content), plainto_tsquery('simple', $2))::double precision AS score
+                FROM chunks c
+                JOIN filtered_documents d ON c.document_id = d.id
+                WHERE to_tsvector('simple', c.content) @@ plainto_tsquery('simple', $2)
+            ) ranked
+            ORDER BY ranked.score DESC, ranked.id
+            LIMIT $3 * 2
+        )
+        SELECT
+            COALESCE(s.id, l.id) AS chunk_id,
+            COALESCE(s.document_id, l.document_id) AS document_id,
+            COALESCE(s.content, l.content) AS content,
+            COALESCE(s.section, l.section) AS section,
+            COALESCE(s.metadata, l.metadata) AS metadata,
+            COALESCE(s.score, 0::double precision) AS semantic_score,
+            COALESCE(l.score, 0::double precision) AS lexical_score,
+            s.rank AS semantic_rank,
+            l.rank AS lexical_rank,
+            (COALESCE(1.0 / ($8 + s.rank), 0) + COALESCE(1.0 / ($8 + l.rank), 0))::double precision AS rrf_score
+        FROM semantic_search s
+        FULL OUTER JOIN lexical_search l ON s.id = l.id
+        ORDER BY rrf_score DESC
+        LIMIT $3
+    $query$, sim.distance, sim.score)
+    USING query_embedding, query_text, match_count, filter_tags, filter_document_ids,
+        filter_created_from, filter_created_to, rrf_k;
+END;
+$$;
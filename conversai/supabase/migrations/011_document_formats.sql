@@ -0,0 +1,8 @@
+-- Plain text uploads and fetched URLs as document source types, and per-document
+-- metadata recording the detected format (and, for URLs, the served content type)
+
+ALTER TABLE documents DROP CONSTRAINT IF EXISTS documents_source_type_check;
+ALTER TABLE documents ADD CONSTRAINT documents_source_type_check
+    CHECK (source_type IN ('md', 'txt', 'pdf', 'html', 'docx', 'csv', 'ocr', 'transcript', 'web', 'url'));
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS metadata jsonb NOT NULL DEFAULT '{}'::jsonb;
@@ -0,0 +1,4 @@
+-- Keep the final IngestResponse of a completed background ingest job, so
+-- GET /api/ingest/jobs/{id} can return it without a second request
+
+ALTER TABLE ingest_jobs ADD COLUMN IF NOT EXISTS result jsonb;
@@ -0,0 +1,119 @@
+-- Let the vector index serve the semantic half of rrf_search again: ordering the
+-- ANN scan by distance and then id makes pgvector sort every matching chunk
+-- instead of walking the HNSW/IVFFlat index. The candidates are now picked by
+-- distance alone and only ranked with the id tie-break afterwards.
+
+CREATE OR REPLACE FUNCTION rrf_search(
+    query_embedding vector(1536),
+    query_text text,
+    match_count int DEFAULT 10,
+    filter_tags text[] DEFAULT NULL,
+    filter_document_ids uuid[] DEFAULT NULL,
+    filter_created_from timestamptz DEFAULT NULL,
+    filter_created_to timestamptz DEFAULT NULL,
+    rrf_k int DEFAULT 60,
+    metric text DEFAULT 'cosine',
+    include_superseded boolean DEFAULT false,
+    filter_content_kind text DEFAULT NULL
+)
+RETURNS TABLE (
+    chunk_id uuid,
+    document_id uuid,
+    content text,
+    section text,
+    metadata jsonb,
+    semantic_score double precision,
+    lexical_score double precision,
+    semantic_rank bigint,
+    lexical_rank bigint,
+    rrf_score double precision
+)
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    sim record;
+BEGIN
+    SELECT * INTO sim FROM similarity_sql(metric);
+
+    RETURN QUERY EXECUTE format($query$
+        WITH filtered_documents AS (
+            SELECT d.id
+            FROM documents d
+            WHERE ($4 IS NULL OR d.tags && $4)
+                AND ($5 IS NULL OR d.id = ANY($5))
+                AND ($6 IS NULL OR d.created_at >= $6)
+                AND ($7 IS NULL OR d.created_at <= $7)
+                AND ($9 OR d.superseded_at IS NULL)
+        ),
+        semantic_search AS (
+            SELECT
+                nearest.id,
+                nearest.document_id,
+                nearest.content,
+                nearest.section,
+                nearest.metadata,
+                nearest.score,
+                ROW_NUMBER() OVER (ORDER BY nearest.distance, nearest.id) AS rank
+            FROM (
+                -- Ordered by distance alone so the HNSW/IVFFlat index can serve it;
+                -- ties are broken by id once the candidates are picked
+                SELECT
+                    c.id,
+                    c.document_id,
+                    c.content,
+                    c.section,
+                    c.metadata,
+                    (%2$s)::double precision AS score,
+                    %1$s AS distance
+                FROM chunks c
+                JOIN filtered_documents d ON c.document_id = d.id
+                WHERE ($10 IS NULL OR COALESCE(c.metadata->>'kind', 'prose') = $10)
+                ORDER BY %1$s
+                LIMIT $3 * 2
+            ) nearest
+        ),
+        lexical_search AS (
+            SELECT
+                ranked.id,
+                ranked.document_id,
+                ranked.content,
+                ranked.section,
+                ranked.metadata,
+                ranked.score,
+                ROW_NUMBER() OVER (ORDER BY ranked.score DESC, ranked.id) AS rank
+            FROM (
+                SELECT
+                    c.id,
+                    c.document_id,
+                    c.content,
+                    c.section,
+                    c.metadata,
+                    ts_rank_cd(to_tsvector('simple', c.content), plainto_tsquery('simple', $2))::double precision AS score
+                FROM chunks c
+                JOIN filtered_documents d ON c.document_id = d.id
+                WHERE to_tsvector('simple', c.content) @@ plainto_tsquery('simple', $2)
+                    AND ($10 IS NULL OR COALESCE(c.metadata->>'kind', 'prose') = $10)
+            ) ranked
+            ORDER BY ranked.score DESC, ranked.id
+            LIMIT $3 * 2
+        )
+        SELECT
+            COALESCE(s.id, l.id) AS chunk_id,
+            COALESCE(s.document_id, l.document_id) AS document_id,
+            COALESCE(s.content, l.content) AS content,
+            COALESCE(s.section, l.section) AS section,
+            COALESCE(s.metadata, l.metadata) AS metadata,
+            COALESCE(s.score, 0::double precision) AS semantic_score,
+            COALESCE(l.score, 0::double precision) AS lexical_score,
+            s.rank AS semantic_rank,
+            l.rank AS lexical_rank,
+            (COALESCE(1.0 / ($8 + s.rank), 0) + COALESCE(1.0 / ($8 + l.rank), 0))::double precision AS rrf_score
+        FROM semantic_search s
+        FULL OUTER JOIN lexical_search l ON s.id = l.id
+        ORDER BY rrf_score DESC
+        LIMIT $3
+    $query$, sim.distance, sim.score)
+    USING query_embedding, query_text, match_count, filter_tags, filter_document_ids,
+        filter_created_from, filter_created_to, rrf_k, include_superseded, filter_content_kind;
+END;
+$$;
@@ -0,0 +1,9 @@
+-- Background ingest jobs report `processing` instead of `running` while they work,
+-- matching the documented queued/processing/completed/failed states
+
+ALTER TABLE ingest_jobs DROP CONSTRAINT IF EXISTS ingest_jobs_status_check;
+
+UPDATE ingest_jobs SET status = 'processing' WHERE status = 'running';
+
+ALTER TABLE ingest_jobs ADD CONSTRAINT ingest_jobs_status_check
+    CHECK (status IN ('queued', 'processing', 'completed', 'failed'));
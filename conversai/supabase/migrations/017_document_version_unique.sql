@@ -0,0 +1,25 @@
+-- One document per (document_key, document_version). Two ingests of the same key
+-- could both read the same latest version and store the same next one; the second
+-- now fails on this index and is answered with 409 `version_conflict`.
+
+-- Keys that already hold duplicates are renumbered in the order they were stored
+UPDATE documents d
+SET document_version = renumbered.version
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY document_key ORDER BY document_version, created_at, id)::int AS version
+    FROM documents
+    WHERE document_key IN (
+        SELECT document_key
+        FROM documents
+        WHERE document_key IS NOT NULL
+        GROUP BY document_key, document_version
+        HAVING count(*) > 1
+    )
+) renumbered
+WHERE d.id = renumbered.id AND d.document_version IS DISTINCT FROM renumbered.version;
+
+DROP INDEX IF EXISTS documents_key_idx;
+
+-- Also finds the current version for a key, as documents_key_idx did
+CREATE UNIQUE INDEX IF NOT EXISTS documents_key_version_idx ON documents (document_key, document_version DESC)
+    WHERE document_key IS NOT NULL;
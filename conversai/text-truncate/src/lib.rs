@@ -0,0 +1,203 @@
+//! Grapheme-aware truncation, shared by the rag-service previews and the
+//! wasm-markdown context builders so both cut text the same way.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Where a truncated string is allowed to end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncateBoundary {
+    Grapheme,
+    Word,
+    Sentence,
+}
+
+/// How the length budget passed to `truncate_with` is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Chars,
+    Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct TruncateOptions<'a> {
+    pub boundary: TruncateBoundary,
+    pub suffix: &'a str,
+    pub unit: LengthUnit,
+}
+
+impl Default for TruncateOptions<'_> {
+    fn default() -> Self {
+        Self {
+            boundary: TruncateBoundary::Grapheme,
+            suffix: "...",
+            unit: LengthUnit::Chars,
+        }
+    }
+}
+
+/// Truncates `text` so that the result, suffix included, is at most `max_len` units long.
+/// Never splits a grapheme cluster and never panics, whatever `max_len` is.
+pub fn truncate_with(text: &str, max_len: usize, options: &TruncateOptions) -> String {
+    let measure = |s: &str| match options.unit {
+        LengthUnit::Chars => s.chars().count(),
+        LengthUnit::Bytes => s.len(),
+    };
+
+    if measure(text) <= max_len {
+        return text.to_string();
+    }
+
+    // Drop the suffix when it would leave no room for any content
+    let suffix_len = measure(options.suffix);
+    let (budget, suffix) = if suffix_len < max_len {
+        (max_len - suffix_len, options.suffix)
+    } else {
+        (max_len, "")
+    };
+
+    let mut end = 0;
+    let mut used = 0;
+    for (idx, grapheme) in text.grapheme_indices(true) {
+        let len = measure(grapheme);
+        if used + len > budget {
+            break;
+        }
+        used += len;
+        end = idx + grapheme.len();
+    }
+
+    // Prefer a natural boundary, as long as it keeps at least half of the budget
+    let keep_from = end / 2;
+    let cut = match options.boundary {
+        TruncateBoundary::Sentence => sentence_boundary(text, end)
+            .filter(|&cut| cut >= keep_from)
+            .or_else(|| word_boundary(text, end).filter(|&cut| cut >= keep_from)),
+        TruncateBoundary::Word => word_boundary(text, end).filter(|&cut| cut >= keep_from),
+        TruncateBoundary::Grapheme => None,
+    }
+    .unwrap_or(end);
+
+    let mut truncated = text[..cut].trim_end().to_string();
+    truncated.push_str(suffix);
+    truncated
+}
+
+// Byte offset just past the last sentence terminator in `text[..end]`
+fn sentence_boundary(text: &str, end: usize) -> Option<usize> {
+    let prefix = &text[..end];
+    let mut chars = prefix.char_indices().peekable();
+    let mut boundary = None;
+
+    while let Some((idx, c)) = chars.next() {
+        let terminator_end = idx + c.len_utf8();
+        match c {
+            // CJK full-width terminators need no trailing whitespace
+            '。' | '！' | '？' => boundary = Some(terminator_end),
+            '.' | '!' | '?' => {
+                let followed_by_space = match chars.peek() {
+                    Some((_, next)) => next.is_whitespace(),
+                    None => text[terminator_end..].chars().next().is_none_or(char::is_whitespace),
+                };
+                if followed_by_space {
+                    boundary = Some(terminator_end);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    boundary
+}
+
+// Byte offset of the end of the last whole word in `text[..end]`
+fn word_boundary(text: &str, end: usize) -> Option<usize> {
+    // The cut already falls between two words
+    if text[end..].chars().next().is_none_or(char::is_whitespace) {
+        return Some(end);
+    }
+
+    text[..end]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(idx, _)| idx)
+        .filter(|&idx| idx > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str, max_len: usize) -> String {
+        truncate_with(text, max_len, &TruncateOptions::default())
+    }
+
+    #[test]
+    fn short_text_is_returned_unchanged() {
+        assert_eq!(chars("hello", 5), "hello");
+        assert_eq!(chars("", 0), "");
+    }
+
+    #[test]
+    fn result_fits_the_budget_with_suffix() {
+        let truncated = chars("abcdefghij", 8);
+        assert_eq!(truncated, "abcde...");
+        assert_eq!(truncated.chars().count(), 8);
+    }
+
+    #[test]
+    fn never_splits_a_grapheme_cluster() {
+        // A family emoji is one grapheme of seven chars
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let text = format!("ab{}cdef", family);
+        assert_eq!(chars(&text, 10), "ab...");
+        assert_eq!(chars(&text, 12), format!("ab{}...", family));
+    }
+
+    #[test]
+    fn keeps_emoji_whole() {
+        let truncated = chars("\u{1F600}\u{1F601}\u{1F602}\u{1F603}\u{1F604}", 4);
+        assert_eq!(truncated, "\u{1F600}...");
+
+        let bytes = TruncateOptions { unit: LengthUnit::Bytes, ..Default::default() };
+        // Each emoji is four bytes, so 10 bytes hold one next to the suffix
+        assert_eq!(truncate_with("\u{1F600}\u{1F601}\u{1F602}", 10, &bytes), "\u{1F600}...");
+    }
+
+    #[test]
+    fn keeps_combining_accents_with_their_letter() {
+        // "e" + combining acute is two chars but one grapheme
+        let text = "cafe\u{301} au lait";
+        let truncated = chars(text, 7);
+        assert_eq!(truncated, "caf...");
+        assert!(!truncated.contains('\u{301}'));
+        assert_eq!(chars(text, 8), "cafe\u{301}...");
+    }
+
+    #[test]
+    fn word_boundary_backs_off_to_whole_words() {
+        let words = TruncateOptions { boundary: TruncateBoundary::Word, ..Default::default() };
+        assert_eq!(truncate_with("the quick brown fox", 14, &words), "the quick...");
+        // A cut that already falls between words is kept
+        assert_eq!(truncate_with("the quick brown fox", 12, &words), "the quick...");
+        // A single long word has no boundary and is cut mid-word
+        assert_eq!(truncate_with("supercalifragilistic", 10, &words), "superca...");
+    }
+
+    #[test]
+    fn sentence_boundary_prefers_full_sentences() {
+        let sentences = TruncateOptions { boundary: TruncateBoundary::Sentence, ..Default::default() };
+        assert_eq!(truncate_with("One two three. Four five six.", 24, &sentences), "One two three....");
+        // No sentence end in the kept half falls back to a word boundary
+        assert_eq!(truncate_with("One two three four five six.", 16, &sentences), "One two three...");
+    }
+
+    #[test]
+    fn max_len_below_the_suffix_drops_the_suffix() {
+        assert_eq!(chars("abcdef", 3), "abc");
+        assert_eq!(chars("abcdef", 2), "ab");
+        assert_eq!(chars("abcdef", 0), "");
+        let long_suffix = TruncateOptions { suffix: " [more]", ..Default::default() };
+        assert_eq!(truncate_with("abcdefghij", 5, &long_suffix), "abcde");
+    }
+}
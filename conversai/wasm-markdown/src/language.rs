@@ -0,0 +1,164 @@
+// Lightweight language detection and language-aware tokenization.
+//
+// Detection only needs to be good enough to pick a tokenizer: CJK scripts are
+// recognised by code point, English and German by stopword counts. Anything
+// ambiguous is reported as `UNDETERMINED` and tokenized on whitespace.
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+pub const UNDETERMINED: &str = "und";
+
+const ENGLISH_STOPWORDS: &[&str] = &[
+    "the", "and", "is", "are", "was", "of", "to", "in", "that", "it", "with", "for",
+    "this", "have", "from", "not", "but", "what", "my", "you",
+];
+
+const GERMAN_STOPWORDS: &[&str] = &[
+    "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "mit", "ich", "auf",
+    "für", "von", "zu", "den", "dem", "sich", "auch", "war", "mein",
+];
+
+// Share of letters that must be CJK before a text is treated as CJK
+const CJK_RATIO: f32 = 0.3;
+
+// Minimum stopword hits before guessing between English and German
+const MIN_STOPWORD_HITS: usize = 2;
+
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{309F}' | '\u{30A0}'..='\u{30FF}')
+}
+
+fn is_hangul(c: char) -> bool {
+    matches!(c, '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}')
+}
+
+pub fn is_cjk(c: char) -> bool {
+    is_han(c) || is_kana(c) || is_hangul(c)
+}
+
+pub fn is_cjk_language(language: &str) -> bool {
+    matches!(language, "zh" | "ja" | "ko")
+}
+
+// Returns an ISO 639-1 code ("en", "de", "zh", "ja", "ko") or `UNDETERMINED`
+pub fn detect_language(text: &str) -> &'static str {
+    let mut letters = 0usize;
+    let mut han = 0usize;
+    let mut kana = 0usize;
+    let mut hangul = 0usize;
+    let mut umlauts = 0usize;
+
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if is_han(c) {
+            han += 1;
+        } else if is_kana(c) {
+            kana += 1;
+        } else if is_hangul(c) {
+            hangul += 1;
+        } else if matches!(c, 'ä' | 'ö' | 'ü' | 'ß' | 'Ä' | 'Ö' | 'Ü') {
+            umlauts += 1;
+        }
+    }
+
+    if letters == 0 {
+        return UNDETERMINED;
+    }
+
+    let cjk = han + kana + hangul;
+    if cjk as f32 / letters as f32 >= CJK_RATIO {
+        // Japanese mixes kana into Han text; Korean is almost entirely Hangul
+        return if kana > 0 {
+            "ja"
+        } else if hangul > han {
+            "ko"
+        } else {
+            "zh"
+        };
+    }
+
+    let mut english = 0usize;
+    let mut german = umlauts.min(MIN_STOPWORD_HITS); // umlauts are evidence, but not conclusive alone
+    for word in text.split_whitespace().map(|w| w.to_lowercase()) {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if ENGLISH_STOPWORDS.contains(&word) {
+            english += 1;
+        }
+        if GERMAN_STOPWORDS.contains(&word) {
+            german += 1;
+        }
+    }
+
+    // Require a clear winner; mixed notes stay undetermined
+    if english >= MIN_STOPWORD_HITS && english > german * 2 {
+        "en"
+    } else if german >= MIN_STOPWORD_HITS && german > english * 2 {
+        "de"
+    } else {
+        UNDETERMINED
+    }
+}
+
+// NFKC form of `text`, so composed and decomposed spellings compare equal. With
+// `fold_diacritics` it's decomposed (NFKD) and the combining marks are dropped instead,
+// so "café" becomes "cafe". CJK characters are kept whole: kana voicing marks and the
+// jamo of Hangul syllables aren't accents.
+pub fn normalize_text(text: &str, fold_diacritics: bool) -> String {
+    if !fold_diacritics {
+        return text.nfkc().collect();
+    }
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        if is_cjk(c) {
+            folded.push(c);
+        } else {
+            folded.extend(std::iter::once(c).nfkd().filter(|&c| !is_combining_mark(c)));
+        }
+    }
+    folded
+}
+
+// Lowercased, normalized word with everything but letters and digits removed
+pub fn normalize_word(word: &str, fold_diacritics: bool) -> String {
+    normalize_text(&word.to_lowercase(), fold_diacritics)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+// Words normalized by `normalize_word`. CJK languages have no word separators, so runs
+// of CJK characters are split into overlapping character bigrams instead.
+pub fn tokenize(text: &str, language: &str, fold_diacritics: bool) -> Vec<String> {
+    let words = text.split_whitespace()
+        .map(|w| normalize_word(w, fold_diacritics))
+        .filter(|w| !w.is_empty());
+
+    if !is_cjk_language(language) {
+        return words.collect();
+    }
+
+    let mut tokens = Vec::new();
+    for word in words {
+        let chars: Vec<char> = word.chars().collect();
+        let mut start = 0;
+        while start < chars.len() {
+            let cjk_run = is_cjk(chars[start]);
+            let end = chars[start..].iter()
+                .position(|&c| is_cjk(c) != cjk_run)
+                .map_or(chars.len(), |offset| start + offset);
+            let run = &chars[start..end];
+
+            if cjk_run && run.len() > 1 {
+                tokens.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+            } else {
+                tokens.push(run.iter().collect());
+            }
+            start = end;
+        }
+    }
+    tokens
+}
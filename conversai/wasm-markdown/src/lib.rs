@@ -1,13 +1,47 @@
 use wasm_bindgen::prelude::*;
-use pulldown_cmark::{Parser, Event, Tag, HeadingLevel};
+#[cfg(feature = "parse")]
+use pulldown_cmark::{Parser, Event, Tag};
 use serde::{Serialize, Deserialize};
+use std::cmp::Ordering;
+#[cfg(feature = "json")]
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 
+mod language;
+
+use language::{detect_language, normalize_text, normalize_word, tokenize};
+use text_truncate::{truncate_with, LengthUnit, TruncateBoundary, TruncateOptions};
+
 // Console logging for debugging
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
+    
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+    
+    // High-resolution timer, available in windows and workers alike
+    #[cfg(feature = "json")]
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+// Native builds (unit tests) have no JS host to call into
+#[cfg(not(target_arch = "wasm32"))]
+fn log(_s: &str) {}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn date_now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "json"))]
+fn performance_now() -> f64 {
+    date_now()
 }
 
 macro_rules! console_log {
@@ -22,13 +56,113 @@ pub struct MarkdownSection {
     tags: Vec<String>,
     relevance: f32,
     category: String,
+    // Usage stats for frecency ranking; defaulted so older exports still import
+    #[serde(default)]
+    access_count: u32,
+    #[serde(default)]
+    last_accessed: Option<f64>, // ms since epoch
+    // Detected when the section is added; "und" if uncertain
+    #[serde(default)]
+    language: String,
+    // Titles of the enclosing headings, ending with this section's own title
+    #[serde(default)]
+    heading_path: Vec<String>,
+}
+
+// Structured search hit with a per-component score breakdown
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchHit {
+    id: String,
+    title: String,
+    heading_path: Vec<String>,
+    category: String,
+    language: String,
+    score: f32,
+    // Components are raw sums before the category multiplier is applied
+    title_score: f32,
+    tag_score: f32,
+    body_score: f32,
+    matched_terms: Vec<String>,
+}
+
+// Hit fused from several query variants by `search_multi`
+#[derive(Serialize, Clone, Debug)]
+pub struct MultiSearchHit {
+    #[serde(flatten)]
+    hit: SearchHit, // as scored by the best-ranking variant
+    fused_score: f32,
+    variants: Vec<usize>, // indices of the query variants that returned this section
+}
+
+// Per-query-term report included in `search_details`
+#[derive(Serialize, Clone, Debug)]
+pub struct TermReport {
+    term: String,
+    matched: bool,
+    suggestions: Vec<String>,
+}
+
+// Usage boosts halve for every week since a section was last used
+const FRECENCY_HALF_LIFE_DAYS: f64 = 7.0;
+
+// Section id substrings pinned to the top of `search` results unless reconfigured
+const DEFAULT_PINNED_SECTIONS: [&str; 2] = ["personal_identity", "personal_background"];
+
+// Standard reciprocal rank fusion constant
+#[cfg(feature = "json")]
+const RRF_K: f32 = 60.0;
+
+// Requests above this many results are clamped; 0 means "as many as fit the budget"
+const MAX_RESULTS_CAP: usize = 1000;
+
+// Bounds for `set_max_context_length`; below the minimum a single section barely fits
+const MIN_CONTEXT_LENGTH: usize = 1000;
+const MAX_CONTEXT_LENGTH: usize = 2_000_000;
+
+// Appended to a context cut short by `max_context_length`
+const TRUNCATION_NOTE: &str = "\n[Context truncated due to length limits]";
+
+// Ranked hits plus how many sections scored before the result limit was applied
+struct Ranking {
+    hits: Vec<(usize, SearchHit)>,
+    #[cfg(feature = "json")]
+    candidates: usize,
+    #[cfg(feature = "json")]
+    limited: bool,
+}
+
+// Outcome of assembling a context string
+struct ContextBuild {
+    context: String,
+    total_length: usize,
+    included: usize,
+}
+
+#[cfg(feature = "json")]
+impl ContextBuild {
+    // Sections always end in a blank line, so only a cut-off context ends with the note
+    fn truncated(&self) -> bool {
+        self.context.ends_with(TRUNCATION_NOTE)
+    }
 }
 
 #[wasm_bindgen]
 pub struct MarkdownProcessor {
     sections: Vec<MarkdownSection>,
-    index: HashMap<String, Vec<usize>>, // word -> section indices
+    doc_ids: Vec<u32>, // stable internal id of each section, parallel to `sections`
+    positions: HashMap<u32, usize>, // internal id -> position in `sections`
+    next_doc_id: u32, // only ever grows, so section ids and postings are never reused
+    index: HashMap<String, Vec<(u32, u32)>>, // word -> (internal section id, term frequency)
+    #[cfg(feature = "json")]
+    term_buckets: HashMap<char, BTreeSet<String>>, // first character -> terms in `index`, for `suggest`
+    title_index: HashMap<String, Vec<u32>>, // lowercased title -> internal section ids
+    title_terms: HashMap<String, Vec<(u32, bool)>>, // title/tag word -> (internal section id, from title)
     max_context_length: usize,
+    frecency_weight: f32,
+    pinned_sections: Vec<String>, // section id substrings always included in `search` results
+    pinning_enabled: bool,
+    fold_diacritics: bool, // index and match "café" and "cafe" as the same word
+    search_envelope: bool, // wrap `search_json` results with timing and counts
 }
 
 #[wasm_bindgen]
@@ -38,17 +172,37 @@ impl MarkdownProcessor {
         console_log!("🦀 Initializing Rust Markdown Processor");
         Self {
             sections: Vec::new(),
+            doc_ids: Vec::new(),
+            positions: HashMap::new(),
+            next_doc_id: 0,
             index: HashMap::new(),
+            #[cfg(feature = "json")]
+            term_buckets: HashMap::new(),
+            title_index: HashMap::new(),
+            title_terms: HashMap::new(),
             max_context_length: 100000, // ~25k tokens
+            frecency_weight: 0.0,
+            pinned_sections: DEFAULT_PINNED_SECTIONS.iter().map(|id| id.to_string()).collect(),
+            pinning_enabled: true,
+            fold_diacritics: true,
+            search_envelope: false,
         }
     }
     
     pub fn clear(&mut self) {
         self.sections.clear();
+        self.doc_ids.clear();
+        self.positions.clear();
+        self.next_doc_id = 0;
         self.index.clear();
+        #[cfg(feature = "json")]
+        self.term_buckets.clear();
+        self.title_index.clear();
+        self.title_terms.clear();
         console_log!("Cleared all sections and index");
     }
     
+    #[cfg(feature = "parse")]
     pub fn load_markdown(&mut self, content: &str, category: &str) {
         let parser = Parser::new(content);
         let mut current_section = MarkdownSection {
@@ -58,54 +212,72 @@ impl MarkdownProcessor {
             tags: Vec::new(),
             relevance: 0.0,
             category: category.to_string(),
+            access_count: 0,
+            last_accessed: None,
+            language: String::new(),
+            heading_path: Vec::new(),
         };
         
         let mut in_heading = false;
-        let mut heading_level = 1;
+        let mut heading_level = 0;
+        // Open headings as (level, title); a skipped level (H1 then H3) simply has no entry
+        let mut heading_stack: Vec<(usize, String)> = Vec::new();
         
         for event in parser {
             match event {
-                Event::Start(Tag::Heading(level, _, _)) => {
+                Event::Start(Tag::Heading(level, ..)) => {
                     // Save previous section if it has content
                     if !current_section.content.is_empty() {
                         self.add_section(current_section.clone());
                     }
                     
-                    // Start new section
+                    // Close siblings and deeper headings of the previous section
+                    heading_level = level as usize;
+                    while heading_stack.last().is_some_and(|(open, _)| *open >= heading_level) {
+                        heading_stack.pop();
+                    }
+                    
+                    // Start new section, numbered like the internal id it will get
                     current_section = MarkdownSection {
-                        id: format!("{}_{}", category, self.sections.len()),
+                        id: format!("{}_{}", category, self.next_doc_id),
                         title: String::new(),
                         content: String::new(),
                         tags: Vec::new(),
                         relevance: 0.0,
                         category: category.to_string(),
+                        access_count: 0,
+                        last_accessed: None,
+                        language: String::new(),
+                        heading_path: Vec::new(),
                     };
                     
                     in_heading = true;
-                    heading_level = match level {
-                        HeadingLevel::H1 => 1,
-                        HeadingLevel::H2 => 2,
-                        HeadingLevel::H3 => 3,
-                        HeadingLevel::H4 => 4,
-                        HeadingLevel::H5 => 5,
-                        HeadingLevel::H6 => 6,
-                    };
                 }
                 Event::End(Tag::Heading(..)) => {
                     in_heading = false;
+                    
+                    current_section.title = current_section.title.trim().to_string();
+                    // Extract tags from title (words that might be keywords)
+                    current_section.tags = current_section.title.split_whitespace()
+                        .filter(|w| w.len() > 3)
+                        .map(|w| w.to_lowercase())
+                        .collect();
+                    
+                    heading_stack.push((heading_level, current_section.title.clone()));
+                    current_section.heading_path = heading_stack.iter()
+                        .map(|(_, title)| title.clone())
+                        .collect();
+                }
+                // Headings with inline markup or several setext lines arrive in pieces
+                Event::Text(text) | Event::Code(text) if in_heading => {
+                    current_section.title.push_str(&text);
+                }
+                Event::SoftBreak | Event::HardBreak if in_heading => {
+                    current_section.title.push(' ');
                 }
                 Event::Text(text) => {
-                    if in_heading {
-                        current_section.title = text.to_string();
-                        // Extract tags from title (words that might be keywords)
-                        current_section.tags = text.split_whitespace()
-                            .filter(|w| w.len() > 3)
-                            .map(|w| w.to_lowercase())
-                            .collect();
-                    } else {
-                        current_section.content.push_str(&text);
-                        current_section.content.push(' ');
-                    }
+                    current_section.content.push_str(&text);
+                    current_section.content.push(' ');
                 }
                 Event::SoftBreak | Event::HardBreak => {
                     current_section.content.push('\n');
@@ -123,135 +295,841 @@ impl MarkdownProcessor {
     }
     
     fn add_section(&mut self, mut section: MarkdownSection) {
-        let section_idx = self.sections.len();
+        Self::prepare_section(&mut section);
         
-        // Trim content
-        section.content = section.content.trim().to_string();
+        let doc = self.next_doc_id;
+        self.next_doc_id += 1;
+        self.positions.insert(doc, self.sections.len());
+        self.doc_ids.push(doc);
+        self.sections.push(section);
+        self.index_section(self.sections.len() - 1);
+    }
+    
+    // Adds a section from JSON; a section with the same id is replaced in place
+    #[cfg(feature = "json")]
+    pub fn upsert_section(&mut self, json: &str) {
+        let mut section = match serde_json::from_str::<MarkdownSection>(json) {
+            Ok(section) => section,
+            Err(e) => {
+                console_log!("Error parsing section: {}", e);
+                return;
+            }
+        };
         
-        // Build inverted index for search
-        let words = section.content.split_whitespace()
-            .chain(section.title.split_whitespace());
-            
-        for word in words {
-            let word_lower = word.to_lowercase()
-                .chars()
-                .filter(|c| c.is_alphanumeric())
-                .collect::<String>();
-                
-            if !word_lower.is_empty() {
-                self.index.entry(word_lower)
-                    .or_insert_with(Vec::new)
-                    .push(section_idx);
+        match self.sections.iter().position(|s| s.id == section.id) {
+            Some(idx) => {
+                // Only this section's postings are rebuilt
+                self.unindex_section(idx);
+                Self::prepare_section(&mut section);
+                self.sections[idx] = section;
+                self.index_section(idx);
+                console_log!("Updated section '{}'", self.sections[idx].id);
+            }
+            None => {
+                self.add_section(section);
+                console_log!("Added section '{}'", self.sections[self.sections.len() - 1].id);
             }
         }
+    }
+    
+    // Removes a section and purges its postings; returns false if the id is unknown
+    pub fn remove_section(&mut self, id: &str) -> bool {
+        let Some(idx) = self.sections.iter().position(|s| s.id == id) else {
+            return false;
+        };
         
-        self.sections.push(section);
+        self.unindex_section(idx);
+        self.sections.remove(idx);
+        let doc = self.doc_ids.remove(idx);
+        
+        // Postings hold stable ids, so only the id -> position lookup needs to shift
+        self.positions.remove(&doc);
+        for (pos, doc) in self.doc_ids.iter().enumerate().skip(idx) {
+            self.positions.insert(*doc, pos);
+        }
+        
+        console_log!("Removed section '{}'", id);
+        true
     }
     
     pub fn search(&mut self, query: &str, max_results: usize) -> String {
         console_log!("🔍 Searching for: {}", query);
         
-        // Calculate relevance scores for each section
-        let mut scores: Vec<(usize, f32)> = Vec::new();
-        let query_words: Vec<String> = query.split_whitespace()
-            .map(|w| w.to_lowercase()
-                .chars()
-                .filter(|c| c.is_alphanumeric())
-                .collect())
-            .filter(|w: &String| !w.is_empty())
+        let hits: Vec<(usize, Option<f32>)> = self.rank_sections(query, max_results)
+            .hits
+            .into_iter()
+            .map(|(idx, hit)| (idx, Some(hit.score)))
+            .collect();
+        
+        let built = self.build_context(&hits);
+        self.record_access(&hits[..built.included]);
+        
+        console_log!("✅ Built context with {} sections, {} chars", built.included, built.total_length);
+        built.context
+    }
+    
+    // Adds ranked sections until `max_context_length` is reached
+    pub fn search_fill_budget(&mut self, query: &str) -> String {
+        self.search(query, 0)
+    }
+    
+    // Runs several phrasings of one query (JSON array of strings), fuses their rankings
+    // with reciprocal rank fusion and builds a single context string
+    #[cfg(feature = "json")]
+    pub fn search_multi(&mut self, queries_json: &str, max_results: usize) -> String {
+        let hits: Vec<(usize, Option<f32>)> = self.rank_multi(queries_json, max_results)
+            .into_iter()
+            .map(|(idx, hit)| (idx, Some(hit.hit.score)))
+            .collect();
+        
+        let built = self.build_context(&hits);
+        self.record_access(&hits[..built.included]);
+        
+        console_log!("✅ Built fused context with {} sections, {} chars", built.included, built.total_length);
+        built.context
+    }
+    
+    // Structured variant of `search_multi`, reporting which variants contributed each hit
+    #[cfg(feature = "json")]
+    pub fn search_multi_json(&self, queries_json: &str, max_results: usize) -> String {
+        let hits: Vec<MultiSearchHit> = self.rank_multi(queries_json, max_results)
+            .into_iter()
+            .map(|(_, hit)| hit)
+            .collect();
+        
+        serde_json::to_string(&hits).unwrap_or_else(|_| "[]".to_string())
+    }
+    
+    // Structured variant of `search`: returns the ranked hits as a JSON array, or with
+    // `set_search_envelope(true)` as `{ took_ms, total_candidates, returned, results }`
+    #[cfg(feature = "json")]
+    pub fn search_json(&self, query: &str, max_results: usize) -> String {
+        let started = self.search_envelope.then(performance_now);
+        let ranking = self.rank_sections(query, max_results);
+        let total_candidates = ranking.candidates;
+        let hits: Vec<SearchHit> = ranking.hits
+            .into_iter()
+            .map(|(_, hit)| hit)
+            .collect();
+        
+        let serialized = match started {
+            Some(started) => serde_json::to_string(&serde_json::json!({
+                "took_ms": performance_now() - started,
+                "total_candidates": total_candidates,
+                "returned": hits.len(),
+                "results": hits,
+            })),
+            None => serde_json::to_string(&hits),
+        };
+        match serialized {
+            Ok(json) => json,
+            Err(e) => {
+                console_log!("Error serializing search results: {}", e);
+                "[]".to_string()
+            }
+        }
+    }
+    
+    // Like `search_json`, plus a per-term report with "did you mean" suggestions for unmatched terms
+    #[cfg(feature = "json")]
+    pub fn search_details(&self, query: &str, max_results: usize) -> String {
+        let ranking = self.rank_sections(query, max_results);
+        let context_hits: Vec<(usize, Option<f32>)> = ranking.hits.iter()
+            .map(|(idx, hit)| (*idx, Some(hit.score)))
+            .collect();
+        let built = self.build_context(&context_hits);
+        
+        // Why the context stopped growing: the character budget, the result count, or neither
+        let stopped_by = if built.truncated() {
+            "budget"
+        } else if ranking.limited {
+            "max_results"
+        } else {
+            "exhausted"
+        };
+        
+        let hits: Vec<SearchHit> = ranking.hits.into_iter()
+            .map(|(_, hit)| hit)
             .collect();
         
-        for (idx, section) in self.sections.iter().enumerate() {
-            let mut score = 0.0;
+        let terms: Vec<TermReport> = Self::query_terms(query, self.fold_diacritics)
+            .into_iter()
+            .map(|term| {
+                let matched = self.index.contains_key(&term) || self.title_terms.contains_key(&term);
+                let suggestions = if matched {
+                    Vec::new()
+                } else {
+                    self.suggestions_for(&term, 3)
+                };
+                TermReport { term, matched, suggestions }
+            })
+            .collect();
+        
+        serde_json::json!({
+            "results": hits,
+            "terms": terms,
+            "candidates": ranking.candidates,
+            "included_in_context": built.included,
+            "stopped_by": stopped_by,
+        }).to_string()
+    }
+    
+    // Index terms close to the last word of `term`, ranked by edit distance then
+    // collection frequency (JSON array), so suggestions follow what is being typed
+    #[cfg(feature = "json")]
+    pub fn suggest(&self, term: &str, max_suggestions: usize) -> String {
+        let suggestions = match Self::normalize_query(term, self.fold_diacritics).pop() {
+            Some(last) => self.suggestions_for(&last, max_suggestions),
+            None => Vec::new(),
+        };
+        serde_json::to_string(&suggestions).unwrap_or_else(|_| "[]".to_string())
+    }
+    
+    // Exact, case-insensitive title lookup; returns the section as JSON or an empty string
+    #[cfg(feature = "json")]
+    pub fn get_section_by_title(&self, title: &str) -> String {
+        let section = self.title_index.get(&title.trim().to_lowercase())
+            .and_then(|docs| docs.first())
+            .map(|doc| &self.sections[self.positions[doc]]);
+        
+        match section {
+            Some(section) => serde_json::to_string(section).unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    // Section with this id as JSON, or an empty string; read-only, so usage stats are untouched
+    #[cfg(feature = "json")]
+    pub fn get_section(&self, id: &str) -> String {
+        match self.sections.iter().find(|s| s.id == id) {
+            Some(section) => serde_json::to_string(section).unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    // All sections in `category`, in load order (JSON array, empty if none match)
+    #[cfg(feature = "json")]
+    pub fn get_sections_by_category(&self, category: &str) -> String {
+        let sections: Vec<&MarkdownSection> = self.sections.iter()
+            .filter(|s| s.category == category)
+            .collect();
+        serde_json::to_string(&sections).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    // Scores sections against titles and tags only, returning ranked hits as JSON
+    #[cfg(feature = "json")]
+    pub fn search_titles(&self, query: &str, max_results: usize) -> String {
+        let query_words = Self::normalize_query(query, self.fold_diacritics);
+        let mut by_section: HashMap<usize, SearchHit> = HashMap::new();
+        
+        for word in &query_words {
+            let Some(postings) = self.title_terms.get(word) else { continue };
             
-            // Score based on word matches in index
-            for word in &query_words {
-                if let Some(indices) = self.index.get(word) {
-                    if indices.contains(&idx) {
-                        // Count frequency
-                        let freq = indices.iter().filter(|&&i| i == idx).count() as f32;
-                        score += freq;
-                    }
-                }
+            for &(doc, from_title) in postings {
+                let idx = self.positions[&doc];
+                let section = &self.sections[idx];
+                let hit = by_section.entry(idx).or_insert_with(|| SearchHit {
+                    id: section.id.clone(),
+                    title: section.title.clone(),
+                    heading_path: section.heading_path.clone(),
+                    category: section.category.clone(),
+                    language: section.language.clone(),
+                    score: 0.0,
+                    title_score: 0.0,
+                    tag_score: 0.0,
+                    body_score: 0.0,
+                    matched_terms: Vec::new(),
+                });
                 
-                // Title match gets huge bonus
-                if section.title.to_lowercase().contains(word) {
-                    score += 10.0;
+                // Same weights as the full-text path: title 10, tag 5
+                if from_title {
+                    hit.title_score += 10.0;
+                    hit.score += 10.0;
+                } else {
+                    hit.tag_score += 5.0;
+                    hit.score += 5.0;
                 }
                 
-                // Tag match gets medium bonus
-                for tag in &section.tags {
-                    if tag.contains(word) {
-                        score += 5.0;
-                    }
+                if !hit.matched_terms.contains(word) {
+                    hit.matched_terms.push(word.clone());
+                }
+            }
+        }
+        
+        let mut hits: Vec<(usize, SearchHit)> = by_section.into_iter().collect();
+        hits.sort_by(|a, b| score_order(b.1.score, a.1.score).then(a.0.cmp(&b.0)));
+        // 0 means unbounded, as for the other searches
+        if max_results > 0 {
+            hits.truncate(max_results.min(MAX_RESULTS_CAP));
+        }
+        
+        let hits: Vec<SearchHit> = hits.into_iter().map(|(_, hit)| hit).collect();
+        serde_json::to_string(&hits).unwrap_or_else(|_| "[]".to_string())
+    }
+    
+    // Builds the `# RELEVANT CONTEXT` string for an ordered JSON array of section ids.
+    // Returns `{ "context": String, "warnings": [String] }`; unknown ids are skipped and reported.
+    #[cfg(feature = "json")]
+    pub fn build_context_for(&self, ids_json: &str) -> String {
+        let mut warnings: Vec<String> = Vec::new();
+        
+        let ids: Vec<String> = match serde_json::from_str(ids_json) {
+            Ok(ids) => ids,
+            Err(e) => {
+                console_log!("Error parsing section ids: {}", e);
+                warnings.push(format!("invalid ids JSON: {}", e));
+                Vec::new()
+            }
+        };
+        
+        let mut hits: Vec<(usize, Option<f32>)> = Vec::new();
+        for id in &ids {
+            match self.sections.iter().position(|s| &s.id == id) {
+                Some(idx) if !hits.iter().any(|(i, _)| *i == idx) => hits.push((idx, None)),
+                Some(_) => warnings.push(format!("duplicate section id: {}", id)),
+                None => warnings.push(format!("unknown section id: {}", id)),
+            }
+        }
+        
+        let built = self.build_context(&hits);
+        
+        serde_json::json!({
+            "context": built.context,
+            "warnings": warnings,
+        }).to_string()
+    }
+    
+    pub fn get_full_context(&self) -> String {
+        self.build_full_context().context
+    }
+    
+    // Size report for `get_full_context` without returning the context itself:
+    // `{ included_sections, omitted_sections, total_chars, estimated_tokens, truncated }`
+    #[cfg(feature = "json")]
+    pub fn get_full_context_info(&self) -> String {
+        let built = self.build_full_context();
+        let total_chars = built.context.chars().count();
+        
+        serde_json::json!({
+            "included_sections": built.included,
+            "omitted_sections": self.sections.len() - built.included,
+            "total_chars": total_chars,
+            "estimated_tokens": total_chars / 4, // ~4 chars per token
+            "truncated": built.truncated(),
+        }).to_string()
+    }
+    
+    // Weight of the usage-based boost; 0 (default) keeps ranking purely content-based
+    pub fn set_frecency_weight(&mut self, weight: f32) {
+        self.frecency_weight = if weight.is_finite() { weight.max(0.0) } else { 0.0 };
+        console_log!("Set frecency weight to {}", self.frecency_weight);
+    }
+    
+    // Replaces the pinned sections with a JSON array of section id substrings; each pattern
+    // pins the first section whose id contains it, ahead of the scored results
+    #[cfg(feature = "json")]
+    pub fn set_pinned_sections(&mut self, json: &str) {
+        match serde_json::from_str::<Vec<String>>(json) {
+            Ok(patterns) => {
+                self.pinned_sections = patterns.into_iter().filter(|p| !p.is_empty()).collect();
+                console_log!("Set {} pinned section patterns", self.pinned_sections.len());
+            }
+            Err(e) => console_log!("Error parsing pinned sections: {}", e),
+        }
+    }
+    
+    // When disabled, search results are purely score-ranked
+    pub fn set_pinning_enabled(&mut self, enabled: bool) {
+        self.pinning_enabled = enabled;
+        console_log!("Section pinning {}", if enabled { "enabled" } else { "disabled" });
+    }
+    
+    // Accented and unaccented spellings match while enabled (default); turn it off for
+    // languages where diacritics tell words apart. Changing it rebuilds the index.
+    pub fn set_fold_diacritics(&mut self, enabled: bool) {
+        if enabled == self.fold_diacritics {
+            return;
+        }
+        self.fold_diacritics = enabled;
+        self.rebuild_index();
+        console_log!("Diacritic folding {}", if enabled { "enabled" } else { "disabled" });
+    }
+    
+    // Off by default, so existing callers keep getting a bare array from `search_json`
+    pub fn set_search_envelope(&mut self, enabled: bool) {
+        self.search_envelope = enabled;
+        console_log!("Search envelope {}", if enabled { "enabled" } else { "disabled" });
+    }
+    
+    pub fn reset_usage_stats(&mut self) {
+        for section in &mut self.sections {
+            section.access_count = 0;
+            section.last_accessed = None;
+        }
+        console_log!("Reset usage stats for {} sections", self.sections.len());
+    }
+    
+    // Values outside MIN_CONTEXT_LENGTH..=MAX_CONTEXT_LENGTH are clamped
+    pub fn set_max_context_length(&mut self, length: usize) {
+        let clamped = length.clamp(MIN_CONTEXT_LENGTH, MAX_CONTEXT_LENGTH);
+        if clamped != length {
+            console_log!(
+                "Max context length {} is outside {}..={}, clamped to {}",
+                length, MIN_CONTEXT_LENGTH, MAX_CONTEXT_LENGTH, clamped
+            );
+        }
+        self.max_context_length = clamped;
+        console_log!("Set max context length to {} chars", clamped);
+    }
+    
+    pub fn get_max_context_length(&self) -> usize {
+        self.max_context_length
+    }
+    
+    pub fn get_section_count(&self) -> usize {
+        self.sections.len()
+    }
+    
+    pub fn get_index_size(&self) -> usize {
+        self.index.len()
+    }
+    
+    // Export sections as JSON
+    #[cfg(feature = "json")]
+    pub fn export_sections(&self) -> String {
+        match serde_json::to_string(&self.sections) {
+            Ok(json) => json,
+            Err(e) => {
+                console_log!("Error exporting sections: {}", e);
+                "[]".to_string()
+            }
+        }
+    }
+    
+    // Import sections from JSON
+    #[cfg(feature = "json")]
+    pub fn import_sections(&mut self, json: &str) {
+        match serde_json::from_str::<Vec<MarkdownSection>>(json) {
+            Ok(sections) => {
+                self.clear();
+                for section in sections {
+                    self.add_section(section);
+                }
+                console_log!("Imported {} sections", self.sections.len());
+            }
+            Err(e) => {
+                console_log!("Error importing sections: {}", e);
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl MarkdownProcessor {
+    // Compact binary snapshot of all sections, available in every feature combination.
+    // Not self-describing: only import snapshots exported by the same build version.
+    pub fn export_sections_binary(&self) -> Vec<u8> {
+        match bincode::serialize(&self.sections) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                console_log!("Error exporting sections: {}", e);
+                Vec::new()
+            }
+        }
+    }
+    
+    pub fn import_sections_binary(&mut self, data: &[u8]) {
+        match bincode::deserialize::<Vec<MarkdownSection>>(data) {
+            Ok(sections) => {
+                self.clear();
+                for section in sections {
+                    self.add_section(section);
+                }
+                console_log!("Imported {} sections", self.sections.len());
+            }
+            Err(e) => {
+                console_log!("Error importing sections: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for MarkdownProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkdownProcessor {
+    fn normalize_query(query: &str, fold_diacritics: bool) -> Vec<String> {
+        query.split_whitespace()
+            .map(|w| normalize_word(w, fold_diacritics))
+            .filter(|w| !w.is_empty())
+            .collect()
+    }
+    
+    // Query terms, tokenized the same way as sections in the query's language
+    fn query_terms(query: &str, fold_diacritics: bool) -> Vec<String> {
+        tokenize(query, detect_language(query), fold_diacritics)
+    }
+    
+    // Trims content and detects the section language before indexing
+    fn prepare_section(section: &mut MarkdownSection) {
+        section.content = section.content.trim().to_string();
+        section.language = detect_language(&format!("{} {}", section.title, section.content)).to_string();
+    }
+    
+    // Title and tag words of a section, flagged by whether they came from the title
+    fn title_term_entries(section: &MarkdownSection, fold_diacritics: bool) -> Vec<(String, bool)> {
+        let title_words = Self::normalize_query(&section.title, fold_diacritics);
+        let tag_words = section.tags.iter()
+            .flat_map(|tag| Self::normalize_query(tag, fold_diacritics));
+        
+        title_words.into_iter().map(|w| (w, true))
+            .chain(tag_words.map(|w| (w, false)))
+            .collect()
+    }
+    
+    fn index_section(&mut self, idx: usize) {
+        let section = &self.sections[idx];
+        let doc = self.doc_ids[idx];
+        
+        // Build inverted index for search, one posting per distinct term
+        let words = tokenize(&section.content, &section.language, self.fold_diacritics)
+            .into_iter()
+            .chain(tokenize(&section.title, &section.language, self.fold_diacritics));
+        
+        let mut term_freqs: HashMap<String, u32> = HashMap::new();
+        for word in words {
+            *term_freqs.entry(word).or_insert(0) += 1;
+        }
+        for (word, tf) in term_freqs {
+            #[cfg(feature = "json")]
+            if let Some(first) = word.chars().next() {
+                self.term_buckets.entry(first).or_default().insert(word.clone());
+            }
+            self.index.entry(word)
+                .or_default()
+                .push((doc, tf));
+        }
+        
+        // Secondary index over titles and tags for title-only lookups
+        let title_key = section.title.trim().to_lowercase();
+        if !title_key.is_empty() {
+            self.title_index.entry(title_key)
+                .or_default()
+                .push(doc);
+        }
+        
+        for (word, from_title) in Self::title_term_entries(section, self.fold_diacritics) {
+            self.title_terms.entry(word)
+                .or_default()
+                .push((doc, from_title));
+        }
+    }
+    
+    #[cfg(feature = "json")]
+    fn remove_from_bucket(buckets: &mut HashMap<char, BTreeSet<String>>, word: &str) {
+        let Some(first) = word.chars().next() else { return };
+        if let Some(bucket) = buckets.get_mut(&first) {
+            bucket.remove(word);
+            if bucket.is_empty() {
+                buckets.remove(&first);
+            }
+        }
+    }
+    
+    // Reindexes every section from scratch, e.g. after the normalization changed
+    fn rebuild_index(&mut self) {
+        self.index.clear();
+        #[cfg(feature = "json")]
+        self.term_buckets.clear();
+        self.title_index.clear();
+        self.title_terms.clear();
+        for idx in 0..self.sections.len() {
+            self.index_section(idx);
+        }
+    }
+    
+    // Drops every posting of section `idx`, touching only the terms that section contains
+    fn unindex_section(&mut self, idx: usize) {
+        let section = &self.sections[idx];
+        let doc = self.doc_ids[idx];
+        
+        let words = tokenize(&section.content, &section.language, self.fold_diacritics)
+            .into_iter()
+            .chain(tokenize(&section.title, &section.language, self.fold_diacritics));
+        for word in words {
+            if let Some(postings) = self.index.get_mut(&word) {
+                postings.retain(|&(d, _)| d != doc);
+                if postings.is_empty() {
+                    self.index.remove(&word);
+                    #[cfg(feature = "json")]
+                    Self::remove_from_bucket(&mut self.term_buckets, &word);
                 }
             }
+        }
+        
+        let title_key = section.title.trim().to_lowercase();
+        if let Some(postings) = self.title_index.get_mut(&title_key) {
+            postings.retain(|&d| d != doc);
+            if postings.is_empty() {
+                self.title_index.remove(&title_key);
+            }
+        }
+        
+        for (word, _) in Self::title_term_entries(section, self.fold_diacritics) {
+            if let Some(postings) = self.title_terms.get_mut(&word) {
+                postings.retain(|&(d, _)| d != doc);
+                if postings.is_empty() {
+                    self.title_terms.remove(&word);
+                }
+            }
+        }
+    }
+    
+    fn score_section(&self, idx: usize, query_words: &[String]) -> SearchHit {
+        let section = &self.sections[idx];
+        let doc = self.doc_ids[idx];
+        let title_lower = normalize_text(&section.title.to_lowercase(), self.fold_diacritics);
+        let mut hit = SearchHit {
+            id: section.id.clone(),
+            title: section.title.clone(),
+            heading_path: section.heading_path.clone(),
+            category: section.category.clone(),
+            language: section.language.clone(),
+            score: 0.0,
+            title_score: 0.0,
+            tag_score: 0.0,
+            body_score: 0.0,
+            matched_terms: Vec::new(),
+        };
+        
+        for word in query_words {
+            let mut matched = false;
             
-            // Category priority (personal > context > knowledge)
-            match section.category.as_str() {
-                "personal" => score *= 2.0,
-                "context" => score *= 1.5,
-                _ => {}
+            // Score based on word matches in index
+            if let Some(postings) = self.index.get(word) {
+                if let Some(&(_, tf)) = postings.iter().find(|(d, _)| *d == doc) {
+                    hit.body_score += tf as f32;
+                    matched = true;
+                }
+            }
+            
+            // Title match gets huge bonus
+            if title_lower.contains(word.as_str()) {
+                hit.title_score += 10.0;
+                matched = true;
             }
             
-            if score > 0.0 {
-                scores.push((idx, score));
+            // Tag match gets medium bonus
+            for tag in &section.tags {
+                if normalize_text(tag, self.fold_diacritics).contains(word.as_str()) {
+                    hit.tag_score += 5.0;
+                    matched = true;
+                }
+            }
+            
+            if matched && !hit.matched_terms.contains(word) {
+                hit.matched_terms.push(word.clone());
             }
         }
         
-        // Sort by relevance score
-        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        scores.truncate(max_results);
+        let mut score = hit.body_score + hit.title_score + hit.tag_score;
+        
+        // Category priority (personal > context > knowledge)
+        match section.category.as_str() {
+            "personal" => score *= 2.0,
+            "context" => score *= 1.5,
+            _ => {}
+        }
+        
+        hit.score = score;
+        hit
+    }
+    
+    #[cfg(feature = "json")]
+    fn suggestions_for(&self, term: &str, max_suggestions: usize) -> Vec<String> {
+        let term_chars: Vec<char> = term.chars().collect();
+        let Some(&first) = term_chars.first() else { return Vec::new() };
         
-        // Always include core personal info if available
-        let core_sections = ["personal_identity", "personal_background"];
-        for core_id in &core_sections {
-            if let Some(idx) = self.sections.iter().position(|s| s.id.contains(core_id)) {
-                if !scores.iter().any(|(i, _)| *i == idx) {
-                    scores.insert(0, (idx, 100.0)); // High priority
+        // Short terms only tolerate a single edit
+        let max_distance = if term_chars.len() <= 4 { 1 } else { 2 };
+        
+        // Bounded scan: only the bucket of terms sharing the first character, within the length window
+        let Some(bucket) = self.term_buckets.get(&first) else { return Vec::new() };
+        let mut candidates: Vec<(usize, usize, &String)> = bucket.iter()
+            .filter(|candidate| candidate.as_str() != term)
+            .filter(|candidate| {
+                let len = candidate.chars().count();
+                len.abs_diff(term_chars.len()) <= max_distance
+            })
+            .filter_map(|candidate| {
+                let candidate_chars: Vec<char> = candidate.chars().collect();
+                bounded_levenshtein(&term_chars, &candidate_chars, max_distance)
+                    .map(|distance| {
+                        let freq = self.index.get(candidate)
+                            .map_or(0, |postings| postings.iter().map(|&(_, tf)| tf as usize).sum());
+                        (distance, freq, candidate)
+                    })
+            })
+            .collect();
+        
+        // Closest first, then most frequent, then alphabetical for stable output
+        candidates.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)));
+        candidates.into_iter()
+            .take(max_suggestions)
+            .map(|(_, _, candidate)| candidate.clone())
+            .collect()
+    }
+    
+    // Fits as much of a section as `remaining` bytes allow, ending on a sentence or word
+    // boundary. Returns an empty string if not even the heading plus some content fits.
+    fn partial_section(section_text: &str, remaining: usize) -> String {
+        let options = TruncateOptions {
+            boundary: TruncateBoundary::Sentence,
+            suffix: "",
+            unit: LengthUnit::Bytes,
+        };
+        let partial = truncate_with(section_text, remaining, &options);
+        
+        match partial.split_once('\n') {
+            Some((_, body)) if !body.trim().is_empty() => partial + "\n",
+            _ => String::new(),
+        }
+    }
+    
+    fn record_access(&mut self, hits: &[(usize, Option<f32>)]) {
+        let now = date_now();
+        for &(idx, _) in hits {
+            let section = &mut self.sections[idx];
+            section.access_count += 1;
+            section.last_accessed = Some(now);
+        }
+    }
+    
+    // Fuses the rankings of each query variant with reciprocal rank fusion
+    #[cfg(feature = "json")]
+    fn rank_multi(&self, queries_json: &str, max_results: usize) -> Vec<(usize, MultiSearchHit)> {
+        let queries: Vec<String> = match serde_json::from_str(queries_json) {
+            Ok(queries) => queries,
+            Err(e) => {
+                console_log!("Error parsing query variants: {}", e);
+                return Vec::new();
+            }
+        };
+        
+        let mut fused: HashMap<usize, (MultiSearchHit, usize)> = HashMap::new();
+        
+        for (variant, query) in queries.iter().enumerate() {
+            for (rank, (idx, hit)) in self.rank_sections(query, max_results).hits.into_iter().enumerate() {
+                let contribution = 1.0 / (RRF_K + rank as f32 + 1.0);
+                
+                match fused.get_mut(&idx) {
+                    Some((entry, best_rank)) => {
+                        entry.fused_score += contribution;
+                        entry.variants.push(variant);
+                        if rank < *best_rank {
+                            entry.hit = hit;
+                            *best_rank = rank;
+                        }
+                    }
+                    None => {
+                        let entry = MultiSearchHit {
+                            hit,
+                            fused_score: contribution,
+                            variants: vec![variant],
+                        };
+                        fused.insert(idx, (entry, rank));
+                    }
                 }
             }
         }
         
-        // Build context string
+        let mut hits: Vec<(usize, MultiSearchHit)> = fused.into_iter()
+            .map(|(idx, (hit, _))| (idx, hit))
+            .collect();
+        hits.sort_by(|a, b| {
+            score_order(b.1.fused_score, a.1.fused_score).then(a.0.cmp(&b.0))
+        });
+        
+        if max_results > 0 {
+            hits.truncate(max_results.min(MAX_RESULTS_CAP));
+        }
+        hits
+    }
+    
+    // ln(1 + uses), decayed by the time since the last use
+    fn frecency(section: &MarkdownSection, now: f64) -> f32 {
+        let Some(last_accessed) = section.last_accessed else { return 0.0 };
+        
+        let age_days = ((now - last_accessed) / 86_400_000.0).max(0.0);
+        let decay = 0.5_f64.powf(age_days / FRECENCY_HALF_LIFE_DAYS);
+        ((section.access_count as f64).ln_1p() * decay) as f32
+    }
+    
+    // "Parent > Child" so identically titled subsections can be told apart
+    fn display_title(section: &MarkdownSection) -> String {
+        if section.heading_path.len() > 1 {
+            section.heading_path.join(" > ")
+        } else {
+            section.title.clone()
+        }
+    }
+    
+    // Formats sections into the context string, honoring `max_context_length`
+    fn build_context(&self, hits: &[(usize, Option<f32>)]) -> ContextBuild {
         let mut context = String::new();
         let mut total_length = 0;
+        let mut included = 0;
         
         context.push_str("# RELEVANT CONTEXT\n\n");
         
-        let scores_count = scores.len();
-        for (idx, score) in scores {
+        for &(idx, score) in hits {
             let section = &self.sections[idx];
-            let section_text = format!(
-                "## {} (Relevance: {:.1})\n{}\n\n",
-                section.title,
-                score,
-                section.content
-            );
+            let section_text = match score {
+                Some(score) => format!(
+                    "## {} (Relevance: {:.1})\n{}\n\n",
+                    Self::display_title(section),
+                    score,
+                    section.content
+                ),
+                None => format!("## {}\n{}\n\n", Self::display_title(section), section.content),
+            };
             
             // Check if adding this section would exceed max length
             if total_length + section_text.len() > self.max_context_length {
-                context.push_str("\n[Context truncated due to length limits]");
+                let remaining = self.max_context_length.saturating_sub(total_length);
+                let partial = Self::partial_section(&section_text, remaining);
+                if !partial.is_empty() {
+                    included += 1;
+                }
+                total_length += partial.len();
+                context.push_str(&partial);
+                context.push_str(TRUNCATION_NOTE);
                 break;
             }
             
             context.push_str(&section_text);
             total_length += section_text.len();
+            included += 1;
         }
         
-        console_log!("✅ Built context with {} sections, {} chars", scores_count, total_length);
-        context
+        ContextBuild { context, total_length, included }
     }
     
-    pub fn get_full_context(&self) -> String {
+    // All sections grouped by category in priority order, cut off at `max_context_length`.
+    // Sections outside the known categories and any section cut short count as omitted.
+    fn build_full_context(&self) -> ContextBuild {
         let mut context = String::new();
         let mut total_length = 0;
+        let mut included = 0;
         
         // Group by category
         let mut by_category: HashMap<String, Vec<&MarkdownSection>> = HashMap::new();
         for section in &self.sections {
             by_category.entry(section.category.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(section);
         }
         
@@ -266,56 +1144,275 @@ impl MarkdownProcessor {
                     let section_text = format!("## {}\n{}\n\n", section.title, section.content);
                     
                     if total_length + section_text.len() > self.max_context_length {
-                        context.push_str("\n[Context truncated due to length limits]");
-                        return context;
+                        let remaining = self.max_context_length.saturating_sub(total_length);
+                        context.push_str(&Self::partial_section(&section_text, remaining));
+                        context.push_str(TRUNCATION_NOTE);
+                        return ContextBuild { context, total_length, included };
                     }
                     
                     context.push_str(&section_text);
                     total_length += section_text.len();
+                    included += 1;
                 }
             }
         }
         
-        context
+        ContextBuild { context, total_length, included }
     }
     
-    pub fn set_max_context_length(&mut self, length: usize) {
-        self.max_context_length = length;
-        console_log!("Set max context length to {} chars", length);
+    // Shared ranking used by both the string and the structured search paths
+    fn rank_sections(&self, query: &str, max_results: usize) -> Ranking {
+        let query_words = Self::query_terms(query, self.fold_diacritics);
+        
+        // Calculate relevance scores for each section. A query without terms (empty,
+        // whitespace, punctuation) matches nothing, so only the pinned sections remain.
+        let mut hits: Vec<(usize, SearchHit)> = if query_words.is_empty() {
+            Vec::new()
+        } else {
+            (0..self.sections.len())
+                .map(|idx| (idx, self.score_section(idx, &query_words)))
+                .filter(|(_, hit)| hit.score > 0.0)
+                .collect()
+        };
+        
+        // Frequently and recently used sections get a boost on top of their content score
+        if self.frecency_weight > 0.0 {
+            let now = date_now();
+            for (idx, hit) in hits.iter_mut() {
+                hit.score += self.frecency_weight * Self::frecency(&self.sections[*idx], now);
+            }
+        }
+        
+        // 0 means unbounded (the context budget decides); absurd values are clamped
+        let limit = match max_results {
+            0 => usize::MAX,
+            n => n.min(MAX_RESULTS_CAP),
+        };
+        let candidates = hits.len();
+        let limited = candidates > limit;
+        
+        // Sort by relevance score, partitioning first so only the kept hits get fully sorted.
+        // Equal scores fall back to the section id so results don't shuffle between runs.
+        let by_score = |a: &(usize, SearchHit), b: &(usize, SearchHit)| {
+            score_order(b.1.score, a.1.score).then_with(|| a.1.id.cmp(&b.1.id))
+        };
+        if limited {
+            hits.select_nth_unstable_by(limit, by_score);
+            hits.truncate(limit);
+        }
+        hits.sort_by(by_score);
+        
+        // Always include pinned sections (core personal info by default) if available
+        let pinned = if self.pinning_enabled { self.pinned_sections.as_slice() } else { &[] };
+        for pattern in pinned {
+            if let Some(idx) = self.sections.iter().position(|s| s.id.contains(pattern.as_str())) {
+                if !hits.iter().any(|(i, _)| *i == idx) {
+                    let mut hit = self.score_section(idx, &query_words);
+                    hit.score = 100.0; // High priority
+                    hits.insert(0, (idx, hit));
+                }
+            }
+        }
+        
+        Ranking {
+            hits,
+            #[cfg(feature = "json")]
+            candidates,
+            #[cfg(feature = "json")]
+            limited,
+        }
     }
-    
-    pub fn get_section_count(&self) -> usize {
-        self.sections.len()
+}
+
+// Total order on scores with NaN below everything, for sorting without panics
+fn score_order(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).unwrap(),
     }
-    
-    pub fn get_index_size(&self) -> usize {
-        self.index.len()
+}
+
+// Levenshtein distance between `a` and `b`, or `None` once it is known to exceed `max`
+#[cfg(feature = "json")]
+fn bounded_levenshtein(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
     }
     
-    // Export sections as JSON
-    pub fn export_sections(&self) -> String {
-        match serde_json::to_string(&self.sections) {
-            Ok(json) => json,
-            Err(e) => {
-                console_log!("Error exporting sections: {}", e);
-                "[]".to_string()
-            }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        let mut row_min = curr[0];
+        
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost)
+                .min(prev[j + 1] + 1)
+                .min(curr[j] + 1);
+            row_min = row_min.min(curr[j + 1]);
         }
+        
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
     }
     
-    // Import sections from JSON
-    pub fn import_sections(&mut self, json: &str) {
-        match serde_json::from_str::<Vec<MarkdownSection>>(json) {
-            Ok(sections) => {
-                self.clear();
-                for section in sections {
-                    self.add_section(section);
-                }
-                console_log!("Imported {} sections", self.sections.len());
-            }
-            Err(e) => {
-                console_log!("Error importing sections: {}", e);
-            }
+    let distance = prev[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+#[cfg(all(test, feature = "parse"))]
+mod tests {
+    use super::*;
+
+    fn ids(processor: &MarkdownProcessor) -> Vec<String> {
+        processor.sections.iter().map(|s| s.id.clone()).collect()
+    }
+
+    #[test]
+    fn section_ids_stay_unique_after_removal() {
+        let mut processor = MarkdownProcessor::new();
+        processor.load_markdown("# One\nfirst\n\n# Two\nsecond\n\n# Three\nthird\n", "knowledge");
+        assert!(processor.remove_section("knowledge_1"));
+
+        processor.load_markdown("# Four\nfourth\n", "knowledge");
+
+        let ids = ids(&processor);
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(unique.len(), ids.len(), "duplicate ids in {:?}", ids);
+        assert_eq!(ids, ["knowledge_0", "knowledge_2", "knowledge_3"]);
+    }
+
+    #[test]
+    fn search_stays_correct_after_removing_a_middle_section() {
+        let mut processor = MarkdownProcessor::new();
+        processor.set_pinning_enabled(false);
+        processor.load_markdown(
+            "# Apples\nApples grow on trees.\n\n# Bananas\nBananas grow in bunches.\n\n# Cherries\nCherries grow on trees too.\n",
+            "fruit",
+        );
+        assert!(processor.remove_section("fruit_1"));
+
+        let hits = |query: &str| -> Vec<(String, String)> {
+            processor
+                .rank_sections(query, 0)
+                .hits
+                .iter()
+                .map(|(idx, hit)| (hit.id.clone(), processor.sections[*idx].id.clone()))
+                .collect()
+        };
+        // Each hit's id and the section its index points at must agree
+        assert_eq!(hits("cherries"), [("fruit_2".to_string(), "fruit_2".to_string())]);
+        assert_eq!(hits("apples"), [("fruit_0".to_string(), "fruit_0".to_string())]);
+        assert!(hits("bananas").is_empty());
+        let mut trees: Vec<String> = hits("trees").into_iter().map(|(id, _)| id).collect();
+        trees.sort();
+        assert_eq!(trees, ["fruit_0", "fruit_2"]);
+    }
+
+    #[test]
+    fn equal_scores_are_ordered_by_section_id() {
+        let mut processor = MarkdownProcessor::new();
+        // Loaded first but sorting after, so load order can't stand in for the tie-break
+        processor.load_markdown("# Note\nshared wording\n", "zeta");
+        processor.load_markdown("# Note\nshared wording\n", "alpha");
+
+        for _ in 0..3 {
+            let ranking = processor.rank_sections("shared", 0);
+            let ids: Vec<&str> = ranking.hits.iter().map(|(_, hit)| hit.id.as_str()).collect();
+            assert_eq!(ids, ["alpha_1", "zeta_0"]);
+            assert_eq!(ranking.hits[0].1.score, ranking.hits[1].1.score);
+        }
+    }
+
+    #[test]
+    fn empty_queries_return_only_the_pinned_sections() {
+        let mut processor = MarkdownProcessor::new();
+        processor.load_markdown("# Me\nMy name is Ada.\n", "personal_identity");
+        processor.load_markdown("# Rust\nOwnership and borrowing.\n", "knowledge");
+
+        for query in ["", "   ", "?!"] {
+            let ranking = processor.rank_sections(query, 0);
+            let ids: Vec<&str> = ranking.hits.iter().map(|(_, hit)| hit.id.as_str()).collect();
+            assert_eq!(ids, ["personal_identity_0"], "query {:?}", query);
         }
+
+        processor.set_pinning_enabled(false);
+        assert!(processor.rank_sections("", 0).hits.is_empty());
+    }
+
+    #[test]
+    fn nan_scores_sort_last_without_panicking() {
+        let mut scores = [1.0, f32::NAN, 3.0, 2.0];
+        scores.sort_by(|a, b| score_order(*b, *a));
+        assert_eq!(scores[..3], [3.0, 2.0, 1.0]);
+        assert!(scores[3].is_nan());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn full_context_info_counts_characters_not_bytes() {
+        let mut processor = MarkdownProcessor::new();
+        processor.load_markdown("# Größe
+Über 日本語 straße
+", "knowledge");
+
+        let context = processor.get_full_context();
+        let info: serde_json::Value = serde_json::from_str(&processor.get_full_context_info()).unwrap();
+        let chars = context.chars().count();
+        assert!(chars < context.len());
+        assert_eq!(info["total_chars"], chars);
+        assert_eq!(info["estimated_tokens"], chars / 4);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn search_titles_treats_zero_max_results_as_unbounded() {
+        let mut processor = MarkdownProcessor::new();
+        processor.load_markdown("# Rust basics\none\n\n# Rust traits\ntwo\n\n# Rust macros\nthree\n", "knowledge");
+
+        let count = |max_results| {
+            let hits: Vec<serde_json::Value> =
+                serde_json::from_str(&processor.search_titles("rust", max_results)).unwrap();
+            hits.len()
+        };
+        assert_eq!(count(0), 3);
+        assert_eq!(count(2), 2);
+        assert_eq!(count(10), 3);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn suggest_corrects_the_last_word_only() {
+        let mut processor = MarkdownProcessor::new();
+        processor.load_markdown("# Rust traits\nTraits describe shared behaviour.\n", "knowledge");
+
+        let suggest = |term: &str| -> Vec<String> {
+            serde_json::from_str(&processor.suggest(term, 5)).unwrap()
+        };
+        assert_eq!(suggest("traitz"), ["traits"]);
+        assert_eq!(suggest("rust traitz"), ["traits"]);
+        assert!(suggest("").is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn suggest_forgets_terms_of_removed_sections() {
+        let mut processor = MarkdownProcessor::new();
+        processor.load_markdown("# One\nbehaviour\n\n# Two\nbehavior\n", "knowledge");
+        assert_eq!(processor.suggest("behaviorr", 5), r#"["behavior","behaviour"]"#);
+
+        assert!(processor.remove_section("knowledge_1"));
+        assert_eq!(processor.suggest("behaviorr", 5), r#"["behaviour"]"#);
+        processor.clear();
+        assert_eq!(processor.suggest("behaviorr", 5), "[]");
     }
 }